@@ -2,16 +2,26 @@ use std::path::PathBuf;
 
 use clap::Parser;
 use info::InfoArgs;
-use sealed_common::{metadata::LevelFilter, util::tracing::setup_tracing};
+use sealed_common::{
+    metadata::LevelFilter,
+    util::tracing::{level_filter, setup_tracing, LogFile},
+};
 
 use crate::{error::SealedCliResult, init::init_config};
 
+mod clean;
 mod cluster;
+mod config;
 mod docker_handler;
 mod info;
+mod render;
+mod run;
 pub(crate) mod sealedinfra;
 mod serverinfra;
+mod shell;
+mod task;
 mod terraform;
+mod validate;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -24,12 +34,31 @@ pub struct Cli {
     #[arg(short, long)]
     pub root: Option<PathBuf>,
 
-    #[clap(short('l'), long, value_name("LEVEL"), default_value("info"))]
-    pub log_level: LevelFilter,
+    /// Default log level, overridden by `RUST_LOG` [default: log_level setting]
+    #[clap(short('l'), long, value_name("LEVEL"))]
+    pub log_level: Option<LevelFilter>,
+
+    /// Also write the logs to this file [default: log_file setting]
+    #[arg(long, global = true, value_name("PATH"))]
+    pub log_file: Option<PathBuf>,
 
     #[arg(short, long)]
     pub settings: Option<PathBuf>,
 
+    #[arg(
+        long,
+        global = true,
+        help = "Always pull images instead of using the local image cache"
+    )]
+    pub no_image_cache: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Print progress messages without animating spinners (also set by NO_SPINNER)"
+    )]
+    pub no_spinner: bool,
+
     #[command(subcommand)]
     pub cmd: Command,
 }
@@ -40,7 +69,10 @@ impl Default for Cli {
             settings: Some(PathBuf::from("config/config.yaml")),
             verbose: false,
             root: None,
-            log_level: LevelFilter::INFO,
+            log_level: None,
+            log_file: None,
+            no_image_cache: false,
+            no_spinner: false,
             cmd: Command::Info(InfoArgs {}),
         }
     }
@@ -50,6 +82,8 @@ impl Default for Cli {
 pub enum Command {
     #[command(about = "Show information about sealedinfra")]
     Info(InfoArgs),
+    #[command(about = "Inspect the settings")]
+    Config(config::ConfigArgs),
     #[command(about = "Manage clusters", alias = "c")]
     Cluster(cluster::ClusterArgs),
     #[command(about = "Manage terraform", alias = "t")]
@@ -60,22 +94,52 @@ pub enum Command {
     Docker(Box<docker_handler::DockerHandlerArgs>),
     #[command(about = "Manage server infrastructure")]
     Server(serverinfra::ServerInitArgs),
+    #[command(about = "Render the manifests for an app config")]
+    Render(render::RenderArgs),
+    #[command(about = "Inspect the tasks in a taskfile")]
+    Task(task::TaskArgs),
+    #[command(about = "Run a task and the tasks it depends on")]
+    Run(run::RunArgs),
+    #[command(about = "Open an interactive shell in the container a task runs in")]
+    Shell(shell::ShellArgs),
+    #[command(about = "Check a taskfile or app config without running anything")]
+    Validate(validate::ValidateArgs),
+    #[command(about = "Delete the containers and task images left behind by earlier runs")]
+    Clean(clean::CleanArgs),
 }
 
 pub async fn exec() -> SealedCliResult {
     dotenv::dotenv().ok();
     let cli = Cli::parse();
-    setup_tracing(Some(cli.log_level)).await;
     let cfg = init_config(&cli).expect("Unable to initialize config");
+    let log_level = cli.log_level.unwrap_or(level_filter(cfg.log_level));
+    let log_file = match &cli.log_file {
+        Some(path) => Some(LogFile {
+            path: path.clone(),
+            ..cfg
+                .log_file
+                .clone()
+                .unwrap_or_else(|| LogFile::new(path.clone()))
+        }),
+        None => cfg.log_file.clone(),
+    };
+    setup_tracing(Some(log_level), cfg.log_format, log_file.as_ref()).await;
 
     match cli.cmd {
         Command::Info(args) => info::run(args, cfg).await?,
+        Command::Config(args) => config::run(args, cfg).await?,
         Command::Cluster(args) => cluster::run(args, cfg).await?,
         Command::Terraform(args) => terraform::run(args, cfg).await?,
         Command::SI(args) => sealedinfra::run(*args, cfg).await?,
         Command::Docker(args) => docker_handler::run(*args, cfg).await?,
         // #[cfg(feature = "server")]
         Command::Server(args) => serverinfra::run(args, cfg).await?,
+        Command::Render(args) => render::run(args, cfg).await?,
+        Command::Task(args) => task::run(args, cfg).await?,
+        Command::Run(args) => run::run(args, cfg).await?,
+        Command::Shell(args) => shell::run(args, cfg).await?,
+        Command::Validate(args) => validate::run(args, cfg).await?,
+        Command::Clean(args) => clean::run(args, cfg).await?,
     }
     Ok(())
 }
@@ -0,0 +1,55 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use clap::Parser;
+use sealed_common::settings::Settings;
+use sealed_services::{clean_service::clean, kept_container_service::kept_containers_path};
+
+use crate::error::SealedCliResult;
+
+#[derive(Parser, Debug, Clone)]
+pub struct CleanArgs {
+    /// Also delete running containers, even if a task may still be using them, and the cached task
+    /// images
+    #[arg(long)]
+    pub all: bool,
+
+    /// Print the containers and images which would be deleted without deleting them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Repository the task images are tagged in
+    #[arg(long, default_value = "sealed")]
+    pub docker_repo: String,
+
+    /// Path to the Docker CLI
+    #[arg(long, default_value = "docker")]
+    pub docker_cli: String,
+}
+
+pub async fn run(args: CleanArgs, config: &Settings) -> SealedCliResult<()> {
+    let cleaned = clean(
+        &args.docker_cli,
+        &args.docker_repo,
+        &kept_containers_path(&config.working_directory),
+        args.all,
+        args.dry_run,
+        &Arc::new(AtomicBool::new(false)),
+    )?;
+
+    if args.dry_run {
+        for container in &cleaned.containers {
+            println!("Would delete container {container}");
+        }
+        for image in &cleaned.images {
+            println!("Would delete image {image}");
+        }
+    } else {
+        println!(
+            "Deleted {} container(s) and {} image(s).",
+            cleaned.containers.len(),
+            cleaned.images.len()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use clap::{Parser, ValueEnum};
+use sealed_common::settings::Settings;
+
+use crate::error::{SealedCliError, SealedCliResult};
+
+#[derive(Parser, Debug, Clone)]
+#[command(arg_required_else_help = true)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum Subcommand {
+    #[command(
+        about = "Print the effective settings, after merging every config file and SEALED__ variable"
+    )]
+    Print(PrintArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct PrintArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Yaml)]
+    pub format: Format,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+}
+
+// The settings with credentials masked, in the requested format
+fn render(config: &Settings, format: Format) -> SealedCliResult<String> {
+    let settings = config.redacted();
+    match format {
+        Format::Yaml => {
+            serde_yaml::to_string(&settings).map_err(|e| SealedCliError::Runtime(e.to_string()))
+        }
+        Format::Json => serde_json::to_string_pretty(&settings)
+            .map(|json| json + "\n")
+            .map_err(|e| SealedCliError::Runtime(e.to_string())),
+    }
+}
+
+pub async fn run(args: ConfigArgs, config: &Settings) -> SealedCliResult<()> {
+    match args.subcommand {
+        Subcommand::Print(args) => print!("{}", render(config, args.format)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sealed_common::settings::REDACTED;
+
+    use super::*;
+
+    fn settings() -> Settings {
+        serde_yaml::from_str(
+            r"
+working_directory: /var/lib/sealed
+ssh_key: /home/flynn/.ssh/id_rsa
+remote_cache:
+  bucket: grid
+  access_key_id: AKIAFLYNN
+  secret_access_key: hunter2
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_print_yaml() {
+        let output = render(&settings(), Format::Yaml).unwrap();
+        let printed: serde_yaml::Value = serde_yaml::from_str(&output).unwrap();
+
+        assert_eq!(printed["working_directory"], "/var/lib/sealed");
+        assert_eq!(printed["ssh_key"], "/home/flynn/.ssh/id_rsa");
+        assert_eq!(printed["remote_cache"]["secret_access_key"], REDACTED);
+        assert_eq!(printed["remote_cache"]["access_key_id"], REDACTED);
+        assert!(!output.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_print_json() {
+        let output = render(&settings(), Format::Json).unwrap();
+        let printed: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(printed["remote_cache"]["secret_access_key"], REDACTED);
+        assert_eq!(printed["remote_cache"]["bucket"], "grid");
+    }
+}
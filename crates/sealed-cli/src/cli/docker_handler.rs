@@ -8,7 +8,8 @@ use docker_helpers::{DockerBuilderOptions, DockerInstanceOption};
 use git2::Repository;
 use log::{debug, info};
 use sealed_common::{
-    fs_utils::{expand_path, find_file_by_name},
+    error::SealedError,
+    fs_utils::{expand_path, find_dockerfile, find_file_by_name},
     git_ops::parse_repo_name,
     settings::Settings,
 };
@@ -19,8 +20,8 @@ use std::fs::canonicalize;
 use tokio::process::Command;
 
 mod build;
-// mod generate;
-mod docker_helpers;
+pub(crate) mod docker_helpers;
+mod generate;
 mod run;
 
 pub async fn run(args: DockerHandlerArgs, config: &Settings) -> SealedCliResult<()> {
@@ -28,13 +29,12 @@ pub async fn run(args: DockerHandlerArgs, config: &Settings) -> SealedCliResult<
     let (mut docker_args, config) = docker_args.merge_with_config(config)?;
     docker_args.validate()?;
 
-    match &docker_args.subcmd {
-        // Some(SubCommand::Generate) => generate::run(docker_args, config).await,
+    match docker_args.subcmd.clone() {
+        Some(SubCommand::Generate(generate_args)) => {
+            generate::run(docker_args, &generate_args, &config).await
+        }
         Some(SubCommand::Build) => build::run(docker_args, &config).await,
         Some(SubCommand::Run) => run::run(docker_args, &config).await,
-        Some(_cmd) => Err(SealedCliError::Runtime(
-            "Unhandled command: for now".to_string(),
-        )),
         None => Err(SealedCliError::Runtime(
             "No subcommand specified or unhandled command".to_string(),
         )),
@@ -65,8 +65,8 @@ pub struct DockerCommandArgs {
 
 #[derive(Debug, Parser, Clone)]
 pub enum SubCommand {
-    /// Generate the docker run command
-    Generate,
+    /// Write the resolved docker command to a file or stdout instead of running it
+    Generate(generate::GenerateArgs),
     /// Build the docker run command
     Build,
     /// Run the docker run command
@@ -114,6 +114,17 @@ impl DockerHandlerArgs {
 
 impl DockerHandlerArgs {
     pub fn to_docker_buildx_command_string(&self, config: &Settings) -> SealedCliResult<String> {
+        let cmd_string = self
+            .to_docker_buildx_args(config)?
+            .into_iter()
+            .map(|s| shell_escape::escape(s.into()))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(cmd_string)
+    }
+
+    pub fn to_docker_buildx_args(&self, config: &Settings) -> SealedCliResult<Vec<String>> {
         let repo_name = self.get_repo_name()?;
         let mut cmd_parts: Vec<String> = vec![
             "docker".to_string(),
@@ -137,8 +148,8 @@ impl DockerHandlerArgs {
         for tag in &self.docker.builder.tags {
             cmd_parts.extend_from_slice(&["-t".to_string(), tag.to_string()]);
         }
-        for label in &self.docker.builder.labels {
-            cmd_parts.extend_from_slice(&["--label".to_string(), label.to_string()]);
+        for label in self.docker.builder.resolved_labels()? {
+            cmd_parts.extend_from_slice(&["--label".to_string(), label]);
         }
         if self.docker.builder.quiet {
             cmd_parts.push("--quiet".to_string());
@@ -146,6 +157,15 @@ impl DockerHandlerArgs {
         if self.docker.builder.no_cache {
             cmd_parts.push("--no-cache".to_string());
         }
+        if self.docker.builder.pull {
+            cmd_parts.push("--pull".to_string());
+        }
+        for cache_from in &self.docker.builder.cache_from {
+            cmd_parts.extend_from_slice(&["--cache-from".to_string(), cache_from.to_string()]);
+        }
+        for cache_to in &self.docker.builder.cache_to {
+            cmd_parts.extend_from_slice(&["--cache-to".to_string(), cache_to.to_string()]);
+        }
         for platform in &self.docker.builder.platforms {
             cmd_parts.extend_from_slice(&["--platform".to_string(), platform.to_string()]);
         }
@@ -167,12 +187,15 @@ impl DockerHandlerArgs {
         if let Some(ref dockerfile) = self.docker.builder.dockerfile {
             cmd_parts.extend_from_slice(&["--file".to_string(), dockerfile.to_string()]);
         }
+        if let Some(ref target) = self.docker.builder.target {
+            cmd_parts.extend_from_slice(&["--target".to_string(), target.to_string()]);
+        }
         if self.docker.builder.verbose {
             cmd_parts.push("--verbose".to_string());
         }
 
-        for arg in &self.docker.builder.build_args {
-            cmd_parts.extend_from_slice(&["--build-arg".to_string(), arg.to_string()]);
+        for arg in self.docker.builder.resolved_build_args()? {
+            cmd_parts.extend_from_slice(&["--build-arg".to_string(), arg]);
         }
 
         let tag = format!(
@@ -191,18 +214,18 @@ impl DockerHandlerArgs {
             Some(dockerfile) => {
                 cmd_parts.extend_from_slice(&["-f".to_string(), dockerfile.to_string()]);
             }
-            None => {
-                if let Ok(found_dockerfile) = find_file_by_name(Path::new(in_dir), "Dockerfile") {
-                    if let Some(path_str) = found_dockerfile.to_str() {
-                        let dockerfile_path = path_str.to_owned();
-                        let dockerfile_path = expand_path(Path::new(&dockerfile_path));
-                        cmd_parts.extend_from_slice(&[
-                            "-f".to_string(),
-                            format!("{}", dockerfile_path.to_string_lossy()),
-                        ]);
-                    }
+            None => match find_dockerfile(Path::new(in_dir)) {
+                Ok(found_dockerfile) => {
+                    let dockerfile_path = expand_path(&found_dockerfile);
+                    cmd_parts.extend_from_slice(&[
+                        "-f".to_string(),
+                        format!("{}", dockerfile_path.to_string_lossy()),
+                    ]);
                 }
-            }
+                // Let docker report the missing Dockerfile
+                Err(SealedError::FileNotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            },
         }
 
         if let Some(ref secrets) = self.docker.instance.secrets {
@@ -241,13 +264,7 @@ impl DockerHandlerArgs {
 
         cmd_parts.push(in_dir.to_string());
 
-        let cmd_string = cmd_parts
-            .into_iter()
-            .map(|s| shell_escape::escape(s.into()))
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        Ok(cmd_string)
+        Ok(cmd_parts)
     }
 
     pub fn to_docker_run_command_string(&self, config: &Settings) -> SealedCliResult<String> {
@@ -258,12 +275,20 @@ impl DockerHandlerArgs {
             cmd_parts.push("--rm".to_string());
         }
 
+        if self.docker.instance.detach {
+            cmd_parts.push("-d".to_string());
+        }
+
+        if let Some(ref restart) = self.docker.instance.restart {
+            cmd_parts.extend_from_slice(&["--restart".to_string(), restart.to_string()]);
+        }
+
         for volume in &self.docker.instance.volumes {
             cmd_parts.extend_from_slice(&["-v".to_string(), volume.to_string()]);
         }
 
-        for env_var in &self.docker.instance.env {
-            cmd_parts.extend_from_slice(&["-e".to_string(), env_var.to_string()]);
+        for env_var in self.docker.instance.resolved_env()? {
+            cmd_parts.extend_from_slice(&["-e".to_string(), env_var]);
         }
 
         if let Some(ref name) = self.docker.instance.name {
@@ -274,6 +299,18 @@ impl DockerHandlerArgs {
             cmd_parts.extend_from_slice(&["-u".to_string(), user.to_string()]);
         }
 
+        if let Some(ref network) = self.docker.instance.network {
+            cmd_parts.extend_from_slice(&["--network".to_string(), network.to_string()]);
+        }
+
+        if let Some(ref hostname) = self.docker.instance.hostname {
+            cmd_parts.extend_from_slice(&["--hostname".to_string(), hostname.to_string()]);
+        }
+
+        if let Some(platform) = self.run_platform() {
+            cmd_parts.extend_from_slice(&["--platform".to_string(), platform]);
+        }
+
         let tag = format!(
             "{}:{}",
             repo_name,
@@ -297,6 +334,25 @@ impl DockerHandlerArgs {
         Ok(cmd_string)
     }
 
+    /// Prefixes `cmd` with the docker environment variables, as it would be typed in a shell.
+    pub fn with_env_prefix(&self, cmd: &str) -> String {
+        let mut parts = self.get_env_prefix();
+        parts.push(cmd.to_string());
+        parts.join(" ")
+    }
+
+    /// The platform to run on: the explicit one, or else the build platform if there was exactly
+    /// one. With several build platforms, docker picks the one matching the host.
+    pub fn run_platform(&self) -> Option<String> {
+        if let Some(ref platform) = self.docker.instance.platform {
+            return Some(platform.clone());
+        }
+        match self.docker.builder.platforms.as_slice() {
+            [platform] => Some(platform.clone()),
+            _ => None,
+        }
+    }
+
     pub fn get_env_prefix(&self) -> Vec<String> {
         let mut env_prefix: Vec<String> = Vec::new();
 
@@ -334,6 +390,22 @@ impl DockerHandlerArgs {
             ));
         }
 
+        self.docker.instance.validate()?;
+
+        // An inline cache is written into the image, which `--no-cache` doesn't build from a cache
+        // in the first place
+        if self.docker.builder.no_cache
+            && self.docker.builder.cache_to.iter().any(|cache_to| {
+                cache_to
+                    .split(',')
+                    .any(|option| option.trim() == "type=inline")
+            })
+        {
+            return Err(SealedCliError::Runtime(
+                "--cache-to type=inline can't be combined with --no-cache".to_string(),
+            ));
+        }
+
         if self.docker.instance.docker_config.repository.is_some() {
             let repo_as_path = self
                 .docker
@@ -360,7 +432,9 @@ impl DockerHandlerArgs {
         } else if let Some(image) = image {
             Ok(image)
         } else {
-            panic!("No repository or image specified");
+            Err(SealedCliError::Runtime(
+                "No repository or image specified".to_string(),
+            ))
         }
     }
     pub fn merge_with_config(
@@ -384,13 +458,7 @@ impl DockerHandlerArgs {
     }
 
     pub fn with_repo(&mut self, config: &Settings) -> SealedCliResult<Repository> {
-        let branch = self
-            .docker
-            .instance
-            .docker_config
-            .branch
-            .clone()
-            .unwrap_or("main".to_string());
+        let git_ref = self.docker.instance.docker_config.git_ref();
         let repository = self
             .docker
             .instance
@@ -398,9 +466,10 @@ impl DockerHandlerArgs {
             .repository
             .clone()
             .unwrap();
-        let repo = GitRepoService::fetch(&repository, &branch, config)?;
-        let short_sha = GitRepoService::short_sha(&repo)?;
-        self.docker.instance.docker_config.tag = Some(short_sha);
+        let repo = GitRepoService::fetch(&repository, &git_ref, config)?;
+        let sha =
+            GitRepoService::commit_sha(&repo, self.docker.instance.docker_config.tag_sha_length())?;
+        self.docker.instance.docker_config.tag = Some(sha);
 
         info!("Repository cloned: {}", repo.path().display());
         self.docker.builder.current_dir = Some(
@@ -423,7 +492,13 @@ fn merge_instance(mut instance: DockerInstanceOption, config: &Value) -> DockerI
         instance.commands = get_str_sequence(config, "commands").unwrap_or(instance.commands);
         instance.volumes = get_str_sequence(config, "volumes").unwrap_or(instance.volumes);
         instance.env = get_str_sequence(config, "env").unwrap_or(instance.env);
+        instance.env_file = get_str_value(config, "env_file").or(instance.env_file);
         instance.rm = get_bool_value(config, "rm").unwrap_or(instance.rm);
+        instance.platform = get_str_value(config, "platform").or(instance.platform);
+        instance.restart = get_str_value(config, "restart").or(instance.restart);
+        instance.detach = get_bool_value(config, "detach").unwrap_or(instance.detach);
+        instance.network = get_str_value(config, "network").or(instance.network);
+        instance.hostname = get_str_value(config, "hostname").or(instance.hostname);
 
         if let Some(docker_config) = config.get("docker_config") {
             if let Some(docker_config) = docker_config.as_mapping() {
@@ -431,10 +506,20 @@ fn merge_instance(mut instance: DockerInstanceOption, config: &Value) -> DockerI
                     .or(instance.docker_config.repository);
                 instance.docker_config.branch =
                     get_str_value(docker_config, "branch").or(instance.docker_config.branch);
+                instance.docker_config.git_tag =
+                    get_str_value(docker_config, "git_tag").or(instance.docker_config.git_tag);
+                instance.docker_config.commit =
+                    get_str_value(docker_config, "commit").or(instance.docker_config.commit);
                 instance.docker_config.image =
                     get_str_value(docker_config, "image").or(instance.docker_config.image);
                 instance.docker_config.tag =
                     get_str_value(docker_config, "tag").or(instance.docker_config.tag);
+                instance.docker_config.sha_length = docker_config
+                    .get("sha_length")
+                    .and_then(|v| v.as_u64())
+                    .map_or(instance.docker_config.sha_length, |v| v as usize);
+                instance.docker_config.full_sha = get_bool_value(docker_config, "full_sha")
+                    .unwrap_or(instance.docker_config.full_sha);
             }
         }
     }
@@ -451,6 +536,9 @@ fn merge_builder(mut builder: DockerBuilderOptions, config: &Value) -> DockerBui
         builder.labels = get_str_sequence(config, "labels").unwrap_or(builder.labels);
         builder.quiet = get_bool_value(config, "quiet").unwrap_or(builder.quiet);
         builder.no_cache = get_bool_value(config, "no_cache").unwrap_or(builder.no_cache);
+        builder.pull = get_bool_value(config, "pull").unwrap_or(builder.pull);
+        builder.cache_from = get_str_sequence(config, "cache_from").unwrap_or(builder.cache_from);
+        builder.cache_to = get_str_sequence(config, "cache_to").unwrap_or(builder.cache_to);
         builder.platforms = get_str_sequence(config, "platforms").unwrap_or(builder.platforms);
         builder.current_dir = get_str_value(config, "current_dir").or(builder.current_dir);
         builder.cpu_quota = get_str_value(config, "cpu_quota").or(builder.cpu_quota);
@@ -458,6 +546,7 @@ fn merge_builder(mut builder: DockerBuilderOptions, config: &Value) -> DockerBui
         builder.cpu_share = get_str_value(config, "cpu_share").or(builder.cpu_share);
         builder.memory = get_str_value(config, "memory").or(builder.memory);
         builder.memory_swap = get_str_value(config, "memory_swap").or(builder.memory_swap);
+        builder.target = get_str_value(config, "target").or(builder.target);
         builder.verbose = get_bool_value(config, "verbose").unwrap_or(builder.verbose);
         builder.docker_host = get_str_value(config, "docker_host").or(builder.docker_host);
         builder.docker_tls_verify =
@@ -496,3 +585,190 @@ fn merge_config(mut config: Settings, other: &Value) -> Settings {
     }
     config
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_settings() -> Settings {
+        serde_yaml::from_str("ssh_key: null").unwrap()
+    }
+
+    fn test_args() -> DockerHandlerArgs {
+        let mut args = DockerHandlerArgs::default();
+        args.docker.instance.docker_config.image = Some("sealed-app".to_string());
+        args.docker.builder.dockerfile = Some("Dockerfile".to_string());
+        args
+    }
+
+    fn has_pair(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2)
+            .any(|pair| pair[0] == flag && pair[1] == value)
+    }
+
+    #[test]
+    fn test_buildx_args_with_registry_cache() {
+        let mut args = test_args();
+        args.docker.builder.pull = true;
+        args.docker.builder.cache_from = vec!["type=registry,ref=user/app:cache".to_string()];
+        args.docker.builder.cache_to = vec!["type=inline".to_string()];
+
+        let cmd = args.to_docker_buildx_args(&test_settings()).unwrap();
+        assert!(cmd.contains(&"--pull".to_string()));
+        assert!(has_pair(
+            &cmd,
+            "--cache-from",
+            "type=registry,ref=user/app:cache"
+        ));
+        assert!(has_pair(&cmd, "--cache-to", "type=inline"));
+    }
+
+    #[test]
+    fn test_buildx_args_without_registry_cache() {
+        let cmd = test_args().to_docker_buildx_args(&test_settings()).unwrap();
+        assert!(!cmd.contains(&"--pull".to_string()));
+        assert!(!cmd.contains(&"--cache-from".to_string()));
+        assert!(!cmd.contains(&"--cache-to".to_string()));
+    }
+
+    #[test]
+    fn test_buildx_target() {
+        let mut args = test_args();
+        args.docker.builder.target = Some("test-stage".to_string());
+
+        let cmd = args.to_docker_buildx_args(&test_settings()).unwrap();
+        assert!(has_pair(&cmd, "--target", "test-stage"));
+        // The target doesn't affect the tag
+        assert!(has_pair(&cmd, "-t", "sealed-app:latest"));
+
+        let cmd_string = args
+            .to_docker_buildx_command_string(&test_settings())
+            .unwrap();
+        assert!(cmd_string.contains(" --target test-stage "));
+        assert!(cmd_string.starts_with("docker buildx build "));
+    }
+
+    #[test]
+    fn test_buildx_target_is_escaped() {
+        let mut args = test_args();
+        args.docker.builder.target = Some("stage; rm -rf /".to_string());
+
+        let cmd_string = args
+            .to_docker_buildx_command_string(&test_settings())
+            .unwrap();
+        assert!(cmd_string.contains(" --target 'stage; rm -rf /' "));
+    }
+
+    #[test]
+    fn test_run_platform_from_single_build_platform() {
+        let mut args = test_args();
+        args.docker.builder.platforms = vec!["linux/arm64".to_string()];
+
+        let cmd = args.to_docker_run_command_string(&test_settings()).unwrap();
+        assert!(cmd.contains(" --platform linux/arm64 "));
+    }
+
+    #[test]
+    fn test_run_platform_with_multiple_build_platforms() {
+        let mut args = test_args();
+        args.docker.builder.platforms = vec!["linux/amd64".to_string(), "linux/arm64".to_string()];
+        assert_eq!(args.run_platform(), None);
+
+        let cmd = args.to_docker_run_command_string(&test_settings()).unwrap();
+        assert!(!cmd.contains("--platform"));
+
+        // An explicit run platform still wins
+        args.docker.instance.platform = Some("linux/arm64".to_string());
+        assert_eq!(args.run_platform(), Some("linux/arm64".to_string()));
+    }
+
+    #[test]
+    fn test_run_restart_and_detach() {
+        let mut args = test_args();
+        args.docker.instance.rm = false;
+        args.docker.instance.restart = Some("on-failure:3".to_string());
+        args.docker.instance.detach = true;
+        assert!(args.validate().is_ok());
+
+        let cmd = args.to_docker_run_command_string(&test_settings()).unwrap();
+        assert!(cmd.starts_with("docker run -d --restart 'on-failure:3' "));
+        assert!(!cmd.contains("--rm"));
+
+        let cmd = test_args()
+            .to_docker_run_command_string(&test_settings())
+            .unwrap();
+        assert!(!cmd.contains(" -d "));
+        assert!(!cmd.contains("--restart"));
+    }
+
+    #[test]
+    fn test_run_network_and_hostname() {
+        let mut args = test_args();
+        args.docker.instance.network = Some("grid".to_string());
+        args.docker.instance.hostname = Some("api.grid".to_string());
+
+        let cmd = args.to_docker_run_command_string(&test_settings()).unwrap();
+        assert!(cmd.contains(" --network grid --hostname api.grid "));
+
+        let cmd = test_args()
+            .to_docker_run_command_string(&test_settings())
+            .unwrap();
+        assert!(!cmd.contains("--network"));
+        assert!(!cmd.contains("--hostname"));
+    }
+
+    #[test]
+    fn test_merge_instance_network_and_hostname() {
+        let config: Value = serde_yaml::from_str("network: grid\nhostname: api").unwrap();
+        let instance = merge_instance(DockerInstanceOption::default(), &config);
+        assert_eq!(instance.network, Some("grid".to_string()));
+        assert_eq!(instance.hostname, Some("api".to_string()));
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_restart_policy() {
+        let mut args = test_args();
+        args.docker.instance.rm = false;
+        args.docker.instance.restart = Some("whenever".to_string());
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_merge_instance_restart_and_detach() {
+        let config: Value = serde_yaml::from_str(
+            "restart: always
+detach: true",
+        )
+        .unwrap();
+        let instance = merge_instance(DockerInstanceOption::default(), &config);
+        assert_eq!(instance.restart, Some("always".to_string()));
+        assert!(instance.detach);
+
+        // Missing settings leave the instance alone
+        let instance = merge_instance(instance, &serde_yaml::from_str("rm: false").unwrap());
+        assert_eq!(instance.restart, Some("always".to_string()));
+        assert!(instance.detach);
+    }
+
+    #[test]
+    fn test_merge_builder_cache_options() {
+        let config: Value =
+            serde_yaml::from_str("pull: true\ncache_from:\n  - type=gha\ncache_to:\n  - type=gha")
+                .unwrap();
+        let builder = merge_builder(DockerBuilderOptions::default(), &config);
+        assert!(builder.pull);
+        assert_eq!(builder.cache_from, vec!["type=gha"]);
+        assert_eq!(builder.cache_to, vec!["type=gha"]);
+    }
+
+    #[test]
+    fn test_validate_rejects_inline_cache_without_cache() {
+        let mut args = test_args();
+        args.docker.builder.no_cache = true;
+        args.docker.builder.cache_to = vec!["type=inline".to_string()];
+        assert!(args.validate().is_err());
+
+        args.docker.builder.cache_to = vec!["type=registry,ref=user/app:cache".to_string()];
+        assert!(args.validate().is_ok());
+    }
+}
@@ -57,7 +57,7 @@ pub async fn run(args: &mut DockerHandlerArgs, config: &Settings) -> SealedCliRe
     let env_prefix = args.get_env_prefix();
 
     if args.dry_run {
-        println!("cmd: {}", cmd);
+        println!("cmd: {}", args.with_env_prefix(&cmd));
         Ok(())
     } else {
         debug!("cmd: {}", cmd);
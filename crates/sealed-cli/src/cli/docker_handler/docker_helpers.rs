@@ -1,6 +1,9 @@
 use std::{fmt::Display, path::PathBuf, process::Command};
 
 use clap::{Args, Parser};
+use sealed_common::env_file::parse_env_file;
+use sealed_common::error::{SealedError, SealedResult};
+use sealed_services::git_repo_service::GitRef;
 use serde::{Deserialize, Serialize};
 
 #[derive(Args, Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +23,17 @@ pub struct DockerBuilderOptions {
     pub quiet: bool,
     #[arg(long)]
     pub no_cache: bool,
+    /// Always pull the newer versions of the base images
+    #[arg(long)]
+    pub pull: bool,
+    /// External cache sources (e.g. `type=registry,ref=user/app:cache`)
+    #[arg(long)]
+    #[serde(default)]
+    pub cache_from: Vec<String>,
+    /// Cache export destinations (e.g. `type=inline`)
+    #[arg(long)]
+    #[serde(default)]
+    pub cache_to: Vec<String>,
     #[arg(long)]
     pub platforms: Vec<String>,
     #[arg(long)]
@@ -46,6 +60,10 @@ pub struct DockerBuilderOptions {
     pub docker_cert_path: Option<String>,
     #[arg(long)]
     pub dockerfile: Option<String>,
+    /// The stage of a multi-stage Dockerfile to build
+    #[arg(long)]
+    #[serde(default)]
+    pub target: Option<String>,
     #[arg(long, short = 'a')]
     pub build_args: Vec<String>,
 }
@@ -60,6 +78,9 @@ impl Default for DockerBuilderOptions {
             labels: vec![],
             quiet: false,
             no_cache: false,
+            pull: false,
+            cache_from: vec![],
+            cache_to: vec![],
             platforms: vec![],
             current_dir: None,
             cpu_quota: Some("50000".to_string()),
@@ -73,26 +94,159 @@ impl Default for DockerBuilderOptions {
             docker_output: None,
             docker_cert_path: None,
             dockerfile: None,
+            target: None,
             build_args: vec![],
         }
     }
 }
-#[derive(Args, Debug, Clone, Serialize, Deserialize, Default)]
+
+impl DockerBuilderOptions {
+    /// The build args as `KEY=VALUE` entries. A bare `KEY` takes its value from the environment.
+    pub fn resolved_build_args(&self) -> SealedResult<Vec<String>> {
+        self.build_args
+            .iter()
+            .map(|arg| {
+                parse_build_arg(arg, |key| std::env::var(key).ok())
+                    .map(|(key, value)| format!("{}={}", key, value))
+            })
+            .collect()
+    }
+
+    /// The labels as `KEY=VALUE` entries, with `KEY=@path` values read from the file at `path`.
+    pub fn resolved_labels(&self) -> SealedResult<Vec<String>> {
+        self.labels
+            .iter()
+            .map(|label| parse_label(label).map(|(key, value)| format!("{}={}", key, value)))
+            .collect()
+    }
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(char::is_whitespace)
+}
+
+/// Accepts `KEY=VALUE`, or `KEY` to take the value of that variable from `lookup`.
+fn parse_build_arg(
+    arg: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> SealedResult<(String, String)> {
+    let invalid =
+        |reason: &str| SealedError::BadRequest(format!("Invalid build arg `{}`: {}", arg, reason));
+
+    match arg.split_once('=') {
+        Some((key, value)) if valid_key(key) => Ok((key.to_string(), value.to_string())),
+        Some(_) => Err(invalid("expected `KEY=VALUE` or `KEY`")),
+        None if valid_key(arg) => lookup(arg)
+            .map(|value| (arg.to_string(), value))
+            .ok_or_else(|| invalid("the variable is not set in the environment")),
+        None => Err(invalid("expected `KEY=VALUE` or `KEY`")),
+    }
+}
+
+/// Accepts `KEY=VALUE`. A value of `@path` is replaced by the contents of that file, without a
+/// trailing newline.
+fn parse_label(label: &str) -> SealedResult<(String, String)> {
+    let invalid =
+        |reason: &str| SealedError::BadRequest(format!("Invalid label `{}`: {}", label, reason));
+
+    let (key, value) = label
+        .split_once('=')
+        .filter(|(key, _)| valid_key(key))
+        .ok_or_else(|| invalid("expected `KEY=VALUE`"))?;
+
+    let value = match value.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| invalid(&format!("unable to read {}: {}", path, e)))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string(),
+        None => value.to_string(),
+    };
+
+    Ok((key.to_string(), value))
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
 pub struct DockerSpecificArgs {
     /// Repository
     #[arg(long, short = 'r', alias = "repo")]
     pub repository: Option<String>,
     /// Branch
-    #[arg(long, short = 'b')]
+    #[arg(long, short = 'b', conflicts_with_all = ["git_tag", "commit"])]
     pub branch: Option<String>,
 
+    /// Git tag to check out instead of a branch
+    #[arg(long, conflicts_with = "commit")]
+    #[serde(default)]
+    pub git_tag: Option<String>,
+
+    /// Commit to check out instead of a branch
+    #[arg(long)]
+    #[serde(default)]
+    pub commit: Option<String>,
+
     /// Image
     #[arg(long, short, alias = "img", conflicts_with = "repository")]
     pub image: Option<String>,
 
     /// Tag
-    #[arg(long, short, default_value = "latest", conflicts_with = "branch")]
+    #[arg(
+        long,
+        short,
+        default_value = "latest",
+        conflicts_with_all = ["branch", "git_tag", "commit"]
+    )]
     pub tag: Option<String>,
+
+    /// Length of the commit SHA used as the tag when building from a repository. It's made longer
+    /// if needed to be unambiguous.
+    #[arg(long, default_value_t = DEFAULT_SHA_LENGTH, conflicts_with = "full_sha")]
+    #[serde(default = "default_sha_length")]
+    pub sha_length: usize,
+
+    /// Tag the image with the full commit SHA rather than an abbreviation
+    #[arg(long)]
+    #[serde(default)]
+    pub full_sha: bool,
+}
+
+impl Default for DockerSpecificArgs {
+    fn default() -> Self {
+        Self {
+            repository: None,
+            branch: None,
+            git_tag: None,
+            commit: None,
+            image: None,
+            tag: None,
+            sha_length: DEFAULT_SHA_LENGTH,
+            full_sha: false,
+        }
+    }
+}
+
+pub const DEFAULT_SHA_LENGTH: usize = 7;
+
+fn default_sha_length() -> usize {
+    DEFAULT_SHA_LENGTH
+}
+
+impl DockerSpecificArgs {
+    /// What to check out of the repository: the commit or tag if one was given, otherwise the
+    /// branch (`main` by default).
+    pub fn git_ref(&self) -> GitRef {
+        if let Some(ref commit) = self.commit {
+            GitRef::Commit(commit.clone())
+        } else if let Some(ref tag) = self.git_tag {
+            GitRef::Tag(tag.clone())
+        } else {
+            GitRef::Branch(self.branch.clone().unwrap_or("main".to_string()))
+        }
+    }
+
+    /// The length to abbreviate the commit SHA to, or `None` for the full SHA.
+    pub fn tag_sha_length(&self) -> Option<usize> {
+        (!self.full_sha).then_some(self.sha_length)
+    }
 }
 
 #[derive(Parser, Debug, Clone, Serialize, Deserialize)]
@@ -103,11 +257,15 @@ pub struct DockerInstanceOption {
     #[arg(long, action)]
     pub rm: bool,
     /// Volumes
-    #[arg(long, short = 'v', default_value = "default_volumes")]
+    #[arg(long, short = 'v', default_values_t = default_volumes())]
     pub volumes: Vec<String>,
     /// Environment variables
-    #[arg(long, short = 'e', alias = "e", default_value = "default_env")]
+    #[arg(long, short = 'e', alias = "e", default_values_t = default_env())]
     pub env: Vec<String>,
+    /// A dotenv-style file of environment variables. Variables given with `--env` take precedence.
+    #[arg(long)]
+    #[serde(default)]
+    pub env_file: Option<String>,
     /// Name
     #[arg(long, short = 'n')]
     pub name: Option<String>,
@@ -121,6 +279,31 @@ pub struct DockerInstanceOption {
     #[arg(long, short = 's')]
     pub secrets: Option<Vec<String>>,
 
+    /// Platform to run the image on. Defaults to the build platform when exactly one was given.
+    #[arg(long)]
+    #[serde(default)]
+    pub platform: Option<String>,
+
+    /// Restart policy: `no`, `on-failure[:max-retries]`, `always` or `unless-stopped`
+    #[arg(long)]
+    #[serde(default)]
+    pub restart: Option<String>,
+
+    /// Run the container in the background and print its ID
+    #[arg(long)]
+    #[serde(default)]
+    pub detach: bool,
+
+    /// Network to connect the container to, e.g. a user-defined network shared with a database
+    #[arg(long)]
+    #[serde(default)]
+    pub network: Option<String>,
+
+    /// Hostname of the container
+    #[arg(long)]
+    #[serde(default)]
+    pub hostname: Option<String>,
+
     #[command(flatten)]
     pub docker_config: DockerSpecificArgs,
 }
@@ -137,13 +320,158 @@ impl Default for DockerInstanceOption {
                 "logging:/var/log:rw".to_string(),
             ],
             env: vec!["HOME=/app".to_string()],
+            env_file: None,
             name: None,
             user: None,
             commands: vec![],
             docker_config: DockerSpecificArgs::default(),
             secrets: None,
+            platform: None,
+            restart: None,
+            detach: false,
+            network: None,
+            hostname: None,
+        }
+    }
+}
+
+impl DockerInstanceOption {
+    /// Checks the shape of the volume and environment specs, so mistakes are reported here rather
+    /// than as a confusing error from docker.
+    pub fn validate(&self) -> SealedResult<()> {
+        for volume in &self.volumes {
+            validate_volume(volume)?;
+        }
+        for env in &self.env {
+            validate_env(env)?;
+        }
+        if let Some(ref network) = self.network {
+            if network.trim().is_empty() {
+                return Err(SealedError::BadRequest(
+                    "Invalid network: the name is empty".to_string(),
+                ));
+            }
+        }
+        if let Some(ref restart) = self.restart {
+            validate_restart_policy(restart)?;
+            // Docker refuses to remove a container it's supposed to restart
+            if self.rm && restart != "no" {
+                return Err(SealedError::BadRequest(format!(
+                    "The restart policy `{}` can't be combined with --rm",
+                    restart
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DockerInstanceOption {
+    /// The environment as `KEY=VALUE` entries: the variables from `env_file` that aren't also set
+    /// explicitly, followed by the explicit ones.
+    pub fn resolved_env(&self) -> SealedResult<Vec<String>> {
+        let Some(ref env_file) = self.env_file else {
+            return Ok(self.env.clone());
+        };
+
+        let contents = std::fs::read_to_string(env_file).map_err(|e| {
+            SealedError::BadRequest(format!("Unable to read env file {}: {}", env_file, e))
+        })?;
+
+        let explicit_keys: Vec<&str> = self
+            .env
+            .iter()
+            .map(|env| env.split_once('=').map_or(env.as_str(), |(key, _)| key))
+            .collect();
+
+        let mut env: Vec<String> = parse_env_file(&contents)?
+            .into_iter()
+            .filter(|(key, _)| !explicit_keys.contains(&key.as_str()))
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        env.extend(self.env.iter().cloned());
+        Ok(env)
+    }
+}
+
+const VOLUME_MODES: &[&str] = &[
+    "ro",
+    "rw",
+    "z",
+    "Z",
+    "consistent",
+    "cached",
+    "delegated",
+    "nocopy",
+];
+
+/// Accepts `source:target[:mode]` with an absolute target, or the `type=...` mount form, which
+/// must name a destination.
+fn validate_volume(volume: &str) -> SealedResult<()> {
+    let invalid =
+        |reason: &str| SealedError::BadRequest(format!("Invalid volume `{}`: {}", volume, reason));
+
+    if volume.starts_with("type=") {
+        let mut has_destination = false;
+        for option in volume.split(',') {
+            let Some((key, _)) = option.split_once('=') else {
+                return Err(invalid("mount options must be `key=value`"));
+            };
+            if matches!(key, "destination" | "target" | "dst") {
+                has_destination = true;
+            }
+        }
+        if !has_destination {
+            return Err(invalid("the mount has no destination"));
+        }
+        return Ok(());
+    }
+
+    let parts: Vec<&str> = volume.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err(invalid("expected `host:container[:mode]`"));
+    }
+    if parts[0].is_empty() {
+        return Err(invalid("the host path is empty"));
+    }
+    if !parts[1].starts_with('/') {
+        return Err(invalid("the container path must be absolute"));
+    }
+    if let Some(mode) = parts.get(2) {
+        if !mode.split(',').all(|mode| VOLUME_MODES.contains(&mode)) {
+            return Err(invalid("unknown mode"));
         }
     }
+    Ok(())
+}
+
+/// Accepts `KEY=VALUE`, where the value may be empty but the key may not.
+fn validate_env(env: &str) -> SealedResult<()> {
+    match env.split_once('=') {
+        Some((key, _)) if !key.is_empty() && !key.contains(char::is_whitespace) => Ok(()),
+        _ => Err(SealedError::BadRequest(format!(
+            "Invalid environment variable `{}`: expected `KEY=VALUE`",
+            env
+        ))),
+    }
+}
+
+/// Accepts the policies `docker run --restart` does. `on-failure` may limit the number of retries.
+fn validate_restart_policy(policy: &str) -> SealedResult<()> {
+    let valid = match policy.split_once(':') {
+        Some(("on-failure", retries)) => retries.parse::<u32>().is_ok(),
+        Some(_) => false,
+        None => matches!(policy, "no" | "on-failure" | "always" | "unless-stopped"),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(SealedError::BadRequest(format!(
+            "Invalid restart policy `{}`: expected `no`, `on-failure[:max-retries]`, `always` or \
+             `unless-stopped`",
+            policy
+        )))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -278,6 +606,213 @@ mod tests {
         assert_eq!(docker_args.env, vec!["HOME=/app"]);
     }
 
+    #[test]
+    fn test_docker_image_options_defaults() {
+        let docker_args = DockerInstanceOption::try_parse_from(vec!["instance"]).unwrap();
+        assert_eq!(docker_args.volumes, default_volumes());
+        assert_eq!(docker_args.env, default_env());
+        assert!(docker_args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_volumes() {
+        for volume in [
+            "/etc:/etc",
+            "/etc:/etc:ro",
+            "logging:/var/log:rw",
+            "./src:/app/src:ro,z",
+            "type=bind,source=/tmp,target=/tmp",
+        ]
+        .into_iter()
+        .chain(default_volumes().iter().map(String::as_str))
+        {
+            assert!(validate_volume(volume).is_ok(), "{}", volume);
+        }
+
+        for volume in [
+            "/etc",
+            ":/etc",
+            "/etc:etc",
+            "/etc:/etc:rx",
+            "/a:/b:ro:extra",
+            "type=tmpfs,tmpfs-size=100",
+            "type=tmpfs,oops,destination=/app",
+        ] {
+            match validate_volume(volume) {
+                Err(SealedError::BadRequest(message)) => assert!(message.contains(volume)),
+                result => panic!("{} was accepted: {:?}", volume, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_env() {
+        assert!(validate_env("HOME=/app").is_ok());
+        assert!(validate_env("EMPTY=").is_ok());
+        assert!(validate_env("URL=postgres://a:b@c/d?x=y").is_ok());
+
+        for env in ["HOME", "=value", "MY VAR=1"] {
+            match validate_env(env) {
+                Err(SealedError::BadRequest(message)) => assert!(message.contains(env)),
+                result => panic!("{} was accepted: {:?}", env, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_restart_policy() {
+        for policy in [
+            "no",
+            "on-failure",
+            "on-failure:5",
+            "always",
+            "unless-stopped",
+        ] {
+            assert!(validate_restart_policy(policy).is_ok(), "{}", policy);
+        }
+
+        for policy in ["sometimes", "on-failure:", "on-failure:-1", "always:3", ""] {
+            match validate_restart_policy(policy) {
+                Err(SealedError::BadRequest(message)) => {
+                    assert!(message.contains(&format!("`{}`", policy)))
+                }
+                result => panic!("{} was accepted: {:?}", policy, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_instance_validate_restart() {
+        let mut instance = DockerInstanceOption {
+            rm: false,
+            restart: Some("unless-stopped".to_string()),
+            ..Default::default()
+        };
+        assert!(instance.validate().is_ok());
+
+        instance.restart = Some("forever".to_string());
+        assert!(instance.validate().is_err());
+
+        // A container which restarts can't be removed when it exits
+        instance.rm = true;
+        instance.restart = Some("always".to_string());
+        assert!(instance.validate().is_err());
+        instance.restart = Some("no".to_string());
+        assert!(instance.validate().is_ok());
+    }
+
+    #[test]
+    fn test_instance_validate_network() {
+        let mut instance = DockerInstanceOption {
+            network: Some("grid".to_string()),
+            ..Default::default()
+        };
+        assert!(instance.validate().is_ok());
+
+        for network in ["", "  "] {
+            instance.network = Some(network.to_string());
+            assert!(matches!(
+                instance.validate(),
+                Err(SealedError::BadRequest(message)) if message.contains("network")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_instance_validate_reports_bad_entry() {
+        let instance = DockerInstanceOption {
+            volumes: vec!["/etc:/etc:ro".to_string(), "/etc".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            instance.validate(),
+            Err(SealedError::BadRequest(message)) if message.contains("`/etc`")
+        ));
+    }
+
+    #[test]
+    fn test_resolved_env_prefers_explicit_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        std::fs::write(&env_file, "HOME=/root\nLANG=C.UTF-8\n").unwrap();
+
+        let instance = DockerInstanceOption {
+            env: vec!["HOME=/app".to_string()],
+            env_file: Some(env_file.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            instance.resolved_env().unwrap(),
+            vec!["LANG=C.UTF-8", "HOME=/app"]
+        );
+    }
+
+    #[test]
+    fn test_parse_build_arg() {
+        let lookup = |key: &str| (key == "FROM_ENV").then(|| "inherited".to_string());
+
+        assert_eq!(
+            parse_build_arg("VERSION=1.2", lookup).unwrap(),
+            ("VERSION".to_string(), "1.2".to_string())
+        );
+        assert_eq!(
+            parse_build_arg("EMPTY=", lookup).unwrap(),
+            ("EMPTY".to_string(), "".to_string())
+        );
+        assert_eq!(
+            parse_build_arg("FROM_ENV", lookup).unwrap(),
+            ("FROM_ENV".to_string(), "inherited".to_string())
+        );
+
+        for arg in ["UNSET", "=value", "MY ARG=1", ""] {
+            match parse_build_arg(arg, lookup) {
+                Err(SealedError::BadRequest(message)) => {
+                    assert!(message.contains(&format!("`{}`", arg)))
+                }
+                result => panic!("{} was accepted: {:?}", arg, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let description = dir.path().join("description.txt");
+        std::fs::write(&description, "Reticulates splines.\n").unwrap();
+
+        assert_eq!(
+            parse_label("maintainer=me").unwrap(),
+            ("maintainer".to_string(), "me".to_string())
+        );
+        assert_eq!(
+            parse_label(&format!("description=@{}", description.display())).unwrap(),
+            (
+                "description".to_string(),
+                "Reticulates splines.".to_string()
+            )
+        );
+
+        let missing = format!("description=@{}", dir.path().join("missing").display());
+        for label in ["maintainer", "=me", "my label=x", missing.as_str()] {
+            match parse_label(label) {
+                Err(SealedError::BadRequest(message)) => assert!(message.contains(label)),
+                result => panic!("{} was accepted: {:?}", label, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_git_ref() {
+        let mut args = DockerSpecificArgs::default();
+        assert_eq!(args.git_ref(), GitRef::Branch("main".to_string()));
+
+        args.git_tag = Some("v1.0".to_string());
+        assert_eq!(args.git_ref(), GitRef::Tag("v1.0".to_string()));
+
+        args.commit = Some("0123abcd".to_string());
+        assert_eq!(args.git_ref(), GitRef::Commit("0123abcd".to_string()));
+    }
+
     #[test]
     fn test_docker_builder_options_parsing() {
         let opts = DockerBuilderOptions {
@@ -288,6 +823,9 @@ mod tests {
             labels: vec!["maintainer=me".to_string()],
             quiet: false,
             no_cache: true,
+            pull: false,
+            cache_from: vec![],
+            cache_to: vec![],
             platforms: vec!["linux/amd64".to_string()],
             current_dir: Some("/tmp".to_string()),
             cpu_quota: Some("60000".to_string()),
@@ -301,6 +839,7 @@ mod tests {
             memory: Some("8096000".to_string()),
             memory_swap: Some("16192000".to_string()),
             dockerfile: None,
+            target: None,
             build_args: vec![],
         };
         let serialized = serde_json::to_string(&opts).unwrap();
@@ -1,9 +1,269 @@
-use crate::{error::SealedResult, settings::Settings};
+use std::{fs::write, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+use sealed_common::settings::Settings;
+
+use crate::error::{SealedCliError, SealedCliResult};
 
 use super::DockerHandlerArgs;
 
-pub async fn run(args: DockerHandlerArgs, _config: &Settings) -> SealedResult<()> {
-    println!("Generating docker run command: {:#?}", args);
+#[derive(Parser, Debug, Clone, Default)]
+pub struct GenerateArgs {
+    /// The docker command to generate [default: the build command, then the run command]
+    #[arg(value_enum)]
+    pub command: Option<GeneratedCommand>,
+
+    /// Generate a shell script which builds the image and then runs it
+    #[arg(long, conflicts_with = "command")]
+    pub script: bool,
+
+    /// Don't fetch the repository. The tag is then the configured one rather than the commit SHA.
+    #[arg(long)]
+    pub no_clone: bool,
+
+    /// Write the command to this file instead of stdout (`-` is stdout)
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratedCommand {
+    Build,
+    Run,
+}
+
+/// The fully resolved command as it would be typed in a shell, including the docker environment
+/// variables.
+pub fn generate(
+    args: &DockerHandlerArgs,
+    command: GeneratedCommand,
+    config: &Settings,
+) -> SealedCliResult<String> {
+    let cmd = match command {
+        GeneratedCommand::Build => args.to_docker_buildx_command_string(config)?,
+        GeneratedCommand::Run => args.to_docker_run_command_string(config)?,
+    };
+    Ok(args.with_env_prefix(&cmd))
+}
+
+/// A shell script which builds the image and then runs it, stopping if the build fails.
+pub fn script(args: &DockerHandlerArgs, config: &Settings) -> SealedCliResult<String> {
+    Ok(format!(
+        "#!/bin/sh\n\
+         # Generated by `sealed-cli docker generate --script`\n\
+         set -eu\n\
+         {}\n\
+         {}\n",
+        generate(args, GeneratedCommand::Build, config)?,
+        generate(args, GeneratedCommand::Run, config)?,
+    ))
+}
+
+/// What `generate` writes: the script, the chosen command, or both commands, one per line.
+fn render(
+    args: &DockerHandlerArgs,
+    generate_args: &GenerateArgs,
+    config: &Settings,
+) -> SealedCliResult<String> {
+    if generate_args.script {
+        return script(args, config);
+    }
+
+    let commands = match generate_args.command {
+        Some(command) => vec![command],
+        None => vec![GeneratedCommand::Build, GeneratedCommand::Run],
+    };
+    let mut output = String::new();
+    for command in commands {
+        output.push_str(&generate(args, command, config)?);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Writes the commands instead of running them, so they can be committed or run by another system.
+/// With a repository, it's fetched first so the tag is the commit SHA, as for `build` and `run`,
+/// unless `--no-clone` is passed.
+pub async fn run(
+    args: &mut DockerHandlerArgs,
+    generate_args: &GenerateArgs,
+    config: &Settings,
+) -> SealedCliResult<()> {
+    // Fail before cloning anything
+    args.get_repo_name()?;
+    if args.docker.instance.docker_config.repository.is_some() && !generate_args.no_clone {
+        args.with_repo(config)?;
+    }
+
+    let output = render(args, generate_args, config)?;
+    match &generate_args.output {
+        Some(path) if path.as_os_str() != "-" => {
+            write(path, output).map_err(|e| {
+                SealedCliError::Runtime(format!("unable to write {}: {}", path.display(), e))
+            })?;
+        }
+        _ => print!("{output}"),
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    fn test_settings() -> Settings {
+        serde_yaml::from_str("ssh_key: null").unwrap()
+    }
+
+    fn test_args() -> DockerHandlerArgs {
+        let mut args = DockerHandlerArgs::default();
+        args.docker.instance.docker_config.image = Some("sealed-app".to_string());
+        args.docker.instance.docker_config.tag = Some("3f9c2ab".to_string());
+        args.docker.instance.env = vec![r#"GREETING=it's "quoted" $HOME"#.to_string()];
+        args.docker.builder.dockerfile = Some("ci/Dockerfile".to_string());
+        args.docker.builder.docker_host = Some("ssh://deploy@build host".to_string());
+        args
+    }
+
+    /// Splits a command into words the way `sh` does.
+    fn shell_words(command: &str) -> Vec<String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("set -- {command}; printf '%s\\0' \"$@\""))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{command}");
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_generated_build_round_trips() {
+        let args = test_args();
+        let command = generate(&args, GeneratedCommand::Build, &test_settings()).unwrap();
+
+        let words = shell_words(&command);
+        assert_eq!(words[0], "DOCKER_HOST=ssh://deploy@build host");
+        assert_eq!(
+            words[1..],
+            args.to_docker_buildx_args(&test_settings()).unwrap()
+        );
+        assert!(words.contains(&"sealed-app:3f9c2ab".to_string()));
+    }
+
+    #[test]
+    fn test_generated_run_round_trips() {
+        let command = generate(&test_args(), GeneratedCommand::Run, &test_settings()).unwrap();
+
+        let words = shell_words(&command);
+        assert_eq!(words[0], "DOCKER_HOST=ssh://deploy@build host");
+        assert_eq!(words[1..3], ["docker", "run"]);
+        assert!(words
+            .windows(2)
+            .any(|pair| pair == ["-e", r#"GREETING=it's "quoted" $HOME"#]));
+        assert_eq!(words.last().unwrap(), "sealed-app:3f9c2ab");
+    }
+
+    #[test]
+    fn test_generated_script() {
+        let args = test_args();
+        let config = test_settings();
+        let script = script(&args, &config).unwrap();
+
+        let lines = script.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert_eq!(lines[2], "set -eu");
+        assert_eq!(
+            lines[3],
+            generate(&args, GeneratedCommand::Build, &config).unwrap()
+        );
+        assert_eq!(
+            lines[4],
+            generate(&args, GeneratedCommand::Run, &config).unwrap()
+        );
+
+        // The script is valid shell.
+        let status = Command::new("sh")
+            .args(["-n", "-c", &script])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_render_both_commands_by_default() {
+        let args = test_args();
+        let config = test_settings();
+        let output = render(&args, &GenerateArgs::default(), &config).unwrap();
+
+        assert_eq!(
+            output,
+            format!(
+                "{}\n{}\n",
+                generate(&args, GeneratedCommand::Build, &config).unwrap(),
+                generate(&args, GeneratedCommand::Run, &config).unwrap(),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_without_clone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.sh");
+        let generate_args = GenerateArgs {
+            script: true,
+            no_clone: true,
+            output: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let mut args = test_args();
+        args.docker.instance.docker_config.image = None;
+        args.docker.instance.docker_config.repository =
+            Some("https://github.com/flynn/grid.git".to_string());
+        run(&mut args, &generate_args, &test_settings())
+            .await
+            .unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written, script(&args, &test_settings()).unwrap());
+        assert!(written.contains(" 'grid:3f9c2ab'"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_without_repository_or_image() {
+        let error = run(
+            &mut DockerHandlerArgs::default(),
+            &GenerateArgs::default(),
+            &test_settings(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("No repository or image specified"));
+    }
+
+    #[test]
+    fn test_generate_args() {
+        let parse = |args: &[&str]| {
+            GenerateArgs::try_parse_from(std::iter::once("generate").chain(args.iter().copied()))
+        };
+
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.command, None);
+        assert!(!args.script && !args.no_clone);
+        let args = parse(&["run", "-o", "run.sh", "--no-clone"]).unwrap();
+        assert_eq!(args.command, Some(GeneratedCommand::Run));
+        assert_eq!(args.output, Some(PathBuf::from("run.sh")));
+        assert!(args.no_clone);
+        assert!(parse(&["build", "--script"]).is_err());
+    }
+}
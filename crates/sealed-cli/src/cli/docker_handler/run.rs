@@ -1,3 +1,5 @@
+use std::{io::Write, process::Output};
+
 use sealed_common::{info, settings::Settings};
 use tokio::process::Command;
 
@@ -9,8 +11,52 @@ pub async fn run(args: &mut DockerHandlerArgs, config: &Settings) -> SealedCliRe
     let repo = args.with_repo(config)?;
     info!("Repository cloned: {}", repo.path().display());
 
+    let output = match run_container(args, config, &mut std::io::stdout()).await? {
+        Some(output) => output,
+        None => return Ok(()),
+    };
+
+    if let Some(id) = detached_container_id(args, &output) {
+        println!("{}", id);
+        return Ok(());
+    }
+
+    println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(SealedCliError::Runtime(
+            "Docker build command failed".to_string(),
+        ))
+    }
+}
+
+/// The ID of the container a detached run started, which is all `docker run -d` prints.
+fn detached_container_id(args: &DockerHandlerArgs, output: &Output) -> Option<String> {
+    if !args.docker.instance.detach || !output.status.success() {
+        return None;
+    }
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!id.is_empty()).then_some(id)
+}
+
+/// Runs the `docker run` command, or only writes it to `out` on a dry run, in which case no
+/// process is spawned and `None` is returned.
+async fn run_container(
+    args: &DockerHandlerArgs,
+    config: &Settings,
+    out: &mut impl Write,
+) -> SealedCliResult<Option<Output>> {
     let cmd = args.to_docker_run_command_string(config)?;
 
+    if args.dry_run {
+        writeln!(out, "cmd: {}", args.with_env_prefix(&cmd))
+            .map_err(|e| SealedCliError::Runtime(e.to_string()))?;
+        return Ok(None);
+    }
+
     let mut command = Command::new("sh");
     command.arg("-c").arg(cmd);
 
@@ -29,14 +75,54 @@ pub async fn run(args: &mut DockerHandlerArgs, config: &Settings) -> SealedCliRe
         .await
         .map_err(|e| SealedCliError::Runtime(e.to_string()))?;
 
-    println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+    Ok(Some(output))
+}
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(SealedCliError::Runtime(
-            "Docker build command failed".to_string(),
-        ))
+#[cfg(test)]
+mod tests {
+    use std::process::Command as StdCommand;
+
+    use super::*;
+
+    fn output(script: &str) -> Output {
+        StdCommand::new("sh").args(["-c", script]).output().unwrap()
+    }
+
+    #[test]
+    fn test_detached_container_id() {
+        let mut args = DockerHandlerArgs::default();
+        let started = output("echo 4f1c2e9b7a3d");
+        assert_eq!(detached_container_id(&args, &started), None);
+
+        args.docker.instance.detach = true;
+        assert_eq!(
+            detached_container_id(&args, &started),
+            Some("4f1c2e9b7a3d".to_string())
+        );
+        assert_eq!(detached_container_id(&args, &output("exit 125")), None);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_prints_without_spawning() {
+        let mut args = DockerHandlerArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+        args.docker.instance.docker_config.image = Some("sealed-app".to_string());
+        args.docker.instance.volumes = vec!["/data:/data:ro".to_string()];
+        args.docker.instance.env = vec!["GREETING=hello world".to_string()];
+        args.docker.builder.docker_host = Some("tcp://docker:2375".to_string());
+        let config: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+
+        let mut out = vec![];
+        let output = run_container(&args, &config, &mut out).await.unwrap();
+        assert!(output.is_none());
+
+        let printed = String::from_utf8(out).unwrap();
+        assert_eq!(
+            printed,
+            "cmd: DOCKER_HOST='tcp://docker:2375' docker run --rm -v '/data:/data:ro' \
+             -e 'GREETING=hello world' 'sealed-app:latest'\n"
+        );
     }
 }
@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use sealed_common::settings::Settings;
+use sealed_operator::app_config::AppConfig;
+
+use crate::error::{SealedCliError, SealedCliResult};
+
+#[derive(Parser, Debug, Clone)]
+pub struct RenderArgs {
+    /// Path to the app config to render
+    pub path: PathBuf,
+}
+
+// Print the manifests an app config produces, without touching a cluster
+pub async fn run(args: RenderArgs, _config: &Settings) -> SealedCliResult<()> {
+    let contents = std::fs::read_to_string(&args.path).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to read {}: {}", args.path.display(), e))
+    })?;
+    let app: AppConfig = serde_yaml::from_str(&contents).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", args.path.display(), e))
+    })?;
+
+    print!("{}", app.render()?);
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::available_parallelism,
+};
+
+use clap::Parser;
+use log::warn;
+use sealed_common::settings::Settings;
+use sealed_database::taskfile::TaskFile;
+use sealed_services::{
+    kept_container_service::kept_containers_path,
+    plan_service::TaskStatus,
+    remote_cache_service::{RemoteCache, S3Cache},
+    run_service::run_tasks,
+    taskfile_service::parse_file,
+};
+
+use crate::{
+    cli::task::{no_cache, read_env_file},
+    error::{SealedCliError, SealedCliResult},
+};
+
+#[derive(Parser, Debug, Clone)]
+pub struct RunArgs {
+    /// Task to run, along with its dependencies (defaults to the default tasks)
+    pub task: Option<String>,
+
+    /// Path to the taskfile
+    #[arg(short, long, default_value = "taskfile.yml")]
+    pub file: PathBuf,
+
+    /// Number of tasks whose inputs are archived at once [default: number of CPUs]. The tasks
+    /// themselves still run one at a time
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Dotenv file providing task environment variables which aren't set in the environment
+    #[arg(long)]
+    pub env_from_file: Option<PathBuf>,
+
+    /// Run a task even if its image is cached (repeatable), or every task if no task is given
+    #[arg(
+        long,
+        visible_alias = "force",
+        value_name = "TASK",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    pub no_cache: Vec<String>,
+
+    /// Repository the task images are tagged in
+    #[arg(long, default_value = "sealed")]
+    pub docker_repo: String,
+
+    /// Path to the Docker CLI
+    #[arg(long, default_value = "docker")]
+    pub docker_cli: String,
+
+    /// Hash every input file, rather than reusing the hashes of files unchanged since the last run
+    #[arg(long)]
+    pub rehash_inputs: bool,
+
+    /// Keep the container of a failing task so it can be inspected. `clean` removes it later.
+    #[arg(long)]
+    pub keep_container: bool,
+
+    /// Extra arguments for `docker container create`, after `--`. They disable the cache.
+    #[arg(last = true, value_name = "EXTRA_DOCKER_ARGUMENTS")]
+    pub extra_docker_arguments: Vec<String>,
+}
+
+// The task named on the command line, or the default tasks if there isn't one
+fn roots<'a>(
+    task_file: &'a TaskFile,
+    task: Option<&'a str>,
+    path: &Path,
+) -> SealedCliResult<Vec<&'a str>> {
+    match task {
+        Some(task) if task_file.tasks.contains_key(task) => Ok(vec![task]),
+        Some(task) => Err(SealedCliError::ParseConfig(format!(
+            "{} has no task {}",
+            path.display(),
+            task
+        ))),
+        None => task_file
+            .default
+            .as_ref()
+            .map(|default| default.names().iter().map(String::as_str).collect())
+            .ok_or_else(|| {
+                SealedCliError::ParseConfig(format!(
+                    "{} has no default task, so name the task to run",
+                    path.display()
+                ))
+            }),
+    }
+}
+
+pub async fn run(args: RunArgs, config: &Settings) -> SealedCliResult<()> {
+    let task_file = parse_file(&args.file).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", args.file.display(), e))
+    })?;
+    // Fail before anything runs if the task doesn't exist.
+    roots(&task_file, args.task.as_deref(), &args.file)?;
+    let file_environment = match &args.env_from_file {
+        Some(path) => read_env_file(path)?,
+        None => HashMap::new(),
+    };
+    // The hashes of unchanged input files are reused from the last run.
+    let hash_manifests = (!args.rehash_inputs).then(|| config.working_directory.clone());
+    let kept_containers = kept_containers_path(&config.working_directory);
+    let no_cache = no_cache(&args.no_cache);
+    let settings = config.clone();
+    let jobs = args.jobs.map_or_else(
+        || available_parallelism().map_or(1, usize::from),
+        usize::from,
+    );
+
+    // Ctrl-C stops the running task, and the containers are cleaned up before exiting.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let signal = {
+        let interrupted = interrupted.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                interrupted.store(true, Ordering::SeqCst);
+            }
+        })
+    };
+
+    // Input, output, and mount paths are relative to the taskfile.
+    let source_dir = args
+        .file
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+
+    let result = tokio::task::spawn_blocking(move || {
+        let roots = roots(&task_file, args.task.as_deref(), &args.file)?;
+        // The remote cache is only an optimization, so a misconfigured one doesn't stop the run.
+        let remote_cache = settings.remote_cache.as_ref().and_then(|remote_cache| {
+            S3Cache::new(remote_cache)
+                .map_err(|error| warn!("Unable to use the remote cache: {}", error))
+                .ok()
+        });
+        Ok::<_, SealedCliError>(run_tasks(
+            &args.docker_cli,
+            &args.docker_repo,
+            &task_file,
+            &roots,
+            &source_dir,
+            hash_manifests.as_deref(),
+            &file_environment,
+            &args.extra_docker_arguments,
+            &no_cache,
+            &settings,
+            remote_cache.as_ref().map(|cache| cache as &dyn RemoteCache),
+            jobs,
+            args.keep_container,
+            &kept_containers,
+            None,
+            &interrupted,
+        )?)
+    })
+    .await
+    .map_err(|e| SealedCliError::Runtime(e.to_string()))?;
+    signal.abort();
+
+    let plan = result?;
+    let cached = plan
+        .iter()
+        .filter(|task| task.status == TaskStatus::Cached)
+        .count();
+    println!(
+        "Ran {} task(s), {} from the cache.",
+        plan.len() - cached,
+        cached
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sealed_database::taskfile::parse;
+
+    use super::*;
+
+    const TASK_FILE: &str = r"
+image: encom:os-12
+tasks:
+  install: {}
+  build:
+    dependencies:
+      - install
+";
+
+    #[test]
+    fn test_roots_default_task() {
+        let path = Path::new("taskfile.yml");
+        let task_file = parse(&format!("default: build\n{TASK_FILE}")).unwrap();
+        assert_eq!(roots(&task_file, None, path).unwrap(), vec!["build"]);
+        assert_eq!(
+            roots(&task_file, Some("install"), path).unwrap(),
+            vec!["install"]
+        );
+        assert!(roots(&task_file, Some("deploy"), path).is_err());
+
+        // Without a default, a task has to be named.
+        let task_file = parse(TASK_FILE).unwrap();
+        assert!(roots(&task_file, None, path).is_err());
+    }
+
+    #[test]
+    fn test_run_args() {
+        let args = RunArgs::try_parse_from([
+            "run",
+            "build",
+            "--file",
+            "ci/taskfile.yml",
+            "--jobs",
+            "2",
+            "--keep-container",
+            "--no-cache=install",
+            "--",
+            "--network",
+            "none",
+        ])
+        .unwrap();
+        assert_eq!(args.task.as_deref(), Some("build"));
+        assert_eq!(args.file, PathBuf::from("ci/taskfile.yml"));
+        assert_eq!(args.jobs, Some(2));
+        assert!(args.keep_container);
+        assert_eq!(args.no_cache, vec!["install"]);
+        assert_eq!(args.extra_docker_arguments, vec!["--network", "none"]);
+
+        assert!(RunArgs::try_parse_from(["run", "--jobs", "0"]).is_err());
+        assert_eq!(RunArgs::try_parse_from(["run"]).unwrap().task, None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 use sealed_common::settings::Settings;
 use sealed_operator::installer;
@@ -48,6 +50,19 @@ pub struct InstallArgs {
     /// Install pgAdmin?
     #[arg(long, default_value_t = false)]
     pgadmin: bool,
+
+    /// Print what would be created or updated without changing the cluster
+    #[arg(long, default_value_t = false)]
+    pub plan: bool,
+
+    /// Seconds to wait for each operator to become available [default: install_timeout_secs
+    /// setting]
+    #[arg(long)]
+    pub timeout: Option<u64>,
+
+    /// Directory of extra manifests to apply after the operators
+    #[arg(long)]
+    pub manifests: Option<PathBuf>,
 }
 
 impl From<InstallArgs> for installer::InstallationArgs {
@@ -55,6 +70,9 @@ impl From<InstallArgs> for installer::InstallationArgs {
         installer::InstallationArgs {
             namespace: args.namespace,
             operator_namespace: args.operator_namespace,
+            plan: args.plan,
+            timeout_secs: args.timeout,
+            manifests: args.manifests,
         }
     }
 }
@@ -23,19 +23,41 @@ pub struct ServerStartArgs {
     /// The port to run the server on
     #[arg(long, default_value_t = 9999)]
     port: u16,
+    /// Don't run database migrations on startup
+    #[arg(long, default_value_t = false)]
+    skip_migrations: bool,
+    /// Seconds to wait for in-flight requests to finish when shutting down
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
 }
 
 impl From<ServerStartArgs> for ServerArgs {
     fn from(args: ServerStartArgs) -> Self {
-        ServerArgs { port: args.port }
+        ServerArgs {
+            port: args.port,
+            skip_migrations: args.skip_migrations,
+            shutdown_timeout_secs: args.shutdown_timeout,
+            cors: Default::default(),
+            webhook: Default::default(),
+            rate_limit: Default::default(),
+            database: Default::default(),
+        }
     }
 }
 
-pub async fn run(args: ServerInitArgs, _config: &Settings) -> SealedCliResult<()> {
+pub async fn run(args: ServerInitArgs, config: &Settings) -> SealedCliResult<()> {
     println!("Starting server infrastructure...");
 
     match args.subcommand {
-        Subcommand::Start(args) => start_server(args.into()).await?,
+        Subcommand::Start(args) => {
+            let mut server_args: ServerArgs = args.into();
+            // CORS, webhooks, rate limits and the database pool only come from the settings file
+            server_args.cors = config.server.cors.clone();
+            server_args.webhook = config.server.webhook.clone();
+            server_args.rate_limit = config.server.rate_limit.clone();
+            server_args.database = config.database.clone();
+            start_server(server_args).await?
+        }
     }
 
     Ok(())
@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use clap::Parser;
+use sealed_common::settings::Settings;
+use sealed_services::{
+    shell_service::{spawn_task_shell, task_shell},
+    taskfile_service::parse_file,
+};
+
+use crate::{
+    cli::task::read_env_file,
+    error::{SealedCliError, SealedCliResult},
+};
+
+#[derive(Parser, Debug, Clone)]
+pub struct ShellArgs {
+    /// Task whose container to open a shell in
+    pub task: String,
+
+    /// Path to the taskfile
+    #[arg(short, long, default_value = "taskfile.yml")]
+    pub file: PathBuf,
+
+    /// Dotenv file providing task environment variables which aren't set in the environment
+    #[arg(long)]
+    pub env_from_file: Option<PathBuf>,
+
+    /// Repository the task images are tagged in
+    #[arg(long, default_value = "sealed")]
+    pub docker_repo: String,
+
+    /// Path to the Docker CLI
+    #[arg(long, default_value = "docker")]
+    pub docker_cli: String,
+}
+
+pub async fn run(args: ShellArgs, config: &Settings) -> SealedCliResult<()> {
+    let task_file = parse_file(&args.file).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", args.file.display(), e))
+    })?;
+    if !task_file.tasks.contains_key(&args.task) {
+        return Err(SealedCliError::ParseConfig(format!(
+            "{} has no task {}",
+            args.file.display(),
+            args.task
+        )));
+    }
+    let file_environment = match &args.env_from_file {
+        Some(path) => read_env_file(path)?,
+        None => HashMap::new(),
+    };
+
+    // Ctrl-C goes to the shell in the container, but we record it so a failing exit status is
+    // reported as an interruption rather than an error.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let signal = {
+        let interrupted = interrupted.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                interrupted.store(true, Ordering::SeqCst);
+            }
+        })
+    };
+
+    let hash_algorithm = config.hash_algorithm;
+
+    // Mount paths are relative to the taskfile.
+    let source_dir = args
+        .file
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+
+    let result = tokio::task::spawn_blocking(move || {
+        let shell = task_shell(
+            &args.docker_cli,
+            &args.docker_repo,
+            &task_file,
+            &args.task,
+            &file_environment,
+            hash_algorithm,
+            &interrupted,
+        )?;
+        spawn_task_shell(&args.docker_cli, &source_dir, &shell, &interrupted)
+    })
+    .await
+    .map_err(|e| SealedCliError::Runtime(e.to_string()))?;
+    signal.abort();
+
+    Ok(result?)
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use clap::Parser;
+use sealed_common::{env_file::parse_env_file, settings::Settings};
+use sealed_database::taskfile::TaskFile;
+use sealed_services::{
+    docker_service::ExistingImages,
+    plan_service::{explain, format_plan, NoCache},
+    prune_service::prune,
+    taskfile_service::parse_file,
+};
+use serde::Serialize;
+
+use crate::error::{SealedCliError, SealedCliResult};
+
+#[derive(Parser, Debug, Clone)]
+#[command(arg_required_else_help = true)]
+pub struct TaskArgs {
+    #[command(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum Subcommand {
+    #[command(about = "List the tasks in a taskfile")]
+    List(ListArgs),
+
+    #[command(about = "Show which tasks would run and which would come from the cache")]
+    Explain(ExplainArgs),
+
+    #[command(about = "Delete cached task images which are no longer needed")]
+    Prune(PruneArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ListArgs {
+    /// Path to the taskfile
+    #[arg(short, long, default_value = "taskfile.yml")]
+    pub file: PathBuf,
+
+    /// Print the tasks as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ExplainArgs {
+    /// Tasks to explain, along with their dependencies (defaults to the default tasks)
+    pub tasks: Vec<String>,
+
+    /// Path to the taskfile
+    #[arg(short, long, default_value = "taskfile.yml")]
+    pub file: PathBuf,
+
+    /// Dotenv file providing task environment variables which aren't set in the environment
+    #[arg(long)]
+    pub env_from_file: Option<PathBuf>,
+
+    /// Run a task even if its image is cached (repeatable), or every task if no task is given
+    #[arg(
+        long,
+        visible_alias = "force",
+        value_name = "TASK",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    pub no_cache: Vec<String>,
+
+    /// Repository the task images are tagged in
+    #[arg(long, default_value = "sealed")]
+    pub docker_repo: String,
+
+    /// Path to the Docker CLI
+    #[arg(long, default_value = "docker")]
+    pub docker_cli: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct PruneArgs {
+    /// Number of task images to keep per repository, most recently used first
+    #[arg(long, default_value_t = 5)]
+    pub keep: usize,
+
+    /// Print the images which would be deleted without deleting them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Path to the Docker CLI
+    #[arg(long, default_value = "docker")]
+    pub docker_cli: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+struct TaskSummary {
+    name: String,
+    description: Option<String>,
+    dependencies: Vec<String>,
+    default: bool,
+}
+
+// The tasks in alphabetical order, except that the default tasks come first
+fn summarize(task_file: &TaskFile) -> Vec<TaskSummary> {
+    let mut tasks = task_file
+        .tasks
+        .iter()
+        .map(|(name, task)| TaskSummary {
+            name: name.clone(),
+            description: task.description.clone(),
+            dependencies: task.dependencies.clone(),
+            default: task_file
+                .default
+                .as_ref()
+                .is_some_and(|default| default.names().contains(name)),
+        })
+        .collect::<Vec<_>>();
+    tasks.sort_by(|a, b| b.default.cmp(&a.default).then_with(|| a.name.cmp(&b.name)));
+    tasks
+}
+
+fn format_tasks(tasks: &[TaskSummary]) -> String {
+    let mut output = String::new();
+    for task in tasks {
+        let _ = write!(output, "{}", task.name);
+        if task.default {
+            let _ = write!(output, " (default)");
+        }
+        if let Some(description) = &task.description {
+            let _ = write!(output, ": {}", description);
+        }
+        output.push('\n');
+        if !task.dependencies.is_empty() {
+            let _ = writeln!(output, "  depends on: {}", task.dependencies.join(", "));
+        }
+    }
+    output
+}
+
+async fn list(args: ListArgs, _config: &Settings) -> SealedCliResult<()> {
+    let task_file = parse_file(&args.file).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", args.file.display(), e))
+    })?;
+
+    let tasks = summarize(&task_file);
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&tasks)
+                .map_err(|e| SealedCliError::Runtime(e.to_string()))?
+        );
+    } else {
+        print!("{}", format_tasks(&tasks));
+    }
+
+    Ok(())
+}
+
+// Read the variables of a dotenv file
+pub(crate) fn read_env_file(path: &Path) -> SealedCliResult<HashMap<String, String>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to read {}: {}", path.display(), e))
+    })?;
+    let vars = parse_env_file(&contents).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", path.display(), e))
+    })?;
+    Ok(vars.into_iter().collect())
+}
+
+// Interpret the `--no-cache` flags. A flag without a task applies to every task.
+pub(crate) fn no_cache(flags: &[String]) -> NoCache {
+    if flags.is_empty() {
+        NoCache::None
+    } else if flags.iter().any(String::is_empty) {
+        NoCache::All
+    } else {
+        NoCache::Tasks(flags.iter().cloned().collect())
+    }
+}
+
+async fn explain_tasks(args: ExplainArgs, config: &Settings) -> SealedCliResult<()> {
+    let task_file = parse_file(&args.file).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to parse {}: {}", args.file.display(), e))
+    })?;
+    let file_environment = match &args.env_from_file {
+        Some(path) => read_env_file(path)?,
+        None => HashMap::new(),
+    };
+
+    let roots = args.tasks.iter().map(String::as_str).collect::<Vec<_>>();
+    if let Some(unknown) = roots
+        .iter()
+        .copied()
+        .chain(args.no_cache.iter().map(String::as_str))
+        .find(|task| !task.is_empty() && !task_file.tasks.contains_key(*task))
+    {
+        return Err(SealedCliError::ParseConfig(format!(
+            "{} has no task {}",
+            args.file.display(),
+            unknown
+        )));
+    }
+
+    let plan = explain(
+        &args.docker_cli,
+        &args.docker_repo,
+        &task_file,
+        &roots,
+        &HashMap::new(),
+        &file_environment,
+        &no_cache(&args.no_cache),
+        &ExistingImages::default(),
+        config.hash_algorithm,
+        &Arc::new(AtomicBool::new(false)),
+    )?;
+    print!("{}", format_plan(&plan));
+
+    Ok(())
+}
+
+async fn prune_images(args: PruneArgs, _config: &Settings) -> SealedCliResult<()> {
+    let pruned = prune(
+        &args.docker_cli,
+        args.keep,
+        args.dry_run,
+        &Arc::new(AtomicBool::new(false)),
+    )?;
+
+    if args.dry_run {
+        for image in &pruned {
+            println!("Would delete {image}");
+        }
+    } else {
+        println!("Deleted {} task image(s).", pruned.len());
+    }
+
+    Ok(())
+}
+
+pub async fn run(args: TaskArgs, config: &Settings) -> SealedCliResult<()> {
+    match args.subcommand {
+        Subcommand::List(args) => list(args, config).await,
+        Subcommand::Explain(args) => explain_tasks(args, config).await,
+        Subcommand::Prune(args) => prune_images(args, config).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sealed_database::taskfile::parse;
+
+    use super::*;
+
+    // The comprehensive TaskFile shared with the `sealed-database` tests.
+    const TASK_FILE: &str =
+        include_str!("../../../sealed-database/fixtures/comprehensive_taskfile.yml");
+
+    #[test]
+    fn test_default_task_first() {
+        let tasks = summarize(&parse(TASK_FILE).unwrap());
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|task| (task.name.as_str(), task.default))
+                .collect::<Vec<_>>(),
+            vec![("bar", true), ("foo", false)]
+        );
+
+        // The default task comes first even when it sorts last.
+        let tasks = summarize(&parse(&TASK_FILE.replace("default: bar", "default: foo")).unwrap());
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|task| (task.name.as_str(), task.default))
+                .collect::<Vec<_>>(),
+            vec![("foo", true), ("bar", false)]
+        );
+    }
+
+    #[test]
+    fn test_format_tasks() {
+        let output = format_tasks(&summarize(&parse(TASK_FILE).unwrap()));
+        assert_eq!(
+            output,
+            "bar (default): Reticulate splines.\n  depends on: foo\nfoo\n"
+        );
+    }
+
+    #[test]
+    fn test_read_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        std::fs::write(
+            &path,
+            "# CI secrets\nTOKEN=abc123\nGREETING=\"hello world\"\n",
+        )
+        .unwrap();
+
+        let environment = read_env_file(&path).unwrap();
+        assert_eq!(environment["TOKEN"], "abc123");
+        assert_eq!(environment["GREETING"], "hello world");
+        assert!(read_env_file(&dir.path().join("missing.env")).is_err());
+    }
+
+    #[test]
+    fn test_no_cache_flags() {
+        assert_eq!(no_cache(&[]), NoCache::None);
+        assert_eq!(no_cache(&["".to_owned()]), NoCache::All);
+        assert_eq!(no_cache(&["foo".to_owned(), "".to_owned()]), NoCache::All);
+        assert_eq!(
+            no_cache(&["foo".to_owned(), "bar".to_owned()]),
+            NoCache::Tasks(["foo".to_owned(), "bar".to_owned()].into_iter().collect()),
+        );
+    }
+
+    #[test]
+    fn test_no_cache_parsing() {
+        let parse_args = |args: &[&str]| {
+            ExplainArgs::try_parse_from(std::iter::once("explain").chain(args.iter().copied()))
+                .unwrap()
+                .no_cache
+        };
+
+        assert!(parse_args(&[]).is_empty());
+        assert_eq!(parse_args(&["--no-cache"]), vec![""]);
+        assert_eq!(parse_args(&["--force", "build"]), vec![""]);
+        assert_eq!(
+            parse_args(&["--no-cache=build", "--no-cache=test"]),
+            vec!["build", "test"]
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_value(summarize(&parse(TASK_FILE).unwrap())).unwrap();
+        assert_eq!(json[0]["name"], "bar");
+        assert_eq!(json[0]["default"], true);
+        assert_eq!(json[0]["dependencies"][0], "foo");
+        assert_eq!(json[2]["description"], serde_json::Value::Null);
+    }
+}
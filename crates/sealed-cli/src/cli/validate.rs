@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use sealed_common::settings::Settings;
+use sealed_operator::app_config::AppConfig;
+use sealed_services::taskfile_service::check_file;
+
+use crate::error::{SealedCliError, SealedCliResult};
+
+#[derive(Parser, Debug, Clone)]
+pub struct ValidateArgs {
+    /// Path to the taskfile or app config to validate
+    pub path: PathBuf,
+}
+
+// App configs always have a `name`, which taskfiles don't allow
+fn is_app_config(contents: &str) -> bool {
+    serde_yaml::from_str::<serde_yaml::Value>(contents)
+        .ok()
+        .and_then(|value| value.get("name").cloned())
+        .is_some()
+}
+
+// Every problem with the taskfile or app config at `path`
+fn violations(path: &Path) -> SealedCliResult<Vec<String>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        SealedCliError::ParseConfig(format!("unable to read {}: {}", path.display(), e))
+    })?;
+
+    if is_app_config(&contents) {
+        let app: AppConfig = serde_yaml::from_str(&contents).map_err(|e| {
+            SealedCliError::ParseConfig(format!("unable to parse {}: {}", path.display(), e))
+        })?;
+        Ok(app.violations())
+    } else {
+        let violations = check_file(path).map_err(|e| {
+            SealedCliError::ParseConfig(format!("unable to parse {}: {}", path.display(), e))
+        })?;
+        Ok(violations
+            .iter()
+            .map(|violation| violation.message())
+            .collect())
+    }
+}
+
+// Check a taskfile or app config offline, reporting all of its problems at once
+pub async fn run(args: ValidateArgs, _config: &Settings) -> SealedCliResult<()> {
+    let violations = violations(&args.path)?;
+    if violations.is_empty() {
+        println!("{} is valid.", args.path.display());
+        return Ok(());
+    }
+
+    for violation in &violations {
+        println!("{violation}");
+    }
+    Err(SealedCliError::ParseConfig(format!(
+        "{} has {} problem(s)",
+        args.path.display(),
+        violations.len()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(contents: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_taskfile_reports_all_violations() {
+        let (_dir, path) = write(
+            r"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies: [bar]
+  bar:
+    dependencies: [foo]
+  baz:
+    environment:
+      NOT=VALID: null
+",
+        );
+
+        let violations = violations(&path).unwrap();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.contains("cyclic")));
+        assert!(violations.iter().any(|v| v.contains("NOT=VALID")));
+    }
+
+    #[test]
+    fn test_app_config() {
+        let (_dir, path) = write(
+            r"
+name: web
+image: nginx:latest
+dependencies: []
+ports: [80]
+",
+        );
+        assert!(violations(&path).unwrap().is_empty());
+
+        let (_dir, path) = write(
+            r"
+name: web
+image: nginx:latest
+dependencies: []
+ingress:
+  host: web.example.com
+",
+        );
+        assert_eq!(violations(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unparseable_file() {
+        let (_dir, path) = write("image: [unclosed");
+        assert!(violations(&path).is_err());
+    }
+}
@@ -3,13 +3,21 @@ use sealed_common::{
     settings::{get_config, Settings, CONFIG_INSTANCE},
 };
 
+use sealed_ui::disable_spinner;
+
 use crate::Cli;
 
 pub fn init_config(cli: &Cli) -> SealedResult<&'static Settings> {
-    let settings = match &cli.settings {
+    let mut settings = match &cli.settings {
         None => Settings::from_root(cli.root.clone())?,
         Some(settings) => Settings::from_root(Some(settings.clone()))?,
     };
+    if cli.no_image_cache {
+        settings.image_cache.enabled = false;
+    }
+    if cli.no_spinner {
+        disable_spinner();
+    }
     CONFIG_INSTANCE
         .set(settings)
         .expect("Config already initialized");
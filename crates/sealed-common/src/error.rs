@@ -25,9 +25,11 @@ pub enum SealedError {
     #[error("Interrupted")]
     Interrupted,
     #[error("Failed to run command: {0} {1:?}")]
-    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error>>),
+    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error + Send + Sync>>),
     #[error("System error: {0} {1:?}")]
-    System(String, Option<Box<dyn std::error::Error>>),
+    System(String, Option<Box<dyn std::error::Error + Send + Sync>>),
+    #[error("{message} The command {}.", describe_exit_code(*.code))]
+    TaskExited { message: String, code: i32 },
     /// Any error originating from the `kube-rs` crate
     #[error("Kubernetes reported error: {source}")]
     Kube {
@@ -62,6 +64,16 @@ pub enum SealedError {
     NoData,
 }
 
+// Describe the exit code of a command. Docker reports a container which was killed by signal N as
+// exit code 128 + N, and 137 (SIGKILL) usually means the kernel killed it for running out of memory.
+pub fn describe_exit_code(code: i32) -> String {
+    match code {
+        137 => format!("was killed (exit code {code}), possibly for running out of memory"),
+        129..=159 => format!("was terminated by signal {} (exit code {code})", code - 128),
+        _ => format!("exited with code {code}"),
+    }
+}
+
 // Assuming SealedError is defined somewhere in your project, add this implementation:
 impl From<Box<dyn std::error::Error>> for SealedError {
     fn from(err: Box<dyn std::error::Error>) -> Self {
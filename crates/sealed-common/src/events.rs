@@ -0,0 +1,155 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// How many events a subscriber may fall behind before it starts missing them. Publishing never
+// waits on subscribers, so a slow WebSocket client can't hold up a build.
+const EVENTS_CAPACITY: usize = 256;
+
+// Where a task is in its lifecycle.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Succeeded,
+    // Succeeded without doing any work, because the result was already in the cache
+    Cached,
+    // `exit_code` is `None` when the task was killed or never started
+    Failed { exit_code: Option<i32> },
+}
+
+// A lifecycle change of one of an app's tasks, e.g., its build.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaskEvent {
+    pub app_id: i64,
+    pub task: String,
+    #[serde(flatten)]
+    pub status: TaskStatus,
+}
+
+// Broadcasts task events from the runners to every subscriber, e.g., WebSocket clients.
+#[derive(Clone, Debug)]
+pub struct TaskEvents {
+    sender: broadcast::Sender<TaskEvent>,
+}
+
+impl Default for TaskEvents {
+    fn default() -> Self {
+        Self::new(EVENTS_CAPACITY)
+    }
+}
+
+impl TaskEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    // Events are dropped when nobody is subscribed.
+    pub fn publish(&self, event: TaskEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.sender.subscribe()
+    }
+
+    // A publisher for the lifecycle of `task` of the app `app_id`.
+    pub fn publisher(&self, app_id: i64, task: &str) -> TaskPublisher {
+        TaskPublisher {
+            events: self.clone(),
+            app_id,
+            task: task.to_string(),
+        }
+    }
+
+    // A publisher for the lifecycles of every task of the app `app_id`, e.g., for a task runner.
+    pub fn app(&self, app_id: i64) -> AppPublisher {
+        AppPublisher {
+            events: self.clone(),
+            app_id,
+        }
+    }
+}
+
+// Publishes the status changes of any of an app's tasks.
+#[derive(Clone, Debug)]
+pub struct AppPublisher {
+    events: TaskEvents,
+    app_id: i64,
+}
+
+impl AppPublisher {
+    pub fn publish(&self, task: &str, status: TaskStatus) {
+        self.events.publish(TaskEvent {
+            app_id: self.app_id,
+            task: task.to_string(),
+            status,
+        });
+    }
+}
+
+// Publishes the status changes of a single task.
+#[derive(Clone, Debug)]
+pub struct TaskPublisher {
+    events: TaskEvents,
+    app_id: i64,
+    task: String,
+}
+
+impl TaskPublisher {
+    pub fn publish(&self, status: TaskStatus) {
+        self.events.publish(TaskEvent {
+            app_id: self.app_id,
+            task: self.task.clone(),
+            status,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let event = |status| TaskEvent {
+            app_id: 7,
+            task: "build".to_string(),
+            status,
+        };
+
+        assert_eq!(
+            serde_json::to_value(event(TaskStatus::Succeeded)).unwrap(),
+            json!({ "app_id": 7, "task": "build", "status": "succeeded" })
+        );
+        assert_eq!(
+            serde_json::to_value(event(TaskStatus::Cached)).unwrap(),
+            json!({ "app_id": 7, "task": "build", "status": "cached" })
+        );
+        assert_eq!(
+            serde_json::to_value(event(TaskStatus::Failed { exit_code: Some(2) })).unwrap(),
+            json!({ "app_id": 7, "task": "build", "status": "failed", "exit_code": 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publisher() {
+        let events = TaskEvents::default();
+        // Nobody is listening yet, which is fine
+        events.publisher(1, "build").publish(TaskStatus::Queued);
+
+        let mut receiver = events.subscribe();
+        events.publisher(1, "build").publish(TaskStatus::Running);
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.app_id, 1);
+        assert_eq!(event.status, TaskStatus::Running);
+
+        events.app(2).publish("test", TaskStatus::Cached);
+        let event = receiver.recv().await.unwrap();
+        assert_eq!((event.app_id, event.task.as_str()), (2, "test"));
+        assert_eq!(event.status, TaskStatus::Cached);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod error;
+pub mod events;
 pub mod settings;
 pub mod util;
 
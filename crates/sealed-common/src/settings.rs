@@ -1,4 +1,8 @@
-use std::{env, path::PathBuf, sync::OnceLock};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::Context;
 use config::File;
@@ -8,39 +12,250 @@ use std::fs::canonicalize;
 
 pub static CONFIG_INSTANCE: OnceLock<Settings> = OnceLock::new();
 
-use crate::error::SealedResult;
+use crate::{
+    cache::HashAlgorithm,
+    error::{SealedError, SealedResult},
+    util::{
+        fs_utils::make_dirs,
+        tar::Compression,
+        tracing::{LogFile, LogFormat},
+    },
+};
+
+// Which cross-origin requests the server allows. With no origins, any origin is allowed in
+// development, and the server refuses to start in any other `RUN_MODE`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CorsArgs {
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsArgs {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_headers(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WebhookArgs {
+    // Also act on pushes to branches other than the repository's default branch
+    #[serde(default)]
+    pub all_branches: bool,
+    // Shared with the git host. GitHub signs each delivery with it (`X-Hub-Signature-256`) and
+    // GitLab sends it as `X-Gitlab-Token`. Deliveries are rejected until it's set.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+// How many requests a client may make to the public endpoints. Each client gets a bucket of
+// `requests` tokens which refills over `window_secs`, so short bursts are allowed.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RateLimitArgs {
+    #[serde(default = "default_rate_limit_requests")]
+    pub requests: u32,
+    #[serde(default = "default_rate_limit_window_secs")]
+    pub window_secs: u64,
+    // Identify clients by the last address in `X-Forwarded-For`, which the proxy appended. Only
+    // enable this behind a proxy which appends to the header, or a client could set the address.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitArgs {
+    fn default() -> Self {
+        Self {
+            requests: default_rate_limit_requests(),
+            window_secs: default_rate_limit_window_secs(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+// The connection pool for the server's database. The URL itself comes from `DATABASE_URL`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DatabaseSettings {
+    #[serde(default = "default_database_max_connections")]
+    pub max_connections: u32,
+
+    // How long a request waits for a free connection before failing
+    #[serde(default = "default_database_acquire_timeout_secs")]
+    pub acquire_timeout_secs: u64,
+
+    // How long an unused connection stays open. Connections are never closed for being idle if
+    // this is omitted.
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            max_connections: default_database_max_connections(),
+            acquire_timeout_secs: default_database_acquire_timeout_secs(),
+            idle_timeout_secs: None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ServerArgs {
     pub port: u16,
+    #[serde(default)]
+    pub skip_migrations: bool,
+    // How long in-flight requests get to finish after a shutdown signal
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    #[serde(default)]
+    pub cors: CorsArgs,
+    #[serde(default)]
+    pub webhook: WebhookArgs,
+    #[serde(default)]
+    pub rate_limit: RateLimitArgs,
+    // Copied from the top-level `database` section rather than read from `server`
+    #[serde(skip)]
+    pub database: DatabaseSettings,
 }
 
 impl Default for ServerArgs {
     fn default() -> Self {
-        Self { port: 9999 }
+        Self {
+            port: 9999,
+            skip_migrations: false,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            cors: CorsArgs::default(),
+            webhook: WebhookArgs::default(),
+            rate_limit: RateLimitArgs::default(),
+            database: DatabaseSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ImageCacheSettings {
+    #[serde(default = "default_image_cache_enabled")]
+    pub enabled: bool,
+
+    // Defaults to `image-cache` under the working directory.
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+
+    // The maximum total size of the cached tarballs in bytes. The least recently used tarballs
+    // are evicted once it is exceeded.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+}
+
+impl Default for ImageCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_image_cache_enabled(),
+            directory: None,
+            max_size: None,
+        }
     }
 }
 
+// An S3-compatible bucket for sharing task images between machines.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RemoteCacheSettings {
+    pub bucket: String,
+
+    // Prepended to every key in the bucket.
+    #[serde(default)]
+    pub prefix: String,
+
+    #[serde(default = "default_remote_cache_region")]
+    pub region: String,
+
+    // For S3-compatible stores other than AWS (e.g., MinIO).
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    // If these are omitted, credentials are taken from the environment or the AWS profile.
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Settings {
+    // The default log level, which `RUST_LOG` overrides
     #[serde(default = "default_log_level")]
     pub log_level: LevelFilter,
 
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    // Also write the logs to this file, e.g., to keep the output of long Docker builds
+    #[serde(default)]
+    pub log_file: Option<LogFile>,
+
     #[serde(default = "default_working_directory")]
     pub working_directory: PathBuf,
 
     #[serde(default = "default_ssh_key")]
     pub ssh_key: Option<PathBuf>,
 
+    // Whether to initialize and update submodules (recursively) after cloning or fetching a
+    // repository. Off by default, since it can mean cloning a lot more.
+    #[serde(default)]
+    pub submodules: bool,
+
     #[serde(default = "ServerArgs::default")]
     pub server: ServerArgs,
+
+    #[serde(default = "DatabaseSettings::default")]
+    pub database: DatabaseSettings,
+
+    #[serde(default = "ImageCacheSettings::default")]
+    pub image_cache: ImageCacheSettings,
+
+    #[serde(default)]
+    pub remote_cache: Option<RemoteCacheSettings>,
+
+    // How many times to retry Docker commands which fail transiently (e.g., registry rate limits)
+    #[serde(default = "default_docker_retries")]
+    pub docker_retries: u32,
+
+    // The hash function for cache keys. Changing it invalidates the existing caches.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+
+    // How to compress the files copied into task containers. Worth turning on for remote Docker
+    // hosts.
+    #[serde(default)]
+    pub tar_compression: Compression,
+
+    // How long `install` waits for each operator to become available. Raise it for slow clusters.
+    #[serde(default = "default_install_timeout_secs")]
+    pub install_timeout_secs: u64,
 }
 
 pub fn get_config() -> SealedResult<&'static Settings> {
     Ok(CONFIG_INSTANCE.get().expect("Config not initialized"))
 }
 
+// Environment variables overriding the settings, e.g. `SEALED__SERVER__PORT=8080` for
+// `server.port`.
+pub const ENV_PREFIX: &str = "SEALED";
+const ENV_SEPARATOR: &str = "__";
+
+// Replaces credentials in `Settings::redacted`
+pub const REDACTED: &str = "********";
+
 impl Settings {
+    // Sources are layered from lowest to highest precedence: the `root` file, `config`,
+    // `config/default`, `config.$RUN_MODE`, `$root/config`, and finally `SEALED__*` environment
+    // variables, so the environment overrides every file.
     pub fn from_root(root: Option<PathBuf>) -> SealedResult<Self> {
         let curr_dir = std::env::current_dir().context("unable to get working directory")?;
         let root = root.unwrap_or(curr_dir);
@@ -60,11 +275,68 @@ impl Settings {
                 File::with_name(&format!("{}/config", root.as_path().to_str().unwrap()))
                     .required(false),
             )
+            .add_source(environment_source())
             .build()?;
 
-        let cfg = s.try_deserialize()?;
+        let cfg: Settings = s.try_deserialize()?;
+        cfg.validate()?;
         Ok(cfg)
     }
+
+    // A copy which is safe to print: credentials are masked, but paths (like `ssh_key`) are kept,
+    // since they're what's usually being debugged.
+    pub fn redacted(&self) -> Self {
+        let mask = |value: &Option<String>| value.as_ref().map(|_| REDACTED.to_string());
+        let mut settings = self.clone();
+        if let Some(remote_cache) = settings.remote_cache.as_mut() {
+            remote_cache.access_key_id = mask(&remote_cache.access_key_id);
+            remote_cache.secret_access_key = mask(&remote_cache.secret_access_key);
+        }
+        settings
+    }
+
+    // Check the settings which would otherwise only fail once they're used. The working directory
+    // is created if it doesn't exist yet.
+    pub fn validate(&self) -> SealedResult<()> {
+        check_working_directory(&self.working_directory)
+    }
+}
+
+// Repositories are cloned into the working directory, so it has to be a writable directory.
+fn check_working_directory(path: &Path) -> SealedResult<()> {
+    let config_error = |problem: String| {
+        SealedError::Config(config::ConfigError::Message(format!(
+            "working_directory {problem}"
+        )))
+    };
+
+    if path.as_os_str().is_empty() {
+        return Err(config_error("is empty".to_string()));
+    }
+    if path.exists() && !path.is_dir() {
+        return Err(config_error(format!(
+            "{} is not a directory",
+            path.display()
+        )));
+    }
+    make_dirs(path)
+        .map_err(|e| config_error(format!("{} can't be created: {}", path.display(), e)))?;
+
+    // Creating a file is the only reliable check, since permissions alone don't account for
+    // read-only mounts
+    let probe = path.join(format!(".sealed-write-check-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .map_err(|e| config_error(format!("{} isn't writable: {}", path.display(), e)))?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+fn environment_source() -> config::Environment {
+    config::Environment::with_prefix(ENV_PREFIX)
+        .prefix_separator(ENV_SEPARATOR)
+        .separator(ENV_SEPARATOR)
+        .try_parsing(true)
 }
 
 fn default_log_level() -> LevelFilter {
@@ -75,7 +347,169 @@ fn default_working_directory() -> PathBuf {
     PathBuf::from("/tmp")
 }
 
+fn default_cors_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "DELETE", "OPTIONS"]
+        .iter()
+        .map(|method| method.to_string())
+        .collect()
+}
+
+fn default_cors_headers() -> Vec<String> {
+    vec!["content-type".to_string()]
+}
+
+fn default_rate_limit_requests() -> u32 {
+    60
+}
+
+fn default_rate_limit_window_secs() -> u64 {
+    60
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_database_max_connections() -> u32 {
+    10
+}
+
+fn default_database_acquire_timeout_secs() -> u64 {
+    5
+}
+
+fn default_docker_retries() -> u32 {
+    3
+}
+
+fn default_install_timeout_secs() -> u64 {
+    120
+}
+
+fn default_image_cache_enabled() -> bool {
+    true
+}
+
+fn default_remote_cache_region() -> String {
+    "us-east-1".to_string()
+}
+
 fn default_ssh_key() -> Option<PathBuf> {
     let home = env::var("HOME").unwrap();
     Some(PathBuf::from(format!("{home}/.ssh/id_rsa")))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_overrides_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("settings.yaml");
+        std::fs::write(
+            &file,
+            format!(
+                "server:\n  port: 3000\nworking_directory: {}\n",
+                dir.path().join("from-file").display()
+            ),
+        )
+        .unwrap();
+
+        let from_file = Settings::from_root(Some(file.clone())).unwrap();
+        assert_eq!(from_file.server.port, 3000);
+        assert_eq!(from_file.working_directory, dir.path().join("from-file"));
+
+        let from_env = dir.path().join("from-env");
+        env::set_var("SEALED__SERVER__PORT", "8080");
+        env::set_var("SEALED__WORKING_DIRECTORY", &from_env);
+        let settings = Settings::from_root(Some(file));
+        env::remove_var("SEALED__SERVER__PORT");
+        env::remove_var("SEALED__WORKING_DIRECTORY");
+
+        let settings = settings.unwrap();
+        assert_eq!(settings.server.port, 8080);
+        assert_eq!(settings.working_directory, from_env);
+    }
+
+    #[test]
+    fn test_redacted() {
+        let mut settings: Settings = serde_yaml::from_str(
+            "ssh_key: /home/flynn/.ssh/id_ed25519\nremote_cache:\n  bucket: grid\n  secret_access_key: hunter2\n",
+        )
+        .unwrap();
+
+        let redacted = settings.redacted();
+        let remote_cache = redacted.remote_cache.as_ref().unwrap();
+        assert_eq!(remote_cache.secret_access_key.as_deref(), Some(REDACTED));
+        assert_eq!(remote_cache.access_key_id, None);
+        assert_eq!(remote_cache.bucket, "grid");
+        assert_eq!(
+            redacted.ssh_key,
+            Some(PathBuf::from("/home/flynn/.ssh/id_ed25519"))
+        );
+
+        settings.remote_cache = None;
+        assert_eq!(settings.redacted(), settings);
+    }
+
+    #[test]
+    fn test_tar_compression() {
+        let settings: Settings = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(settings.tar_compression, Compression::None);
+
+        let settings: Settings = serde_yaml::from_str("tar_compression: gzip\n").unwrap();
+        assert_eq!(settings.tar_compression, Compression::Gzip);
+
+        // Docker can't extract zstd archives.
+        assert!(serde_yaml::from_str::<Settings>("tar_compression: zstd\n").is_err());
+    }
+
+    #[test]
+    fn test_log_format() {
+        let settings: Settings = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(settings.log_format, LogFormat::Full);
+
+        let settings: Settings = serde_yaml::from_str("log_format: pretty\n").unwrap();
+        assert_eq!(settings.log_format, LogFormat::Pretty);
+
+        let settings: Settings = serde_yaml::from_str("log_format: json\n").unwrap();
+        assert_eq!(settings.log_format, LogFormat::Json);
+    }
+
+    #[test]
+    fn test_install_timeout() {
+        let settings: Settings = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(settings.install_timeout_secs, 120);
+
+        let settings: Settings = serde_yaml::from_str("install_timeout_secs: 600\n").unwrap();
+        assert_eq!(settings.install_timeout_secs, 600);
+    }
+
+    #[test]
+    fn test_writable_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_working_directory(dir.path()).is_ok());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_working_directory_is_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("repos");
+        std::fs::write(&file, "").unwrap();
+
+        let error = check_working_directory(&file).unwrap_err();
+        assert!(matches!(error, SealedError::Config(_)));
+        assert!(error.to_string().contains("not a directory"));
+    }
+
+    #[test]
+    fn test_working_directory_is_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("work").join("repos");
+
+        assert!(check_working_directory(&path).is_ok());
+        assert!(path.is_dir());
+    }
+}
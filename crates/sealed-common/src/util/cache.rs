@@ -1,11 +1,22 @@
 use {
-    crate::error::{SealedError, SealedResult},
+    crate::{
+        error::{SealedError, SealedResult},
+        util::format::CodeStr,
+    },
+    serde::{Deserialize, Serialize},
     sha1::Sha1,
     sha2::{Digest, Sha256},
     std::{
         collections::HashMap,
-        io::{self, Read},
+        fs::{create_dir_all, metadata, read_to_string, rename, write, File},
+        io::{self, Read, Write},
         path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, SystemTime, UNIX_EPOCH},
     },
     typed_path::{UnixPath, UnixPathBuf},
 };
@@ -19,6 +30,91 @@ use std::os::windows::ffi::OsStrExt;
 // Bump this if we need to invalidate all existing caches for some reason.
 pub const CACHE_VERSION: usize = 0;
 
+// The hash function for the contents of input files, which is where the time goes on large inputs.
+// It's chosen in the settings and passed to `hash_files`, the hash manifests, and the cache keys.
+// Strings and paths (`CryptoHash`, `combine`, and `hash_read`) are always hashed with SHA-256.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    // Much faster on large inputs
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    // Hash a byte string as a hexadecimal string.
+    pub fn hash_bytes(self, bytes: &[u8]) -> String {
+        let mut hasher = Hasher::new(self);
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+
+    // Hash a readable object without loading all of it into memory.
+    pub fn hash_reader<R: Read + ?Sized>(self, input: &mut R) -> io::Result<String> {
+        let mut hasher = Hasher::new(self);
+        io::copy(input, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The version to start cache keys from. It includes the hash algorithm (except for the original
+// SHA-256), so switching algorithms can't collide with existing cache entries.
+pub fn cache_version(algorithm: HashAlgorithm) -> String {
+    match algorithm {
+        HashAlgorithm::Sha256 => format!("{CACHE_VERSION}"),
+        algorithm => format!("{CACHE_VERSION}-{}", algorithm.name()),
+    }
+}
+
 // This trait is implemented by things we can take a cryptographic hash of, such as strings and
 // paths.
 pub trait CryptoHash {
@@ -30,13 +126,13 @@ pub trait CryptoHash {
 
 impl CryptoHash for str {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(self.as_bytes()))
+        HashAlgorithm::Sha256.hash_bytes(self.as_bytes())
     }
 }
 
 impl CryptoHash for String {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(self.as_bytes()))
+        HashAlgorithm::Sha256.hash_bytes(self.as_bytes())
     }
 }
 
@@ -55,25 +151,25 @@ fn path_as_bytes(path: &Path) -> Vec<u8> {
 
 impl CryptoHash for Path {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(path_as_bytes(self)))
+        HashAlgorithm::Sha256.hash_bytes(&path_as_bytes(self))
     }
 }
 
 impl CryptoHash for PathBuf {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(path_as_bytes(self)))
+        HashAlgorithm::Sha256.hash_bytes(&path_as_bytes(self))
     }
 }
 
 impl CryptoHash for UnixPath {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(self.as_bytes()))
+        HashAlgorithm::Sha256.hash_bytes(self.as_bytes())
     }
 }
 
 impl CryptoHash for UnixPathBuf {
     fn crypto_hash(&self) -> String {
-        hex::encode(Sha256::digest(self.as_bytes()))
+        HashAlgorithm::Sha256.hash_bytes(self.as_bytes())
     }
 }
 
@@ -91,16 +187,245 @@ pub fn combine<X: CryptoHash + ?Sized, Y: CryptoHash + ?Sized>(x: &X, y: &Y) ->
 // load all the data in memory at the same time. The guarantees are the same as those of
 // `crypto_hash`.
 pub fn hash_read<R: Read>(input: &mut R) -> SealedResult<String> {
-    let mut hasher = Sha256::new();
-    io::copy(input, &mut hasher).map_err(|err| SealedError::System(err.to_string(), None))?;
-    Ok(hex::encode(hasher.finalize()))
+    HashAlgorithm::Sha256
+        .hash_reader(input)
+        .map_err(|err| SealedError::System(err.to_string(), None))
+}
+
+// Hash the contents of each of the given files with `algorithm`, using up to `threads` threads. The
+// hashes are returned in the same order as `paths`, so the result doesn't depend on how the work was
+// scheduled. Each thread streams one file at a time, so memory use doesn't grow with the size of the
+// files.
+pub fn hash_files(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    threads: usize,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedResult<Vec<String>> {
+    let next = AtomicUsize::new(0);
+    let threads = threads.clamp(1, paths.len().max(1));
+
+    // Errors are kept as `io::Error`s because `SealedError` can't be sent between threads.
+    let results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    while !interrupted.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(path) = paths.get(index) else {
+                            break;
+                        };
+                        results.push((
+                            index,
+                            File::open(path).and_then(|mut file| algorithm.hash_reader(&mut file)),
+                        ));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("A hashing thread panicked."))
+            .collect::<Vec<_>>()
+    });
+
+    if interrupted.load(Ordering::SeqCst) {
+        return Err(SealedError::Interrupted);
+    }
+
+    let mut hashes = vec![String::new(); paths.len()];
+    let mut errors = vec![];
+    for (index, result) in results {
+        match result {
+            Ok(hash) => hashes[index] = hash,
+            Err(error) => errors.push((index, error)),
+        }
+    }
+
+    // Report the error for the first failing path, so the error doesn't depend on scheduling.
+    if let Some((index, error)) = errors.into_iter().min_by_key(|(index, _)| *index) {
+        return Err(SealedError::System(
+            format!(
+                "Unable to read file {}.",
+                paths[index].to_string_lossy().code_str(),
+            ),
+            Some(Box::new(error)),
+        ));
+    }
+
+    Ok(hashes)
+}
+
+// Where the hash manifest of a task is kept. Task names can contain any character, so the file is
+// named after a hash of the name.
+pub fn hash_manifest_path(working_directory: &Path, task: &str) -> PathBuf {
+    working_directory
+        .join(HASH_MANIFESTS_DIR)
+        .join(format!("{}.json", task.crypto_hash()))
+}
+
+const HASH_MANIFESTS_DIR: &str = "input-hashes";
+
+// Files modified this recently aren't recorded in a hash manifest. A file can change again within
+// the resolution of its modification time, and then its size and modification time might not
+// reveal the change.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+// The last known hash of a file, which is reused while its size and modification time are the same
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+struct ManifestEntry {
+    size: u64,
+    modified_nanos: u128,
+    hash: String,
+}
+
+impl ManifestEntry {
+    // The size and modification time of a file, or `None` if they can't be determined
+    fn stat(path: &Path) -> Option<(u64, SystemTime)> {
+        let metadata = metadata(path).ok()?;
+        Some((metadata.len(), metadata.modified().ok()?))
+    }
+}
+
+fn nanos_since_epoch(time: SystemTime) -> Option<u128> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_nanos())
+}
+
+// The hashes of files from an earlier run, so files which haven't changed since don't have to be
+// read again. The hashes are the ones `hash_files` would compute, so the result is the same either
+// way. A manifest written with another cache version or hash algorithm is ignored.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HashManifest {
+    version: String,
+    files: HashMap<PathBuf, ManifestEntry>,
+}
+
+impl HashManifest {
+    // Read a manifest of hashes computed with `algorithm`. A missing, unreadable, or stale manifest is
+    // treated as empty, so every file is hashed.
+    pub fn load(path: &Path, algorithm: HashAlgorithm) -> Self {
+        read_to_string(path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<HashManifest>(&data).ok())
+            .filter(|manifest| manifest.version == cache_version(algorithm))
+            .unwrap_or_default()
+    }
+
+    // Write the manifest, replacing the file in one step so a concurrent reader never sees half of
+    // it.
+    pub fn save(&self, path: &Path) -> SealedResult<()> {
+        let system_error = |error: io::Error| {
+            SealedError::System(
+                format!(
+                    "Unable to write hash manifest {}.",
+                    path.to_string_lossy().code_str(),
+                ),
+                Some(Box::new(error)),
+            )
+        };
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).map_err(system_error)?;
+        }
+        let data = serde_json::to_string(self)
+            .map_err(|error| SealedError::System(error.to_string(), None))?;
+        let temp_path = path.with_extension("json.tmp");
+        write(&temp_path, data).map_err(system_error)?;
+        rename(&temp_path, path).map_err(system_error)
+    }
+
+    // Like `hash_files`, but files whose size and modification time match the manifest aren't
+    // read. Afterwards the manifest holds exactly the given files, so files which are no longer
+    // inputs are forgotten.
+    pub fn hash_files(
+        &mut self,
+        paths: &[PathBuf],
+        algorithm: HashAlgorithm,
+        threads: usize,
+        interrupted: &Arc<AtomicBool>,
+    ) -> SealedResult<Vec<String>> {
+        let started = SystemTime::now();
+        let stats = paths
+            .iter()
+            .map(|path| ManifestEntry::stat(path))
+            .collect::<Vec<_>>();
+
+        let mut hashes = paths
+            .iter()
+            .zip(&stats)
+            .map(|(path, stat)| {
+                let (size, modified) = (*stat)?;
+                let entry = self.files.get(path)?;
+                (entry.size == size && Some(entry.modified_nanos) == nanos_since_epoch(modified))
+                    .then(|| entry.hash.clone())
+            })
+            .collect::<Vec<_>>();
+
+        let stale = hashes
+            .iter()
+            .enumerate()
+            .filter(|(_, hash)| hash.is_none())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let stale_paths = stale
+            .iter()
+            .map(|index| paths[*index].clone())
+            .collect::<Vec<_>>();
+        for (index, hash) in
+            stale
+                .into_iter()
+                .zip(hash_files(&stale_paths, algorithm, threads, interrupted)?)
+        {
+            hashes[index] = Some(hash);
+        }
+        let hashes = hashes.into_iter().flatten().collect::<Vec<_>>();
+
+        self.version = cache_version(algorithm);
+        self.files = paths
+            .iter()
+            .zip(&stats)
+            .zip(&hashes)
+            .filter_map(|((path, stat), hash)| {
+                let (size, modified) = (*stat)?;
+                if started
+                    .duration_since(modified)
+                    .map_or(true, |age| age < RACY_WINDOW)
+                {
+                    return None;
+                }
+                Some((
+                    path.clone(),
+                    ManifestEntry {
+                        size,
+                        modified_nanos: nanos_since_epoch(modified)?,
+                        hash: hash.clone(),
+                    },
+                ))
+            })
+            .collect();
+
+        Ok(hashes)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{combine, hash_read, CryptoHash};
+    use super::{
+        combine, hash_files, hash_manifest_path, hash_read, CryptoHash, HashAlgorithm, HashManifest,
+    };
     use {
-        std::{collections::HashMap, path::Path},
+        std::{
+            collections::HashMap,
+            fs::{write, File},
+            path::{Path, PathBuf},
+            sync::{atomic::AtomicBool, Arc},
+            time::{Duration, SystemTime},
+        },
         typed_path::UnixPath,
     };
 
@@ -179,4 +504,210 @@ mod tests {
         let mut str2 = b"bar" as &[u8];
         assert_ne!(hash_read(&mut str1).unwrap(), hash_read(&mut str2).unwrap());
     }
+
+    #[test]
+    fn hash_files_matches_hash_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..500)
+            .map(|i| {
+                let path = dir.path().join(format!("file-{i}.txt"));
+                write(&path, format!("contents of file {i}")).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let serial = paths
+            .iter()
+            .map(|path| hash_read(&mut File::open(path).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        assert_eq!(
+            hash_files(&paths, HashAlgorithm::Sha256, 8, &interrupted).unwrap(),
+            serial
+        );
+        assert_eq!(
+            hash_files(&paths, HashAlgorithm::Sha256, 1, &interrupted).unwrap(),
+            serial
+        );
+    }
+
+    #[test]
+    fn hash_files_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = vec![dir.path().join("missing")];
+        assert!(hash_files(
+            &paths,
+            HashAlgorithm::Sha256,
+            4,
+            &Arc::new(AtomicBool::new(false))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hash_algorithms_stable() {
+        assert_eq!(
+            HashAlgorithm::Sha256.hash_bytes(b"foo"),
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae",
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.hash_bytes(b"foo"),
+            HashAlgorithm::Blake3.hash_bytes(b"foo"),
+        );
+        assert_eq!(HashAlgorithm::Blake3.hash_bytes(b"foo").len(), 64);
+    }
+
+    #[test]
+    fn hash_algorithms_distinct() {
+        assert_ne!(
+            HashAlgorithm::Sha256.hash_bytes(b"foo"),
+            HashAlgorithm::Blake3.hash_bytes(b"foo"),
+        );
+        assert_ne!(
+            HashAlgorithm::Blake3.hash_bytes(b"foo"),
+            HashAlgorithm::Blake3.hash_bytes(b"bar"),
+        );
+    }
+
+    #[test]
+    fn hash_algorithms_reader_matches_bytes() {
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let mut reader = b"foo" as &[u8];
+            assert_eq!(
+                algorithm.hash_reader(&mut reader).unwrap(),
+                algorithm.hash_bytes(b"foo"),
+            );
+        }
+    }
+
+    // Write a file and move its modification time back, since files modified in the last couple of
+    // seconds aren't recorded in a manifest.
+    fn write_old(path: &Path, contents: &str, age_secs: u64) {
+        write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    fn input_files(dir: &Path) -> Vec<PathBuf> {
+        let paths = vec![dir.join("main.rs"), dir.join("lib.rs")];
+        write_old(&paths[0], "fn main() {}", 60);
+        write_old(&paths[1], "pub mod grid;", 60);
+        paths
+    }
+
+    #[test]
+    fn hash_manifest_reuses_unchanged_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = input_files(dir.path());
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let full = hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted).unwrap();
+
+        let mut manifest = HashManifest::default();
+        assert_eq!(
+            manifest
+                .hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted)
+                .unwrap(),
+            full
+        );
+        let manifest_path = hash_manifest_path(dir.path(), "build");
+        manifest.save(&manifest_path).unwrap();
+
+        let mut loaded = HashManifest::load(&manifest_path, HashAlgorithm::Sha256);
+        assert_eq!(loaded, manifest);
+        assert_eq!(
+            loaded
+                .hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted)
+                .unwrap(),
+            full
+        );
+
+        // The recorded hash is used without reading the file.
+        loaded.files.get_mut(&paths[0]).unwrap().hash = "recorded".to_owned();
+        assert_eq!(
+            loaded
+                .hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted)
+                .unwrap(),
+            vec!["recorded".to_owned(), full[1].clone()],
+        );
+    }
+
+    #[test]
+    fn hash_manifest_rehashes_modified_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = input_files(dir.path());
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let mut manifest = HashManifest::default();
+        let before = manifest
+            .hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted)
+            .unwrap();
+
+        // Same size, different contents and modification time
+        write_old(&paths[0], "fn main() {1}", 30);
+        let after = manifest
+            .hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted)
+            .unwrap();
+        assert_ne!(after[0], before[0]);
+        assert_eq!(after[1], before[1]);
+        assert_eq!(
+            after,
+            hash_files(&paths, HashAlgorithm::Sha256, 2, &interrupted).unwrap()
+        );
+    }
+
+    #[test]
+    fn hash_manifest_skips_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        write(&path, "fn main() {}").unwrap();
+
+        let mut manifest = HashManifest::default();
+        manifest
+            .hash_files(
+                &[path],
+                HashAlgorithm::Sha256,
+                1,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap();
+        assert!(manifest.files.is_empty());
+    }
+
+    #[test]
+    fn hash_manifest_stale_or_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = input_files(dir.path());
+        let manifest_path = hash_manifest_path(dir.path(), "build");
+        assert_eq!(
+            HashManifest::load(&manifest_path, HashAlgorithm::Sha256),
+            HashManifest::default()
+        );
+
+        let mut manifest = HashManifest::default();
+        manifest
+            .hash_files(
+                &paths,
+                HashAlgorithm::Sha256,
+                1,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap();
+        manifest.version = "outdated".to_owned();
+        manifest.save(&manifest_path).unwrap();
+        assert_eq!(
+            HashManifest::load(&manifest_path, HashAlgorithm::Sha256),
+            HashManifest::default()
+        );
+
+        write(&manifest_path, "{").unwrap();
+        assert_eq!(
+            HashManifest::load(&manifest_path, HashAlgorithm::Sha256),
+            HashManifest::default()
+        );
+    }
 }
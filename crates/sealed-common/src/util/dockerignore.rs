@@ -0,0 +1,201 @@
+use std::path::{Component, Path};
+
+use crate::error::SealedResult;
+
+// The file in a source directory which lists paths to leave out of the archives copied into
+// containers, in gitignore syntax
+pub const SEALEDIGNORE: &str = ".sealedignore";
+
+// The rules from a `.dockerignore` file. The matching follows Docker: patterns are relative to the
+// root of the build context, `*` and `?` don't match `/`, `**` matches any number of directories,
+// a `!` prefix re-includes paths, and the last matching pattern wins. A path is also ignored if one
+// of its parent directories is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DockerIgnore {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<String>,
+    exclusion: bool,
+}
+
+impl DockerIgnore {
+    // Read `.dockerignore` in the root of a build context. A missing file ignores nothing.
+    pub fn from_context(context: &Path) -> SealedResult<Self> {
+        let path = context.join(".dockerignore");
+        if !path.is_file() {
+            return Ok(DockerIgnore::default());
+        }
+
+        Ok(DockerIgnore::parse(&std::fs::read_to_string(path)?))
+    }
+
+    // Read `.sealedignore` in a source directory. A missing file ignores nothing.
+    pub fn from_sealedignore(source_dir: &Path) -> SealedResult<Self> {
+        let path = source_dir.join(SEALEDIGNORE);
+        if !path.is_file() {
+            return Ok(DockerIgnore::default());
+        }
+
+        Ok(DockerIgnore::parse_gitignore(&std::fs::read_to_string(
+            path,
+        )?))
+    }
+
+    // Parse rules in gitignore syntax. The difference from `.dockerignore` is that a pattern without
+    // a slash (other than a trailing one) matches at any depth rather than only at the root.
+    pub fn parse_gitignore(contents: &str) -> Self {
+        let contents = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (prefix, pattern) = match line.strip_prefix('!') {
+                    Some(pattern) => ("!", pattern.trim()),
+                    None => ("", line),
+                };
+
+                if pattern.trim_end_matches('/').contains('/') {
+                    format!("{prefix}{pattern}")
+                } else {
+                    format!("{prefix}**/{pattern}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        DockerIgnore::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (exclusion, line) = match line.strip_prefix('!') {
+                    Some(line) => (true, line.trim()),
+                    None => (false, line),
+                };
+
+                let segments = line
+                    .split('/')
+                    .filter(|segment| !segment.is_empty() && *segment != ".")
+                    .map(ToOwned::to_owned)
+                    .collect::<Vec<_>>();
+
+                if segments.is_empty() {
+                    None
+                } else {
+                    Some(Pattern {
+                        segments,
+                        exclusion,
+                    })
+                }
+            })
+            .collect();
+
+        DockerIgnore { patterns }
+    }
+
+    // Whether a path relative to the root of the build context is ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let components = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(segment) => Some(segment.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Check the path and each of its parents, so ignoring a directory ignores everything in it.
+        (1..=components.len()).any(|len| self.matches(&components[..len]))
+    }
+
+    fn matches(&self, components: &[String]) -> bool {
+        let mut ignored = false;
+        for pattern in &self.patterns {
+            if match_segments(&pattern.segments, components) {
+                ignored = !pattern.exclusion;
+            }
+        }
+        ignored
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                match_segment(first.as_bytes(), segment.as_bytes())
+                    && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+// Match a single path segment against a pattern segment with `*` and `?` wildcards.
+fn match_segment(pattern: &[u8], segment: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some((b'*', rest)) => (0..=segment.len()).any(|skip| match_segment(rest, &segment[skip..])),
+        Some((b'?', rest)) => !segment.is_empty() && match_segment(rest, &segment[1..]),
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            segment.first() == rest.first() && match_segment(&rest[1..], &segment[1..])
+        }
+        Some((c, rest)) => segment.first() == Some(c) && match_segment(rest, &segment[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_patterns() {
+        let ignore = DockerIgnore::parse("# Comment\nnode_modules\n/target\n\n");
+        assert!(ignore.is_ignored(Path::new("node_modules")));
+        assert!(ignore.is_ignored(Path::new("node_modules/left-pad/Dockerfile")));
+        assert!(ignore.is_ignored(Path::new("target/debug")));
+        assert!(!ignore.is_ignored(Path::new("src/node_modules")));
+        assert!(!ignore.is_ignored(Path::new("Dockerfile")));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let ignore = DockerIgnore::parse("*.md\n**/vendor\ntemp?");
+        assert!(ignore.is_ignored(Path::new("README.md")));
+        assert!(!ignore.is_ignored(Path::new("docs/README.md")));
+        assert!(ignore.is_ignored(Path::new("vendor/Dockerfile")));
+        assert!(ignore.is_ignored(Path::new("a/b/vendor/Dockerfile")));
+        assert!(ignore.is_ignored(Path::new("temp1")));
+        assert!(!ignore.is_ignored(Path::new("temp12")));
+    }
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let ignore = DockerIgnore::parse_gitignore("target/\n/dist\ndocs/*.md\n*.log\n!keep.log");
+        assert!(ignore.is_ignored(Path::new("target/debug/app")));
+        assert!(ignore.is_ignored(Path::new("crates/foo/target/debug/app")));
+        assert!(ignore.is_ignored(Path::new("dist/index.html")));
+        assert!(!ignore.is_ignored(Path::new("web/dist/index.html")));
+        assert!(ignore.is_ignored(Path::new("docs/README.md")));
+        assert!(!ignore.is_ignored(Path::new("README.md")));
+        assert!(ignore.is_ignored(Path::new("logs/test.log")));
+        assert!(!ignore.is_ignored(Path::new("logs/keep.log")));
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let ignore = DockerIgnore::parse("*.md\n!README.md");
+        assert!(ignore.is_ignored(Path::new("CHANGELOG.md")));
+        assert!(!ignore.is_ignored(Path::new("README.md")));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::{SealedError, SealedResult};
+
+/// Parses a dotenv-style file. Blank lines and `#` comments are skipped, a leading `export` is
+/// allowed, double-quoted values support `\"`, `\\` and `\n` escapes, single-quoted values are
+/// taken literally, and unquoted values end at a ` #` comment.
+pub fn parse_env_file(contents: &str) -> SealedResult<Vec<(String, String)>> {
+    let mut vars = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let invalid = |reason: &str| {
+            SealedError::BadRequest(format!("Invalid env file line {}: {}", index + 1, reason))
+        };
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected `KEY=VALUE`"))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(invalid("invalid key"));
+        }
+
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let mut unquoted = String::new();
+            let mut chars = quoted.chars();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => unquoted.push('\n'),
+                        Some(c) => unquoted.push(c),
+                        None => return Err(invalid("unterminated quote")),
+                    },
+                    Some(c) => unquoted.push(c),
+                    None => return Err(invalid("unterminated quote")),
+                }
+            }
+            unquoted
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            quoted
+                .split_once('\'')
+                .ok_or_else(|| invalid("unterminated quote"))?
+                .0
+                .to_string()
+        } else {
+            value
+                .split_once(" #")
+                .map_or(value, |(value, _)| value)
+                .trim_end()
+                .to_string()
+        };
+
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let contents = r#"
+# Database settings
+DATABASE_URL=postgres://localhost/app
+
+export GREETING="hello \"world\""
+MULTILINE="one\ntwo"
+LITERAL='$HOME # not a comment'
+PORT=8080 # the port
+EMPTY=
+"#;
+        let vars = parse_env_file(contents).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                (
+                    "DATABASE_URL".to_string(),
+                    "postgres://localhost/app".to_string()
+                ),
+                ("GREETING".to_string(), "hello \"world\"".to_string()),
+                ("MULTILINE".to_string(), "one\ntwo".to_string()),
+                ("LITERAL".to_string(), "$HOME # not a comment".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_env_file_errors() {
+        assert!(parse_env_file("NO_EQUALS").is_err());
+        assert!(parse_env_file("KEY=\"unterminated").is_err());
+        assert!(parse_env_file("MY KEY=value").is_err());
+    }
+}
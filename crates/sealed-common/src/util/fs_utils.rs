@@ -1,6 +1,11 @@
 use std::path::{Path, PathBuf};
 
-use crate::error::SealedResult;
+use walkdir::WalkDir;
+
+use crate::{
+    dockerignore::DockerIgnore,
+    error::{SealedError, SealedResult},
+};
 
 pub fn make_dirs(path: &Path) -> SealedResult<()> {
     tracing::debug!("Creating directories: {}", path.display());
@@ -19,27 +24,60 @@ pub fn find_multiple_files_by_name(path: &Path, filenames: &[&str]) -> SealedRes
     find_multiple_files_by_name_recursive(path, filenames)
 }
 
+// Find the Dockerfile for a build context. A Dockerfile at the root of the context wins. Otherwise
+// the context is searched, skipping anything excluded by `.dockerignore` (so a Dockerfile in, e.g.,
+// `node_modules` is never picked up). Finding more than one candidate is an error, since there is
+// no good way to choose between them.
+pub fn find_dockerfile(context: &Path) -> SealedResult<PathBuf> {
+    let root_dockerfile = context.join("Dockerfile");
+    if root_dockerfile.is_file() {
+        return Ok(root_dockerfile);
+    }
+
+    let ignore = DockerIgnore::from_context(context)?;
+    let mut candidates = vec![];
+    for entry in WalkDir::new(context)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| match entry.path().strip_prefix(context) {
+            Ok(relative) => !ignore.is_ignored(relative),
+            Err(_) => true,
+        })
+    {
+        let entry = entry.map_err(|error| SealedError::IOError(error.into()))?;
+        if entry.file_type().is_file() && entry.file_name() == "Dockerfile" {
+            candidates.push(entry.into_path());
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(SealedError::FileNotFound(format!(
+            "Dockerfile in {}",
+            context.display()
+        ))),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(SealedError::Cli(format!(
+            "Found multiple Dockerfiles in {}, pass one explicitly with --dockerfile: {}",
+            context.display(),
+            candidates
+                .iter()
+                .map(|candidate| candidate.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+// Expand a path the way a shell would: a leading `~` or `~user` becomes a home directory, and
+// `$VAR` or `${VAR}` anywhere in the path becomes the value of the environment variable. Variables
+// which aren't set are kept as they are. Then `.` and `..` are resolved.
 pub fn expand_path<P: AsRef<Path>>(path: P) -> PathBuf {
-    let path_ref = path.as_ref();
-    let mut path_buf = PathBuf::new();
+    let expanded = expand_tilde(&expand_variables(&path.as_ref().to_string_lossy()));
 
-    for component in path_ref.components() {
+    let mut path_buf = PathBuf::new();
+    for component in Path::new(&expanded).components() {
         match component {
-            std::path::Component::Normal(os_str) => {
-                let segment = os_str.to_str().unwrap_or("");
-                if segment.starts_with('$') {
-                    // Handle environment variables
-                    if let Some(var_name) = segment.strip_prefix('$') {
-                        if let Ok(var_value) = std::env::var(var_name) {
-                            path_buf.push(var_value);
-                        } else {
-                            path_buf.push(segment);
-                        }
-                    }
-                } else {
-                    path_buf.push(segment);
-                }
-            }
+            std::path::Component::Normal(os_str) => path_buf.push(os_str),
             std::path::Component::RootDir => path_buf.push("/"),
             std::path::Component::CurDir => {} // Skip '.'
             std::path::Component::ParentDir => {
@@ -50,23 +88,87 @@ pub fn expand_path<P: AsRef<Path>>(path: P) -> PathBuf {
         }
     }
 
-    // Handle '~' for home directory
-    if path_buf.starts_with("~") {
-        if let Some(home_dir) = dirs::home_dir() {
-            let mut new_path = PathBuf::new();
-            new_path.push(home_dir);
-            new_path.push(path_buf.strip_prefix("~").unwrap());
-            path_buf = new_path;
-        }
-    }
-
-    // Canonicalize the path to resolve any remaining '..' or '.'
+    // Canonicalize the path to resolve any symlinks
     match path_buf.canonicalize() {
         Ok(canonical_path) => canonical_path,
         Err(_) => path_buf, // If canonicalization fails, return the original path
     }
 }
 
+// Replace `$VAR` and `${VAR}` with the value of the environment variable. Unset variables and
+// unterminated `${` are left untouched.
+fn expand_variables(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+
+        let (name, token_len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => ("", 1),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end + 1)
+        };
+
+        let token = &rest[index..index + token_len];
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => output.push_str(&value),
+            _ => output.push_str(token),
+        }
+        rest = &rest[index + token_len..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+// Replace a leading `~` with the current user's home directory, or `~user` with that user's home
+// directory. If the home directory can't be determined, the path is left untouched.
+fn expand_tilde(input: &str) -> String {
+    let Some(after) = input.strip_prefix('~') else {
+        return input.to_owned();
+    };
+
+    let (user, rest) = match after.find('/') {
+        Some(index) => after.split_at(index),
+        None => (after, ""),
+    };
+
+    let home = if user.is_empty() {
+        dirs::home_dir()
+    } else {
+        user_home_dir(user)
+    };
+
+    match home {
+        Some(home) => format!("{}{}", home.to_string_lossy(), rest),
+        None => input.to_owned(),
+    }
+}
+
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    // Format: name:password:uid:gid:gecos:home:shell
+    std::fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 6 && fields[0] == user)
+        .map(|fields| PathBuf::from(fields[5]))
+}
+
+#[cfg(not(unix))]
+fn user_home_dir(_user: &str) -> Option<PathBuf> {
+    None
+}
+
 fn find_file_by_name_recursive(root: &Path, filename: &str) -> SealedResult<PathBuf> {
     for entry in std::fs::read_dir(root)? {
         let entry = entry?;
@@ -163,22 +265,111 @@ mod tests {
         Ok(temp_root_path)
     }
 
+    #[test]
+    fn test_find_dockerfile_prefers_root() {
+        let temp_root = generate_test_data(false).unwrap();
+        std::fs::create_dir_all(temp_root.join("docker")).unwrap();
+        std::fs::write(temp_root.join("docker/Dockerfile"), "FROM alpine").unwrap();
+        assert_eq!(
+            find_dockerfile(&temp_root).unwrap(),
+            temp_root.join("Dockerfile")
+        );
+    }
+
+    #[test]
+    fn test_find_dockerfile_respects_dockerignore() {
+        let temp_root = tempfile::tempdir().unwrap();
+        let root = temp_root.path();
+        std::fs::create_dir_all(root.join("node_modules/decoy")).unwrap();
+        std::fs::write(root.join("node_modules/decoy/Dockerfile"), "FROM decoy").unwrap();
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        std::fs::write(root.join("app/Dockerfile"), "FROM alpine").unwrap();
+
+        // Without a `.dockerignore`, both are candidates.
+        let error = find_dockerfile(root).unwrap_err().to_string();
+        assert!(error.contains("app/Dockerfile"));
+        assert!(error.contains("node_modules/decoy/Dockerfile"));
+
+        std::fs::write(root.join(".dockerignore"), "node_modules\n").unwrap();
+        assert_eq!(find_dockerfile(root).unwrap(), root.join("app/Dockerfile"));
+    }
+
+    #[test]
+    fn test_find_dockerfile_missing() {
+        let temp_root = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            find_dockerfile(temp_root.path()),
+            Err(SealedError::FileNotFound(_))
+        ));
+    }
+
+    #[test]
     fn test_expand_path() {
+        let home = dirs::home_dir().unwrap().canonicalize().unwrap();
+        let home_parent = home.parent().unwrap().to_owned();
         let paths = vec![
-            ("~/.ssh/id_rsa", "/Users/auser/.ssh/id_rsa"),
-            ("$HOME/.ssh/id_rsa", "/Users/auser/.ssh/id_rsa"),
-            ("$HOME/Documents/../Downloads", "/Users/auser/Downloads"),
-            ("~/../some/relative/path", "/some/relative/path"),
-            ("/absolute/path", "/absolute/path"),
-            ("~/Documents/../Downloads", "/Users/auser/Downloads"),
+            ("~/.ssh/id_rsa", home.join(".ssh/id_rsa")),
+            ("$HOME/.ssh/id_rsa", home.join(".ssh/id_rsa")),
+            ("${HOME}/.ssh/id_rsa", home.join(".ssh/id_rsa")),
+            ("$HOME/Documents/../Downloads", home.join("Downloads")),
+            (
+                "~/../some/relative/path",
+                home_parent.join("some/relative/path"),
+            ),
+            ("/absolute/path", PathBuf::from("/absolute/path")),
+            ("~/Documents/../Downloads", home.join("Downloads")),
         ];
 
         for (input_path, expected_path) in paths {
-            println!("Original: {}", input_path);
-            println!("Expanded: {:?}\n", expand_path(input_path));
+            assert_eq!(expand_path(input_path), expected_path, "{}", input_path);
+        }
+    }
+
+    #[test]
+    fn test_expand_path_mid_path_variable() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            expand_path("/mnt/$HOME/data"),
+            PathBuf::from(format!("/mnt/{}/data", home.to_string_lossy()))
+        );
+        assert_eq!(
+            expand_path("/mnt/prefix-${HOME}/data"),
+            PathBuf::from(format!("/mnt/prefix-{}/data", home.to_string_lossy()))
+        );
+    }
+
+    #[test]
+    fn test_expand_path_undefined_variable() {
+        let name = "SEALED_TEST_SURELY_UNDEFINED_VARIABLE";
+        assert!(std::env::var(name).is_err());
+        assert_eq!(
+            expand_path(format!("/opt/${}/bin", name)),
+            PathBuf::from(format!("/opt/${}/bin", name))
+        );
+        assert_eq!(
+            expand_path(format!("/opt/${{{}}}/bin", name)),
+            PathBuf::from(format!("/opt/${{{}}}/bin", name))
+        );
+        assert_eq!(
+            expand_path("/opt/${UNTERMINATED"),
+            PathBuf::from("/opt/${UNTERMINATED")
+        );
+        assert_eq!(expand_path("/opt/price$"), PathBuf::from("/opt/price$"));
+    }
+
+    #[test]
+    fn test_expand_path_tilde_user() {
+        assert_eq!(
+            expand_path("~sealed-test-no-such-user/file"),
+            PathBuf::from("~sealed-test-no-such-user/file")
+        );
+        assert_eq!(expand_path("/opt/~/file"), PathBuf::from("/opt/~/file"));
+
+        #[cfg(unix)]
+        if let Some(root_home) = user_home_dir("root") {
             assert_eq!(
-                expected_path,
-                expand_path(input_path).as_path().to_str().unwrap()
+                expand_path("~root/file"),
+                root_home.canonicalize().unwrap_or(root_home).join("file")
             );
         }
     }
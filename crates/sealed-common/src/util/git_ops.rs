@@ -1,17 +1,85 @@
 use crate::error::SealedResult;
+use git_url_parse::{GitUrl, GitUrlParseError};
 use std::path::PathBuf;
 use tokio::process::Command;
 
-pub fn parse_repo_name(url: &str) -> SealedResult<String> {
+// What a repository URL says about the repository, in canonical form (see `parse_git_url`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepoMetadata {
+    // e.g., `github.com`. `None` for local repositories.
+    pub host: Option<String>,
+    pub owner: Option<String>,
+    pub name: String,
+    // e.g., `ssh` or `https`. scp-style URLs count as `ssh`.
+    pub protocol: String,
+}
+
+pub fn parse_repo_metadata(url: &str) -> SealedResult<RepoMetadata> {
     let parsed = parse_git_url(url)?;
-    Ok(parsed.name)
+    Ok(RepoMetadata {
+        host: parsed.host,
+        owner: parsed.owner,
+        name: parsed.name,
+        protocol: parsed.scheme.to_string(),
+    })
 }
 
-fn parse_git_url(url: &str) -> SealedResult<git_url_parse::GitUrl> {
-    let parsed = git_url_parse::GitUrl::parse(url)?;
+// The name of the repository, which is also the Docker repository its images are tagged in.
+pub fn parse_repo_name(url: &str) -> SealedResult<String> {
+    Ok(parse_repo_metadata(url)?.name)
+}
+
+// Where a clone of the repository lives relative to the working directory: the host, the owner,
+// and the name (e.g., `github.com/encom/grid`), so same-named repositories don't collide. Parts
+// which the URL doesn't have (e.g., the host of a `file://` URL) are left out.
+pub fn parse_repo_path(url: &str) -> SealedResult<PathBuf> {
+    let metadata = parse_repo_metadata(url)?;
+    Ok([metadata.host, metadata.owner, Some(metadata.name)]
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty() && part != "." && part != "..")
+        .collect())
+}
+
+// Parse a URL into its canonical form, so every way of writing the same repository (ssh, https,
+// scp-style, with or without `.git`, in any case) ends up with the same image and clone. Hosts are
+// case insensitive and Docker repositories have to be lowercase, so both are lowercased.
+fn parse_git_url(url: &str) -> SealedResult<GitUrl> {
+    let mut parsed = GitUrl::parse(url)?;
+    parsed.host = parsed.host.map(|host| host.to_lowercase());
+    let name = parsed.name.to_lowercase();
+    parsed.name = name.strip_suffix(".git").unwrap_or(&name).to_owned();
+
+    if !is_docker_repository_component(&parsed.name) {
+        return Err(GitUrlParseError::UnexpectedFormat.into());
+    }
     Ok(parsed)
 }
 
+// Whether `name` can be a component of a Docker repository: lowercase letters and digits, separated
+// by a single `.` or `_`, a double `__`, or any number of `-`.
+fn is_docker_repository_component(name: &str) -> bool {
+    let mut separator = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            let valid = match separator.as_str() {
+                "" => true,
+                "." | "_" | "__" => i > separator.len(),
+                dashes => i > dashes.len() && dashes.chars().all(|c| c == '-'),
+            };
+            if !valid {
+                return false;
+            }
+            separator.clear();
+        } else if matches!(c, '.' | '_' | '-') {
+            separator.push(c);
+        } else {
+            return false;
+        }
+    }
+    !name.is_empty() && separator.is_empty()
+}
+
 // pub async fn clone_repository(repo_url: &str, target_dir: &PathBuf) -> SealedResult<()> {
 //     let output = Command::new("git")
 //         .arg("clone")
@@ -66,3 +134,89 @@ fn parse_git_url(url: &str) -> SealedResult<git_url_parse::GitUrl> {
 //     Java,
 //     Rust,
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_path() {
+        assert_eq!(
+            parse_repo_path("git@github.com:encom/grid.git").unwrap(),
+            PathBuf::from("github.com/encom/grid")
+        );
+        assert_eq!(
+            parse_repo_path("https://gitlab.com/flynn/grid").unwrap(),
+            PathBuf::from("gitlab.com/flynn/grid")
+        );
+        assert_eq!(
+            parse_repo_name("https://gitlab.com/flynn/grid").unwrap(),
+            "grid"
+        );
+    }
+
+    #[test]
+    fn test_parse_repo_metadata() {
+        let metadata = |host: &str, owner: &str, name: &str, protocol: &str| RepoMetadata {
+            host: Some(host.to_owned()),
+            owner: Some(owner.to_owned()),
+            name: name.to_owned(),
+            protocol: protocol.to_owned(),
+        };
+
+        assert_eq!(
+            parse_repo_metadata("git@github.com:encom/grid.git").unwrap(),
+            metadata("github.com", "encom", "grid", "ssh"),
+        );
+        assert_eq!(
+            parse_repo_metadata("https://github.com/encom/grid").unwrap(),
+            metadata("github.com", "encom", "grid", "https"),
+        );
+        assert_eq!(
+            parse_repo_metadata("https://gitlab.encom.internal/flynn/tron.git").unwrap(),
+            metadata("gitlab.encom.internal", "flynn", "tron", "https"),
+        );
+        assert!(matches!(
+            parse_repo_metadata("https://github.com/encom/grid\0"),
+            Err(crate::error::SealedError::GitUrlParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_urls_normalize_to_the_same_repo() {
+        let urls = [
+            "ssh://git@GitHub.com/encom/Grid.git",
+            "https://github.com/encom/grid",
+            "https://GITHUB.COM/encom/grid.git",
+            "git@github.com:encom/GRID.git",
+        ];
+        for url in urls {
+            assert_eq!(parse_repo_name(url).unwrap(), "grid", "{url}");
+            assert_eq!(
+                parse_repo_path(url).unwrap(),
+                PathBuf::from("github.com/encom/grid"),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_docker_repository() {
+        for url in [
+            "https://github.com/encom/grid+plus",
+            "https://github.com/encom/-grid",
+            "git@github.com:encom/grid..old.git",
+        ] {
+            assert!(matches!(
+                parse_repo_name(url),
+                Err(crate::error::SealedError::GitUrlParseError(_))
+            ));
+        }
+        for name in ["grid", "grid-2", "grid--2", "grid__2", "grid.v2", "g"] {
+            assert!(is_docker_repository_component(name), "{name}");
+        }
+        for name in ["", "grid_", "grid___2", "grid._2", "Grid"] {
+            assert!(!is_docker_repository_component(name), "{name}");
+        }
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(unused)]
 pub mod cache;
 pub mod command;
+pub mod dockerignore;
+pub mod env_file;
 
 pub mod format;
 pub mod fs_utils;
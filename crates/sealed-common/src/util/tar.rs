@@ -1,19 +1,26 @@
 use {
-    super::cache::{self, CryptoHash},
+    super::{
+        cache::{self, CryptoHash, HashAlgorithm, HashManifest},
+        dockerignore::DockerIgnore,
+    },
     crate::{
         error::{SealedError, SealedResult},
         util::format::CodeStr,
     },
+    flate2::write::GzEncoder,
     sealed_ui::spin,
+    serde::{Deserialize, Serialize},
     std::{
-        collections::HashSet,
+        collections::{HashMap, HashSet},
         fs::{read_link, symlink_metadata, File, Metadata},
-        io::{empty, Read, Seek, SeekFrom, Write},
-        path::Path,
+        io::{self, empty, Read, Seek, SeekFrom, Write},
+        num::NonZeroUsize,
+        path::{Path, PathBuf},
         sync::{
             atomic::{AtomicBool, Ordering},
             Arc,
         },
+        thread::available_parallelism,
     },
     tar::{Builder, EntryType, Header},
     typed_path::{TryAsRef, UnixPath, UnixPathBuf},
@@ -32,17 +39,22 @@ use {
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
+// The mode to give a file or directory in the archive. The owner's permissions are kept, but they
+// are granted to everyone, since the entries are owned by root and the task usually runs as
+// another user.
 #[cfg(unix)]
-fn is_file_executable(metadata: &Metadata) -> bool {
-    // Determine if the file has the executable bit set.
-    let mode = metadata.permissions().mode();
-    mode & 0o1 > 0 || mode & 0o10 > 0 || mode & 0o100 > 0
+fn archive_mode(metadata: &Metadata) -> u32 {
+    let owner = (metadata.permissions().mode() >> 6) & 0o7;
+    owner * 0o111
 }
 
 #[cfg(windows)]
-fn is_file_executable(_metadata: &Metadata) -> bool {
-    // Every file on Windows is executable.
-    true
+fn archive_mode(metadata: &Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o555
+    } else {
+        0o777
+    }
 }
 
 // Tar archives must contain only relative paths. For our purposes, the paths will be relative to
@@ -89,12 +101,12 @@ fn add_file<R: Read, W: Write>(
     path_rcr: &UnixPath,
     data: R,
     size: u64,
-    executable: bool,
+    mode: u32,
 ) -> SealedResult<()> {
     // Construct a tar header for this entry.
     let mut header = Header::new_gnu();
     header.set_entry_type(EntryType::Regular);
-    header.set_mode(if executable { 0o777 } else { 0o666 });
+    header.set_mode(mode);
     header.set_size(size);
 
     // Add the entry to the archive.
@@ -169,7 +181,11 @@ fn add_symlink<W: Write>(
 }
 
 // Add a directory to a tar archive.
-fn add_directory<W: Write>(builder: &mut Builder<W>, path_rcr: &UnixPath) -> SealedResult<()> {
+fn add_directory<W: Write>(
+    builder: &mut Builder<W>,
+    path_rcr: &UnixPath,
+    mode: u32,
+) -> SealedResult<()> {
     // If the path has no components, there's nothing to do. The root directory will already exist.
     // Without this check, we could encounter the following error: `paths in archives must have at
     // least one component when setting path for`.
@@ -180,7 +196,7 @@ fn add_directory<W: Write>(builder: &mut Builder<W>, path_rcr: &UnixPath) -> Sea
     // Construct a tar header for this entry.
     let mut header = Header::new_gnu();
     header.set_entry_type(EntryType::Directory);
-    header.set_mode(0o777);
+    header.set_mode(mode);
     header.set_size(0);
 
     // Add the entry to the archive.
@@ -206,7 +222,9 @@ fn add_directory<W: Write>(builder: &mut Builder<W>, path_rcr: &UnixPath) -> Sea
     Ok(())
 }
 
-// Add a file, symlink, or directory to a tar archive.
+// Add a file, symlink, or directory to a tar archive. For files, `file_hash` is the hash of the
+// contents if it has already been computed.
+#[allow(clippy::too_many_arguments)]
 fn add_path<W: Write>(
     builder: &mut Builder<W>,
     content_hashes: &mut Vec<String>,
@@ -215,6 +233,7 @@ fn add_path<W: Write>(
     path_cd: &Path,
     path_rcr: &UnixPath,
     metadata: &Metadata,
+    file_hash: Option<&str>,
 ) -> SealedResult<()> {
     // Check if this path should be added.
     if !can_add_path(visited_paths_rcr, excluded_input_paths_rcr, path_rcr) {
@@ -226,15 +245,13 @@ fn add_path<W: Write>(
     if let Some(parent_rcr) = path_rcr.parent() {
         for ancestor_rcr in parent_rcr.ancestors() {
             if can_add_path(visited_paths_rcr, excluded_input_paths_rcr, ancestor_rcr) {
-                add_directory(builder, ancestor_rcr)?;
+                add_directory(builder, ancestor_rcr, 0o777)?;
             }
         }
     }
 
     // Check the type of the entry.
     if metadata.file_type().is_file() {
-        let executable = is_file_executable(metadata);
-
         // It's a file. Open it so we can compute the hash of its contents and add it to the
         // archive.
         let mut file = File::open(path_cd).map_err(|error| {
@@ -247,25 +264,35 @@ fn add_path<W: Write>(
             )
         })?;
 
-        // Compute the hash of the file contents and metadata.
+        // Compute the hash of the file contents, unless that has already been done.
+        let file_hash = if let Some(file_hash) = file_hash {
+            file_hash.to_owned()
+        } else {
+            let file_hash = cache::hash_read(&mut file)?;
+
+            // Jump back to the beginning of the file so the tar builder can read it.
+            file.seek(SeekFrom::Start(0)).map_err(|error| {
+                SealedError::System(
+                    format!(
+                        "Unable to seek file {}.",
+                        path_cd.to_string_lossy().code_str(),
+                    ),
+                    Some(Box::new(error)),
+                )
+            })?;
+
+            file_hash
+        };
+
+        // Combine the hash of the file contents with the mode it's archived with.
+        let mode = archive_mode(metadata);
         content_hashes.push(cache::combine(
-            &cache::combine(&path_rcr.crypto_hash(), &cache::hash_read(&mut file)?),
-            if executable { "+x" } else { "-x" },
+            &cache::combine(&path_rcr.crypto_hash(), &file_hash),
+            &format!("{mode:o}"),
         ));
 
-        // Jump back to the beginning of the file so the tar builder can read it.
-        file.seek(SeekFrom::Start(0)).map_err(|error| {
-            SealedError::System(
-                format!(
-                    "Unable to seek file {}.",
-                    path_cd.to_string_lossy().code_str(),
-                ),
-                Some(Box::new(error)),
-            )
-        })?;
-
         // Add the file to the archive and return.
-        add_file(builder, path_rcr, file, metadata.len(), executable)
+        add_file(builder, path_rcr, file, metadata.len(), mode)
     } else if metadata.file_type().is_symlink() {
         // It's a symlink. Read the target path.
         let target_path_std = read_link(path_cd).map_err(|error| {
@@ -290,11 +317,15 @@ fn add_path<W: Write>(
         // Add the symlink to the archive.
         add_symlink(builder, path_rcr, target_path)
     } else if metadata.file_type().is_dir() {
-        // It's a directory. Only its name is relevant for the cache key.
-        content_hashes.push(path_rcr.crypto_hash());
+        // It's a directory. Its name and the mode it's archived with are relevant for the cache key.
+        let mode = archive_mode(metadata);
+        content_hashes.push(cache::combine(
+            &path_rcr.crypto_hash(),
+            &format!("{mode:o}"),
+        ));
 
         // Add the directory to the archive.
-        add_directory(builder, path_rcr)
+        add_directory(builder, path_rcr, mode)
     } else {
         Err(SealedError::FailedToRunUserCommand(
             format!(
@@ -306,9 +337,15 @@ fn add_path<W: Write>(
     }
 }
 
-// Construct a tar archive and return a hash of its contents. This function does not follow symbolic
-// links.
-#[allow(clippy::similar_names, clippy::too_many_lines)]
+// Construct a tar archive and return a hash of its contents. Paths matched by `.sealedignore` in the
+// source directory are left out, along with `excluded_input_paths`, so they don't affect the hash
+// either. This function does not follow symbolic links. With a `manifest`, files which haven't
+// changed since it was last updated aren't hashed again, and it's updated with the new hashes.
+#[allow(
+    clippy::similar_names,
+    clippy::too_many_arguments,
+    clippy::too_many_lines
+)]
 pub fn create<W: Write>(
     spinner_message: &str,
     writer: W,
@@ -316,6 +353,8 @@ pub fn create<W: Write>(
     excluded_input_paths_rsd: &[UnixPathBuf],
     source_dir_cd: &Path,
     destination_dir_acr: &UnixPath,
+    manifest: Option<&mut HashManifest>,
+    algorithm: HashAlgorithm,
     interrupted: &Arc<AtomicBool>,
 ) -> SealedResult<(W, String)> {
     // Render a spinner animation in the terminal.
@@ -334,7 +373,7 @@ pub fn create<W: Write>(
     let mut builder = Builder::new(writer);
 
     // Add `destination_dir_acr` to the archive.
-    add_directory(&mut builder, strip_root_rcr(destination_dir_acr))?;
+    add_directory(&mut builder, strip_root_rcr(destination_dir_acr), 0o777)?;
     visited_paths_rcr.insert(UnixPathBuf::new());
 
     // Convert the `excluded_input_paths` to be relative to the container filesystem root.
@@ -345,7 +384,14 @@ pub fn create<W: Write>(
         })
         .collect::<Vec<_>>();
 
-    // Add each path to the archive.
+    // Read the project-wide exclusions.
+    let ignore = DockerIgnore::from_sealedignore(source_dir_cd)?;
+
+    // The paths to add to the archive, in order. They are collected first so the contents of the
+    // files can be hashed in parallel before the archive is written.
+    let mut entries: Vec<(PathBuf, UnixPathBuf, Metadata)> = vec![];
+
+    // Collect the paths.
     for input_path_rsd in input_paths_rsd {
         // The original `input_path` is relative to `source_dir_cd`. Here we make it relative to the
         // current working directory instead.
@@ -359,6 +405,15 @@ pub fn create<W: Write>(
             )
         })?);
 
+        // Skip the path if it's ignored by `.sealedignore`.
+        if ignore.is_ignored(
+            input_path_cd
+                .strip_prefix(source_dir_cd)
+                .unwrap_or(&input_path_cd),
+        ) {
+            continue;
+        }
+
         // Fetch filesystem metadata for `input_path`.
         let input_path_metadata = symlink_metadata(&input_path_cd).map_err(|error| {
             SealedError::System(
@@ -430,6 +485,15 @@ pub fn create<W: Write>(
                     )
                 })?;
 
+                // Skip paths which are ignored by `.sealedignore`, including everything inside
+                // ignored directories.
+                if ignore.is_ignored(entry_path_rsd) {
+                    if entry_metadata.is_dir() {
+                        iterator.skip_current_dir();
+                    }
+                    continue;
+                }
+
                 // Skip descending into directories which are denied by `excluded_input_paths`.
                 // This is merely an optimization, since `add_path` would otherwise skip the
                 // contents of the directory anyway.
@@ -440,35 +504,58 @@ pub fn create<W: Write>(
                     continue;
                 }
 
-                // Add the path to the archive.
-                add_path(
-                    &mut builder,
-                    &mut content_hashes,
-                    &mut visited_paths_rcr,
-                    &excluded_input_paths_rcr,
-                    entry.path(),
-                    entry_path_rcr,
-                    &entry_metadata,
-                )?;
+                entries.push((
+                    entry.path().to_owned(),
+                    entry_path_rcr.to_owned(),
+                    entry_metadata,
+                ));
             }
         } else {
             // Compute the path relative to the container filesystem root.
             let input_path_acr = destination_dir_acr.join(input_path_rsd);
             let input_path_rcr = strip_root_rcr(&input_path_acr);
 
-            // It's not a directory, so hopefully it's a file or symlink. Add it to the archive.
-            add_path(
-                &mut builder,
-                &mut content_hashes,
-                &mut visited_paths_rcr,
-                &excluded_input_paths_rcr,
-                &input_path_cd,
-                input_path_rcr,
-                &input_path_metadata,
-            )?;
+            // It's not a directory, so hopefully it's a file or symlink.
+            entries.push((
+                input_path_cd,
+                input_path_rcr.to_owned(),
+                input_path_metadata,
+            ));
         }
     }
 
+    // Hash the contents of the files in parallel. The hashes are combined in sorted order below,
+    // so the result is the same as hashing them one at a time.
+    let files = entries
+        .iter()
+        .filter(|(_, path_rcr, metadata)| {
+            metadata.file_type().is_file() && !path_excluded(&excluded_input_paths_rcr, path_rcr)
+        })
+        .map(|(path_cd, _, _)| path_cd.clone())
+        .collect::<Vec<_>>();
+    let threads = available_parallelism().map_or(1, NonZeroUsize::get);
+    let hashes = match manifest {
+        Some(manifest) => manifest.hash_files(&files, algorithm, threads, interrupted)?,
+        None => cache::hash_files(&files, algorithm, threads, interrupted)?,
+    };
+    let file_hashes = files.iter().cloned().zip(hashes).collect::<HashMap<_, _>>();
+
+    // Add each path to the archive in sorted order, so the archive doesn't depend on the order in
+    // which the filesystem lists directories. Parents sort before their contents.
+    entries.sort_by(|(_, a_rcr, _), (_, b_rcr, _)| a_rcr.cmp(b_rcr));
+    for (path_cd, path_rcr, metadata) in &entries {
+        add_path(
+            &mut builder,
+            &mut content_hashes,
+            &mut visited_paths_rcr,
+            &excluded_input_paths_rcr,
+            path_cd,
+            path_rcr,
+            metadata,
+            file_hashes.get(path_cd).map(String::as_str),
+        )?;
+    }
+
     // Sort the file hashes to ensure the directory traversal order doesn't matter.
     content_hashes.sort();
 
@@ -484,3 +571,472 @@ pub fn create<W: Write>(
     // Return the tar file and the hash of its contents.
     Ok((builder, content_hashes))
 }
+
+// How the archive sent to Docker is compressed. Compression costs CPU time, but can make copying
+// files to a remote Docker host much faster. Only formats Docker extracts itself are offered, so
+// the archive can be streamed to it as is.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+// Compresses an archive as it's written. `finish` must be called to write the trailer.
+pub enum Compressor<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+}
+
+impl<W: Write> Compressor<W> {
+    pub fn new(writer: W, compression: Compression) -> SealedResult<Self> {
+        Ok(match compression {
+            Compression::None => Compressor::None(writer),
+            Compression::Gzip => {
+                Compressor::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+        })
+    }
+
+    // Finish compressing and return the underlying writer.
+    pub fn finish(self) -> SealedResult<W> {
+        match self {
+            Compressor::None(writer) => Ok(writer),
+            Compressor::Gzip(encoder) => encoder.finish(),
+        }
+        .map_err(|error| {
+            SealedError::System(
+                "Unable to finish compressing the tar archive.".to_owned(),
+                Some(Box::new(error)),
+            )
+        })
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::None(writer) => writer.write(buf),
+            Compressor::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::None(writer) => writer.flush(),
+            Compressor::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{create, Compression, Compressor},
+        crate::util::cache::{self, CryptoHash, HashAlgorithm, HashManifest},
+        flate2::read::GzDecoder,
+        std::{
+            fs::{create_dir, create_dir_all, write, File},
+            io::Read,
+            sync::{atomic::AtomicBool, Arc},
+            time::{Duration, SystemTime},
+        },
+        tar::Archive,
+        typed_path::{UnixPath, UnixPathBuf},
+    };
+
+    // Archive `input_paths` from `source_dir` and return the paths in the archive and the hash.
+    fn archive(source_dir: &std::path::Path, input_paths: &[&str]) -> (Vec<String>, String) {
+        let (data, hash) = create(
+            "Archiving\u{2026}",
+            vec![],
+            &input_paths
+                .iter()
+                .map(|path| UnixPathBuf::from(*path))
+                .collect::<Vec<_>>(),
+            &[],
+            source_dir,
+            UnixPath::new("/scratch"),
+            None,
+            HashAlgorithm::Sha256,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let paths = Archive::new(data.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .trim_end_matches('/')
+                    .to_owned()
+            })
+            .collect();
+        (paths, hash)
+    }
+
+    #[test]
+    fn create_hash_matches_serial_hash() {
+        let source_dir = tempfile::tempdir().unwrap();
+        create_dir(source_dir.path().join("src")).unwrap();
+
+        // Compute the hash the way it was computed before hashing was parallelized: one entry per
+        // path, sorted, and then combined.
+        let mode = |path: &std::path::Path| {
+            format!(
+                "{:o}",
+                super::archive_mode(&std::fs::metadata(path).unwrap())
+            )
+        };
+        let mut content_hashes = vec![cache::combine(
+            &UnixPath::new("scratch/src").crypto_hash(),
+            &mode(&source_dir.path().join("src")),
+        )];
+        for i in 0..200 {
+            let contents = format!("fn main() {{ println!(\"{i}\"); }}");
+            let path = source_dir.path().join(format!("src/{i}.rs"));
+            write(&path, &contents).unwrap();
+            content_hashes.push(cache::combine(
+                &cache::combine(
+                    &UnixPathBuf::from(format!("scratch/src/{i}.rs")).crypto_hash(),
+                    &cache::hash_read(&mut contents.as_bytes()).unwrap(),
+                ),
+                &mode(&path),
+            ));
+        }
+        content_hashes.sort();
+        let serial_hash = content_hashes
+            .iter()
+            .fold(String::new(), |acc, x| cache::combine(&acc, x));
+
+        let (_, hash) = create(
+            "Archiving\u{2026}",
+            vec![],
+            &[UnixPathBuf::from("src")],
+            &[],
+            source_dir.path(),
+            UnixPath::new("/scratch"),
+            None,
+            HashAlgorithm::Sha256,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(hash, serial_hash);
+    }
+
+    #[test]
+    fn create_respects_sealedignore() {
+        let source_dir = tempfile::tempdir().unwrap();
+        create_dir_all(source_dir.path().join("target/debug")).unwrap();
+        create_dir(source_dir.path().join("src")).unwrap();
+        write(source_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        write(source_dir.path().join("target/debug/app"), "binary").unwrap();
+        write(source_dir.path().join(".sealedignore"), "target/\n").unwrap();
+
+        let (paths, hash) = archive(source_dir.path(), &[".", "target/debug/app"]);
+
+        assert!(paths.contains(&"scratch/src/main.rs".to_owned()));
+        assert!(paths.iter().all(|path| !path.contains("target")));
+
+        // Changing an ignored file doesn't change the hash.
+        write(source_dir.path().join("target/debug/app"), "new binary").unwrap();
+        assert_eq!(archive(source_dir.path(), &["."]).1, hash);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let source_dir = tempfile::tempdir().unwrap();
+        create_dir(source_dir.path().join("src")).unwrap();
+        for i in 0..50 {
+            write(
+                source_dir.path().join(format!("src/{i}.rs")),
+                format!("fn main() {{ println!(\"{i}\"); }}\n").repeat(100),
+            )
+            .unwrap();
+        }
+
+        // The files in an archive, with their contents
+        let files = |archive: &mut dyn Read| {
+            let mut files = Archive::new(archive)
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let path = entry.path().unwrap().to_string_lossy().into_owned();
+                    let mut contents = String::new();
+                    entry.read_to_string(&mut contents).unwrap();
+                    (path, contents)
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+
+        let archive = |compression: Compression| {
+            let (compressor, hash) = create(
+                "Archiving\u{2026}",
+                Compressor::new(vec![], compression).unwrap(),
+                &[UnixPathBuf::from("src")],
+                &[],
+                source_dir.path(),
+                UnixPath::new("/scratch"),
+                None,
+                HashAlgorithm::Sha256,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap();
+            (compressor.finish().unwrap(), hash)
+        };
+
+        let (uncompressed, uncompressed_hash) = archive(Compression::None);
+        let expected = files(&mut uncompressed.as_slice());
+
+        let (compressed, hash) = archive(Compression::Gzip);
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+        assert!(compressed.len() < uncompressed.len());
+
+        // Compression doesn't change the cache key.
+        assert_eq!(hash, uncompressed_hash);
+
+        assert_eq!(files(&mut GzDecoder::new(compressed.as_slice())), expected);
+    }
+
+    #[test]
+    fn create_with_manifest_matches_full_hash() {
+        let source_dir = tempfile::tempdir().unwrap();
+        create_dir(source_dir.path().join("src")).unwrap();
+        let write_old = |name: &str, contents: &str| {
+            let path = source_dir.path().join(name);
+            write(&path, contents).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(60))
+                .unwrap();
+        };
+        write_old("src/main.rs", "fn main() {}");
+        write_old("src/lib.rs", "pub mod grid;");
+
+        let hash = |manifest: Option<&mut HashManifest>| {
+            create(
+                "Archiving\u{2026}",
+                vec![],
+                &[UnixPathBuf::from("src")],
+                &[],
+                source_dir.path(),
+                UnixPath::new("/scratch"),
+                manifest,
+                HashAlgorithm::Sha256,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap()
+            .1
+        };
+
+        let mut manifest = HashManifest::default();
+        let full = hash(None);
+        assert_eq!(hash(Some(&mut manifest)), full);
+        assert_eq!(hash(Some(&mut manifest)), full);
+
+        write_old("src/lib.rs", "pub mod disc;");
+        let modified = hash(Some(&mut manifest));
+        assert_ne!(modified, full);
+        assert_eq!(modified, hash(None));
+    }
+
+    // Archive `include` with paths relative to `root`, leaving out `exclude`.
+    fn build_tar(
+        root: &std::path::Path,
+        include: &[UnixPathBuf],
+        exclude: &[UnixPathBuf],
+        writer: Vec<u8>,
+    ) -> crate::error::SealedResult<(Vec<u8>, String)> {
+        create(
+            "Archiving files\u{2026}",
+            writer,
+            include,
+            exclude,
+            root,
+            UnixPath::new("/"),
+            None,
+            HashAlgorithm::Sha256,
+            &Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    // The paths and modes of the entries in an archive, in order
+    fn entries(data: &[u8]) -> Vec<(String, u32)> {
+        Archive::new(data)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry
+                        .path()
+                        .unwrap()
+                        .to_string_lossy()
+                        .trim_end_matches('/')
+                        .to_owned(),
+                    entry.header().mode().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn create_is_sorted_and_deterministic() {
+        let root = tempfile::tempdir().unwrap();
+        create_dir_all(root.path().join("src/b")).unwrap();
+        for path in ["src/z.rs", "src/b/main.rs", "src/a.rs", "README.md"] {
+            write(root.path().join(path), path).unwrap();
+        }
+
+        let include = [UnixPathBuf::from("src"), UnixPathBuf::from("README.md")];
+        let (first, first_hash) = build_tar(root.path(), &include, &[], vec![]).unwrap();
+        let paths = entries(&first)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "README.md",
+                "src",
+                "src/a.rs",
+                "src/b",
+                "src/b/main.rs",
+                "src/z.rs"
+            ],
+        );
+
+        // Listing the include paths in another order makes no difference.
+        let (second, second_hash) = build_tar(
+            root.path(),
+            &[include[1].clone(), include[0].clone()],
+            &[],
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first_hash, second_hash);
+    }
+
+    #[test]
+    fn create_leaves_out_excluded_paths() {
+        let root = tempfile::tempdir().unwrap();
+        create_dir_all(root.path().join("src/generated")).unwrap();
+        create_dir_all(root.path().join("logs")).unwrap();
+        write(root.path().join("src/main.rs"), "fn main() {}").unwrap();
+        write(root.path().join("src/generated/api.rs"), "// generated").unwrap();
+        write(root.path().join("logs/debug.log"), "noise").unwrap();
+        write(root.path().join(".sealedignore"), "logs/\n").unwrap();
+
+        let (data, _) = build_tar(
+            root.path(),
+            &[UnixPathBuf::from(".")],
+            &[UnixPathBuf::from("src/generated")],
+            vec![],
+        )
+        .unwrap();
+
+        let paths = entries(&data)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&"src/main.rs".to_owned()));
+        assert!(paths
+            .iter()
+            .all(|path| !path.contains("generated") && !path.contains("logs")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_keeps_permissions_and_symlinks() {
+        use std::{
+            fs::{set_permissions, Permissions},
+            os::unix::fs::{symlink, PermissionsExt},
+        };
+
+        let root = tempfile::tempdir().unwrap();
+        for (name, mode) in [
+            ("run.sh", 0o755),
+            ("notes.txt", 0o644),
+            ("frozen.txt", 0o444),
+        ] {
+            write(root.path().join(name), name).unwrap();
+            set_permissions(root.path().join(name), Permissions::from_mode(mode)).unwrap();
+        }
+        symlink("run.sh", root.path().join("start")).unwrap();
+
+        let (data, _) = build_tar(
+            root.path(),
+            &["run.sh", "notes.txt", "frozen.txt", "start"].map(UnixPathBuf::from),
+            &[],
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries(&data),
+            vec![
+                ("frozen.txt".to_owned(), 0o444),
+                ("notes.txt".to_owned(), 0o666),
+                ("run.sh".to_owned(), 0o777),
+                ("start".to_owned(), 0o777),
+            ],
+        );
+        let mut archive = Archive::new(data.as_slice());
+        let link = archive
+            .entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|entry| entry.header().entry_type().is_symlink())
+            .unwrap();
+        assert_eq!(link.link_name().unwrap().unwrap().to_str(), Some("run.sh"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_hash_follows_archived_modes() {
+        use std::{
+            fs::{set_permissions, Permissions},
+            os::unix::fs::PermissionsExt,
+        };
+
+        let root = tempfile::tempdir().unwrap();
+        create_dir(root.path().join("src")).unwrap();
+        write(root.path().join("src/notes.txt"), "notes").unwrap();
+        let hash = || {
+            build_tar(root.path(), &[UnixPathBuf::from("src")], &[], vec![])
+                .unwrap()
+                .1
+        };
+        let set_mode = |path: &str, mode: u32| {
+            set_permissions(root.path().join(path), Permissions::from_mode(mode)).unwrap();
+        };
+
+        set_mode("src", 0o755);
+        set_mode("src/notes.txt", 0o644);
+        let writable = hash();
+
+        // Only the owner's permissions are archived, so the others don't affect the hash.
+        set_mode("src/notes.txt", 0o600);
+        assert_eq!(hash(), writable);
+
+        // Making a file or directory read-only changes what's archived, and so the hash.
+        set_mode("src/notes.txt", 0o444);
+        let read_only_file = hash();
+        assert_ne!(read_only_file, writable);
+        set_mode("src", 0o555);
+        assert_ne!(hash(), read_only_file);
+        set_mode("src", 0o755);
+    }
+}
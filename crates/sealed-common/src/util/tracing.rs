@@ -1,18 +1,191 @@
-use std::str::FromStr;
-use tracing::{level_filters::LevelFilter, Level};
+use std::path::{Path, PathBuf};
 
-pub async fn setup_tracing(level: Option<LevelFilter>) {
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{level_filters::LevelFilter, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt, layer::SubscriberExt, registry::LookupSpan, EnvFilter, Layer};
+
+// How log lines are formatted. `full` is the subscriber's single-line default, and JSON is meant
+// for log aggregation in production.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Full,
+    Pretty,
+    Json,
+    Compact,
+}
+
+// When a log file is started afresh. Old files are deleted once there are more than `max_files`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+}
+
+// A file the logs are written to as well as the console. Rotated files get the date appended to
+// their name.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LogFile {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    #[serde(default = "default_max_log_files")]
+    pub max_files: usize,
+}
+
+fn default_max_log_files() -> usize {
+    7
+}
+
+impl LogFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            rotation: LogRotation::default(),
+            max_files: default_max_log_files(),
+        }
+    }
+
+    fn appender(&self) -> anyhow::Result<RollingFileAppender> {
+        let name = self.path.file_name().context("the path has no file name")?;
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let rotation = match self.rotation {
+            LogRotation::Never => Rotation::NEVER,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+        };
+
+        Ok(RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(name.to_string_lossy())
+            .max_log_files(self.max_files.max(1))
+            .build(directory)?)
+    }
+}
+
+// The settings hold a `log` level, which the subscriber doesn't understand.
+pub fn level_filter(level: log::LevelFilter) -> LevelFilter {
+    match level {
+        log::LevelFilter::Off => LevelFilter::OFF,
+        log::LevelFilter::Error => LevelFilter::ERROR,
+        log::LevelFilter::Warn => LevelFilter::WARN,
+        log::LevelFilter::Info => LevelFilter::INFO,
+        log::LevelFilter::Debug => LevelFilter::DEBUG,
+        log::LevelFilter::Trace => LevelFilter::TRACE,
+    }
+}
+
+// `level` is only the default: directives in `RUST_LOG` (e.g., `sealed_server=debug`) override it.
+fn env_filter(level: LevelFilter) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy()
+}
+
+fn console_layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Full => fmt::layer().boxed(),
+        LogFormat::Pretty => fmt::layer().pretty().boxed(),
+        LogFormat::Json => fmt::layer().json().boxed(),
+        LogFormat::Compact => fmt::layer().compact().boxed(),
+    }
+}
+
+// Logs to the console in `format` and, if there's a `log_file`, to it too. A log file which can't
+// be opened is reported but doesn't stop the logs going to the console.
+pub fn subscriber(
+    level: LevelFilter,
+    format: LogFormat,
+    log_file: Option<&LogFile>,
+) -> Box<dyn Subscriber + Send + Sync> {
+    let file_layer = log_file.and_then(|log_file| match log_file.appender() {
+        // Escape codes are only useful on a terminal
+        Ok(appender) => Some(fmt::layer().with_ansi(false).with_writer(appender)),
+        Err(e) => {
+            eprintln!("Unable to log to {}: {:#}", log_file.path.display(), e);
+            None
+        }
+    });
+
+    Box::new(
+        tracing_subscriber::registry()
+            .with(env_filter(level))
+            .with(console_layer(format))
+            .with(file_layer),
+    )
+}
+
+pub async fn setup_tracing(
+    level: Option<LevelFilter>,
+    format: LogFormat,
+    log_file: Option<&LogFile>,
+) {
     let level = level.unwrap_or(LevelFilter::INFO);
-    let subscriber = tracing_subscriber::fmt::Subscriber::builder()
-        .with_max_level(level)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set global subscriber");
+    tracing::subscriber::set_global_default(subscriber(level, format, log_file))
+        .expect("Failed to set global subscriber");
 
     env_logger::init();
 }
 
 pub async fn init_tracing_from_env() {
-    let level = std::env::var("RUST_LOG").unwrap_or("warn".to_string());
-    let level = Level::from_str(&level).unwrap_or(Level::INFO);
-    setup_tracing(Some(LevelFilter::from(level))).await;
+    setup_tracing(Some(LevelFilter::WARN), LogFormat::default(), None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscriber_formats() {
+        for format in [
+            LogFormat::Full,
+            LogFormat::Pretty,
+            LogFormat::Json,
+            LogFormat::Compact,
+        ] {
+            tracing::subscriber::with_default(subscriber(LevelFilter::DEBUG, format, None), || {
+                tracing::info!(format = ?format, "Logging");
+            });
+        }
+    }
+
+    #[test]
+    fn test_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_file = LogFile {
+            path: dir.path().join("sealed.log"),
+            rotation: LogRotation::Never,
+            max_files: 1,
+        };
+
+        tracing::subscriber::with_default(
+            subscriber(LevelFilter::INFO, LogFormat::Full, Some(&log_file)),
+            || tracing::info!("Building the image"),
+        );
+
+        let logs = std::fs::read_to_string(&log_file.path).unwrap();
+        assert!(logs.contains("Building the image"));
+        assert!(!logs.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_log_format_setting() {
+        assert_eq!(
+            serde_yaml::from_str::<LogFormat>("json").unwrap(),
+            LogFormat::Json
+        );
+        assert_eq!(level_filter(log::LevelFilter::Debug), LevelFilter::DEBUG);
+    }
 }
@@ -14,9 +14,21 @@ pub enum SealedDatabaseError {
     #[error("Interrupted")]
     Interrupted,
     #[error("Failed to run command: {0} {1:?}")]
-    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error>>),
+    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error + Send + Sync>>),
     #[error("System error: {0} {1:?}")]
-    System(String, Option<Box<dyn std::error::Error>>),
+    System(String, Option<Box<dyn std::error::Error + Send + Sync>>),
+}
+
+impl SealedDatabaseError {
+    // The message alone, without the error kind or cause. This is how validation problems are
+    // shown to the user.
+    pub fn message(&self) -> String {
+        match self {
+            SealedDatabaseError::FailedToRunUserCommand(message, _)
+            | SealedDatabaseError::System(message, _) => message.clone(),
+            error => error.to_string(),
+        }
+    }
 }
 
 impl From<SealedDatabaseError> for SealedError {
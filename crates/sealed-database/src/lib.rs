@@ -6,7 +6,7 @@ pub mod schema;
 
 pub use database::AppDatabase;
 
-pub type DateWithTimeZone = chrono::NaiveDateTime;
+pub type DateWithTimeZone = chrono::DateTime<chrono::Utc>;
 
 pub use models::*;
 pub use repos::*;
@@ -1,14 +1,16 @@
 use serde::{Deserialize, Serialize};
 
+use crate::DateWithTimeZone;
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
 #[allow(non_snake_case)]
 pub struct FpApp {
-    pub id: i32,
+    pub id: i64,
     pub name: String,
     pub description: String,
     pub app_config: Option<serde_json::Value>,
-    // pub created_at: DateWithTimeZone,
-    // pub updated_at: DateWithTimeZone,
+    pub created_at: DateWithTimeZone,
+    pub updated_at: DateWithTimeZone,
     pub repository_url: Option<String>,
     pub branch: Option<String>,
     pub image: Option<String>,
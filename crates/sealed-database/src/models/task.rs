@@ -2,25 +2,22 @@ use crate::{
     error::SealedDatabaseError,
     taskfile::{command, location, user},
 };
-use sealed_common::{
-    util::{
-        cache::{combine, CryptoHash},
-        format::CodeStr,
-    },
-    CACHE_VERSION,
+use sealed_common::util::{
+    cache::{cache_version, combine, CryptoHash, HashAlgorithm},
+    format::CodeStr,
 };
 
 use super::taskfile::TaskFile;
 
 use {
     crate::error::SealedDatabaseResult,
-    serde::{de::Error, Deserialize, Deserializer},
+    serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer},
     std::{
         collections::HashMap,
         fmt::{self, Display, Formatter},
         path::PathBuf,
     },
-    typed_path::UnixPathBuf,
+    typed_path::{UnixPath, UnixPathBuf},
 };
 
 // The default location for commands and files copied into the container
@@ -29,6 +26,12 @@ pub const DEFAULT_LOCATION: &str = "/scratch";
 // The default user for commands and files copied into the container
 pub const DEFAULT_USER: &str = "root";
 
+// The default interpreter for commands run in the container
+pub const DEFAULT_SHELL: &str = "/bin/sh";
+
+// The default number of seconds to wait before running a failed command again
+pub const DEFAULT_RETRY_DELAY: u64 = 1;
+
 // Deserializer for `UnixPathBuf`
 pub fn deserialize_unix_path_buf<'de, D>(
     deserializer: D,
@@ -74,11 +77,44 @@ where
     Ok(result)
 }
 
+// Serializer for `UnixPathBuf`
+pub fn serialize_unix_path_buf<S>(path: &UnixPathBuf, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+// Serializer for `Option<UnixPathBuf>`
+fn serialize_option_unix_path_buf<S>(
+    path: &Option<UnixPathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match path {
+        Some(path) => serializer.serialize_some(&path.to_string_lossy()),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Serializer for `Vec<UnixPathBuf>`
+fn serialize_vec_unix_path_buf<S>(paths: &[UnixPathBuf], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(paths.iter().map(|path| path.to_string_lossy()))
+}
+
 // This struct represents a path on the host and a corresponding path in the container.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MappingPath {
     pub host_path: PathBuf,
     pub container_path: UnixPathBuf,
+
+    // If `None`, the task-level `mount_readonly` should be used. Set with a `:ro` or `:rw` suffix.
+    pub readonly: Option<bool>,
 }
 
 impl Display for MappingPath {
@@ -88,7 +124,13 @@ impl Display for MappingPath {
             "{}:{}",
             self.host_path.to_string_lossy(),
             self.container_path.to_string_lossy(),
-        )
+        )?;
+
+        match self.readonly {
+            Some(true) => write!(f, ":ro"),
+            Some(false) => write!(f, ":rw"),
+            None => Ok(()),
+        }
     }
 }
 
@@ -106,6 +148,13 @@ impl<'de> serde::de::Visitor<'de> for MappingPathVisitor {
         E: serde::de::Error,
     {
         if let Some((host_path, container_path)) = v.split_once(':') {
+            let (container_path, readonly) = match container_path.split_once(':') {
+                Some((container_path, "ro")) => (container_path, Some(true)),
+                Some((container_path, "rw")) => (container_path, Some(false)),
+                Some(_) => return Err(E::custom("Illegal mount mode. Expected `ro` or `rw`.")),
+                None => (container_path, None),
+            };
+
             Ok(MappingPath {
                 host_path: host_path
                     .parse()
@@ -113,16 +162,28 @@ impl<'de> serde::de::Visitor<'de> for MappingPathVisitor {
                 container_path: container_path
                     .parse()
                     .map_err(|_| E::custom("Illegal container path."))?,
+                readonly,
             })
         } else {
             Ok(MappingPath {
                 host_path: v.parse().map_err(|_| E::custom("Illegal path."))?,
                 container_path: v.parse().map_err(|_| E::custom("Illegal path."))?,
+                readonly: None,
             })
         }
     }
 }
 
+// Serialized in the same `host_path:container_path` form it is parsed from
+impl Serialize for MappingPath {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 impl<'de> Deserialize<'de> for MappingPath {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -133,7 +194,7 @@ impl<'de> Deserialize<'de> for MappingPath {
 }
 
 // This struct represents a task.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Task {
     pub description: Option<String>,
@@ -147,6 +208,7 @@ pub struct Task {
     // - `mount_paths` is nonempty [ref:mount_paths_nand_cache]
     // - `ports` is nonempty [ref:ports_nand_cache]
     // - `extra_docker_arguments` is nonempty [ref:extra_docker_arguments_nand_cache]
+    // - `retries` is nonzero [ref:retries_nand_cache]
     #[serde(default = "default_task_cache")]
     pub cache: bool,
 
@@ -155,41 +217,65 @@ pub struct Task {
     pub environment: HashMap<String, Option<String>>,
 
     // Must be relative [ref:input_paths_relative]
-    #[serde(default, deserialize_with = "deserialize_vec_unix_path_buf")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_vec_unix_path_buf",
+        serialize_with = "serialize_vec_unix_path_buf"
+    )]
     pub input_paths: Vec<UnixPathBuf>,
 
     // Must be relative [ref:excluded_input_paths_relative]
-    #[serde(default, deserialize_with = "deserialize_vec_unix_path_buf")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_vec_unix_path_buf",
+        serialize_with = "serialize_vec_unix_path_buf"
+    )]
     pub excluded_input_paths: Vec<UnixPathBuf>,
 
     // Must be relative [ref:output_paths_relative]
-    #[serde(default, deserialize_with = "deserialize_vec_unix_path_buf")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_vec_unix_path_buf",
+        serialize_with = "serialize_vec_unix_path_buf"
+    )]
     pub output_paths: Vec<UnixPathBuf>,
 
     // Must be relative [ref:output_paths_on_failure_relative]
-    #[serde(default, deserialize_with = "deserialize_vec_unix_path_buf")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_vec_unix_path_buf",
+        serialize_with = "serialize_vec_unix_path_buf"
+    )]
     pub output_paths_on_failure: Vec<UnixPathBuf>,
 
     // Can be relative or absolute (absolute paths are allowed in order to support mounting the
     //   Docker socket, which is usually located at `/var/run/docker.sock`)
     // Must not contain `,` [ref:mount_paths_no_commas]
+    // Must not target the same container path twice [ref:mount_paths_unique] or the location
+    //   [ref:mount_paths_not_location]
     // Must be empty if `cache` is enabled [ref:mount_paths_nand_cache]
     // Can be `host_path:container_path` or a single path if `host_path` is the same as
-    //   `container_path`
+    //   `container_path`, optionally followed by `:ro` or `:rw` to override `mount_readonly`
     #[serde(default)] // [tag:default_mount_paths]
     pub mount_paths: Vec<MappingPath>,
 
+    // The default for mount paths without a `:ro` or `:rw` suffix
     #[serde(default = "default_task_mount_readonly")]
     pub mount_readonly: bool,
 
-    // Must be empty if `cache` is enabled [ref:ports_nand_cache]
+    // Must be empty if `cache` is enabled [ref:ports_nand_cache]. Each entry is passed to
+    // `--publish` and must be well-formed [ref:ports_valid].
     #[serde(default)] // [tag:default_ports]
     pub ports: Vec<String>,
 
     // If `None`, the corresponding top-level value in the TaskFile should be used. There is a
     // helper function [ref:location_helper] which implements that logic. This path must be absolute
     // [ref:task_location_absolute].
-    #[serde(default, deserialize_with = "deserialize_option_unix_path_buf")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_option_unix_path_buf",
+        serialize_with = "serialize_option_unix_path_buf"
+    )]
     pub location: Option<UnixPathBuf>,
 
     // If `None`, the corresponding top-level value in the TaskFile should be used. There is a
@@ -201,11 +287,33 @@ pub struct Task {
     #[serde(default)]
     pub command: String,
 
+    // Run as an argument vector rather than a shell command, so arguments don't need quoting. The
+    // command prefix still runs first, in the shell. Mutually exclusive with `command`
+    // [ref:command_nand_command_args].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command_args: Vec<String>,
+
     // If `None`, the corresponding top-level value in the TaskFile should be used. There is a
     // helper function [ref:command_helper] which implements that logic.
     #[serde(default)]
     pub command_prefix: Option<String>,
 
+    // The interpreter `su` uses to run the command. If `None`, `DEFAULT_SHELL` is used. There is a
+    // helper function [ref:shell_helper] which implements that logic. This path must be absolute
+    // [ref:shell_absolute].
+    #[serde(default)]
+    pub shell: Option<String>,
+
+    // How many more times to run the command if it fails. Must be zero if `cache` is enabled
+    // [ref:retries_nand_cache].
+    #[serde(default)]
+    pub retries: u32,
+
+    // The number of seconds to wait between attempts. If `None`, `DEFAULT_RETRY_DELAY` is used.
+    // There is a helper function [ref:retry_delay_helper] which implements that logic.
+    #[serde(default)]
+    pub retry_delay: Option<u64>,
+
     // Must be empty if `cache` is enabled [ref:extra_docker_arguments_nand_cache]
     #[serde(default)]
     pub extra_docker_arguments: Vec<String>,
@@ -220,80 +328,81 @@ pub fn default_task_mount_readonly() -> bool {
 }
 
 // Check that a task is valid.
-#[allow(clippy::too_many_lines)]
 pub fn check_task(name: &str, task: &Task) -> SealedDatabaseResult<()> {
+    first_violation(task_violations(name, task))
+}
+
+// Fail with the first of a list of problems, if there are any.
+pub fn first_violation(violations: Vec<SealedDatabaseError>) -> SealedDatabaseResult<()> {
+    violations.into_iter().next().map_or(Ok(()), Err)
+}
+
+fn violation(message: String) -> SealedDatabaseError {
+    SealedDatabaseError::FailedToRunUserCommand(message, None)
+}
+
+// Find every problem with a task, rather than stopping at the first one like `check_task`.
+#[allow(clippy::too_many_lines)]
+pub fn task_violations(name: &str, task: &Task) -> Vec<SealedDatabaseError> {
+    let mut violations = vec![];
+
     // Check that environment variable names don't have `=` in them [tag:env_var_equals].
     for variable in task.environment.keys() {
         if variable.contains('=') {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Environment variable {} of task {} contains {}.",
-                    variable.code_str(),
-                    name.code_str(),
-                    "=".code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Environment variable {} of task {} contains {}.",
+                variable.code_str(),
+                name.code_str(),
+                "=".code_str(),
+            )));
         }
     }
 
     // Check that `input_paths` are relative [tag:input_paths_relative].
     for path in &task.input_paths {
         if !path.is_relative() {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Task {} has an absolute {}: {}.",
-                    name.code_str(),
-                    "input_path".code_str(),
-                    path.to_string_lossy().code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Task {} has an absolute {}: {}.",
+                name.code_str(),
+                "input_path".code_str(),
+                path.to_string_lossy().code_str(),
+            )));
         }
     }
 
     // Check that `excluded_input_paths` are relative [tag:excluded_input_paths_relative].
     for path in &task.excluded_input_paths {
         if !path.is_relative() {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Task {} has an absolute {}: {}.",
-                    name.code_str(),
-                    "excluded_input_path".code_str(),
-                    path.to_string_lossy().code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Task {} has an absolute {}: {}.",
+                name.code_str(),
+                "excluded_input_path".code_str(),
+                path.to_string_lossy().code_str(),
+            )));
         }
     }
 
     // Check that `output_paths` are relative [tag:output_paths_relative].
     for path in &task.output_paths {
         if !path.is_relative() {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Task {} has an absolute path in {}: {}.",
-                    name.code_str(),
-                    "output_paths".code_str(),
-                    path.to_string_lossy().code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Task {} has an absolute path in {}: {}.",
+                name.code_str(),
+                "output_paths".code_str(),
+                path.to_string_lossy().code_str(),
+            )));
         }
     }
 
     // Check that `output_paths_on_failure` are relative [tag:output_paths_on_failure_relative].
     for path in &task.output_paths_on_failure {
         if !path.is_relative() {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Task {} has an absolute path in {}: {}.",
-                    name.code_str(),
-                    "output_paths_on_failure".code_str(),
-                    path.to_string_lossy().code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Task {} has an absolute path in {}: {}.",
+                name.code_str(),
+                "output_paths_on_failure".code_str(),
+                path.to_string_lossy().code_str(),
+            )));
         }
     }
 
@@ -303,80 +412,205 @@ pub fn check_task(name: &str, task: &Task) -> SealedDatabaseResult<()> {
         if path.container_path.to_string_lossy().contains(',')
             || path.host_path.to_string_lossy().contains(',')
         {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Mount path {} of task {} has a {}.",
-                    format!("{path}").code_str(),
-                    name.code_str(),
-                    ",".code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Mount path {} of task {} has a {}.",
+                format!("{path}").code_str(),
+                name.code_str(),
+                ",".code_str(),
+            )));
         }
     }
 
     // Check that `location` is absolute [tag:task_location_absolute].
     if let Some(location) = &task.location {
         if !location.is_absolute() {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
-                format!(
-                    "Task {} has a relative {}: {}.",
-                    name.code_str(),
-                    "location".code_str(),
-                    location.to_string_lossy().code_str(),
-                ),
-                None,
-            ));
+            violations.push(violation(format!(
+                "Task {} has a relative {}: {}.",
+                name.code_str(),
+                "location".code_str(),
+                location.to_string_lossy().code_str(),
+            )));
+        }
+    }
+
+    // Check that `shell` is absolute [tag:shell_absolute].
+    if let Some(shell) = &task.shell {
+        if !shell.starts_with('/') {
+            violations.push(violation(format!(
+                "Task {} has a relative {}: {}.",
+                name.code_str(),
+                "shell".code_str(),
+                shell.code_str(),
+            )));
         }
     }
 
     // If a task has any mount paths, then caching should be disabled [tag:mount_paths_nand_cache].
     if !task.mount_paths.is_empty() && task.cache {
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
-            format!(
-                "Task {} has {} but does not disable caching. \
+        violations.push(violation(format!(
+            "Task {} has {} but does not disable caching. \
              To fix this, set {} for this task.",
+            name.code_str(),
+            "mount_paths".code_str(),
+            "cache: false".code_str(),
+        )));
+    }
+
+    // Check that the ports are well-formed [tag:ports_valid].
+    for port in &task.ports {
+        if !is_valid_port(port) {
+            violations.push(violation(format!(
+                "Task {} has an invalid port {}. Ports must look like {}.",
                 name.code_str(),
-                "mount_paths".code_str(),
-                "cache: false".code_str(),
-            ),
-            None,
-        ));
+                port.code_str(),
+                "[host_ip:]host_port:container_port[/tcp|udp]".code_str(),
+            )));
+        }
     }
 
     // If a task exposes ports, then caching should be disabled [tag:ports_nand_cache].
     if !&task.ports.is_empty() && task.cache {
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
-            format!(
-                "Task {} exposes ports but does not disable caching. \
+        violations.push(violation(format!(
+            "Task {} exposes ports but does not disable caching. \
              To fix this, set {} for this task.",
-                name.code_str(),
-                "cache: false".code_str(),
-            ),
-            None,
-        ));
+            name.code_str(),
+            "cache: false".code_str(),
+        )));
     }
 
     // If a task has any extra Docker arguments, then caching should be disabled.
     // [tag:extra_docker_arguments_nand_cache]
     if !&task.extra_docker_arguments.is_empty() && task.cache {
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
-            format!(
-                "Task {} has extra Docker arguments but does not disable caching. \
+        violations.push(violation(format!(
+            "Task {} has extra Docker arguments but does not disable caching. \
              To fix this, set {} for this task.",
+            name.code_str(),
+            "cache: false".code_str(),
+        )));
+    }
+
+    // A task can't have both kinds of command [tag:command_nand_command_args].
+    if !task.command.is_empty() && !task.command_args.is_empty() {
+        violations.push(violation(format!(
+            "Task {} has both {} and {}.",
+            name.code_str(),
+            "command".code_str(),
+            "command_args".code_str(),
+        )));
+    }
+
+    // If a task is retried, then caching should be disabled [tag:retries_nand_cache].
+    if task.retries > 0 && task.cache {
+        violations.push(violation(format!(
+            "Task {} has {} but does not disable caching. \
+             To fix this, set {} for this task.",
+            name.code_str(),
+            "retries".code_str(),
+            "cache: false".code_str(),
+        )));
+    }
+
+    violations
+}
+
+// Check that a port entry has the form `[host_ip:][host_port]:container_port[/proto]` (or just
+// `container_port[/proto]`), with ports between 1 and 65535 and a protocol of `tcp` or `udp`. Ports
+// may be ranges such as `8000-8010`, and a host range must be as long as the container range. The
+// host port may only be left empty after a host IP (`127.0.0.1::80`), to let Docker pick one.
+fn is_valid_port(port: &str) -> bool {
+    let (mapping, protocol) = match port.split_once('/') {
+        Some((mapping, protocol)) => (mapping, Some(protocol)),
+        None => (port, None),
+    };
+    if !matches!(protocol, None | Some("tcp" | "udp")) {
+        return false;
+    }
+
+    let port_number = |number: &str| {
+        if number.chars().all(|c| c.is_ascii_digit()) {
+            number.parse::<u16>().ok().filter(|number| *number > 0)
+        } else {
+            None
+        }
+    };
+    // The length of a port or port range, if it's well-formed.
+    let range_len = |range: &str| match range.split_once('-') {
+        Some((start, end)) => match (port_number(start), port_number(end)) {
+            (Some(start), Some(end)) if start <= end => Some(end - start),
+            _ => None,
+        },
+        None => port_number(range).map(|_| 0),
+    };
+    // The host IP may be an IPv6 address, which has colons of its own.
+    let mut parts = mapping.rsplitn(3, ':');
+    let container_port = parts.next().unwrap_or_default();
+    let host_port = parts.next();
+    let host_ip = parts.next();
+
+    let Some(container_len) = range_len(container_port) else {
+        return false;
+    };
+    let valid_host_port = match host_port {
+        None => true,
+        Some("") => host_ip.is_some(),
+        Some(host_port) => range_len(host_port) == Some(container_len),
+    };
+    valid_host_port
+        && host_ip.is_none_or(|ip| {
+            ip.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<std::net::IpAddr>()
+                .is_ok()
+        })
+}
+
+// Check that the mount paths of a task don't shadow each other or the working directory, given the
+// location the task runs in. Relative container paths are resolved against the location, as they
+// are when the container is created.
+pub fn check_mount_paths(name: &str, task: &Task, location: &UnixPath) -> SealedDatabaseResult<()> {
+    first_violation(mount_path_violations(name, task, location))
+}
+
+// Like `check_mount_paths`, but finds every problem rather than stopping at the first one.
+pub fn mount_path_violations(
+    name: &str,
+    task: &Task,
+    location: &UnixPath,
+) -> Vec<SealedDatabaseError> {
+    let mut violations = vec![];
+    let mut targets: HashMap<UnixPathBuf, &MappingPath> = HashMap::new();
+
+    for path in &task.mount_paths {
+        let target = location.join(&path.container_path);
+
+        // Check that the mount doesn't replace the working directory [tag:mount_paths_not_location].
+        if target == location {
+            violations.push(violation(format!(
+                "Mount path {} of task {} targets the working directory {}.",
+                format!("{path}").code_str(),
                 name.code_str(),
-                "cache: false".code_str(),
-            ),
-            None,
-        ));
+                location.to_string_lossy().code_str(),
+            )));
+        }
+
+        // Check that no two mounts target the same container path [tag:mount_paths_unique].
+        if let Some(other) = targets.insert(target.clone(), path) {
+            violations.push(violation(format!(
+                "Mount paths {} and {} of task {} both target {}.",
+                other.host_path.to_string_lossy().code_str(),
+                path.host_path.to_string_lossy().code_str(),
+                name.code_str(),
+                target.to_string_lossy().code_str(),
+            )));
+        }
     }
 
-    // If we made it this far, the task is valid.
-    Ok(())
+    violations
 }
 
 // Determine the image name for a task based on the name of the image for the previous task in the
-// schedule (or the base image, if this is the first task).
+// schedule (or the base image, if this is the first task). `input_files_hash` must have been
+// computed with `hash_algorithm`.
 pub fn image_name(
     previous_image: &str,
     docker_repo: &str,
@@ -384,18 +618,23 @@ pub fn image_name(
     task: &Task,
     input_files_hash: &str,
     environment: &HashMap<String, String>,
+    hash_algorithm: HashAlgorithm,
 ) -> String {
     // Compute the command for this task.
     let command = command(taskfile, task);
 
     // If there are no environment variables, no input paths, and no command to run, we can just use
     // the image from the previous task.
-    if task.environment.is_empty() && task.input_paths.is_empty() && command.is_empty() {
+    if task.environment.is_empty()
+        && task.input_paths.is_empty()
+        && command.is_empty()
+        && task.command_args.is_empty()
+    {
         return previous_image.to_owned();
     }
 
-    // Start with a hash of the cache version.
-    let mut cache_key: String = format!("{}", CACHE_VERSION).crypto_hash();
+    // Start with a hash of the cache version, which includes the hash algorithm.
+    let mut cache_key: String = cache_version(hash_algorithm).crypto_hash();
 
     // Incorporate the previous image.
     cache_key = combine(&cache_key, previous_image);
@@ -425,6 +664,16 @@ pub fn image_name(
     // Incorporate the command.
     cache_key = combine(&cache_key, &command);
 
+    // Incorporate the command arguments, if any. Tasks without them keep their cache keys.
+    for arg in &task.command_args {
+        cache_key = combine(&cache_key, arg);
+    }
+
+    // Incorporate the shell, if one was chosen.
+    if let Some(shell) = &task.shell {
+        cache_key = combine(&cache_key, shell);
+    }
+
     // We add this "task-" prefix because Docker has a rule that tags cannot be 64-byte hexadecimal
     // strings. See this for more details: https://github.com/moby/moby/issues/20972
     format!("{docker_repo}:task-{cache_key}")
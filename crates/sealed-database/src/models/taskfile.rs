@@ -1,25 +1,45 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
 use sealed_common::{format::series, util::format::CodeStr};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use typed_path::{UnixPath, UnixPathBuf};
 
 use crate::error::{SealedDatabaseError, SealedDatabaseResult};
 
-use super::task::{check_task, Task, DEFAULT_LOCATION, DEFAULT_USER};
+use super::task::{
+    first_violation, mount_path_violations, task_violations, Task, DEFAULT_LOCATION,
+    DEFAULT_RETRY_DELAY, DEFAULT_SHELL, DEFAULT_USER,
+};
 
 // This struct represents a TaskFile.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct TaskFile {
+    // Can be omitted in included TaskFiles, but must be present once they are merged
+    // [ref:TaskFile_image_present]. TaskFiles which are merged must agree on it
+    // [ref:include_same_image].
+    #[serde(default)]
     pub image: String,
 
-    // If present, must point to a task [ref:valid_default]
-    pub default: Option<String>,
+    // Other TaskFiles to merge into this one, as paths relative to this TaskFile or as URLs. Their
+    // tasks are merged in order and the tasks in this TaskFile come last, so later definitions
+    // override earlier ones with the same name [ref:include_merge]. This is empty once the TaskFile
+    // has been parsed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    // If present, every entry must point to a task [ref:valid_default]
+    pub default: Option<DefaultTasks>,
 
     // Must be absolute [ref:TaskFile_location_absolute]
     #[serde(default = "default_location")]
     #[serde(deserialize_with = "crate::models::task::deserialize_unix_path_buf")]
+    #[serde(serialize_with = "crate::models::task::serialize_unix_path_buf")]
     pub location: UnixPathBuf,
 
     #[serde(default = "default_user")]
@@ -32,6 +52,23 @@ pub struct TaskFile {
     pub tasks: HashMap<String, Task>,
 }
 
+// The default task, or a list of default tasks which are run in order.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum DefaultTasks {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl DefaultTasks {
+    pub fn names(&self) -> &[String] {
+        match self {
+            DefaultTasks::Single(name) => std::slice::from_ref(name),
+            DefaultTasks::Multiple(names) => names,
+        }
+    }
+}
+
 fn default_location() -> UnixPathBuf {
     UnixPath::new(DEFAULT_LOCATION).to_owned()
 }
@@ -40,18 +77,230 @@ fn default_user() -> String {
     DEFAULT_USER.to_owned()
 }
 
-// Parse config data.
+// Reads the TaskFiles which a TaskFile includes, given their locations (paths or URLs). The models
+// don't do I/O themselves, so whoever reads TaskFiles from disk or the web provides this.
+pub trait Loader {
+    fn load(&self, location: &str) -> SealedDatabaseResult<String>;
+}
+
+// The loader for TaskFiles which aren't read from anywhere, so they have nothing to include from.
+struct NoIncludes;
+
+impl Loader for NoIncludes {
+    fn load(&self, location: &str) -> SealedDatabaseResult<String> {
+        Err(SealedDatabaseError::FailedToRunUserCommand(
+            format!(
+                "Unable to include {}: only TaskFiles read from a file can include others.",
+                location.code_str(),
+            ),
+            None,
+        ))
+    }
+}
+
+// Parse config data. The data can't include other TaskFiles, since it has no location.
 pub fn parse(task_file_data: &str) -> SealedDatabaseResult<TaskFile> {
+    parse_with(task_file_data, ".", &NoIncludes)
+}
+
+// Parse the TaskFile at `location`. Included TaskFiles are resolved relative to it and read with
+// `loader`.
+pub fn parse_with(
+    task_file_data: &str,
+    location: &str,
+    loader: &dyn Loader,
+) -> SealedDatabaseResult<TaskFile> {
     // Deserialize the data.
-    let task_file: TaskFile = serde_yaml::from_str(task_file_data)
-        .map_err(|e| SealedDatabaseError::System(format!("{e}"), None))?;
+    let task_file = deserialize(task_file_data, location)?;
+
+    // Merge the included TaskFiles. This must happen before validation, since tasks can depend on
+    // tasks from other TaskFiles.
+    let task_file = merge_includes(task_file, location, loader, &mut vec![location.to_owned()])?;
+
+    // Make sure the TaskFile is valid.
+    validate(&task_file)?;
+
+    // Return the TaskFile.
+    Ok(task_file)
+}
+
+fn deserialize(task_file_data: &str, location: &str) -> SealedDatabaseResult<TaskFile> {
+    serde_yaml::from_str(task_file_data).map_err(|e| {
+        SealedDatabaseError::System(
+            if location == "." {
+                format!("{e}")
+            } else {
+                format!("{}: {e}", location.code_str())
+            },
+            None,
+        )
+    })
+}
+
+// Merge the TaskFiles included by a TaskFile, recursively. `ancestors` holds the TaskFiles which
+// are being merged, to detect include cycles.
+fn merge_includes(
+    mut task_file: TaskFile,
+    location: &str,
+    loader: &dyn Loader,
+    ancestors: &mut Vec<String>,
+) -> SealedDatabaseResult<TaskFile> {
+    let mut tasks = HashMap::new();
+    let mut image: Option<(String, String)> = None;
+
+    // Check that the TaskFiles agree on the image [tag:include_same_image].
+    let mut merge_image = |file_image: &str, file_location: &str| {
+        if file_image.is_empty() {
+            return Ok(());
+        }
+        match &image {
+            Some((image, image_location)) if image != file_image => {
+                Err(SealedDatabaseError::FailedToRunUserCommand(
+                    format!(
+                        "{} and {} declare different images: {} and {}.",
+                        image_location.code_str(),
+                        file_location.code_str(),
+                        image.code_str(),
+                        file_image.code_str(),
+                    ),
+                    None,
+                ))
+            }
+            Some(_) => Ok(()),
+            None => {
+                image = Some((file_image.to_owned(), file_location.to_owned()));
+                Ok(())
+            }
+        }
+    };
+
+    // Merge the included TaskFiles in order [tag:include_merge].
+    for include in &task_file.include {
+        let include_location = resolve_include(location, include);
+
+        // Guard against include cycles.
+        if ancestors.contains(&include_location) {
+            return Err(SealedDatabaseError::FailedToRunUserCommand(
+                format!(
+                    "Include cycle: {} -> {}.",
+                    ancestors.join(" -> "),
+                    include_location,
+                ),
+                None,
+            ));
+        }
+
+        ancestors.push(include_location.clone());
+        let included = merge_includes(
+            deserialize(&loader.load(&include_location)?, &include_location)?,
+            &include_location,
+            loader,
+            ancestors,
+        )?;
+        ancestors.pop();
+
+        merge_image(&included.image, &include_location)?;
+        tasks.extend(included.tasks);
+    }
+
+    // This TaskFile comes last, so its tasks override the included ones.
+    merge_image(&task_file.image, location)?;
+    tasks.extend(task_file.tasks);
+
+    task_file.image = image.map(|(image, _)| image).unwrap_or_default();
+    task_file.include = vec![];
+    task_file.tasks = tasks;
+    Ok(task_file)
+}
+
+// Resolve an include relative to the TaskFile which includes it. Paths are normalized, so the same
+// TaskFile is always identified the same way.
+fn resolve_include(location: &str, include: &str) -> String {
+    if is_url(include) {
+        return include.to_owned();
+    }
+
+    if is_url(location) {
+        let base = location.rsplit_once('/').map_or(location, |(base, _)| base);
+        return format!("{base}/{include}");
+    }
+
+    normalize(
+        &Path::new(location)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(include),
+    )
+    .to_string_lossy()
+    .into_owned()
+}
+
+// Remove `.` components from a path, and `..` components along with the directories they leave.
+// Unlike `canonicalize`, this doesn't touch the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+// Merge the includes of the TaskFile at `location` like `parse_with`, but report every problem with
+// it rather than failing on the first one. Only errors which prevent reading the TaskFile at all
+// are returned as `Err`.
+pub fn check_with(
+    task_file_data: &str,
+    location: &str,
+    loader: &dyn Loader,
+) -> SealedDatabaseResult<Vec<SealedDatabaseError>> {
+    let task_file = deserialize(task_file_data, location)?;
+    let task_file = merge_includes(task_file, location, loader, &mut vec![location.to_owned()])?;
+    Ok(violations(&task_file))
+}
+
+// Check that a TaskFile is valid: its dependencies, its location, and each of its tasks.
+pub fn validate(task_file: &TaskFile) -> SealedDatabaseResult<()> {
+    check_dependencies(task_file)?;
+    first_violation(file_violations(task_file))
+}
+
+// Find every problem with a TaskFile, rather than stopping at the first one like `validate`.
+pub fn violations(task_file: &TaskFile) -> Vec<SealedDatabaseError> {
+    let mut violations = dependency_violations(task_file);
+    violations.extend(file_violations(task_file));
+    violations
+}
+
+// Find every problem with a TaskFile other than its dependencies: its location and its tasks.
+fn file_violations(task_file: &TaskFile) -> Vec<SealedDatabaseError> {
+    let mut violations = vec![];
 
-    // Make sure the dependencies are valid.
-    check_dependencies(&task_file)?;
+    // Check that there is an image [tag:TaskFile_image_present].
+    if task_file.image.is_empty() {
+        violations.push(SealedDatabaseError::FailedToRunUserCommand(
+            format!("TaskFile has no {}.", "image".code_str()),
+            None,
+        ));
+    }
 
     // Check that `location` is absolute [tag:TaskFile_location_absolute].
     if !task_file.location.is_absolute() {
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
+        violations.push(SealedDatabaseError::FailedToRunUserCommand(
             format!(
                 "TaskFile has a relative {}: {}.",
                 "location".code_str(),
@@ -61,46 +310,171 @@ pub fn parse(task_file_data: &str) -> SealedDatabaseResult<TaskFile> {
         ));
     }
 
-    // Make sure each task is valid.
-    for (name, task) in &task_file.tasks {
-        check_task(name, task)?;
+    // Make sure each task is valid. Sort them, so the problems are reported in a stable order.
+    let mut tasks = task_file.tasks.iter().collect::<Vec<_>>();
+    tasks.sort_by_key(|(name, _)| *name);
+    for (name, task) in tasks {
+        violations.extend(task_violations(name, task));
+        violations.extend(mount_path_violations(
+            name,
+            task,
+            &location(task_file, task),
+        ));
     }
 
-    // Return the TaskFile.
-    Ok(task_file)
+    violations
 }
 
-// Fetch the variables for a task from the environment.
-pub fn environment(task: &Task) -> Result<HashMap<String, String>, Vec<&str>> {
+// Why the variables for a task couldn't be fetched.
+#[derive(Debug, Eq, PartialEq)]
+pub enum EnvironmentError<'a> {
+    // Variables without a default which aren't set
+    Missing(Vec<&'a str>),
+
+    // The default of `variable` refers to a variable which is neither a variable of the task nor
+    // set in the environment
+    UndefinedReference {
+        variable: &'a str,
+        reference: &'a str,
+    },
+
+    // Defaults which refer to each other, starting and ending with the same variable
+    Cycle(Vec<&'a str>),
+}
+
+impl Display for EnvironmentError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvironmentError::Missing(variables) => write!(
+                f,
+                "requires these environment variables: {}",
+                variables.join(", "),
+            ),
+            EnvironmentError::UndefinedReference {
+                variable,
+                reference,
+            } => write!(
+                f,
+                "refers to the undefined variable {} in the default of {}",
+                reference.code_str(),
+                variable.code_str(),
+            ),
+            EnvironmentError::Cycle(variables) => write!(
+                f,
+                "has environment variables whose defaults refer to each other: {}",
+                variables.join(" -> "),
+            ),
+        }
+    }
+}
+
+// Fetch the variables for a task from the environment. Variables which aren't set in the process's
+// environment are looked up in `fallback` (e.g., the contents of an env file) before falling back
+// to the defaults. Defaults can refer to other variables as `${NAME}`
+// [ref:environment_interpolation].
+pub fn environment<'a>(
+    task: &'a Task,
+    fallback: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, EnvironmentError<'a>> {
     // The result will be a map from variable name to value.
     let mut result = HashMap::new();
 
+    // The defaults which are used, before interpolation.
+    let mut defaults = HashMap::new();
+
     // We accumulate a list of errors to be shown to the user when there is a problem.
     let mut violations = vec![];
 
     // Fetch each environment variable.
     for (arg, default) in &task.environment {
         // Read the variable from the environment.
-        let maybe_var = std::env::var(arg);
+        let maybe_var = lookup(arg, fallback);
 
         // If a default value was provided, use that if the variable is missing from the
         // environment. If there was no default, the variable must be in the environment or else
         // we'll report a violation.
-        if let Some(default) = default {
-            result.insert(arg.clone(), maybe_var.unwrap_or_else(|_| default.clone()));
-        } else if let Ok(var) = maybe_var {
+        if let Some(var) = maybe_var {
             result.insert(arg.clone(), var);
+        } else if let Some(default) = default {
+            defaults.insert(arg.as_str(), default.as_str());
         } else {
             violations.push(arg.as_ref());
         }
     }
 
-    // If there were no violations, return the map. Otherwise, report the violations.
-    if violations.is_empty() {
-        Ok(result)
-    } else {
-        Err(violations)
+    // If there were any violations, report them.
+    if !violations.is_empty() {
+        return Err(EnvironmentError::Missing(violations));
     }
+
+    // Interpolate the defaults. Sort them, so the same error is reported every time.
+    let mut names = defaults.keys().copied().collect::<Vec<_>>();
+    names.sort_unstable();
+    for name in names {
+        interpolate_default(name, &defaults, fallback, &mut result, &mut vec![])?;
+    }
+
+    Ok(result)
+}
+
+// Read a variable from the process's environment, or else from `fallback`.
+fn lookup(name: &str, fallback: &HashMap<String, String>) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .or_else(|| fallback.get(name).cloned())
+}
+
+// [tag:environment_interpolation] Resolve the default of a variable, replacing each `${NAME}` with
+// the value of the task's variable `NAME` (interpolating its default first, if it has one) or else
+// of the environment variable, as found by `lookup`. `stack` holds the defaults being interpolated, to detect
+// cycles. Values without `${` are used as they are.
+fn interpolate_default<'a>(
+    name: &'a str,
+    defaults: &HashMap<&'a str, &'a str>,
+    fallback: &HashMap<String, String>,
+    resolved: &mut HashMap<String, String>,
+    stack: &mut Vec<&'a str>,
+) -> Result<String, EnvironmentError<'a>> {
+    if let Some(value) = resolved.get(name) {
+        return Ok(value.clone());
+    }
+
+    if let Some(position) = stack.iter().position(|variable| *variable == name) {
+        let mut cycle = stack[position..].to_vec();
+        cycle.push(name);
+        return Err(EnvironmentError::Cycle(cycle));
+    }
+
+    stack.push(name);
+    let mut value = String::new();
+    let mut rest = defaults[name];
+    while let Some((before, after)) = rest.split_once("${") {
+        let Some((reference, after)) = after.split_once('}') else {
+            break;
+        };
+
+        value.push_str(before);
+        if defaults.contains_key(reference) {
+            value.push_str(&interpolate_default(
+                reference, defaults, fallback, resolved, stack,
+            )?);
+        } else if let Some(referenced) = resolved.get(reference) {
+            value.push_str(referenced);
+        } else if let Some(referenced) = lookup(reference, fallback) {
+            value.push_str(&referenced);
+        } else {
+            return Err(EnvironmentError::UndefinedReference {
+                variable: name,
+                reference,
+            });
+        }
+        rest = after;
+    }
+    value.push_str(rest);
+    stack.pop();
+
+    resolved.insert(name.to_owned(), value.clone());
+    Ok(value)
 }
 
 // [tag:location_helper] Fetch the location for a task, defaulting to the top-level location if
@@ -136,14 +510,46 @@ pub fn command(task_file: &TaskFile, task: &Task) -> String {
     command
 }
 
+// [tag:shell_helper] Fetch the shell for a task, defaulting to `/bin/sh`.
+pub fn shell(task: &Task) -> &str {
+    task.shell.as_deref().unwrap_or(DEFAULT_SHELL)
+}
+
+// [tag:retry_delay_helper] Fetch the delay between attempts of a task, defaulting to
+// `DEFAULT_RETRY_DELAY` seconds.
+pub fn retry_delay(task: &Task) -> Duration {
+    Duration::from_secs(task.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY))
+}
+
 // Check that all dependencies exist and form a DAG (no cycles).
+fn check_dependencies(task_file: &TaskFile) -> SealedDatabaseResult<()> {
+    first_violation(dependency_violations(task_file))
+}
+
+// Find every problem with the dependencies: missing default tasks and dependencies, and each
+// cycle.
 #[allow(clippy::too_many_lines)]
-fn check_dependencies<'a>(task_file: &'a TaskFile) -> SealedDatabaseResult<()> {
-    // Check the default task [tag:valid_default].
-    let valid_default = task_file
+pub fn dependency_violations<'a>(task_file: &'a TaskFile) -> Vec<SealedDatabaseError> {
+    let mut errors = vec![];
+
+    // Check the default tasks [tag:valid_default].
+    let invalid_defaults = task_file
         .default
         .as_ref()
-        .map_or(true, |default| task_file.tasks.contains_key(default));
+        .map_or(&[] as &[String], DefaultTasks::names)
+        .iter()
+        .filter(|default| !task_file.tasks.contains_key(*default))
+        .map(|default| format!("{}", default.code_str()))
+        .collect::<Vec<_>>();
+    let valid_default = invalid_defaults.is_empty();
+    let invalid_defaults_description = if invalid_defaults.len() == 1 {
+        format!("The default task {} does not exist", invalid_defaults[0])
+    } else {
+        format!(
+            "The default tasks {} do not exist",
+            series(invalid_defaults.as_ref()),
+        )
+    };
 
     // Map from task to vector of invalid dependencies.
     let mut violations: HashMap<String, Vec<String>> = HashMap::new();
@@ -185,32 +591,29 @@ fn check_dependencies<'a>(task_file: &'a TaskFile) -> SealedDatabaseResult<()> {
         );
 
         if valid_default {
-            return Err(SealedDatabaseError::FailedToRunUserCommand(
+            errors.push(SealedDatabaseError::FailedToRunUserCommand(
                 format!("The following tasks have invalid dependencies: {violations_series}."),
                 None,
             ));
+        } else {
+            errors.push(SealedDatabaseError::FailedToRunUserCommand(
+                format!(
+                    "{}, and the following tasks have invalid dependencies: {}.",
+                    invalid_defaults_description, // [ref:valid_default]
+                    violations_series,
+                ),
+                None,
+            ));
         }
-
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
-            format!(
-                "The default task {} does not exist, and the following tasks have invalid \
-             dependencies: {}.",
-                task_file.default.as_ref().unwrap().code_str(), // [ref:valid_default]
-                violations_series,
-            ),
-            None,
-        ));
     } else if !valid_default {
-        return Err(SealedDatabaseError::FailedToRunUserCommand(
-            format!(
-                "The default task {} does not exist.",
-                task_file.default.as_ref().unwrap().code_str(), // [ref:valid_default]
-            ),
+        errors.push(SealedDatabaseError::FailedToRunUserCommand(
+            format!("{invalid_defaults_description}."), // [ref:valid_default]
             None,
         ));
     }
 
-    // Check that the dependencies aren't cyclic [tag:tasks_dag].
+    // Check that the dependencies aren't cyclic [tag:tasks_dag]. Missing dependencies were reported
+    // above and are skipped here.
     let mut visited: HashSet<&'a str> = HashSet::new();
     for task in task_file.tasks.keys() {
         let mut frontier: Vec<(&'a str, usize)> = vec![(task, 0)];
@@ -261,10 +664,14 @@ fn check_dependencies<'a>(task_file: &'a TaskFile) -> SealedDatabaseResult<()> {
                         ),
                     )
                 };
-                return Err(SealedDatabaseError::FailedToRunUserCommand(
+                errors.push(SealedDatabaseError::FailedToRunUserCommand(
                     format!("The dependencies are cyclic. {error_message}"),
                     None,
                 ));
+
+                // The tasks on the cycle are visited, so it won't be reported again from another
+                // task.
+                break;
             }
 
             // If we've never seen this task before, add its dependencies to the frontier.
@@ -275,28 +682,48 @@ fn check_dependencies<'a>(task_file: &'a TaskFile) -> SealedDatabaseResult<()> {
                 ancestors_stack.push(task);
 
                 for dependency in &task_file.tasks[task].dependencies {
-                    frontier.push((dependency, task_depth + 1));
+                    if task_file.tasks.contains_key(dependency) {
+                        frontier.push((dependency, task_depth + 1));
+                    }
                 }
             }
         }
     }
 
-    // No violations
-    Ok(())
+    errors
 }
 
 #[cfg(test)]
 mod tests {
     use {
         super::{
-            check_dependencies, check_task, command, environment, location, parse, user, Task,
-            TaskFile, DEFAULT_LOCATION, DEFAULT_USER,
+            check_dependencies, check_with, command, dependency_violations, environment, location,
+            parse, parse_with, resolve_include, shell, user, DefaultTasks, EnvironmentError,
+            Loader, Task, TaskFile, DEFAULT_LOCATION, DEFAULT_USER,
         },
-        crate::task::{image_name, MappingPath},
+        crate::{
+            error::{SealedDatabaseError, SealedDatabaseResult},
+            task::{check_mount_paths, check_task, image_name, task_violations, MappingPath},
+        },
+        sealed_common::util::cache::HashAlgorithm,
         std::{collections::HashMap, env, path::Path},
         typed_path::UnixPath,
     };
 
+    // Serves TaskFiles from memory, by location.
+    struct MemoryLoader(HashMap<&'static str, &'static str>);
+
+    impl Loader for MemoryLoader {
+        fn load(&self, location: &str) -> SealedDatabaseResult<String> {
+            self.0
+                .get(location)
+                .map(|&data| data.to_owned())
+                .ok_or_else(|| {
+                    SealedDatabaseError::System(format!("Unable to read {location}."), None)
+                })
+        }
+    }
+
     #[test]
     fn parse_empty() {
         let input = r"
@@ -306,6 +733,7 @@ image: encom:os-12
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -344,12 +772,17 @@ foo: {}
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -439,6 +872,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -473,14 +910,17 @@ extra_docker_arguments:
                     MappingPath {
                         host_path: Path::new("wibble").to_owned(),
                         container_path: UnixPath::new("wibble").to_owned(),
+                        readonly: None,
                     },
                     MappingPath {
                         host_path: Path::new("/wobble").to_owned(),
                         container_path: UnixPath::new("/wobble").to_owned(),
+                        readonly: None,
                     },
                     MappingPath {
                         host_path: Path::new("wubble").to_owned(),
                         container_path: UnixPath::new("wabble").to_owned(),
+                        readonly: None,
                     },
                 ],
                 mount_readonly: true,
@@ -489,13 +929,18 @@ extra_docker_arguments:
                 user: Some("waldo".to_owned()),
                 command: "flob".to_owned(),
                 command_prefix: Some("flob_prefix".to_owned()),
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec!["--cpus".to_owned(), "4".to_owned()],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
-            default: Some("bar".to_owned()),
+            include: vec![],
+            default: Some(DefaultTasks::Single("bar".to_owned())),
             location: UnixPath::new("/default_location").to_owned(),
             user: "default_user".to_owned(),
             command_prefix: "prefix".to_owned(),
@@ -526,13 +971,18 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
-            default: Some("foo".to_owned()),
+            include: vec![],
+            default: Some(DefaultTasks::Single("foo".to_owned())),
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
             command_prefix: String::new(),
@@ -563,13 +1013,18 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
-            default: Some("bar".to_owned()),
+            include: vec![],
+            default: Some(DefaultTasks::Single("bar".to_owned())),
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
             command_prefix: String::new(),
@@ -585,6 +1040,7 @@ extra_docker_arguments:
     fn check_dependencies_empty() {
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -616,12 +1072,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -632,6 +1093,66 @@ extra_docker_arguments:
         assert!(check_dependencies(&task_file).is_ok());
     }
 
+    #[test]
+    fn check_with_reports_every_violation() {
+        let data = r"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies:
+      - bar
+  bar:
+    dependencies:
+      - foo
+  baz:
+    environment:
+      FOO=BAR: null
+";
+        let loader = MemoryLoader(HashMap::new());
+
+        let violations = check_with(data, "/grid/sealed.yml", &loader)
+            .unwrap()
+            .iter()
+            .map(SealedDatabaseError::message)
+            .collect::<Vec<_>>();
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("cyclic"));
+        assert!(violations[1].contains("FOO=BAR"));
+        assert!(parse_with(data, "/grid/sealed.yml", &loader).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_dependency_cycle() {
+        let data = r"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies:
+      - bar
+  bar:
+    dependencies:
+      - foo
+";
+
+        let result = parse(data);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().message().contains("cyclic"));
+    }
+
+    #[test]
+    fn check_with_valid() {
+        let data = "image: encom:os-12
+tasks:
+  foo: {}
+";
+
+        assert!(
+            check_with(data, "/grid/sealed.yml", &MemoryLoader(HashMap::new()))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
     #[test]
     fn check_task_dependencies_nonempty() {
         let mut tasks = HashMap::new();
@@ -653,6 +1174,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -674,12 +1199,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -711,6 +1241,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -732,12 +1266,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -771,12 +1310,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -810,6 +1354,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -831,12 +1379,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -870,6 +1423,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -891,6 +1448,10 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
@@ -912,12 +1473,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -953,12 +1519,107 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
         assert!(check_task("foo", &task).is_ok());
     }
 
+    #[test]
+    fn task_violations_reports_every_problem() {
+        let mut environment = HashMap::new();
+        environment.insert("corge=grault".to_owned(), None);
+
+        let task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment,
+            input_paths: vec![UnixPath::new("/garply").to_owned()],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let violations = task_violations("foo", &task)
+            .iter()
+            .map(SealedDatabaseError::message)
+            .collect::<Vec<_>>();
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("corge=grault"));
+        assert!(violations[1].contains("/garply"));
+
+        // The fail-fast check reports the first one, with the same message
+        let result = check_task("foo", &task);
+        assert_eq!(result.unwrap_err().message(), violations[0]);
+    }
+
+    #[test]
+    fn check_task_command_and_command_args() {
+        let task_file = parse(
+            r"
+image: encom:os-12
+tasks:
+  foo:
+    command_args: [echo, hello world]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            task_file.tasks["foo"].command_args,
+            vec!["echo".to_owned(), "hello world".to_owned()],
+        );
+
+        let mut task = task_file.tasks["foo"].clone();
+        task.command = "echo hello".to_owned();
+        let result = check_task("foo", &task);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().message().contains("command_args"));
+    }
+
+    #[test]
+    fn dependency_violations_reports_missing_dependencies_and_cycles() {
+        let task_file: TaskFile = serde_yaml::from_str(
+            r"
+image: encom:os-12
+tasks:
+  foo:
+    dependencies: [bar, qux]
+  bar:
+    dependencies: [foo]
+",
+        )
+        .unwrap();
+
+        let violations = dependency_violations(&task_file)
+            .iter()
+            .map(SealedDatabaseError::message)
+            .collect::<Vec<_>>();
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("invalid dependencies"));
+        assert!(violations[1].contains("cyclic"));
+        assert_eq!(
+            check_dependencies(&task_file).unwrap_err().message(),
+            violations[0]
+        );
+    }
+
     #[test]
     fn check_task_environment_equals() {
         let mut environment = HashMap::new();
@@ -982,6 +1643,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -990,6 +1655,47 @@ extra_docker_arguments:
         assert!(result.unwrap_err().to_string().contains('='));
     }
 
+    #[test]
+    fn parse_mount_path_readonly() {
+        assert_eq!(
+            serde_yaml::from_str::<MappingPath>("foo:/bar:ro").unwrap(),
+            MappingPath {
+                host_path: Path::new("foo").to_owned(),
+                container_path: UnixPath::new("/bar").to_owned(),
+                readonly: Some(true),
+            },
+        );
+    }
+
+    #[test]
+    fn parse_mount_path_readwrite() {
+        assert_eq!(
+            serde_yaml::from_str::<MappingPath>("foo:/bar:rw").unwrap(),
+            MappingPath {
+                host_path: Path::new("foo").to_owned(),
+                container_path: UnixPath::new("/bar").to_owned(),
+                readonly: Some(false),
+            },
+        );
+    }
+
+    #[test]
+    fn parse_mount_path_default_mode() {
+        assert_eq!(
+            serde_yaml::from_str::<MappingPath>("foo:/bar").unwrap(),
+            MappingPath {
+                host_path: Path::new("foo").to_owned(),
+                container_path: UnixPath::new("/bar").to_owned(),
+                readonly: None,
+            },
+        );
+    }
+
+    #[test]
+    fn parse_mount_path_invalid_mode() {
+        assert!(serde_yaml::from_str::<MappingPath>("foo:/bar:rx").is_err());
+    }
+
     #[test]
     fn check_task_paths_ok() {
         let task = Task {
@@ -1005,18 +1711,22 @@ extra_docker_arguments:
                 MappingPath {
                     host_path: Path::new("quuy").to_owned(),
                     container_path: UnixPath::new("quuz").to_owned(),
+                    readonly: None,
                 },
                 MappingPath {
                     host_path: Path::new("quuy").to_owned(),
                     container_path: UnixPath::new("/quuz").to_owned(),
+                    readonly: None,
                 },
                 MappingPath {
                     host_path: Path::new("quuy").to_owned(),
                     container_path: UnixPath::new("/quuz").to_owned(),
+                    readonly: None,
                 },
                 MappingPath {
                     host_path: Path::new("/quuy").to_owned(),
                     container_path: UnixPath::new("/quuz").to_owned(),
+                    readonly: None,
                 },
             ],
             mount_readonly: false,
@@ -1025,6 +1735,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1049,6 +1763,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1075,6 +1793,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1101,6 +1823,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1127,6 +1853,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1149,6 +1879,7 @@ extra_docker_arguments:
             mount_paths: vec![MappingPath {
                 host_path: Path::new("bar,baz").to_owned(),
                 container_path: UnixPath::new("bar,baz").to_owned(),
+                readonly: None,
             }],
             mount_readonly: false,
             ports: vec![],
@@ -1156,6 +1887,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1164,6 +1899,148 @@ extra_docker_arguments:
         assert!(result.unwrap_err().to_string().contains("bar,baz"));
     }
 
+    #[test]
+    fn check_mount_paths_duplicate_targets() {
+        let task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: false,
+            environment: HashMap::new(),
+            input_paths: vec![],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![
+                MappingPath {
+                    host_path: Path::new("bar").to_owned(),
+                    container_path: UnixPath::new("qux").to_owned(),
+                    readonly: None,
+                },
+                MappingPath {
+                    host_path: Path::new("baz").to_owned(),
+                    container_path: UnixPath::new("/code/qux").to_owned(),
+                    readonly: None,
+                },
+            ],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let result = check_mount_paths("foo", &task, UnixPath::new("/code"));
+        assert!(result.is_err());
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("bar"));
+        assert!(message.contains("baz"));
+
+        assert!(check_mount_paths("foo", &task, UnixPath::new("/scratch")).is_ok());
+    }
+
+    #[test]
+    fn check_mount_paths_target_is_location() {
+        let task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: false,
+            environment: HashMap::new(),
+            input_paths: vec![],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![MappingPath {
+                host_path: Path::new("bar").to_owned(),
+                container_path: UnixPath::new("/code").to_owned(),
+                readonly: None,
+            }],
+            mount_readonly: false,
+            ports: vec![],
+            location: Some(UnixPath::new("/code").to_owned()),
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let result = check_mount_paths("foo", &task, UnixPath::new("/code"));
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("working directory"));
+    }
+
+    #[test]
+    fn check_task_relative_shell() {
+        let task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            input_paths: vec![],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: Some("bash".to_owned()),
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let result = check_task("foo", &task);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("bash"));
+    }
+
+    #[test]
+    fn shell_default() {
+        let mut task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            input_paths: vec![],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+        assert_eq!(shell(&task), "/bin/sh");
+
+        task.shell = Some("/bin/bash".to_owned());
+        assert_eq!(shell(&task), "/bin/bash");
+    }
+
     #[test]
     fn check_task_paths_relative_location() {
         let task = Task {
@@ -1182,6 +2059,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1204,6 +2085,7 @@ extra_docker_arguments:
             mount_paths: vec![MappingPath {
                 host_path: Path::new("bar").to_owned(),
                 container_path: UnixPath::new("bar").to_owned(),
+                readonly: None,
             }],
             mount_readonly: false,
             ports: vec![],
@@ -1211,6 +2093,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1233,6 +2119,7 @@ extra_docker_arguments:
             mount_paths: vec![MappingPath {
                 host_path: Path::new("bar").to_owned(),
                 container_path: UnixPath::new("bar").to_owned(),
+                readonly: None,
             }],
             mount_readonly: false,
             ports: vec!["3000:80".to_owned()],
@@ -1240,12 +2127,66 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
         assert!(check_task("foo", &task).is_ok());
     }
 
+    fn task_with_ports(ports: &[&str]) -> Task {
+        let mut task: Task = serde_yaml::from_str("cache: false").unwrap();
+        task.ports = ports.iter().map(|port| (*port).to_owned()).collect();
+        task
+    }
+
+    #[test]
+    fn check_task_ports_valid() {
+        let task = task_with_ports(&[
+            "3000",
+            "3000:80",
+            "3000:80/tcp",
+            "53:53/udp",
+            "127.0.0.1:8080:80",
+            "[::1]:8080:80/tcp",
+            "8000-8010:8000-8010",
+            "9000-9001:80-81/udp",
+            "127.0.0.1::80",
+            "[::1]::8000-8010",
+        ]);
+
+        assert!(check_task("foo", &task).is_ok());
+    }
+
+    #[test]
+    fn check_task_ports_out_of_range() {
+        for port in ["3000:65536", "0:80", "70000"] {
+            let result = check_task("foo", &task_with_ports(&[port]));
+            assert!(result.is_err());
+            assert!(result.unwrap_err().message().contains(port));
+        }
+    }
+
+    #[test]
+    fn check_task_ports_not_numeric() {
+        for port in [
+            "3000:80x",
+            "http",
+            "3000:80/sctp",
+            "localhost:3000:80",
+            "+80",
+            "8010-8000:8010-8000",
+            "8000-8010:8000-8005",
+            "8000-:80",
+            ":80",
+        ] {
+            assert!(check_task("foo", &task_with_ports(&[port])).is_err());
+        }
+    }
+
     #[test]
     fn check_task_caching_enabled_with_ports() {
         let task = Task {
@@ -1264,6 +2205,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1290,6 +2235,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1314,6 +2263,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec!["--cpus".to_owned(), "4".to_owned()],
         };
 
@@ -1340,6 +2293,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec!["--cpus".to_owned(), "4".to_owned()],
         };
 
@@ -1364,10 +2321,14 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
-        assert_eq!(environment(&task), Ok(HashMap::new()));
+        assert_eq!(environment(&task, &HashMap::new()), Ok(HashMap::new()));
     }
 
     #[test]
@@ -1393,6 +2354,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1401,7 +2366,7 @@ extra_docker_arguments:
 
         env::set_var("foo1", "baz");
         assert_eq!(env::var("foo1"), Ok("baz".to_owned()));
-        assert_eq!(environment(&task), Ok(expected));
+        assert_eq!(environment(&task, &HashMap::new()), Ok(expected));
     }
 
     #[test]
@@ -1427,6 +2392,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1435,7 +2404,7 @@ extra_docker_arguments:
 
         env::remove_var("foo2");
         assert!(env::var("foo2").is_err());
-        assert_eq!(environment(&task), Ok(expected));
+        assert_eq!(environment(&task, &HashMap::new()), Ok(expected));
     }
 
     #[test]
@@ -1461,14 +2430,94 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
         env::remove_var("foo3");
         assert!(env::var("foo3").is_err());
-        let result = environment(&task);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err()[0].to_owned(), "foo3");
+        let result = environment(&task, &HashMap::new());
+        assert_eq!(result, Err(EnvironmentError::Missing(vec!["foo3"])));
+    }
+
+    #[test]
+    fn environment_fallback() {
+        let task = task_with_environment("  foo10: null\n  foo11: null");
+        let fallback = HashMap::from([
+            ("foo10".to_owned(), "from file".to_owned()),
+            ("foo11".to_owned(), "from file".to_owned()),
+        ]);
+
+        // The file satisfies a variable which the process's environment lacks, but the process's
+        // environment wins.
+        env::remove_var("foo10");
+        env::set_var("foo11", "from process");
+        let result = environment(&task, &fallback).unwrap();
+        assert_eq!(result["foo10"], "from file");
+        assert_eq!(result["foo11"], "from process");
+    }
+
+    #[test]
+    fn environment_missing_from_fallback() {
+        let task = task_with_environment("  foo12: null");
+        let fallback = HashMap::from([("foo13".to_owned(), "unrelated".to_owned())]);
+
+        env::remove_var("foo12");
+        assert_eq!(
+            environment(&task, &fallback),
+            Err(EnvironmentError::Missing(vec!["foo12"])),
+        );
+    }
+
+    fn task_with_environment(environment: &str) -> Task {
+        serde_yaml::from_str(&format!("environment:\n{environment}")).unwrap()
+    }
+
+    #[test]
+    fn environment_default_interpolated() {
+        let task = task_with_environment(
+            "  foo4_bin: ${foo4_home}/bin\n  foo4_home: /home/flynn\n  foo4_greeting: hi ${foo5}\n  foo4_literal: $5 {x}",
+        );
+
+        env::remove_var("foo4_bin");
+        env::remove_var("foo4_home");
+        env::remove_var("foo4_greeting");
+        env::remove_var("foo4_literal");
+        env::set_var("foo5", "there");
+        let result = environment(&task, &HashMap::new()).unwrap();
+        assert_eq!(result["foo4_bin"], "/home/flynn/bin");
+        assert_eq!(result["foo4_greeting"], "hi there");
+        assert_eq!(result["foo4_literal"], "$5 {x}");
+    }
+
+    #[test]
+    fn environment_default_undefined_reference() {
+        let task = task_with_environment("  foo6: ${foo7}/bin");
+
+        env::remove_var("foo6");
+        env::remove_var("foo7");
+        assert_eq!(
+            environment(&task, &HashMap::new()),
+            Err(EnvironmentError::UndefinedReference {
+                variable: "foo6",
+                reference: "foo7",
+            }),
+        );
+    }
+
+    #[test]
+    fn environment_default_cycle() {
+        let task = task_with_environment("  foo8: ${foo9}\n  foo9: x${foo8}");
+
+        env::remove_var("foo8");
+        env::remove_var("foo9");
+        assert_eq!(
+            environment(&task, &HashMap::new()),
+            Err(EnvironmentError::Cycle(vec!["foo8", "foo9", "foo8"])),
+        );
     }
 
     #[test]
@@ -1492,12 +2541,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1532,12 +2586,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1572,12 +2631,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1612,12 +2676,17 @@ extra_docker_arguments:
                 user: Some("bar".to_owned()),
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1649,12 +2718,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1689,12 +2763,17 @@ extra_docker_arguments:
                 user: None,
                 command: "echo hello".to_owned(),
                 command_prefix: None,
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1729,12 +2808,17 @@ extra_docker_arguments:
                 user: None,
                 command: String::new(),
                 command_prefix: Some("set -euxo pipefail".to_owned()),
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1769,12 +2853,17 @@ extra_docker_arguments:
                 user: None,
                 command: "echo hello".to_owned(),
                 command_prefix: Some("set -euxo pipefail".to_owned()),
+                shell: None,
+                retries: 0,
+                retry_delay: None,
+                command_args: vec![],
                 extra_docker_arguments: vec![],
             },
         );
 
         let task_file = TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1794,6 +2883,7 @@ extra_docker_arguments:
 
         TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1809,6 +2899,7 @@ extra_docker_arguments:
 
         TaskFile {
             image: "encom:os-12".to_owned(),
+            include: vec![],
             default: None,
             location: UnixPath::new(DEFAULT_LOCATION).to_owned(),
             user: DEFAULT_USER.to_owned(),
@@ -1840,6 +2931,10 @@ extra_docker_arguments:
             user: None,
             command: String::new(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1858,6 +2953,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -1886,6 +2982,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1904,6 +3004,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -1912,6 +3013,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -1938,6 +3040,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -1955,6 +3061,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image2,
@@ -1963,6 +3070,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -1996,6 +3104,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2015,6 +3127,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2034,6 +3150,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2042,6 +3159,7 @@ extra_docker_arguments:
                 &taskfile.tasks["bar"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -2073,6 +3191,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2092,6 +3214,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2111,6 +3237,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2119,6 +3246,7 @@ extra_docker_arguments:
                 &taskfile.tasks["bar"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -2147,6 +3275,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2167,6 +3299,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment1,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2175,6 +3308,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment2,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -2200,6 +3334,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2218,6 +3356,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash1,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2226,10 +3365,62 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash2,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
 
+    #[test]
+    fn image_name_hash_algorithm() {
+        let previous_image = "corge";
+        let docker_repo = "task";
+
+        let task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            input_paths: vec![UnixPath::new("flob").to_owned()],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: "echo wibble".to_owned(),
+            command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let taskfile = taskfile_with_task(task);
+
+        let input_files_hash = "grault";
+
+        let full_environment = HashMap::new();
+
+        let key = |hash_algorithm| {
+            image_name(
+                previous_image,
+                docker_repo,
+                &taskfile,
+                &taskfile.tasks["foo"],
+                input_files_hash,
+                &full_environment,
+                hash_algorithm,
+            )
+        };
+
+        assert_eq!(key(HashAlgorithm::Sha256), key(HashAlgorithm::Sha256));
+        assert_eq!(key(HashAlgorithm::Blake3), key(HashAlgorithm::Blake3));
+        assert_ne!(key(HashAlgorithm::Sha256), key(HashAlgorithm::Blake3));
+    }
+
     #[test]
     fn image_name_location() {
         let previous_image = "corge";
@@ -2251,6 +3442,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2270,6 +3465,10 @@ extra_docker_arguments:
             user: None,
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2287,6 +3486,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2295,6 +3495,7 @@ extra_docker_arguments:
                 &taskfile.tasks["bar"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -2320,6 +3521,10 @@ extra_docker_arguments:
             user: Some("foo".to_owned()),
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2339,6 +3544,10 @@ extra_docker_arguments:
             user: Some("bar".to_owned()),
             command: "echo wibble".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2356,6 +3565,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2364,6 +3574,7 @@ extra_docker_arguments:
                 &taskfile.tasks["bar"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
@@ -2389,6 +3600,10 @@ extra_docker_arguments:
             user: None,
             command: "echo foo".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2408,6 +3623,10 @@ extra_docker_arguments:
             user: None,
             command: "echo bar".to_owned(),
             command_prefix: None,
+            shell: None,
+            retries: 0,
+            retry_delay: None,
+            command_args: vec![],
             extra_docker_arguments: vec![],
         };
 
@@ -2425,6 +3644,7 @@ extra_docker_arguments:
                 &taskfile.tasks["foo"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
             image_name(
                 previous_image,
@@ -2433,7 +3653,218 @@ extra_docker_arguments:
                 &taskfile.tasks["bar"],
                 input_files_hash,
                 &full_environment,
+                HashAlgorithm::default(),
             ),
         );
     }
+
+    #[test]
+    fn parse_with_include() {
+        let loader = MemoryLoader(HashMap::from([(
+            "/grid/base.yml",
+            r"
+image: encom:os-12
+tasks:
+  lint:
+    command: cargo clippy
+  build:
+    command: make
+",
+        )]));
+
+        let task_file = parse_with(
+            r"
+include:
+  - base.yml
+tasks:
+  build:
+    dependencies:
+      - lint
+    command: cargo build
+",
+            "/grid/project.yml",
+            &loader,
+        )
+        .unwrap();
+
+        assert_eq!(task_file.image, "encom:os-12");
+        assert!(task_file.include.is_empty());
+        assert_eq!(task_file.tasks.len(), 2);
+        assert_eq!(task_file.tasks["lint"].command, "cargo clippy");
+        assert_eq!(task_file.tasks["build"].command, "cargo build");
+    }
+
+    #[test]
+    fn parse_with_include_cycle() {
+        let loader = MemoryLoader(HashMap::from([
+            ("/grid/a.yml", "image: encom:os-12\ninclude: [lib/b.yml]"),
+            ("/grid/lib/b.yml", "include: [../a.yml]"),
+        ]));
+
+        let result = parse_with(
+            "image: encom:os-12\ninclude: [lib/b.yml]",
+            "/grid/a.yml",
+            &loader,
+        );
+
+        assert!(result.is_err());
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("Include cycle"));
+        assert!(message.contains("/grid/lib/b.yml -> /grid/a.yml"));
+    }
+
+    #[test]
+    fn parse_with_include_different_images() {
+        let loader = MemoryLoader(HashMap::from([("/grid/base.yml", "image: encom:os-11")]));
+
+        let result = parse_with(
+            "image: encom:os-12\ninclude: [base.yml]",
+            "/grid/project.yml",
+            &loader,
+        );
+
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("declare different images"));
+    }
+
+    #[test]
+    fn parse_include_without_location() {
+        let result = parse("image: encom:os-12\ninclude: [base.yml]");
+
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("only TaskFiles read from a file"));
+    }
+
+    #[test]
+    fn resolve_include_relative() {
+        assert_eq!(
+            resolve_include("/grid/lib/b.yml", "../a.yml"),
+            "/grid/a.yml"
+        );
+        assert_eq!(
+            resolve_include("/grid/a.yml", "./lib/b.yml"),
+            "/grid/lib/b.yml"
+        );
+        assert_eq!(
+            resolve_include("https://encom.example/tasks/a.yml", "b.yml"),
+            "https://encom.example/tasks/b.yml"
+        );
+        assert_eq!(
+            resolve_include("/grid/a.yml", "https://encom.example/b.yml"),
+            "https://encom.example/b.yml"
+        );
+    }
+
+    #[test]
+    fn parse_missing_image() {
+        let result = parse("tasks: {}");
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("has no"));
+    }
+
+    #[test]
+    fn parse_default_list() {
+        let task_file = parse(
+            r"
+image: encom:os-12
+default:
+  - build
+  - test
+tasks:
+  build: {}
+  test: {}
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            task_file.default,
+            Some(DefaultTasks::Multiple(vec![
+                "build".to_owned(),
+                "test".to_owned(),
+            ])),
+        );
+        assert_eq!(
+            task_file.default.unwrap().names(),
+            &["build".to_owned(), "test".to_owned()],
+        );
+    }
+
+    #[test]
+    fn parse_default_single() {
+        let task_file = parse(
+            r"
+image: encom:os-12
+default: build
+tasks:
+  build: {}
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            task_file.default,
+            Some(DefaultTasks::Single("build".to_owned())),
+        );
+        assert_eq!(task_file.default.unwrap().names(), &["build".to_owned()]);
+    }
+
+    #[test]
+    fn parse_default_list_missing_task() {
+        let result = parse(
+            r"
+image: encom:os-12
+default:
+  - build
+  - deploy
+tasks:
+  build: {}
+",
+        );
+
+        assert!(result.is_err());
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("deploy"));
+        assert!(!message.contains("build"));
+    }
+
+    #[test]
+    fn check_task_retries_cache() {
+        let mut task = Task {
+            description: None,
+            dependencies: vec![],
+            cache: true,
+            environment: HashMap::new(),
+            input_paths: vec![],
+            excluded_input_paths: vec![],
+            output_paths: vec![],
+            output_paths_on_failure: vec![],
+            mount_paths: vec![],
+            mount_readonly: false,
+            ports: vec![],
+            location: None,
+            user: None,
+            command: String::new(),
+            command_prefix: None,
+            shell: None,
+            retries: 2,
+            retry_delay: None,
+            command_args: vec![],
+            extra_docker_arguments: vec![],
+        };
+
+        let result = check_task("foo", &task);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("retries"));
+
+        task.cache = false;
+        assert!(check_task("foo", &task).is_ok());
+    }
 }
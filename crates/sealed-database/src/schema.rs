@@ -8,6 +8,15 @@ use utoipa::IntoParams;
 pub struct PaginationParams {
     pub offset: Option<i64>,
     pub limit: Option<i64>,
+    /// Free-text search from `/apps?search=`. For now it matches app names, and
+    /// `name_contains` takes precedence when both are given.
+    pub search: Option<String>,
+    /// Only return apps whose name contains this substring, ignoring case
+    pub name_contains: Option<String>,
+    /// Only return apps with this repository url, ignoring case
+    pub repository_url: Option<String>,
+    /// Return `{items, total}` instead of a bare array
+    pub envelope: Option<bool>,
 }
 
 pub struct Pagination {
@@ -15,6 +24,13 @@ pub struct Pagination {
     pub limit: i64,
 }
 
+// Filters for listing apps, an unset (or empty) filter matches everything
+#[derive(Debug, Default, Clone)]
+pub struct AppFilter {
+    pub name_contains: Option<String>,
+    pub repository_url: Option<String>,
+}
+
 // Create
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateFpAppSchema {
@@ -1,13 +1,36 @@
 use std::collections::BTreeMap;
 
-use k8s_openapi::api::core::v1::Service;
+use anyhow::Context;
+use k8s_openapi::api::apps::v1::{StatefulSet, StatefulSetSpec};
+use k8s_openapi::api::autoscaling::v2::{
+    CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerSpec, MetricSpec,
+    MetricTarget, ResourceMetricSource,
+};
+use k8s_openapi::api::core::v1::{
+    ConfigMap, ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, EnvVar,
+    LocalObjectReference, PersistentVolumeClaim, PersistentVolumeClaimSpec,
+    PersistentVolumeClaimVolumeSource, PodTemplateSpec, ResourceRequirements, Service, Volume,
+    VolumeMount, VolumeResourceRequirements,
+};
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
+    IngressServiceBackend, IngressSpec as IngressSpecK8s, IngressTLS, ServiceBackendPort,
+};
 use k8s_openapi::api::{apps::v1::Deployment, core::v1::ServicePort};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use sealed_common::cache::combine;
+use sealed_common::env_file::parse_env_file;
 use serde::{Deserialize, Serialize};
 
-use crate::error::SealedOperatorResult;
+use crate::error::{SealedOperatorError, SealedOperatorResult};
 
 use super::helpers::image_or_from_language;
 
+/// Pod template annotation carrying the hash of the app's ConfigMap data. Changing the
+/// ConfigMap changes the annotation, which in turn triggers a rolling update.
+pub const CONFIG_HASH_ANNOTATION: &str = "sealedinfra.io/config-hash";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
     pub name: String,
@@ -19,6 +42,140 @@ pub struct AppConfig {
     pub replicas: Option<i32>,
     pub labels: Option<BTreeMap<String, String>>,
     pub ports: Option<Vec<i32>>,
+    #[serde(default)]
+    pub init_containers: Vec<ContainerSpec>,
+    #[serde(default)]
+    pub sidecars: Vec<ContainerSpec>,
+    #[serde(default)]
+    pub volumes: Vec<VolumeSpec>,
+    #[serde(default)]
+    pub volume_mounts: Vec<VolumeMountSpec>,
+    /// Deploy as a StatefulSet instead of a Deployment
+    #[serde(default)]
+    pub stateful: bool,
+    pub ingress: Option<IngressSpec>,
+    /// Scale with CPU load instead of running a fixed number of `replicas`
+    pub autoscaling: Option<AutoscalingSpec>,
+    /// Secrets holding the credentials for pulling the app's images from private registries
+    pub image_pull_secrets: Option<Vec<String>>,
+}
+
+/// Exposes the app's service outside the cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngressSpec {
+    pub host: String,
+    /// Path prefix routed to the app, defaults to `/`
+    pub path: Option<String>,
+    /// Service port to route to, defaults to the first of the app's ports
+    pub port: Option<i32>,
+    /// Secret holding the TLS certificate for `host`
+    pub tls_secret: Option<String>,
+    pub ingress_class: Option<String>,
+}
+
+/// Bounds and target of the app's HorizontalPodAutoscaler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoscalingSpec {
+    pub min_replicas: i32,
+    pub max_replicas: i32,
+    /// Average CPU utilization to scale towards, as a percentage of the requested CPU
+    pub target_cpu_utilization: i32,
+    /// CPU requested by the app container, as a Kubernetes quantity (e.g. `250m`)
+    pub cpu_request: String,
+}
+
+/// A persistent volume requested by the app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeSpec {
+    pub name: String,
+    /// Requested storage, as a Kubernetes quantity (e.g. `1Gi`)
+    pub size: String,
+    pub storage_class: Option<String>,
+    pub access_modes: Option<Vec<String>>,
+}
+
+impl VolumeSpec {
+    fn to_persistent_volume_claim(
+        &self,
+        name: String,
+        labels: Option<BTreeMap<String, String>>,
+    ) -> SealedOperatorResult<PersistentVolumeClaim> {
+        if !is_quantity(&self.size) {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "volume {} has an invalid size {}",
+                self.name, self.size
+            )));
+        }
+
+        Ok(PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(name),
+                labels,
+                ..Default::default()
+            },
+            spec: Some(PersistentVolumeClaimSpec {
+                access_modes: Some(
+                    self.access_modes
+                        .clone()
+                        .unwrap_or_else(|| vec!["ReadWriteOnce".to_string()]),
+                ),
+                storage_class_name: self.storage_class.clone(),
+                resources: Some(VolumeResourceRequirements {
+                    requests: Some(BTreeMap::from([(
+                        "storage".to_string(),
+                        Quantity(self.size.clone()),
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// Mounts one of the app's volumes into the app container.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeMountSpec {
+    /// Name of the volume to mount
+    pub name: String,
+    pub mount_path: String,
+    pub read_only: Option<bool>,
+}
+
+/// An extra container run alongside the app, either as an init container or a sidecar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerSpec {
+    pub name: String,
+    pub image: String,
+    pub command: Option<Vec<String>>,
+    pub environment: Option<Vec<String>>,
+    pub ports: Option<Vec<i32>>,
+    /// CPU requested by the container, as a Kubernetes quantity. Required for sidecars of
+    /// autoscaled apps, as utilization is measured against every container in the pod.
+    pub cpu_request: Option<String>,
+}
+
+impl ContainerSpec {
+    fn to_container(&self) -> Container {
+        Container {
+            name: self.name.clone(),
+            image: Some(self.image.clone()),
+            command: self.command.clone(),
+            env: Some(env_vars(&self.environment)),
+            ports: self.ports.as_ref().map(|ports| {
+                ports
+                    .iter()
+                    .map(|port| ContainerPort {
+                        container_port: *port,
+                        ..Default::default()
+                    })
+                    .collect()
+            }),
+            resources: self.cpu_request.as_deref().map(cpu_requests),
+            ..Default::default()
+        }
+    }
 }
 
 impl AppConfig {
@@ -36,54 +193,433 @@ impl AppConfig {
     /// The function `into_deployment` returns a `SealedResult<Deployment>`, where `Deployment` is a
     /// Kubernetes deployment object.
     pub fn into_deployment(&self) -> SealedOperatorResult<Deployment> {
-        let mut env = vec![];
-        if let Some(env_vars) = &self.environment {
-            for env_var in env_vars {
-                let parts: Vec<&str> = env_var.splitn(2, '=').collect();
-                if parts.len() == 2 {
-                    env.push(k8s_openapi::api::core::v1::EnvVar {
-                        name: parts[0].to_string(),
-                        value: Some(parts[1].to_string()),
-                        ..Default::default()
-                    });
-                }
-            }
-        }
-
-        let image = image_or_from_language(self.image.clone(), &self.name);
         let metadata = self.generate_metadata();
 
-        let replicas = self.replicas.unwrap_or(1);
-        let selector = k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector {
-            match_labels: Some(self.generate_labels()),
-            ..Default::default()
-        };
+        let mut template = self.pod_template()?;
+        if let Some(spec) = template.spec.as_mut() {
+            spec.volumes = self.pod_volumes();
+        }
 
         let deployment = Deployment {
             metadata,
             spec: Some(k8s_openapi::api::apps::v1::DeploymentSpec {
-                replicas: Some(replicas),
-                selector,
-                template: k8s_openapi::api::core::v1::PodTemplateSpec {
-                    metadata: Some(kube::api::ObjectMeta {
-                        labels: Some(self.generate_labels()),
-                        ..Default::default()
-                    }),
-                    spec: Some(k8s_openapi::api::core::v1::PodSpec {
-                        containers: vec![k8s_openapi::api::core::v1::Container {
-                            name: self.name.clone(),
-                            image: Some(image),
-                            env: Some(env),
+                replicas: self.static_replicas(),
+                selector: self.generate_selector(),
+                template,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        Ok(deployment)
+    }
+
+    /// Converts a `stateful` app into a `StatefulSet`. Each volume becomes a volume claim
+    /// template, so every replica gets its own claim rather than sharing one.
+    pub fn into_stateful_set(&self) -> SealedOperatorResult<StatefulSet> {
+        let volume_claim_templates = self
+            .volumes
+            .iter()
+            .map(|volume| volume.to_persistent_volume_claim(volume.name.clone(), None))
+            .collect::<SealedOperatorResult<Vec<_>>>()?;
+
+        let stateful_set = StatefulSet {
+            metadata: self.generate_metadata(),
+            spec: Some(StatefulSetSpec {
+                replicas: self.static_replicas(),
+                selector: self.generate_selector(),
+                service_name: self.name.clone(),
+                template: self.pod_template()?,
+                volume_claim_templates: if volume_claim_templates.is_empty() {
+                    None
+                } else {
+                    Some(volume_claim_templates)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        Ok(stateful_set)
+    }
+
+    /// The replica count of the workload, unless the HorizontalPodAutoscaler owns it.
+    fn static_replicas(&self) -> Option<i32> {
+        match self.autoscaling {
+            Some(_) => None,
+            None => Some(self.replicas.unwrap_or(1)),
+        }
+    }
+
+    /// Builds the HorizontalPodAutoscaler scaling the app's workload on CPU utilization, if the
+    /// app has autoscaling configured.
+    pub fn into_hpa(&self) -> SealedOperatorResult<Option<HorizontalPodAutoscaler>> {
+        let autoscaling = match &self.autoscaling {
+            Some(autoscaling) => autoscaling,
+            None => return Ok(None),
+        };
+
+        if autoscaling.min_replicas < 1 || autoscaling.min_replicas > autoscaling.max_replicas {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} has invalid autoscaling bounds: min {} must be at least 1 and at most max {}",
+                self.name, autoscaling.min_replicas, autoscaling.max_replicas
+            )));
+        }
+        if autoscaling.target_cpu_utilization < 1 {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} has an invalid autoscaling CPU target {}",
+                self.name, autoscaling.target_cpu_utilization
+            )));
+        }
+        if !is_quantity(&autoscaling.cpu_request) {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} has an invalid autoscaling CPU request {}",
+                self.name, autoscaling.cpu_request
+            )));
+        }
+        // Utilization is undefined unless every container in the pod requests CPU
+        if let Some(sidecar) = self.sidecars.iter().find(|s| s.cpu_request.is_none()) {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} autoscales on CPU but sidecar {} has no CPU request",
+                self.name, sidecar.name
+            )));
+        }
+
+        let kind = if self.stateful {
+            "StatefulSet"
+        } else {
+            "Deployment"
+        };
+        Ok(Some(HorizontalPodAutoscaler {
+            metadata: self.generate_metadata(),
+            spec: Some(HorizontalPodAutoscalerSpec {
+                scale_target_ref: CrossVersionObjectReference {
+                    api_version: Some("apps/v1".to_string()),
+                    kind: kind.to_string(),
+                    name: self.name.clone(),
+                },
+                min_replicas: Some(autoscaling.min_replicas),
+                max_replicas: autoscaling.max_replicas,
+                metrics: Some(vec![MetricSpec {
+                    type_: "Resource".to_string(),
+                    resource: Some(ResourceMetricSource {
+                        name: "cpu".to_string(),
+                        target: MetricTarget {
+                            type_: "Utilization".to_string(),
+                            average_utilization: Some(autoscaling.target_cpu_utilization),
                             ..Default::default()
+                        },
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+    }
+
+    /// The claims backing the app's volumes. Stateful apps get theirs from the StatefulSet's
+    /// claim templates instead, so this is empty for them.
+    pub fn into_persistent_volume_claims(
+        &self,
+    ) -> SealedOperatorResult<Vec<PersistentVolumeClaim>> {
+        if self.stateful {
+            return Ok(vec![]);
+        }
+        self.volumes
+            .iter()
+            .map(|volume| {
+                volume.to_persistent_volume_claim(
+                    self.claim_name(volume),
+                    Some(self.generate_labels()),
+                )
+            })
+            .collect()
+    }
+
+    /// Builds the Ingress routing `ingress.host`/`ingress.path` to the app's service, if the
+    /// app has an ingress configured.
+    pub fn into_ingress(&self) -> SealedOperatorResult<Option<Ingress>> {
+        let ingress = match &self.ingress {
+            Some(ingress) => ingress,
+            None => return Ok(None),
+        };
+
+        let ports = self.ports.as_deref().unwrap_or_default();
+        let port = match ingress.port.or_else(|| ports.first().copied()) {
+            Some(port) if ports.contains(&port) => port,
+            Some(port) => {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "ingress port {} is not one of the ports of {}",
+                    port, self.name
+                )))
+            }
+            None => {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "ingress for {} needs the app to expose a port",
+                    self.name
+                )))
+            }
+        };
+
+        let backend = IngressBackend {
+            service: Some(IngressServiceBackend {
+                name: self.name.clone(),
+                port: Some(ServiceBackendPort {
+                    number: Some(port),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        };
+
+        Ok(Some(Ingress {
+            metadata: ObjectMeta {
+                name: Some(self.name.clone()),
+                labels: Some(self.generate_labels()),
+                ..Default::default()
+            },
+            spec: Some(IngressSpecK8s {
+                ingress_class_name: ingress.ingress_class.clone(),
+                rules: Some(vec![IngressRule {
+                    host: Some(ingress.host.clone()),
+                    http: Some(HTTPIngressRuleValue {
+                        paths: vec![HTTPIngressPath {
+                            path: Some(ingress.path.clone().unwrap_or_else(|| "/".to_string())),
+                            path_type: "Prefix".to_string(),
+                            backend,
                         }],
-                        ..Default::default()
                     }),
-                },
+                }]),
+                tls: ingress.tls_secret.as_ref().map(|secret| {
+                    vec![IngressTLS {
+                        hosts: Some(vec![ingress.host.clone()]),
+                        secret_name: Some(secret.clone()),
+                    }]
+                }),
                 ..Default::default()
             }),
             ..Default::default()
+        }))
+    }
+
+    /// Builds every manifest the app produces and collects the problems found, instead of stopping
+    /// at the first one like `render`.
+    pub fn violations(&self) -> Vec<String> {
+        let workload = if self.stateful {
+            self.into_stateful_set().map(drop)
+        } else {
+            self.into_deployment().map(drop)
         };
-        Ok(deployment)
+        [
+            self.into_config_map().map(drop),
+            self.into_persistent_volume_claims().map(drop),
+            workload,
+            self.into_hpa().map(drop),
+            self.into_service().map(drop),
+            self.into_ingress().map(drop),
+        ]
+        .into_iter()
+        .filter_map(Result::err)
+        .map(|error| match error {
+            SealedOperatorError::InvalidConfig(violation) => violation,
+            error => error.to_string(),
+        })
+        .collect()
+    }
+
+    /// Renders every manifest the app produces as a multi-document YAML string, without
+    /// talking to a cluster.
+    pub fn render(&self) -> SealedOperatorResult<String> {
+        let mut docs = vec![];
+        if let Some(config_map) = self.into_config_map()? {
+            docs.push(serde_yaml::to_string(&config_map)?);
+        }
+        for pvc in self.into_persistent_volume_claims()? {
+            docs.push(serde_yaml::to_string(&pvc)?);
+        }
+        if self.stateful {
+            docs.push(serde_yaml::to_string(&self.into_stateful_set()?)?);
+        } else {
+            docs.push(serde_yaml::to_string(&self.into_deployment()?)?);
+        }
+        if let Some(hpa) = self.into_hpa()? {
+            docs.push(serde_yaml::to_string(&hpa)?);
+        }
+        docs.push(serde_yaml::to_string(&self.into_service()?)?);
+        if let Some(ingress) = self.into_ingress()? {
+            docs.push(serde_yaml::to_string(&ingress)?);
+        }
+        Ok(docs.join("---\n"))
+    }
+
+    /// The app's container image. The compose file and the k8s manifests have to agree on it.
+    fn container_image(&self) -> String {
+        image_or_from_language(
+            self.image.clone(),
+            self.language.as_deref().unwrap_or(&self.name),
+        )
+    }
+
+    fn compose_service(&self) -> ComposeService {
+        let mut depends_on = self.dependencies.clone();
+        depends_on.sort();
+        depends_on.dedup();
+
+        ComposeService {
+            image: self.container_image(),
+            ports: self
+                .ports
+                .iter()
+                .flatten()
+                .map(|port| format!("{}:{}", port, port))
+                .collect(),
+            environment: env_vars(&self.environment)
+                .into_iter()
+                .map(|env| (env.name, env.value.unwrap_or_default()))
+                .collect(),
+            env_file: self.env_file.iter().cloned().collect(),
+            depends_on,
+        }
+    }
+
+    fn claim_name(&self, volume: &VolumeSpec) -> String {
+        format!("{}-{}", self.name, volume.name)
+    }
+
+    fn pod_template(&self) -> SealedOperatorResult<PodTemplateSpec> {
+        self.validate_containers()?;
+        self.validate_volume_mounts()?;
+
+        let env = env_vars(&self.environment);
+        let image = self.container_image();
+        let annotations = self
+            .config_map_data()?
+            .map(|data| BTreeMap::from([(CONFIG_HASH_ANNOTATION.to_string(), config_hash(&data))]));
+
+        let volume_mounts: Vec<VolumeMount> = self
+            .volume_mounts
+            .iter()
+            .map(|mount| VolumeMount {
+                name: mount.name.clone(),
+                mount_path: mount.mount_path.clone(),
+                read_only: mount.read_only,
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(PodTemplateSpec {
+            metadata: Some(kube::api::ObjectMeta {
+                labels: Some(self.generate_labels()),
+                annotations,
+                ..Default::default()
+            }),
+            spec: Some(k8s_openapi::api::core::v1::PodSpec {
+                init_containers: if self.init_containers.is_empty() {
+                    None
+                } else {
+                    Some(
+                        self.init_containers
+                            .iter()
+                            .map(ContainerSpec::to_container)
+                            .collect(),
+                    )
+                },
+                containers: std::iter::once(Container {
+                    name: self.name.clone(),
+                    image: Some(image),
+                    env: Some(env),
+                    // Variables in `env` take precedence over those from `envFrom`
+                    env_from: self.env_file.as_ref().map(|_| {
+                        vec![EnvFromSource {
+                            config_map_ref: Some(ConfigMapEnvSource {
+                                name: self.config_map_name(),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }]
+                    }),
+                    volume_mounts: if volume_mounts.is_empty() {
+                        None
+                    } else {
+                        Some(volume_mounts)
+                    },
+                    resources: self
+                        .autoscaling
+                        .as_ref()
+                        .map(|autoscaling| cpu_requests(&autoscaling.cpu_request)),
+                    ..Default::default()
+                })
+                .chain(self.sidecars.iter().map(ContainerSpec::to_container))
+                .collect(),
+                image_pull_secrets: self.image_pull_secrets.as_ref().map(|secrets| {
+                    secrets
+                        .iter()
+                        .map(|name| LocalObjectReference { name: name.clone() })
+                        .collect()
+                }),
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn pod_volumes(&self) -> Option<Vec<Volume>> {
+        if self.volumes.is_empty() {
+            return None;
+        }
+        let volumes = self
+            .volumes
+            .iter()
+            .map(|volume| Volume {
+                name: volume.name.clone(),
+                persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                    claim_name: self.claim_name(volume),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect();
+        Some(volumes)
+    }
+
+    fn generate_selector(&self) -> LabelSelector {
+        LabelSelector {
+            match_labels: Some(self.generate_labels()),
+            ..Default::default()
+        }
+    }
+
+    /// Every volume mount has to refer to one of the app's volumes.
+    fn validate_volume_mounts(&self) -> SealedOperatorResult<()> {
+        for mount in &self.volume_mounts {
+            if !self.volumes.iter().any(|volume| volume.name == mount.name) {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "volume mount {} does not refer to a defined volume",
+                    mount.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Init container and sidecar names must be valid DNS-1123 labels and unique within the
+    /// pod, including the app container itself.
+    fn validate_containers(&self) -> SealedOperatorResult<()> {
+        let mut names = vec![self.name.as_str()];
+        for container in self.init_containers.iter().chain(self.sidecars.iter()) {
+            if !is_dns1123_label(&container.name) {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "container name {} is not a valid DNS-1123 label",
+                    container.name
+                )));
+            }
+            if names.contains(&container.name.as_str()) {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "container name {} is used more than once",
+                    container.name
+                )));
+            }
+            names.push(&container.name);
+        }
+        Ok(())
     }
 
     fn generate_labels(&self) -> BTreeMap<String, String> {
@@ -101,6 +637,39 @@ impl AppConfig {
         }
     }
 
+    pub fn config_map_name(&self) -> String {
+        format!("{}-config", self.name)
+    }
+
+    /// Reads the variables of the `env_file` (if any) into the data of the app's ConfigMap, which
+    /// the containers load through `envFrom`.
+    pub fn config_map_data(&self) -> SealedOperatorResult<Option<BTreeMap<String, String>>> {
+        match &self.env_file {
+            Some(env_file) => {
+                let data = std::fs::read_to_string(env_file)
+                    .with_context(|| format!("unable to read env file {}", env_file))?;
+                let vars = parse_env_file(&data).map_err(|e| {
+                    SealedOperatorError::InvalidConfig(format!("env file {}: {}", env_file, e))
+                })?;
+                Ok(Some(vars.into_iter().collect()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn into_config_map(&self) -> SealedOperatorResult<Option<ConfigMap>> {
+        let config_map = self.config_map_data()?.map(|data| ConfigMap {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some(self.config_map_name()),
+                labels: Some(self.generate_labels()),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        });
+        Ok(config_map)
+    }
+
     pub fn into_service(&self) -> SealedOperatorResult<Service> {
         let ports: Vec<ServicePort> = self
             .ports
@@ -128,3 +697,677 @@ impl AppConfig {
         Ok(service)
     }
 }
+
+#[derive(Debug, Serialize)]
+struct ComposeFile {
+    services: BTreeMap<String, ComposeService>,
+}
+
+/// Fields are serialized in declaration order and the maps are `BTreeMap`s, so the output only
+/// changes when the apps do.
+#[derive(Debug, Serialize)]
+struct ComposeService {
+    image: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    env_file: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+}
+
+/// Renders a `docker-compose.yml` running every app, as a local equivalent of deploying them.
+/// Each app becomes a service named after it, publishing its ports on the same host ports and
+/// depending on the services of its `dependencies`, which must be among `apps`.
+pub fn to_compose(apps: &[AppConfig]) -> SealedOperatorResult<String> {
+    let mut services = BTreeMap::new();
+    for app in apps {
+        if services
+            .insert(app.name.clone(), app.compose_service())
+            .is_some()
+        {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} is defined more than once",
+                app.name
+            )));
+        }
+    }
+
+    for app in apps {
+        if let Some(missing) = app
+            .dependencies
+            .iter()
+            .find(|dependency| !services.contains_key(*dependency))
+        {
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "app {} depends on {}, which is not one of the apps",
+                app.name, missing
+            )));
+        }
+    }
+
+    Ok(serde_yaml::to_string(&ComposeFile { services })?)
+}
+
+/// Parses `KEY=VALUE` entries into container environment variables, skipping malformed ones.
+fn env_vars(environment: &Option<Vec<String>>) -> Vec<EnvVar> {
+    environment
+        .iter()
+        .flatten()
+        .filter_map(|env_var| env_var.split_once('='))
+        .map(|(name, value)| EnvVar {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// A DNS-1123 label is at most 63 lowercase alphanumeric characters or '-', starting and
+/// ending with an alphanumeric character.
+fn is_dns1123_label(name: &str) -> bool {
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    !name.is_empty()
+        && name.len() <= 63
+        && name.chars().all(|c| valid_char(c) || c == '-')
+        && name.starts_with(valid_char)
+        && name.ends_with(valid_char)
+}
+
+/// Checks `value` is a Kubernetes quantity: a decimal number with an optional binary
+/// (`Ki`, `Mi`, ...) or decimal (`k`, `M`, ...) suffix, e.g. `500Mi` or `1.5G`.
+fn is_quantity(value: &str) -> bool {
+    const SUFFIXES: [&str; 12] = [
+        "Ki", "Mi", "Gi", "Ti", "Pi", "Ei", "m", "k", "M", "G", "T", "P",
+    ];
+    let number = SUFFIXES
+        .iter()
+        .find_map(|suffix| value.strip_suffix(suffix))
+        .or_else(|| value.strip_suffix('E'))
+        .unwrap_or(value);
+
+    let mut parts = number.splitn(2, '.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next();
+    !whole.is_empty()
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.is_none_or(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Resource requirements requesting `cpu`, which the HorizontalPodAutoscaler measures
+/// utilization against.
+fn cpu_requests(cpu: &str) -> ResourceRequirements {
+    ResourceRequirements {
+        requests: Some(BTreeMap::from([(
+            "cpu".to_string(),
+            Quantity(cpu.to_string()),
+        )])),
+        ..Default::default()
+    }
+}
+
+/// Computes a stable hash of ConfigMap data. `BTreeMap` iterates in key order, so the
+/// hash only changes when the keys or values do.
+pub fn config_hash(data: &BTreeMap<String, String>) -> String {
+    data.iter().fold(String::new(), |acc, (key, value)| {
+        combine(&acc, &combine(key, value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app_config(env_file: Option<String>) -> AppConfig {
+        AppConfig {
+            name: "test-app".to_string(),
+            image: Some("nginx:latest".to_string()),
+            language: None,
+            dependencies: vec![],
+            environment: None,
+            env_file,
+            replicas: None,
+            labels: None,
+            ports: Some(vec![80]),
+            init_containers: vec![],
+            sidecars: vec![],
+            volumes: vec![],
+            volume_mounts: vec![],
+            stateful: false,
+            ingress: None,
+            autoscaling: None,
+            image_pull_secrets: None,
+        }
+    }
+
+    fn test_ingress(port: Option<i32>) -> IngressSpec {
+        IngressSpec {
+            host: "app.example.com".to_string(),
+            path: Some("/api".to_string()),
+            port,
+            tls_secret: Some("app-tls".to_string()),
+            ingress_class: Some("nginx".to_string()),
+        }
+    }
+
+    fn test_volume(size: &str) -> VolumeSpec {
+        VolumeSpec {
+            name: "data".to_string(),
+            size: size.to_string(),
+            storage_class: Some("standard".to_string()),
+            access_modes: None,
+        }
+    }
+
+    fn test_volume_mount() -> VolumeMountSpec {
+        VolumeMountSpec {
+            name: "data".to_string(),
+            mount_path: "/var/lib/data".to_string(),
+            read_only: None,
+        }
+    }
+
+    fn test_container(name: &str) -> ContainerSpec {
+        ContainerSpec {
+            name: name.to_string(),
+            image: "busybox:latest".to_string(),
+            command: None,
+            environment: None,
+            ports: None,
+            cpu_request: None,
+        }
+    }
+
+    fn pod_annotations(deployment: &Deployment) -> Option<BTreeMap<String, String>> {
+        deployment
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.template.metadata.as_ref())
+            .and_then(|metadata| metadata.annotations.clone())
+    }
+
+    #[test]
+    fn test_deployment_without_env_file_has_no_config_hash() {
+        let deployment = test_app_config(None).into_deployment().unwrap();
+        assert!(pod_annotations(&deployment).is_none());
+    }
+
+    #[test]
+    fn test_config_change_alters_pod_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        let app = test_app_config(Some(env_file.to_string_lossy().to_string()));
+
+        std::fs::write(&env_file, "FOO=bar\n").unwrap();
+        let before = app.into_deployment().unwrap();
+        std::fs::write(&env_file, "FOO=baz\n").unwrap();
+        let after = app.into_deployment().unwrap();
+
+        let before_hash = pod_annotations(&before).unwrap()[CONFIG_HASH_ANNOTATION].clone();
+        let after_hash = pod_annotations(&after).unwrap()[CONFIG_HASH_ANNOTATION].clone();
+        assert_ne!(before_hash, after_hash);
+        assert_ne!(before.spec.unwrap().template, after.spec.unwrap().template);
+    }
+
+    fn test_autoscaling(min_replicas: i32, max_replicas: i32) -> AutoscalingSpec {
+        AutoscalingSpec {
+            min_replicas,
+            max_replicas,
+            target_cpu_utilization: 70,
+            cpu_request: "250m".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hpa_targets_deployment() {
+        let mut app = test_app_config(None);
+        app.replicas = Some(3);
+        app.autoscaling = Some(test_autoscaling(2, 10));
+
+        let deployment = app.into_deployment().unwrap();
+        let hpa = app.into_hpa().unwrap().unwrap();
+        let spec = hpa.spec.unwrap();
+
+        assert_eq!(spec.scale_target_ref.kind, "Deployment");
+        assert_eq!(Some(spec.scale_target_ref.name), deployment.metadata.name);
+        assert_eq!(spec.min_replicas, Some(2));
+        assert_eq!(spec.max_replicas, 10);
+        let target = &spec.metrics.unwrap()[0].resource.clone().unwrap();
+        assert_eq!(target.name, "cpu");
+        assert_eq!(target.target.average_utilization, Some(70));
+
+        // The HPA owns the replica count, and utilization is measured against the CPU request
+        let deployment_spec = deployment.spec.unwrap();
+        assert_eq!(deployment_spec.replicas, None);
+        let container = &deployment_spec.template.spec.unwrap().containers[0];
+        let requests = container.resources.as_ref().unwrap().requests.as_ref();
+        assert_eq!(requests.unwrap()["cpu"], Quantity("250m".to_string()));
+    }
+
+    #[test]
+    fn test_hpa_requires_cpu_requests() {
+        let mut app = test_app_config(None);
+        app.autoscaling = Some(AutoscalingSpec {
+            cpu_request: "lots".to_string(),
+            ..test_autoscaling(1, 3)
+        });
+        assert!(app.into_hpa().is_err());
+
+        app.autoscaling = Some(test_autoscaling(1, 3));
+        app.sidecars = vec![test_container("proxy")];
+        assert!(app.into_hpa().is_err());
+
+        app.sidecars[0].cpu_request = Some("100m".to_string());
+        assert!(app.into_hpa().unwrap().is_some());
+        let pod_spec = app
+            .into_deployment()
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap();
+        let requests = pod_spec.containers[1].resources.as_ref().unwrap();
+        assert_eq!(
+            requests.requests.as_ref().unwrap()["cpu"],
+            Quantity("100m".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hpa_requires_min_at_most_max() {
+        let mut app = test_app_config(None);
+        app.autoscaling = Some(test_autoscaling(5, 2));
+        assert!(matches!(
+            app.into_hpa(),
+            Err(SealedOperatorError::InvalidConfig(_))
+        ));
+
+        app.autoscaling = Some(test_autoscaling(0, 2));
+        assert!(app.into_hpa().is_err());
+    }
+
+    #[test]
+    fn test_violations_are_collected() {
+        let mut app = test_app_config(None);
+        assert!(app.violations().is_empty());
+
+        app.autoscaling = Some(test_autoscaling(5, 2));
+        app.ingress = Some(test_ingress(Some(8080)));
+        let violations = app.violations();
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("autoscaling"));
+        assert!(violations[1].contains("8080"));
+    }
+
+    #[test]
+    fn test_no_hpa_without_autoscaling() {
+        let app = test_app_config(None);
+        assert!(app.into_hpa().unwrap().is_none());
+        assert_eq!(
+            app.into_deployment().unwrap().spec.unwrap().replicas,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_image_pull_secrets() {
+        let pod_spec = |app: &AppConfig| app.into_deployment().unwrap().spec.unwrap().template.spec;
+
+        let mut app = test_app_config(None);
+        assert!(pod_spec(&app).unwrap().image_pull_secrets.is_none());
+
+        app.image_pull_secrets = Some(vec!["registry".to_string(), "mirror".to_string()]);
+        let secrets = pod_spec(&app).unwrap().image_pull_secrets.unwrap();
+        assert_eq!(
+            secrets
+                .iter()
+                .map(|secret| secret.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["registry", "mirror"]
+        );
+    }
+
+    #[test]
+    fn test_env_file_is_loaded_from_config_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        std::fs::write(
+            &env_file,
+            "# database\nexport DB_HOST=db\nDB_NAME=\"app\"\n\nDEBUG=false\n",
+        )
+        .unwrap();
+        let mut app = test_app_config(Some(env_file.to_string_lossy().to_string()));
+        app.environment = Some(vec!["DEBUG=true".to_string()]);
+
+        let pod_spec = app
+            .into_deployment()
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap();
+        let container = &pod_spec.containers[0];
+        let env_from = container.env_from.as_ref().unwrap();
+        assert_eq!(env_from.len(), 1);
+        assert_eq!(
+            env_from[0].config_map_ref.as_ref().unwrap().name,
+            "test-app-config"
+        );
+        // `environment` is still inlined, overriding the ConfigMap
+        assert_eq!(
+            container.env.as_ref().unwrap()[0].value.as_deref(),
+            Some("true")
+        );
+
+        assert_eq!(
+            app.config_map_data().unwrap().unwrap(),
+            BTreeMap::from([
+                ("DB_HOST".to_string(), "db".to_string()),
+                ("DB_NAME".to_string(), "app".to_string()),
+                ("DEBUG".to_string(), "false".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_no_env_from_without_env_file() {
+        let deployment = test_app_config(None).into_deployment().unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.containers[0].env_from.is_none());
+    }
+
+    #[test]
+    fn test_config_hash_is_stable() {
+        let data = BTreeMap::from([
+            ("env".to_string(), "FOO=bar".to_string()),
+            ("other".to_string(), "value".to_string()),
+        ]);
+        assert_eq!(config_hash(&data), config_hash(&data.clone()));
+    }
+
+    #[test]
+    fn test_deployment_with_init_container_and_sidecar() {
+        let mut app = test_app_config(None);
+        app.init_containers = vec![ContainerSpec {
+            command: Some(vec!["./migrate".to_string()]),
+            environment: Some(vec!["DATABASE_URL=postgres://db".to_string()]),
+            ..test_container("migrate")
+        }];
+        app.sidecars = vec![ContainerSpec {
+            ports: Some(vec![8080]),
+            ..test_container("proxy")
+        }];
+
+        let pod_spec = app
+            .into_deployment()
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap();
+
+        let init_containers = pod_spec.init_containers.unwrap();
+        assert_eq!(init_containers.len(), 1);
+        assert_eq!(init_containers[0].name, "migrate");
+        assert_eq!(
+            init_containers[0].command,
+            Some(vec!["./migrate".to_string()])
+        );
+        assert_eq!(
+            init_containers[0].env.as_ref().unwrap()[0].name,
+            "DATABASE_URL"
+        );
+
+        let names: Vec<&str> = pod_spec
+            .containers
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["test-app", "proxy"]);
+        assert_eq!(
+            pod_spec.containers[1].ports.as_ref().unwrap()[0].container_port,
+            8080
+        );
+    }
+
+    #[test]
+    fn test_deployment_rejects_duplicate_container_names() {
+        let mut app = test_app_config(None);
+        app.init_containers = vec![test_container("proxy")];
+        app.sidecars = vec![test_container("proxy")];
+        assert!(app.into_deployment().is_err());
+
+        let mut app = test_app_config(None);
+        app.sidecars = vec![test_container("test-app")];
+        assert!(app.into_deployment().is_err());
+    }
+
+    #[test]
+    fn test_deployment_rejects_invalid_container_names() {
+        for name in ["Proxy", "-proxy", "proxy_1", ""] {
+            let mut app = test_app_config(None);
+            app.sidecars = vec![test_container(name)];
+            assert!(
+                app.into_deployment().is_err(),
+                "{} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_persistent_volume_claims_and_mounts() {
+        let mut app = test_app_config(None);
+        app.volumes = vec![test_volume("1Gi")];
+        app.volume_mounts = vec![test_volume_mount()];
+
+        let claims = app.into_persistent_volume_claims().unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].metadata.name, Some("test-app-data".to_string()));
+        let claim_spec = claims[0].spec.as_ref().unwrap();
+        assert_eq!(claim_spec.storage_class_name, Some("standard".to_string()));
+        assert_eq!(
+            claim_spec.access_modes,
+            Some(vec!["ReadWriteOnce".to_string()])
+        );
+        let requests = claim_spec
+            .resources
+            .as_ref()
+            .unwrap()
+            .requests
+            .as_ref()
+            .unwrap();
+        assert_eq!(requests["storage"], Quantity("1Gi".to_string()));
+
+        let pod_spec = app
+            .into_deployment()
+            .unwrap()
+            .spec
+            .unwrap()
+            .template
+            .spec
+            .unwrap();
+        let volumes = pod_spec.volumes.unwrap();
+        assert_eq!(volumes[0].name, "data");
+        assert_eq!(
+            volumes[0]
+                .persistent_volume_claim
+                .as_ref()
+                .unwrap()
+                .claim_name,
+            "test-app-data"
+        );
+        let mounts = pod_spec.containers[0].volume_mounts.as_ref().unwrap();
+        assert_eq!(mounts[0].name, "data");
+        assert_eq!(mounts[0].mount_path, "/var/lib/data");
+    }
+
+    #[test]
+    fn test_stateful_app_uses_claim_templates() {
+        let mut app = test_app_config(None);
+        app.stateful = true;
+        app.volumes = vec![test_volume("500Mi")];
+        app.volume_mounts = vec![test_volume_mount()];
+
+        assert!(app.into_persistent_volume_claims().unwrap().is_empty());
+
+        let spec = app.into_stateful_set().unwrap().spec.unwrap();
+        let templates = spec.volume_claim_templates.unwrap();
+        assert_eq!(templates[0].metadata.name, Some("data".to_string()));
+        assert!(spec.template.spec.unwrap().volumes.is_none());
+    }
+
+    #[test]
+    fn test_invalid_volume_size_is_rejected() {
+        for size in ["1GB", "Gi", "1.Gi", "-1Gi", ""] {
+            let mut app = test_app_config(None);
+            app.volumes = vec![test_volume(size)];
+            assert!(
+                app.into_persistent_volume_claims().is_err(),
+                "{} should be rejected",
+                size
+            );
+        }
+        for size in ["1Gi", "1.5G", "100", "250m"] {
+            assert!(is_quantity(size), "{} should be accepted", size);
+        }
+    }
+
+    #[test]
+    fn test_mount_of_unknown_volume_is_rejected() {
+        let mut app = test_app_config(None);
+        app.volume_mounts = vec![test_volume_mount()];
+        assert!(app.into_deployment().is_err());
+    }
+
+    #[test]
+    fn test_ingress_for_host_and_path() {
+        let mut app = test_app_config(None);
+        app.ingress = Some(test_ingress(None));
+
+        let ingress = app.into_ingress().unwrap().unwrap();
+        assert_eq!(ingress.metadata.name, Some("test-app".to_string()));
+        let spec = ingress.spec.unwrap();
+        assert_eq!(spec.ingress_class_name, Some("nginx".to_string()));
+
+        let rule = &spec.rules.unwrap()[0];
+        assert_eq!(rule.host, Some("app.example.com".to_string()));
+        let path = &rule.http.as_ref().unwrap().paths[0];
+        assert_eq!(path.path, Some("/api".to_string()));
+        assert_eq!(path.path_type, "Prefix");
+        let service = path.backend.service.as_ref().unwrap();
+        assert_eq!(service.name, "test-app");
+        assert_eq!(service.port.as_ref().unwrap().number, Some(80));
+
+        let tls = &spec.tls.unwrap()[0];
+        assert_eq!(tls.secret_name, Some("app-tls".to_string()));
+        assert_eq!(tls.hosts, Some(vec!["app.example.com".to_string()]));
+    }
+
+    #[test]
+    fn test_ingress_port_must_be_exposed() {
+        let mut app = test_app_config(None);
+        app.ingress = Some(test_ingress(Some(8080)));
+        assert!(app.into_ingress().is_err());
+
+        app.ports = None;
+        app.ingress = Some(test_ingress(None));
+        assert!(app.into_ingress().is_err());
+    }
+
+    #[test]
+    fn test_no_ingress_configured() {
+        assert!(test_app_config(None).into_ingress().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_render_round_trips_through_multidoc_deserialize() {
+        let mut app = test_app_config(None);
+        app.volumes = vec![test_volume("1Gi")];
+        app.volume_mounts = vec![test_volume_mount()];
+        app.ingress = Some(test_ingress(None));
+
+        let rendered = app.render().unwrap();
+        let docs = crate::installer::multidoc_deserialize(&rendered).unwrap();
+        let kinds: Vec<&str> = docs
+            .iter()
+            .map(|doc| doc["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["PersistentVolumeClaim", "Deployment", "Service", "Ingress"]
+        );
+
+        let deployment: Deployment = serde_yaml::from_value(docs[1].clone()).unwrap();
+        assert_eq!(deployment, app.into_deployment().unwrap());
+    }
+
+    #[test]
+    fn test_compose_with_dependency() {
+        let mut db = test_app_config(None);
+        db.name = "db".to_string();
+        db.image = Some("postgres:16".to_string());
+        db.ports = Some(vec![5432]);
+        db.environment = Some(vec!["POSTGRES_PASSWORD=postgres".to_string()]);
+
+        let mut api = test_app_config(None);
+        api.name = "api".to_string();
+        api.ports = Some(vec![8080, 9090]);
+        api.dependencies = vec!["db".to_string()];
+        api.environment = Some(vec![
+            "RUST_LOG=info".to_string(),
+            "DATABASE_URL=postgres://db:5432/app".to_string(),
+        ]);
+
+        let compose = to_compose(&[db, api]).unwrap();
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&compose).unwrap();
+        let yaml = |value: &str| serde_yaml::from_str::<serde_yaml::Value>(value).unwrap();
+
+        let api = &parsed["services"]["api"];
+        assert_eq!(api["image"], yaml("nginx:latest"));
+        assert_eq!(api["ports"], yaml(r#"["8080:8080", "9090:9090"]"#));
+        assert_eq!(api["depends_on"], yaml("[db]"));
+        assert_eq!(
+            api["environment"],
+            yaml("{DATABASE_URL: 'postgres://db:5432/app', RUST_LOG: info}")
+        );
+
+        let db = &parsed["services"]["db"];
+        assert_eq!(db["image"], yaml("postgres:16"));
+        assert_eq!(db["ports"], yaml(r#"["5432:5432"]"#));
+        assert!(db.get("depends_on").is_none());
+
+        // Services are in alphabetical order
+        assert!(compose.find("  api:").unwrap() < compose.find("  db:").unwrap());
+    }
+
+    #[test]
+    fn test_compose_is_deterministic() {
+        let app = |name: &str| {
+            let mut app = test_app_config(None);
+            app.name = name.to_string();
+            app
+        };
+
+        assert_eq!(
+            to_compose(&[app("b"), app("a")]).unwrap(),
+            to_compose(&[app("a"), app("b")]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compose_rejects_unknown_dependency() {
+        let mut app = test_app_config(None);
+        app.dependencies = vec!["cache".to_string()];
+
+        assert!(matches!(
+            to_compose(&[app]),
+            Err(SealedOperatorError::InvalidConfig(message)) if message.contains("cache")
+        ));
+    }
+}
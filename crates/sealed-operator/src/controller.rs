@@ -1,10 +1,12 @@
 use anyhow::Result;
 use async_recursion::async_recursion;
 use k8s_openapi::api::core::v1::Service;
-use kube::Client;
+use kube::{Client, ResourceExt};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::error::SealedOperatorResult;
+use crate::error::{SealedOperatorError, SealedOperatorResult};
+use crate::installer;
 
 use super::app_config::AppConfig;
 use super::operator::crd::FpApp;
@@ -12,18 +14,41 @@ use sealed_common::error::SealedResult;
 pub struct SIController {
     client: Arc<Client>,
     fp_app: Arc<FpApp>,
+    /// The apps deployed by `deploy_apps`
+    apps: Vec<AppConfig>,
+    /// Where the apps' resources are created. Defaults to the namespace of the `FpApp`.
+    namespace: String,
 }
 
 impl SIController {
     pub async fn new(client: Arc<Client>, fp_app: Arc<FpApp>) -> Result<Self> {
-        Ok(Self { client, fp_app })
+        let namespace = fp_app.namespace().unwrap_or("default".to_string());
+        Ok(Self {
+            client,
+            fp_app,
+            apps: vec![],
+            namespace,
+        })
     }
 
-    async fn deploy_apps(&self) -> Result<()> {
-        // for (_, app) in &self.config.apps {
-        //     self.deploy_app(app).await?;
-        // }
-        Ok(())
+    pub fn with_apps(mut self, apps: Vec<AppConfig>) -> Self {
+        self.apps = apps;
+        self
+    }
+
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Deploys every app, each after the apps it depends on. Returns the applied resources as
+    /// `Kind/name`.
+    async fn deploy_apps(&self) -> SealedOperatorResult<Vec<String>> {
+        let mut applied = vec![];
+        for app in deployment_order(&self.apps)? {
+            applied.extend(installer::deploy(&self.client, app, &self.namespace).await?);
+        }
+        Ok(applied)
     }
 
     #[async_recursion]
@@ -39,54 +64,105 @@ impl SIController {
         Ok(())
     }
 
-    // async fn create_config_map(&self, app: &AppConfig, env_file: &str) -> Result<()> {
-    //     let cm_api: Api<ConfigMap> = Api::namespaced(self.client.clone(), "default");
-    //     let cm_data = fs::read_to_string(env_file)?;
-    //     let cm = ConfigMap {
-    //         metadata: kube::api::ObjectMeta {
-    //             name: Some(format!("{}-config", app.name)),
-    //             ..Default::default()
-    //         },
-    //         data: Some(BTreeMap::from_iter(vec![("env".to_string(), cm_data)])),
-    //         ..Default::default()
-    //     };
-    //     cm_api.create(&PostParams::default(), &cm).await?;
-    //     Ok(())
-    // }
-
-    // async fn create_deployment(&self, app: &AppConfig) -> Result<()> {
-    //     let deployments: Api<Deployment> = Api::namespaced(self.client.clone(), "default");
-    //     let deployment = self.generate_deployment(app)?;
-    //     deployments
-    //         .create(&PostParams::default(), &deployment)
-    //         .await?;
-    //     Ok(())
-    // }
-
-    // fn generate_deployment(&self, app: &AppConfig) -> SealedResult<Deployment> {
-    //     app.into_deployment()
-    // }
-
-    // async fn create_service(&self, app: &AppConfig) -> Result<()> {
-    //     let services: Api<Service> = Api::namespaced(self.client.clone(), "default");
-    //     let service = self.generate_service(app)?;
-    //     services.create(&PostParams::default(), &service).await?;
-    //     Ok(())
-    // }
-
     fn generate_service(&self, app: &AppConfig) -> SealedOperatorResult<Service> {
         app.into_service()
     }
 }
 
-fn image_or_from_language(image: Option<String>, language: &str) -> String {
-    match image {
-        Some(image) => image,
-        None => match language {
-            "python" => "python:3.12".to_string(),
-            "node" => "node:20".to_string(),
-            "rust" => "rust".to_string(),
-            _ => "alpine:latest".to_string(),
-        },
+/// Orders `apps` so that every app comes after its dependencies, which must all be among `apps`.
+/// Apps without dependencies between them keep their relative order. A dependency cycle is an
+/// error rather than endless recursion.
+fn deployment_order(apps: &[AppConfig]) -> SealedOperatorResult<Vec<&AppConfig>> {
+    let by_name: HashMap<&str, &AppConfig> =
+        apps.iter().map(|app| (app.name.as_str(), app)).collect();
+
+    fn visit<'a>(
+        app: &'a AppConfig,
+        by_name: &HashMap<&str, &'a AppConfig>,
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a AppConfig>,
+    ) -> SealedOperatorResult<()> {
+        if order.iter().any(|ordered| ordered.name == app.name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|name| *name == app.name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(&app.name);
+            return Err(SealedOperatorError::InvalidConfig(format!(
+                "dependency cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        path.push(&app.name);
+        for dependency in &app.dependencies {
+            let Some(dependency) = by_name.get(dependency.as_str()) else {
+                return Err(SealedOperatorError::InvalidConfig(format!(
+                    "app {} depends on {}, which is not one of the apps",
+                    app.name, dependency
+                )));
+            };
+            visit(dependency, by_name, path, order)?;
+        }
+        path.pop();
+
+        order.push(app);
+        Ok(())
+    }
+
+    let mut order = vec![];
+    for app in apps {
+        visit(app, &by_name, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, dependencies: &[&str]) -> AppConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "dependencies": dependencies,
+        }))
+        .unwrap()
+    }
+
+    fn names(apps: Vec<&AppConfig>) -> Vec<&str> {
+        apps.into_iter().map(|app| app.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_deployment_order_puts_dependencies_first() {
+        let apps = vec![
+            app("web", &["api"]),
+            app("api", &["db", "cache"]),
+            app("cache", &[]),
+            app("db", &[]),
+        ];
+        assert_eq!(
+            names(deployment_order(&apps).unwrap()),
+            vec!["db", "cache", "api", "web"]
+        );
+    }
+
+    #[test]
+    fn test_deployment_order_detects_cycles() {
+        let apps = vec![app("a", &["b"]), app("b", &["c"]), app("c", &["a"])];
+        match deployment_order(&apps) {
+            Err(SealedOperatorError::InvalidConfig(message)) => {
+                assert!(message.contains("a -> b -> c -> a"), "{}", message)
+            }
+            result => panic!("Expected a cycle error, got {:?}", result.map(names)),
+        }
+
+        // An app depending on itself
+        assert!(deployment_order(&[app("a", &["a"])]).is_err());
+    }
+
+    #[test]
+    fn test_deployment_order_rejects_unknown_dependency() {
+        assert!(deployment_order(&[app("api", &["db"])]).is_err());
     }
 }
@@ -11,9 +11,15 @@ pub enum SealedOperatorError {
     #[error("Parsing error: {0}")]
     Parsing(#[from] ParseGroupVersionError),
 
+    #[error("Invalid app config: {0}")]
+    InvalidConfig(String),
+
     #[error("Timeout error: {0}")]
     Timeout(#[from] tokio::time::error::Elapsed),
 
+    #[error("The {operator} operator wasn't available within {seconds}s")]
+    OperatorTimeout { operator: String, seconds: f64 },
+
     /// Any error originating from the `kube-rs` crate
     #[error("Kubernetes reported error: {source}")]
     Kube {
@@ -34,4 +40,10 @@ pub enum SealedOperatorError {
         #[from]
         source: serde_yaml::Error,
     },
+
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
 }
@@ -1,12 +1,92 @@
+/// The image and default tag for each supported language, plus any aliases for its name.
+const LANGUAGE_IMAGES: &[(&[&str], &str, &str)] = &[
+    (&["python"], "python", "3.12"),
+    (&["node", "nodejs"], "node", "20"),
+    (&["rust"], "rust", "latest"),
+    (&["go", "golang"], "golang", "1.22"),
+    (&["ruby"], "ruby", "3.3"),
+    (&["java"], "eclipse-temurin", "21"),
+    (&["deno"], "denoland/deno", "latest"),
+];
+
+/// The image used for languages we don't know about.
+const FALLBACK_IMAGE: &str = "alpine:latest";
+
+/// Returns `image` if one was given, otherwise an image for `language`. The language may pick a
+/// version with an `@` suffix (`python@3.11` is `python:3.11`), which is used as the image tag in
+/// place of the language's default.
 #[inline]
 pub fn image_or_from_language(image: Option<String>, language: &str) -> String {
-    match image {
-        Some(image) => image,
-        None => match language {
-            "python" => "python:3.12".to_string(),
-            "node" => "node:20".to_string(),
-            "rust" => "rust".to_string(),
-            _ => "alpine:latest".to_string(),
-        },
+    if let Some(image) = image {
+        return image;
+    }
+
+    let (name, version) = match language.trim().split_once('@') {
+        Some((name, version)) if !version.is_empty() => (name, Some(version)),
+        Some((name, _)) => (name, None),
+        None => (language.trim(), None),
+    };
+    let name = name.to_ascii_lowercase();
+
+    LANGUAGE_IMAGES
+        .iter()
+        .find(|(aliases, _, _)| aliases.contains(&name.as_str()))
+        .map_or(FALLBACK_IMAGE.to_string(), |(_, image, default_tag)| {
+            format!("{}:{}", image, version.unwrap_or(default_tag))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_for_each_language() {
+        for (language, image) in [
+            ("python", "python:3.12"),
+            ("node", "node:20"),
+            ("nodejs", "node:20"),
+            ("rust", "rust:latest"),
+            ("go", "golang:1.22"),
+            ("golang", "golang:1.22"),
+            ("ruby", "ruby:3.3"),
+            ("java", "eclipse-temurin:21"),
+            ("deno", "denoland/deno:latest"),
+            ("Python", "python:3.12"),
+        ] {
+            assert_eq!(
+                image_or_from_language(None, language),
+                image,
+                "{}",
+                language
+            );
+        }
+    }
+
+    #[test]
+    fn test_versioned_language() {
+        assert_eq!(image_or_from_language(None, "python@3.11"), "python:3.11");
+        assert_eq!(
+            image_or_from_language(None, "java@17"),
+            "eclipse-temurin:17"
+        );
+        assert_eq!(image_or_from_language(None, "go@1.21"), "golang:1.21");
+        // An empty version means the default
+        assert_eq!(image_or_from_language(None, "node@"), "node:20");
+    }
+
+    #[test]
+    fn test_unknown_language_falls_back_to_alpine() {
+        assert_eq!(image_or_from_language(None, "cobol"), "alpine:latest");
+        assert_eq!(image_or_from_language(None, "cobol@85"), "alpine:latest");
+        assert_eq!(image_or_from_language(None, ""), "alpine:latest");
+    }
+
+    #[test]
+    fn test_explicit_image_wins() {
+        assert_eq!(
+            image_or_from_language(Some("encom/grid:1.0".to_string()), "python@3.11"),
+            "encom/grid:1.0"
+        );
     }
 }
@@ -1,36 +1,80 @@
-use std::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::{
+    api::apps::v1::{Deployment, StatefulSet},
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+};
 use kube::{
     api::{ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams},
     discovery::{ApiCapabilities, Scope},
-    runtime::wait::{await_condition, Condition},
-    Api, Client, Discovery, ResourceExt,
+    runtime::wait::{await_condition, conditions, Condition},
+    Api, Client, Discovery, Resource, ResourceExt,
 };
 use sealed_common::settings::Settings;
 use serde::{Deserialize, Serialize};
 use tracing::{info, trace, warn};
 
 use crate::{
+    app_config::AppConfig,
     error::{SealedOperatorError, SealedOperatorResult},
     k8s::namespace::SINamespace,
 };
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const FIELD_MANAGER: &str = "kubectl-light";
 const CNPG_YAML: &str = include_str!("../config/operators/cnpg-1.22.1.yaml");
 const NGINX_YAML: &str = include_str!("../config/operators/nginx-ingress.yaml");
+/// The deployment (and its namespace) each bundled manifest runs its operator in.
+const CNPG_DEPLOYMENT: (&str, &str) = ("cnpg-controller-manager", "cnpg-system");
+const NGINX_DEPLOYMENT: (&str, &str) = ("ingress-nginx-controller", "ingress-nginx");
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstallationArgs {
     pub namespace: String,
     pub operator_namespace: String,
+    /// Print what would be applied instead of changing the cluster
+    #[serde(default)]
+    pub plan: bool,
+    /// How long to wait for each operator to become available, overriding
+    /// `install_timeout_secs` from the settings
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// A directory of extra manifests, e.g., the user's own operators, applied after the bundled
+    /// ones
+    #[serde(default)]
+    pub manifests: Option<PathBuf>,
 }
 
 pub async fn install(args: InstallationArgs, config: &Settings) -> SealedOperatorResult<()> {
     info!("Installing sealed infrastructure");
     let client = connect_to_cluster(config).await?;
+    if args.plan {
+        let mut yamls = vec![CNPG_YAML.to_string(), NGINX_YAML.to_string()];
+        if let Some(manifests) = &args.manifests {
+            for file in manifest_files(manifests)? {
+                yamls.push(std::fs::read_to_string(&file)?);
+            }
+        }
+        for yaml in &yamls {
+            for change in plan(&client, yaml, None).await? {
+                println!("{}", change);
+            }
+        }
+        return Ok(());
+    }
     let ns = SINamespace::new(&args.namespace);
     let operator_ns = SINamespace::new(&args.operator_namespace);
+
+    let timeout = Duration::from_secs(args.timeout_secs.unwrap_or(config.install_timeout_secs));
+    install_postgres_operator(&client, timeout).await?;
+    install_nginx_operator(&client, timeout).await?;
+    if let Some(manifests) = &args.manifests {
+        info!("Applying the manifests in {}", manifests.display());
+        apply_dir(&client, manifests, timeout).await?;
+    }
     Ok(())
 }
 
@@ -41,51 +85,231 @@ async fn connect_to_cluster(config: &Settings) -> SealedOperatorResult<Client> {
     Ok(client)
 }
 
-async fn install_postgres_operator(client: &Client) -> SealedOperatorResult<()> {
-    info!("Installing cloud native postgres operator (TODO)");
+async fn install_postgres_operator(client: &Client, timeout: Duration) -> SealedOperatorResult<()> {
+    info!("Installing cloud native postgres operator");
     apply(client, CNPG_YAML, None).await?;
     info!("Waiting for cloud native postgres operator to be available...");
-    let deploys: Api<Deployment> = Api::namespaced(client.clone(), "postgres-operator");
-    let establish = await_condition(deploys, "postgres-operator", is_deployment_available());
-    let _ = tokio::time::timeout(Duration::from_secs(120), establish).await?;
-    Ok(())
+    let (deployment, namespace) = CNPG_DEPLOYMENT;
+    wait_for_operator(
+        client,
+        "cloud native postgres",
+        deployment,
+        namespace,
+        timeout,
+    )
+    .await
 }
 
-async fn install_nginx_operator(client: &Client) -> SealedOperatorResult<()> {
-    info!("Installing nginx operator (TODO)");
+async fn install_nginx_operator(client: &Client, timeout: Duration) -> SealedOperatorResult<()> {
+    info!("Installing nginx operator");
     apply(client, NGINX_YAML, None).await?;
 
     info!("Waiting for nginx operator to be available...");
-    let deploys: Api<Deployment> = Api::namespaced(client.clone(), "ingress-nginx");
-    let establish = await_condition(
-        deploys,
-        "nginx-ingress-controller",
-        is_deployment_available(),
-    );
-    let _ = tokio::time::timeout(Duration::from_secs(120), establish).await?;
+    let (deployment, namespace) = NGINX_DEPLOYMENT;
+    wait_for_operator(client, "nginx", deployment, namespace, timeout).await
+}
+
+/// Waits for the deployment running `operator`, naming the operator if it doesn't come up in time.
+async fn wait_for_operator(
+    client: &Client,
+    operator: &str,
+    deployment: &str,
+    namespace: &str,
+    timeout: Duration,
+) -> SealedOperatorResult<()> {
+    match wait_for_deployment(client, deployment, namespace, timeout).await {
+        Err(SealedOperatorError::Timeout(_)) => Err(SealedOperatorError::OperatorTimeout {
+            operator: operator.to_string(),
+            seconds: timeout.as_secs_f64(),
+        }),
+        result => result,
+    }
+}
 
+/// Waits up to `timeout` for every desired replica of the deployment `name` in `namespace` to be
+/// available.
+pub async fn wait_for_deployment(
+    client: &Client,
+    name: &str,
+    namespace: &str,
+    timeout: Duration,
+) -> SealedOperatorResult<()> {
+    let deploys: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let available = await_condition(deploys, name, is_deployment_available());
+    tokio::time::timeout(timeout, available)
+        .await?
+        .map_err(|e| SealedOperatorError::Runtime(e.into()))?;
     Ok(())
 }
 
+/// Whether all of the deployment's desired replicas, one unless set, are available.
 fn is_deployment_available() -> impl Condition<Deployment> {
     |obj: Option<&Deployment>| {
-        if let Some(deployment) = &obj {
-            if let Some(status) = &deployment.status {
-                if let Some(phase) = &status.available_replicas {
-                    return phase > &1;
+        obj.is_some_and(|deployment| {
+            let desired = deployment
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.replicas)
+                .unwrap_or(1);
+            let available = deployment
+                .status
+                .as_ref()
+                .and_then(|status| status.available_replicas)
+                .unwrap_or(0);
+            available >= desired
+        })
+    }
+}
+
+async fn apply(client: &Client, yaml: &str, namespace: Option<&str>) -> SealedOperatorResult<()> {
+    let discovery = Discovery::new(client.clone()).run().await?;
+    for (obj, gvk) in documents(yaml)? {
+        if apply_document(client, &discovery, &obj, &gvk, namespace)
+            .await?
+            .is_none()
+        {
+            warn!("Cannot apply document for unknown {:?}", gvk);
+        }
+    }
+
+    Ok(())
+}
+
+/// Server-side applies a document, returning `Kind/name`, or `None` when the cluster doesn't
+/// serve its GVK.
+async fn apply_document(
+    client: &Client,
+    discovery: &Discovery,
+    obj: &DynamicObject,
+    gvk: &GroupVersionKind,
+    namespace: Option<&str>,
+) -> SealedOperatorResult<Option<String>> {
+    let Some((ar, caps)) = discovery.resolve_gvk(gvk) else {
+        return Ok(None);
+    };
+    let namespace = obj.metadata.namespace.as_deref().or(namespace);
+    let name = obj.name_any();
+    let api = dynamic_api(ar, caps, client.clone(), namespace, false);
+    trace!("Applying {}: \n{}", gvk.kind, serde_yaml::to_string(obj)?);
+    let data: serde_json::Value = serde_json::to_value(obj)?;
+    let _r = api
+        .patch(
+            &name,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(data),
+        )
+        .await?;
+    info!("applied {} {}", gvk.kind, name);
+    Ok(Some(format!("{}/{}", gvk.kind, name)))
+}
+
+/// Applies every document of every `*.yaml`/`*.yml` file under `path`, returning `Kind/name` for
+/// each applied resource in order. Namespaces go first, then CRDs, then everything else in file
+/// order. Custom resources whose CRD was only just applied aren't served yet on the first pass,
+/// so they're retried once those CRDs are established (waiting up to `timeout`) and discovery has
+/// been refreshed.
+pub async fn apply_dir(
+    client: &Client,
+    path: &Path,
+    timeout: Duration,
+) -> SealedOperatorResult<Vec<String>> {
+    let mut pending = vec![];
+    for file in manifest_files(path)? {
+        pending.extend(documents(&std::fs::read_to_string(&file)?)?);
+    }
+    pending.sort_by_key(|(_, gvk)| apply_order(gvk));
+
+    let mut applied = vec![];
+    let mut crds = vec![];
+    let mut discovery = Discovery::new(client.clone()).run().await?;
+    for retry in [false, true] {
+        if retry {
+            if pending.is_empty() {
+                break;
+            }
+            wait_for_crds(client, &crds, timeout).await?;
+            discovery = Discovery::new(client.clone()).run().await?;
+        }
+        let mut unknown = vec![];
+        for (obj, gvk) in pending {
+            match apply_document(client, &discovery, &obj, &gvk, None).await? {
+                Some(resource) => {
+                    if apply_order(&gvk) == 1 {
+                        crds.push(obj.name_any());
+                    }
+                    applied.push(resource);
                 }
+                None => unknown.push((obj, gvk)),
             }
         }
-        false
+        pending = unknown;
+    }
+
+    if let Some((obj, gvk)) = pending.first() {
+        return Err(SealedOperatorError::Runtime(anyhow::anyhow!(
+            "cannot apply {} {}: the cluster doesn't serve {:?}",
+            gvk.kind,
+            obj.name_any(),
+            gvk
+        )));
     }
+    Ok(applied)
 }
 
-async fn apply(client: &Client, yaml: &str, namespace: Option<&str>) -> SealedOperatorResult<()> {
-    let ssapply = PatchParams::apply("kubectl-light").force();
-    let discovery = Discovery::new(client.clone()).run().await?;
+/// Waits up to `timeout` for each of the CRDs `names` to be established, i.e., served by the API.
+async fn wait_for_crds(
+    client: &Client,
+    names: &[String],
+    timeout: Duration,
+) -> SealedOperatorResult<()> {
+    let crds: Api<CustomResourceDefinition> = Api::all(client.clone());
+    let established = futures::future::try_join_all(
+        names
+            .iter()
+            .map(|name| await_condition(crds.clone(), name, conditions::is_crd_established())),
+    );
+    tokio::time::timeout(timeout, established)
+        .await?
+        .map_err(|e| SealedOperatorError::Runtime(e.into()))?;
+    Ok(())
+}
+
+/// Namespaces and CRDs have to exist before the resources that use them.
+fn apply_order(gvk: &GroupVersionKind) -> u8 {
+    match (gvk.group.as_str(), gvk.kind.as_str()) {
+        ("", "Namespace") => 0,
+        ("apiextensions.k8s.io", "CustomResourceDefinition") => 1,
+        _ => 2,
+    }
+}
+
+/// The YAML files under `dir`, sorted by path.
+fn manifest_files(dir: &Path) -> SealedOperatorResult<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(manifest_files(&path)?);
+        } else if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml")
+        ) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Parses every document in `yaml`, along with the GVK from its TypeMeta.
+fn documents(yaml: &str) -> SealedOperatorResult<Vec<(DynamicObject, GroupVersionKind)>> {
+    let mut documents = vec![];
     for doc in multidoc_deserialize(yaml)? {
+        // e.g., after a trailing `---`
+        if doc.is_null() {
+            continue;
+        }
         let obj: DynamicObject = serde_yaml::from_value(doc)?;
-        let namespace = obj.metadata.namespace.as_deref().or(namespace);
         let gvk = if let Some(tm) = &obj.types {
             GroupVersionKind::try_from(tm)?
         } else {
@@ -94,19 +318,225 @@ async fn apply(client: &Client, yaml: &str, namespace: Option<&str>) -> SealedOp
                 obj
             )));
         };
+        documents.push((obj, gvk));
+    }
+    Ok(documents)
+}
+
+/// What applying a document would do to the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedAction {
+    Create,
+    /// The paths of the fields whose value would change
+    Update(Vec<String>),
+    Unchanged,
+    /// The cluster doesn't serve the document's GVK, so it can't be applied
+    UnknownKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedChange {
+    pub kind: String,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub action: PlannedAction,
+}
+
+impl std::fmt::Display for PlannedChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match &self.action {
+            PlannedAction::Create => "create",
+            PlannedAction::Update(_) => "update",
+            PlannedAction::Unchanged => "unchanged",
+            PlannedAction::UnknownKind => "unknown kind",
+        };
+        write!(f, "{}: {} {}", action, self.kind, self.name)?;
+        if let Some(namespace) = &self.namespace {
+            write!(f, " in {}", namespace)?;
+        }
+        if let PlannedAction::Update(fields) = &self.action {
+            write!(f, " ({})", fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Works out what `apply` would do with `yaml`, comparing each document against the live object
+/// when there is one. Only reads from the cluster.
+pub async fn plan(
+    client: &Client,
+    yaml: &str,
+    namespace: Option<&str>,
+) -> SealedOperatorResult<Vec<PlannedChange>> {
+    let discovery = Discovery::new(client.clone()).run().await?;
+    let mut changes = vec![];
+    for (obj, gvk) in documents(yaml)? {
+        let namespace = obj.metadata.namespace.as_deref().or(namespace);
         let name = obj.name_any();
-        if let Some((ar, caps)) = discovery.resolve_gvk(&gvk) {
-            let api = dynamic_api(ar, caps, client.clone(), namespace, false);
-            trace!("Applying {}: \n{}", gvk.kind, serde_yaml::to_string(&obj)?);
-            let data: serde_json::Value = serde_json::to_value(&obj)?;
-            let _r = api.patch(&name, &ssapply, &Patch::Apply(data)).await?;
-            info!("applied {} {}", gvk.kind, name);
-        } else {
-            warn!("Cannot apply document for unknown {:?}", gvk);
+        let (action, namespace) = match discovery.resolve_gvk(&gvk) {
+            Some((ar, caps)) => {
+                // Cluster scoped objects ignore the namespace
+                let namespace = namespace.filter(|_| caps.scope == Scope::Namespaced);
+                let api = dynamic_api(ar, caps, client.clone(), namespace, false);
+                let action = match api.get_opt(&name).await? {
+                    Some(live) => {
+                        let mut fields = vec![];
+                        changed_fields(
+                            &serde_json::to_value(&obj)?,
+                            &serde_json::to_value(&live)?,
+                            "",
+                            &mut fields,
+                        );
+                        if fields.is_empty() {
+                            PlannedAction::Unchanged
+                        } else {
+                            PlannedAction::Update(fields)
+                        }
+                    }
+                    None => PlannedAction::Create,
+                };
+                (action, namespace)
+            }
+            None => {
+                warn!("Cannot apply document for unknown {:?}", gvk);
+                (PlannedAction::UnknownKind, namespace)
+            }
+        };
+        changes.push(PlannedChange {
+            kind: gvk.kind,
+            name,
+            namespace: namespace.map(str::to_string),
+            action,
+        });
+    }
+    Ok(changes)
+}
+
+/// Collects the paths of the fields set in `desired` whose value differs in `live`. Fields only
+/// set in `live` are left out, since a server-side apply leaves them alone. That includes the
+/// defaults the API server fills in for list items, so lists of the same length are compared item
+/// by item. Empty values in `desired` match missing fields, since the server drops them too.
+fn changed_fields(
+    desired: &serde_json::Value,
+    live: &serde_json::Value,
+    path: &str,
+    changed: &mut Vec<String>,
+) {
+    match (desired, live) {
+        (serde_json::Value::Object(desired), serde_json::Value::Object(live)) => {
+            for (key, value) in desired {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match live.get(key) {
+                    Some(live) => changed_fields(value, live, &path, changed),
+                    None if is_empty_value(value) => {}
+                    None => changed.push(path),
+                }
+            }
+        }
+        (serde_json::Value::Array(desired), serde_json::Value::Array(live))
+            if desired.len() == live.len() =>
+        {
+            for (index, (desired, live)) in desired.iter().zip(live).enumerate() {
+                changed_fields(desired, live, &format!("{}[{}]", path, index), changed);
+            }
         }
+        (desired, live) if desired != live => changed.push(path.to_string()),
+        _ => {}
     }
+}
 
-    Ok(())
+/// Whether `value` is null or an empty list or object, which the API server doesn't store.
+fn is_empty_value(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(items) => items.is_empty(),
+        serde_json::Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Server-side applies every resource an app produces (the same set `AppConfig::render`
+/// renders) into `namespace`, returning `Kind/name` for each applied resource in order.
+pub async fn deploy(
+    client: &Client,
+    app: &AppConfig,
+    namespace: &str,
+) -> SealedOperatorResult<Vec<String>> {
+    // Build every manifest before applying any, so an invalid config doesn't leave the app
+    // half deployed
+    let config_map = app.into_config_map()?;
+    let claims = app.into_persistent_volume_claims()?;
+    let workload = if app.stateful {
+        Workload::StatefulSet(app.into_stateful_set()?)
+    } else {
+        Workload::Deployment(app.into_deployment()?)
+    };
+    let hpa = app.into_hpa()?;
+    let service = app.into_service()?;
+    let ingress = app.into_ingress()?;
+
+    let mut applied = vec![];
+    if let Some(config_map) = &config_map {
+        applied.push(apply_resource(client, config_map, namespace).await?);
+    }
+    for claim in &claims {
+        applied.push(apply_resource(client, claim, namespace).await?);
+    }
+    applied.push(match &workload {
+        Workload::Deployment(deployment) => apply_resource(client, deployment, namespace).await?,
+        Workload::StatefulSet(stateful_set) => {
+            apply_resource(client, stateful_set, namespace).await?
+        }
+    });
+    if let Some(hpa) = &hpa {
+        applied.push(apply_resource(client, hpa, namespace).await?);
+    }
+    applied.push(apply_resource(client, &service, namespace).await?);
+    if let Some(ingress) = &ingress {
+        applied.push(apply_resource(client, ingress, namespace).await?);
+    }
+
+    Ok(applied)
+}
+
+enum Workload {
+    Deployment(Deployment),
+    StatefulSet(StatefulSet),
+}
+
+/// Server-side applies a namespaced resource whose type is known at compile time. Unlike
+/// `apply`, this doesn't need to run discovery against the cluster first. Returns `Kind/name`.
+pub async fn apply_resource<K>(
+    client: &Client,
+    resource: &K,
+    namespace: &str,
+) -> SealedOperatorResult<String>
+where
+    K: Resource<DynamicType = ()> + Serialize,
+{
+    let ar = ApiResource::erase::<K>(&());
+    let caps = ApiCapabilities {
+        scope: Scope::Namespaced,
+        subresources: vec![],
+        operations: vec![],
+    };
+    let api = dynamic_api(ar, caps, client.clone(), Some(namespace), false);
+
+    let name = resource.name_any();
+    let data = serde_json::to_value(resource)?;
+    api.patch(
+        &name,
+        &PatchParams::apply(FIELD_MANAGER).force(),
+        &Patch::Apply(data),
+    )
+    .await?;
+    info!("applied {} {}", K::kind(&()), name);
+
+    Ok(format!("{}/{}", K::kind(&()), name))
 }
 
 pub fn multidoc_deserialize(data: &str) -> SealedOperatorResult<Vec<serde_yaml::Value>> {
@@ -133,3 +563,380 @@ fn dynamic_api(
         Api::default_namespaced_with(client, &ar)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use http::{Request, Response};
+    use kube::client::Body;
+
+    use super::*;
+
+    fn test_app() -> AppConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": "test-app",
+            "image": "nginx:latest",
+            "dependencies": [],
+            "ports": [80],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_deploy_applies_deployment_and_service() {
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        // Answer each apply with the object that was sent, recording what was applied
+        let cluster = tokio::spawn(async move {
+            let mut requests = vec![];
+            while let Some((request, send)) = handle.next_request().await {
+                requests.push((request.method().clone(), request.uri().path().to_string()));
+                let body = request.into_body().collect_bytes().await.unwrap();
+                send.send_response(Response::builder().body(Body::from(body.to_vec())).unwrap());
+            }
+            requests
+        });
+
+        let applied = deploy(&client, &test_app(), "apps").await.unwrap();
+        drop(client);
+        let requests = cluster.await.unwrap();
+
+        assert_eq!(applied, vec!["Deployment/test-app", "Service/test-app"]);
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    http::Method::PATCH,
+                    "/apis/apps/v1/namespaces/apps/deployments/test-app".to_string()
+                ),
+                (
+                    http::Method::PATCH,
+                    "/api/v1/namespaces/apps/services/test-app".to_string()
+                ),
+            ]
+        );
+    }
+
+    /// Discovery for a cluster serving only Namespaces and ConfigMaps.
+    fn discovery(path: &str) -> Option<serde_json::Value> {
+        match path {
+            "/api" => Some(serde_json::json!({
+                "kind": "APIVersions",
+                "versions": ["v1"],
+                "serverAddressByClientCIDRs": [],
+            })),
+            "/apis" => Some(serde_json::json!({
+                "kind": "APIGroupList",
+                "apiVersion": "v1",
+                "groups": [],
+            })),
+            "/api/v1" => Some(serde_json::json!({
+                "kind": "APIResourceList",
+                "groupVersion": "v1",
+                "resources": [
+                    {
+                        "name": "namespaces",
+                        "singularName": "namespace",
+                        "namespaced": false,
+                        "kind": "Namespace",
+                        "verbs": ["get", "patch"],
+                    },
+                    {
+                        "name": "configmaps",
+                        "singularName": "configmap",
+                        "namespaced": true,
+                        "kind": "ConfigMap",
+                        "verbs": ["get", "patch"],
+                    },
+                ],
+            })),
+            _ => None,
+        }
+    }
+
+    fn not_found() -> serde_json::Value {
+        serde_json::json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "message": "not found",
+            "reason": "NotFound",
+            "code": 404,
+        })
+    }
+
+    fn respond(
+        send: tower_test::mock::SendResponse<Response<Body>>,
+        status: u16,
+        body: &serde_json::Value,
+    ) {
+        send.send_response(
+            Response::builder()
+                .status(status)
+                .body(Body::from(serde_json::to_vec(body).unwrap()))
+                .unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_doesnt_patch() {
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        // One of the ConfigMaps is already in the cluster
+        let cluster = tokio::spawn(async move {
+            let mut requests = vec![];
+            while let Some((request, send)) = handle.next_request().await {
+                let path = request.uri().path().to_string();
+                requests.push((request.method().clone(), path.clone()));
+                if let Some(body) = discovery(&path) {
+                    respond(send, 200, &body);
+                } else if path == "/api/v1/namespaces/apps/configmaps/existing" {
+                    let live = serde_json::json!({
+                        "apiVersion": "v1",
+                        "kind": "ConfigMap",
+                        "metadata": { "name": "existing", "namespace": "apps", "uid": "1" },
+                        "data": { "color": "blue", "size": "large" },
+                    });
+                    respond(send, 200, &live);
+                } else {
+                    respond(send, 404, &not_found());
+                }
+            }
+            requests
+        });
+
+        let yaml = r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: new
+data:
+  color: red
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: existing
+data:
+  color: green
+  size: large
+---
+apiVersion: example.com/v1
+kind: Widget
+metadata:
+  name: gadget
+"#;
+        let changes = plan(&client, yaml, Some("apps")).await.unwrap();
+        drop(client);
+        let requests = cluster.await.unwrap();
+
+        let change = |kind: &str, name: &str, action| PlannedChange {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            namespace: Some("apps".to_string()),
+            action,
+        };
+        assert_eq!(
+            changes,
+            vec![
+                change("ConfigMap", "new", PlannedAction::Create),
+                change(
+                    "ConfigMap",
+                    "existing",
+                    PlannedAction::Update(vec!["data.color".to_string()])
+                ),
+                change("Widget", "gadget", PlannedAction::UnknownKind),
+            ]
+        );
+        assert!(requests
+            .iter()
+            .all(|(method, _)| *method == http::Method::GET));
+    }
+
+    #[tokio::test]
+    async fn test_apply_dir_applies_namespaces_first() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a-config.yaml"),
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: settings\n  namespace: team\n---\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("namespaces")).unwrap();
+        std::fs::write(
+            dir.path().join("namespaces").join("team.yml"),
+            "apiVersion: v1\nkind: Namespace\nmetadata:\n  name: team\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a manifest").unwrap();
+
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+        let cluster = tokio::spawn(async move {
+            let mut patches = vec![];
+            while let Some((request, send)) = handle.next_request().await {
+                let path = request.uri().path().to_string();
+                if let Some(body) = discovery(&path) {
+                    respond(send, 200, &body);
+                } else if request.method() == http::Method::PATCH {
+                    patches.push(path);
+                    let body = request.into_body().collect_bytes().await.unwrap();
+                    respond(send, 200, &serde_json::from_slice(&body).unwrap());
+                } else {
+                    respond(send, 404, &not_found());
+                }
+            }
+            patches
+        });
+
+        let applied = apply_dir(&client, dir.path(), Duration::from_secs(5))
+            .await
+            .unwrap();
+        drop(client);
+        let patches = cluster.await.unwrap();
+
+        assert_eq!(applied, vec!["Namespace/team", "ConfigMap/settings"]);
+        assert_eq!(
+            patches,
+            vec![
+                "/api/v1/namespaces/team",
+                "/api/v1/namespaces/team/configmaps/settings"
+            ]
+        );
+    }
+
+    fn deployment(desired: Option<i32>, available: Option<i32>) -> Deployment {
+        Deployment {
+            spec: Some(k8s_openapi::api::apps::v1::DeploymentSpec {
+                replicas: desired,
+                ..Default::default()
+            }),
+            status: Some(k8s_openapi::api::apps::v1::DeploymentStatus {
+                available_replicas: available,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_deployment_available() {
+        let available =
+            |deployment: &Deployment| is_deployment_available().matches_object(Some(deployment));
+
+        assert!(available(&deployment(Some(1), Some(1))));
+        assert!(!available(&deployment(Some(1), Some(0))));
+        assert!(!available(&deployment(Some(3), Some(2))));
+        assert!(available(&deployment(Some(3), Some(3))));
+        // One replica is desired by default, and none are available until reported
+        assert!(available(&deployment(None, Some(1))));
+        assert!(!available(&deployment(None, None)));
+        assert!(!is_deployment_available().matches_object(None));
+    }
+
+    #[test]
+    fn test_operator_deployments_match_manifests() {
+        for (yaml, (name, namespace)) in
+            [(CNPG_YAML, CNPG_DEPLOYMENT), (NGINX_YAML, NGINX_DEPLOYMENT)]
+        {
+            assert!(
+                documents(yaml).unwrap().iter().any(|(obj, gvk)| {
+                    gvk.kind == "Deployment"
+                        && obj.name_any() == name
+                        && obj.metadata.namespace.as_deref() == Some(namespace)
+                }),
+                "no deployment {} in {}",
+                name,
+                namespace
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_crds_timeout() {
+        // The cluster never answers, so the CRD is never established
+        let (service, _handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        let error = wait_for_crds(
+            &client,
+            &["widgets.example.com".to_string()],
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(error, SealedOperatorError::Timeout(_)));
+        // Nothing to wait for
+        wait_for_crds(&client, &[], Duration::from_millis(50))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_operator_timeout() {
+        // The cluster never answers, so the deployment never becomes available
+        let (service, _handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        let start = std::time::Instant::now();
+        let error = wait_for_operator(
+            &client,
+            "nginx",
+            NGINX_DEPLOYMENT.0,
+            NGINX_DEPLOYMENT.1,
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(matches!(
+            &error,
+            SealedOperatorError::OperatorTimeout { operator, .. } if operator == "nginx"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "The nginx operator wasn't available within 0.05s"
+        );
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let desired =
+            serde_json::json!({ "spec": { "replicas": 2, "image": "nginx" }, "labels": ["a"] });
+        let live =
+            serde_json::json!({ "spec": { "replicas": 1, "image": "nginx", "paused": false } });
+        let mut changed = vec![];
+        changed_fields(&desired, &live, "", &mut changed);
+        changed.sort();
+        assert_eq!(changed, vec!["labels", "spec.replicas"]);
+    }
+
+    #[test]
+    fn test_changed_fields_ignores_server_defaults() {
+        let desired = serde_json::to_value(test_app().into_deployment().unwrap()).unwrap();
+
+        // The app as the API server returns it once applied: empty lists dropped and defaults
+        // filled in, including inside the containers
+        let mut live = desired.clone();
+        live["metadata"]["uid"] = "1".into();
+        live["spec"]["strategy"] = serde_json::json!({ "type": "RollingUpdate" });
+        let container = &mut live["spec"]["template"]["spec"]["containers"][0];
+        container.as_object_mut().unwrap().remove("env");
+        container["imagePullPolicy"] = "Always".into();
+        container["terminationMessagePath"] = "/dev/termination-log".into();
+        live["status"] = serde_json::json!({ "replicas": 1 });
+
+        let mut changed = vec![];
+        changed_fields(&desired, &live, "", &mut changed);
+        assert!(changed.is_empty(), "{:?}", changed);
+
+        live["spec"]["template"]["spec"]["containers"][0]["image"] = "nginx:1.27".into();
+        changed_fields(&desired, &live, "", &mut changed);
+        assert_eq!(
+            changed,
+            vec!["spec.template.spec.containers[0].image".to_string()]
+        );
+    }
+}
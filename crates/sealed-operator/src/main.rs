@@ -1,4 +1,20 @@
+use clap::Parser;
+use sealed_operator::operator::operator;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Reconciles FpApp resources")]
+struct Args {
+    /// Only watch FpApps in this namespace. Defaults to `WATCH_NAMESPACE`, or every namespace.
+    #[arg(long)]
+    namespace: Option<String>,
+}
+
 #[tokio::main]
 async fn main() {
-    println!("Hello, world!");
+    let args = Args::parse();
+
+    if let Err(e) = operator(args.namespace).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
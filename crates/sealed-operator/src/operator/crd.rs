@@ -8,13 +8,88 @@ use serde::{Deserialize, Serialize};
     version = "v1",
     kind = "FpApp",
     plural = "fpapps",
+    shortname = "fpa",
+    category = "all",
+    category = "sealedinfra",
     derive = "PartialEq",
-    namespaced
+    namespaced,
+    status = "FpAppStatus",
+    printcolumn = r#"{"name":"Name", "type":"string", "jsonPath":".metadata.name"}"#,
+    printcolumn = r#"{"name":"Image", "type":"string", "jsonPath":".spec.image"}"#,
+    printcolumn = r#"{"name":"Branch", "type":"string", "jsonPath":".spec.branch"}"#,
+    printcolumn = r#"{"name":"Phase", "type":"string", "jsonPath":".status.phase"}"#,
+    printcolumn = r#"{"name":"Ready", "type":"integer", "jsonPath":".status.readyReplicas"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#
 )]
 pub struct FpAppSpec {
     pub replicas: i32,
     pub version: String,
+    pub image: Option<String>,
+    pub branch: Option<String>,
     pub pgadmin: Option<bool>,
     pub development: Option<bool>,
     pub testing: Option<bool>,
 }
+
+/// Where an `FpApp` is in its lifecycle, as last observed by the operator.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, JsonSchema)]
+pub enum FpAppPhase {
+    /// Not reconciled yet
+    Pending,
+    Deploying,
+    Ready,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FpAppStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<FpAppPhase>,
+    /// The `metadata.generation` the phase applies to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+    /// A human-readable explanation of the phase, e.g. the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ready_replicas: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::CustomResourceExt;
+
+    use super::*;
+
+    #[test]
+    fn test_crd_names() {
+        let crd = FpApp::crd();
+        assert_eq!(crd.spec.names.short_names, Some(vec!["fpa".to_string()]));
+        let categories = crd.spec.names.categories.unwrap_or_default();
+        assert!(categories.contains(&"all".to_string()));
+    }
+
+    #[test]
+    fn test_crd_printer_columns() {
+        let crd = FpApp::crd();
+        let columns = crd.spec.versions[0]
+            .additional_printer_columns
+            .clone()
+            .unwrap_or_default();
+        let names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Name", "Image", "Branch", "Phase", "Ready", "Age"]
+        );
+        let ready = columns.iter().find(|c| c.name == "Ready").unwrap();
+        assert_eq!(ready.json_path, ".status.readyReplicas");
+    }
+
+    #[test]
+    fn test_crd_has_status_subresource() {
+        let crd = FpApp::crd();
+        let subresources = crd.spec.versions[0].subresources.clone().unwrap();
+        assert!(subresources.status.is_some());
+    }
+}
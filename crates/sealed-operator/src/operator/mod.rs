@@ -1,23 +1,65 @@
 pub mod crd;
 mod finalizer;
 mod reconcile;
+mod status;
 
+use anyhow::Context;
 use crd::FpApp;
 use futures::StreamExt;
 
 use kube::{runtime::Controller, Api, Client};
 use reconcile::ContextData;
-use sealed_common::util::tracing::setup_tracing;
+use sealed_common::settings::Settings;
+use sealed_common::util::tracing::{level_filter, setup_tracing};
 use std::sync::Arc;
 
 use crate::error::SealedOperatorResult;
 
-pub async fn operator() -> SealedOperatorResult<()> {
-    setup_tracing(None).await;
+/// Restricts the operator to one namespace when `--namespace` isn't given.
+pub const WATCH_NAMESPACE_ENV: &str = "WATCH_NAMESPACE";
+
+/// Which `FpApp`s the operator reconciles. Watching a single namespace only needs RBAC for that
+/// namespace, which is what multi-tenant clusters want.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchScope {
+    AllNamespaces,
+    Namespace(String),
+}
+
+impl WatchScope {
+    /// The `--namespace` flag takes precedence over `WATCH_NAMESPACE`. Without either (or with
+    /// an empty value), every namespace is watched.
+    pub fn resolve(flag: Option<String>, env: Option<String>) -> Self {
+        match flag.or(env).map(|namespace| namespace.trim().to_string()) {
+            Some(namespace) if !namespace.is_empty() => WatchScope::Namespace(namespace),
+            _ => WatchScope::AllNamespaces,
+        }
+    }
+
+    fn api(&self, client: Client) -> Api<FpApp> {
+        match self {
+            WatchScope::AllNamespaces => Api::all(client),
+            WatchScope::Namespace(namespace) => Api::namespaced(client, namespace),
+        }
+    }
+}
+
+/// Runs the operator until the controller stream ends. `namespace` is the `--namespace` flag.
+pub async fn operator(namespace: Option<String>) -> SealedOperatorResult<()> {
+    let settings = Settings::from_root(None).context("unable to load the settings")?;
+    setup_tracing(
+        Some(level_filter(settings.log_level)),
+        settings.log_format,
+        settings.log_file.as_ref(),
+    )
+    .await;
+
+    let scope = WatchScope::resolve(namespace, std::env::var(WATCH_NAMESPACE_ENV).ok());
+    println!("Watching {:?}", scope);
 
     let kubernetes_client = Client::try_default().await?;
 
-    let crd_api: Api<FpApp> = Api::all(kubernetes_client.clone());
+    let crd_api: Api<FpApp> = scope.api(kubernetes_client.clone());
     let context: Arc<ContextData> = Arc::new(ContextData::new(kubernetes_client.clone()));
 
     Controller::new(crd_api.clone(), Default::default())
@@ -36,3 +78,45 @@ pub async fn operator() -> SealedOperatorResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use http::{Request, Response};
+    use kube::client::Body;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_scope() {
+        assert_eq!(WatchScope::resolve(None, None), WatchScope::AllNamespaces);
+        assert_eq!(
+            WatchScope::resolve(None, Some("".to_string())),
+            WatchScope::AllNamespaces
+        );
+        assert_eq!(
+            WatchScope::resolve(None, Some("tenant-a".to_string())),
+            WatchScope::Namespace("tenant-a".to_string())
+        );
+        assert_eq!(
+            WatchScope::resolve(Some("tenant-b".to_string()), Some("tenant-a".to_string())),
+            WatchScope::Namespace("tenant-b".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_for_scope() {
+        let (service, _handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        assert_eq!(
+            WatchScope::AllNamespaces.api(client.clone()).resource_url(),
+            "/apis/fp.com/v1/fpapps"
+        );
+        assert_eq!(
+            WatchScope::Namespace("tenant-a".to_string())
+                .api(client)
+                .resource_url(),
+            "/apis/fp.com/v1/namespaces/tenant-a/fpapps"
+        );
+    }
+}
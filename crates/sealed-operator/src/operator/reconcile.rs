@@ -1,27 +1,71 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use crate::controller::SIController;
 use crate::error::SealedOperatorError;
 use crate::error::SealedOperatorResult;
 
-use super::crd::FpApp;
+use super::crd::{FpApp, FpAppPhase};
 use super::finalizer;
+use super::status;
 use kube::runtime::controller::Action;
 use kube::Client;
 use kube::Resource;
 use kube::ResourceExt;
 use std::time::Duration;
 
+/// The requeue delay after an object's first failed reconcile.
+const BASE_ERROR_REQUEUE: Duration = Duration::from_secs(5);
+
+/// The longest an object waits before being retried, however often it has failed.
+const MAX_ERROR_REQUEUE: Duration = Duration::from_secs(300);
+
 pub struct ContextData {
     client: Client,
+    backoff: ErrorBackoff,
 }
 
 impl ContextData {
     pub fn new(client: Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            backoff: ErrorBackoff::default(),
+        }
     }
 }
 
+/// Counts consecutive reconcile failures per object, so a flapping dependency (e.g. a throttling
+/// API server) is retried less and less often, until the object reconciles again.
+#[derive(Default)]
+struct ErrorBackoff {
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl ErrorBackoff {
+    /// Records a failure and returns how long to wait before retrying: the base delay, doubled
+    /// for every previous consecutive failure, up to the maximum.
+    fn next(&self, key: &str) -> Duration {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(key.to_string()).or_insert(0);
+        *count = count.saturating_add(1);
+        BASE_ERROR_REQUEUE
+            .saturating_mul(2u32.saturating_pow(*count - 1))
+            .min(MAX_ERROR_REQUEUE)
+    }
+
+    fn reset(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+}
+
+fn backoff_key(fp_app: &FpApp) -> String {
+    format!(
+        "{}/{}",
+        fp_app.namespace().unwrap_or_default(),
+        fp_app.name_any()
+    )
+}
+
 enum SealedAction {
     Create,
     NoOp,
@@ -32,6 +76,12 @@ pub async fn reconcile(
     fp_app: Arc<FpApp>,
     context: Arc<ContextData>,
 ) -> SealedOperatorResult<Action> {
+    let action = reconcile_app(fp_app.clone(), &context).await?;
+    context.backoff.reset(&backoff_key(&fp_app));
+    Ok(action)
+}
+
+async fn reconcile_app(fp_app: Arc<FpApp>, context: &ContextData) -> SealedOperatorResult<Action> {
     let client: Client = context.client.clone();
     let namespace = fp_app.namespace().unwrap_or("default".to_string());
     let name = fp_app.name_any();
@@ -42,10 +92,35 @@ pub async fn reconcile(
 
     match determine_action(&fp_app) {
         SealedAction::Create => {
-            finalizer::add(client.clone(), &name, &namespace).await?;
-
-            si_controller.deploy_app().await?;
-            Ok(Action::await_change())
+            if let Some(requeue) = set_status(
+                &client,
+                &fp_app,
+                &namespace,
+                FpAppPhase::Deploying,
+                "Deploying",
+            )
+            .await?
+            {
+                return Ok(requeue);
+            }
+            if let Err(error) = si_controller.deploy_app().await {
+                set_status(
+                    &client,
+                    &fp_app,
+                    &namespace,
+                    FpAppPhase::Error,
+                    error.to_string(),
+                )
+                .await?;
+                return Err(error);
+            }
+            // Only once the app exists is there anything for the finalizer to clean up
+            if !has_finalizer(&fp_app) {
+                finalizer::add(client.clone(), &name, &namespace).await?;
+            }
+            let ready =
+                set_status(&client, &fp_app, &namespace, FpAppPhase::Ready, "Deployed").await?;
+            Ok(ready.unwrap_or_else(Action::await_change))
         }
         SealedAction::Delete => {
             finalizer::delete(client.clone(), &name, &namespace).await?;
@@ -60,26 +135,189 @@ pub async fn reconcile(
     }
 }
 
-fn determine_action(fp_app: &FpApp) -> SealedAction {
-    if fp_app.meta().deletion_timestamp.is_some() {
-        SealedAction::Delete
-    } else if fp_app
+/// Patches the status subresource. Returns the action to take instead of carrying on when the
+/// patch conflicts with another writer: a quick requeue, so the next attempt works from the
+/// latest version of the object.
+async fn set_status(
+    client: &Client,
+    fp_app: &FpApp,
+    namespace: &str,
+    phase: FpAppPhase,
+    message: impl Into<String>,
+) -> SealedOperatorResult<Option<Action>> {
+    match status::update(client.clone(), fp_app, namespace, phase, message).await {
+        Ok(_) => Ok(None),
+        Err(error) if status::is_conflict(&error) => {
+            Ok(Some(Action::requeue(Duration::from_secs(1))))
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn has_finalizer(fp_app: &FpApp) -> bool {
+    fp_app
         .meta()
         .finalizers
         .as_ref()
-        .map_or(true, |finalizers| finalizers.is_empty())
-    {
-        return SealedAction::Create;
-    } else {
+        .is_some_and(|finalizers| !finalizers.is_empty())
+}
+
+/// Deploys until the status records the current generation as ready, so a deploy that failed or
+/// was interrupted (e.g. by a conflicting status write) is retried on the next reconcile.
+fn determine_action(fp_app: &FpApp) -> SealedAction {
+    if fp_app.meta().deletion_timestamp.is_some() {
+        return SealedAction::Delete;
+    }
+    let deployed = fp_app.status.as_ref().is_some_and(|status| {
+        status.phase == Some(FpAppPhase::Ready)
+            && status.observed_generation.is_some()
+            && status.observed_generation == fp_app.meta().generation
+    });
+    if deployed && has_finalizer(fp_app) {
         SealedAction::NoOp
+    } else {
+        SealedAction::Create
     }
 }
 
 pub fn on_error(
     fp_app: Arc<FpApp>,
     error: &SealedOperatorError,
-    _context: Arc<ContextData>,
+    context: Arc<ContextData>,
 ) -> Action {
     eprintln!("Reconciliation error:\n{:?}.\n{:?}", error, fp_app);
-    Action::requeue(Duration::from_secs(5))
+    Action::requeue(context.backoff.next(&backoff_key(&fp_app)))
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{Method, Request, Response};
+    use kube::client::Body;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn test_app() -> Value {
+        json!({
+            "apiVersion": "fp.com/v1",
+            "kind": "FpApp",
+            "metadata": { "name": "test-app", "namespace": "apps", "generation": 2 },
+            "spec": { "replicas": 1, "version": "1.0.0" },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_marks_app_ready() {
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(service, "default");
+
+        // Answer every patch with the app, recording what was patched
+        let cluster = tokio::spawn(async move {
+            let mut requests = vec![];
+            while let Some((request, send)) = handle.next_request().await {
+                let method = request.method().clone();
+                let path = request.uri().path().to_string();
+                let body = request.into_body().collect_bytes().await.unwrap();
+                requests.push((
+                    method,
+                    path,
+                    serde_json::from_slice::<Value>(&body).unwrap(),
+                ));
+                send.send_response(
+                    Response::builder()
+                        .body(Body::from(test_app().to_string().into_bytes()))
+                        .unwrap(),
+                );
+            }
+            requests
+        });
+
+        let fp_app: FpApp = serde_json::from_value(test_app()).unwrap();
+        let context = Arc::new(ContextData::new(client));
+        reconcile(Arc::new(fp_app), context).await.unwrap();
+        let requests = cluster.await.unwrap();
+
+        let status_path = "/apis/fp.com/v1/namespaces/apps/fpapps/test-app/status";
+        let (method, path, body) = requests.last().unwrap();
+        assert_eq!(method, Method::PATCH);
+        assert_eq!(path, status_path);
+        assert_eq!(body["status"]["phase"], "Ready");
+        assert_eq!(body["status"]["observedGeneration"], 2);
+
+        let phases = requests
+            .iter()
+            .filter(|(_, path, _)| path == status_path)
+            .map(|(_, _, body)| body["status"]["phase"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(phases, vec!["Deploying", "Ready"]);
+
+        // The finalizer goes on once the app is deployed, just before it's marked ready
+        let app_path = "/apis/fp.com/v1/namespaces/apps/fpapps/test-app";
+        let (_, path, body) = &requests[requests.len() - 2];
+        assert_eq!(path, app_path);
+        assert_eq!(body["metadata"]["finalizers"][0], "apps.fp.com/finalizer");
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|(_, path, _)| path == app_path)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_determine_action() {
+        let app = |finalizer: bool, phase: Option<&str>, observed: i64| {
+            let mut app = test_app();
+            if finalizer {
+                app["metadata"]["finalizers"] = json!(["apps.fp.com/finalizer"]);
+            }
+            if let Some(phase) = phase {
+                app["status"] = json!({ "phase": phase, "observedGeneration": observed });
+            }
+            serde_json::from_value::<FpApp>(app).unwrap()
+        };
+        let is_create = |fp_app: FpApp| matches!(determine_action(&fp_app), SealedAction::Create);
+
+        assert!(is_create(app(false, None, 0)));
+        // A deploy that failed after the finalizer was added is retried
+        assert!(is_create(app(true, Some("Error"), 2)));
+        assert!(is_create(app(true, Some("Deploying"), 2)));
+        // As is a change to the spec since the last deploy
+        assert!(is_create(app(true, Some("Ready"), 1)));
+        assert!(matches!(
+            determine_action(&app(true, Some("Ready"), 2)),
+            SealedAction::NoOp
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_on_error_backs_off() {
+        let (service, _handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let context = Arc::new(ContextData::new(Client::new(service, "default")));
+        let fp_app: Arc<FpApp> = Arc::new(serde_json::from_value(test_app()).unwrap());
+        let error = SealedOperatorError::InvalidConfig("flapping".to_string());
+
+        let delays = (0..8)
+            .map(|_| on_error(fp_app.clone(), &error, context.clone()))
+            .collect::<Vec<_>>();
+        let expected = [5, 10, 20, 40, 80, 160, 300, 300]
+            .map(|seconds| Action::requeue(Duration::from_secs(seconds)));
+        assert_eq!(delays, expected);
+
+        // Other objects have their own backoff
+        let mut other: FpApp = serde_json::from_value(test_app()).unwrap();
+        other.metadata.name = Some("other-app".to_string());
+        assert_eq!(
+            on_error(Arc::new(other), &error, context.clone()),
+            Action::requeue(BASE_ERROR_REQUEUE)
+        );
+
+        // A successful reconcile starts over
+        context.backoff.reset(&backoff_key(&fp_app));
+        assert_eq!(
+            on_error(fp_app, &error, context),
+            Action::requeue(BASE_ERROR_REQUEUE)
+        );
+    }
 }
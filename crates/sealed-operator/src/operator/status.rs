@@ -0,0 +1,100 @@
+use kube::{
+    api::{Patch, PatchParams},
+    Api, Client, Error, Resource, ResourceExt,
+};
+use serde_json::{json, Value};
+
+use crate::error::SealedOperatorResult;
+
+use super::crd::{FpApp, FpAppPhase, FpAppStatus};
+
+/// The server-side apply field manager owning the status fields.
+const FIELD_MANAGER: &str = "sealed-operator";
+
+/// The apply patch for the status subresource. Server-side apply needs the full type information,
+/// and only the fields set here are owned by the operator, so `readyReplicas` is left alone.
+pub fn status_patch(fp_app: &FpApp, phase: FpAppPhase, message: impl Into<String>) -> Value {
+    let status = FpAppStatus {
+        phase: Some(phase),
+        observed_generation: fp_app.meta().generation,
+        message: Some(message.into()),
+        ready_replicas: None,
+    };
+    json!({
+        "apiVersion": FpApp::api_version(&()),
+        "kind": FpApp::kind(&()),
+        "status": status,
+    })
+}
+
+pub async fn update(
+    client: Client,
+    fp_app: &FpApp,
+    namespace: &str,
+    phase: FpAppPhase,
+    message: impl Into<String>,
+) -> SealedOperatorResult<FpApp, Error> {
+    let api: Api<FpApp> = Api::namespaced(client, namespace);
+    let patch = status_patch(fp_app, phase, message);
+    api.patch_status(
+        &fp_app.name_any(),
+        &PatchParams::apply(FIELD_MANAGER),
+        &Patch::Apply(&patch),
+    )
+    .await
+}
+
+/// Whether the patch was rejected because another field manager owns the status, or the object
+/// changed underneath us. Either way, the next reconcile will see the latest version.
+pub fn is_conflict(error: &Error) -> bool {
+    matches!(error, Error::Api(response) if response.code == 409)
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::core::ErrorResponse;
+
+    use super::*;
+
+    fn test_app() -> FpApp {
+        serde_json::from_value(json!({
+            "apiVersion": "fp.com/v1",
+            "kind": "FpApp",
+            "metadata": { "name": "test-app", "namespace": "apps", "generation": 3 },
+            "spec": { "replicas": 1, "version": "1.0.0" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_status_patch() {
+        let patch = status_patch(&test_app(), FpAppPhase::Error, "image not found");
+
+        assert_eq!(
+            patch,
+            json!({
+                "apiVersion": "fp.com/v1",
+                "kind": "FpApp",
+                "status": {
+                    "phase": "Error",
+                    "observedGeneration": 3,
+                    "message": "image not found",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        let error = |code| {
+            Error::Api(ErrorResponse {
+                status: "Failure".to_string(),
+                message: String::new(),
+                reason: String::new(),
+                code,
+            })
+        };
+        assert!(is_conflict(&error(409)));
+        assert!(!is_conflict(&error(404)));
+    }
+}
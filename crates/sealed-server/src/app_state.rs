@@ -1,8 +1,15 @@
 use std::sync::Arc;
 
-use sealed_common::error::SealedResult;
+use sealed_common::{
+    error::{SealedError, SealedResult},
+    events::TaskEvents,
+    settings::{DatabaseSettings, RateLimitArgs, WebhookArgs},
+    warn,
+};
 use sealed_database::{database::get_app_database, AppDatabase};
 
+use crate::{metrics::Metrics, rate_limit::RateLimiter};
+
 pub type SharedAppState = Arc<AppState>;
 
 // #[derive(Debug, Clone)]
@@ -29,16 +36,86 @@ pub type SharedAppState = Arc<AppState>;
 //         Ok(())
 //     }
 // }
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct AppState {
     pub db: AppDatabase,
+    pub metrics: Metrics,
+    /// `None` when no cluster was reachable at startup, in which case deploys fail
+    pub kube: Option<kube::Client>,
+    pub webhook: WebhookArgs,
+    /// Shared by every rate-limited route, so a client's requests count across all of them
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Lifecycle events of the tasks run for apps, e.g., builds
+    pub events: TaskEvents,
+}
+
+// `kube::Client` isn't `Debug`, so only whether there is one is shown
+impl std::fmt::Debug for AppState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppState")
+            .field("db", &self.db)
+            .field("metrics", &self.metrics)
+            .field("kube", &self.kube.as_ref().map(|_| "kube::Client"))
+            .field("webhook", &self.webhook)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("events", &self.events)
+            .finish()
+    }
 }
 
 impl AppState {
-    pub async fn new() -> SealedResult<Self> {
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must set");
-        let db = get_app_database(&database_url).await?;
+    /// Connects to `DATABASE_URL` with a pool configured by `database`. The pool is created once
+    /// here and shared by every request through the state.
+    pub async fn new(skip_migrations: bool, database: &DatabaseSettings) -> SealedResult<Self> {
+        let database_url = database_url(std::env::var("DATABASE_URL").ok())?;
+        let db = get_app_database(&database_url, skip_migrations, database).await?;
+
+        let mut state = Self::with_database(db)?;
+        match kube::Client::try_default().await {
+            Ok(client) => state.kube = Some(client),
+            Err(e) => warn!(
+                "Not connected to a Kubernetes cluster, deploys will fail: {}",
+                e
+            ),
+        }
+        Ok(state)
+    }
+
+    pub fn with_database(db: AppDatabase) -> SealedResult<Self> {
+        let metrics = Metrics::new()?;
+        Ok(Self {
+            db,
+            metrics,
+            kube: None,
+            webhook: WebhookArgs::default(),
+            rate_limiter: Arc::new(RateLimiter::new(&RateLimitArgs::default())),
+            events: TaskEvents::default(),
+        })
+    }
+}
+
+fn database_url(value: Option<String>) -> SealedResult<String> {
+    match value {
+        Some(url) if !url.trim().is_empty() => Ok(url),
+        _ => Err(SealedError::ServerError(
+            "DATABASE_URL must be set to start the server".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(Self { db })
+    #[test]
+    fn test_missing_database_url() {
+        for value in [None, Some(String::new())] {
+            let error = database_url(value).unwrap_err();
+            assert!(error.to_string().contains("DATABASE_URL must be set"));
+        }
+        assert_eq!(
+            database_url(Some("postgres://db".to_string())).unwrap(),
+            "postgres://db"
+        );
     }
 }
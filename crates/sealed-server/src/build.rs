@@ -0,0 +1,305 @@
+use std::{
+    convert::Infallible,
+    process::{ExitStatus, Stdio},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use axum::response::sse::Event;
+use futures::Stream;
+use sealed_common::events::{TaskPublisher, TaskStatus};
+use sealed_database::app::FpApp;
+use sealed_services::services::docker_service;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+
+use crate::{
+    error::{SealedServerError, SealedServerResult},
+    metrics::Metrics,
+};
+
+/// The image an app is built into, as `image:tag`.
+pub fn image_reference(app: &FpApp) -> String {
+    let image = match app.image.as_deref() {
+        Some(image) if !image.is_empty() => image,
+        _ => app.name.as_str(),
+    };
+    let tag = match app.tag.as_deref() {
+        Some(tag) if !tag.is_empty() => tag,
+        _ => "latest",
+    };
+    format!("{}:{}", image, tag)
+}
+
+/// Build the `docker build` command for an app, using its repository as the build context.
+pub fn build_command(app: &FpApp) -> SealedServerResult<Command> {
+    let repository_url = match app.repository_url.as_deref() {
+        Some(url) if !url.is_empty() => url,
+        _ => {
+            return Err(SealedServerError::ServerError(format!(
+                "App {} has no repository url to build from",
+                app.id
+            )))
+        }
+    };
+
+    let context = match app.branch.as_deref() {
+        Some(branch) if !branch.is_empty() => format!("{}#{}", repository_url, branch),
+        _ => repository_url.to_string(),
+    };
+
+    let mut command = Command::new("docker");
+    command
+        .arg("build")
+        .arg("-t")
+        .arg(image_reference(app))
+        .arg(context)
+        .env("DOCKER_BUILDKIT", "1");
+
+    Ok(command)
+}
+
+/// Run `command` and stream every stdout/stderr line as an SSE event, finishing with an
+/// `exit` event carrying the exit status. Dropping the stream kills the child process. The
+/// command's lifecycle is published through `publisher`, if any. If the command builds `image`
+/// and the image's ID doesn't change, the build was satisfied from the cache and is published as
+/// cached.
+pub fn stream_command(
+    command: Command,
+    image: Option<String>,
+    metrics: Option<Metrics>,
+    publisher: Option<TaskPublisher>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let (tx, rx) = mpsc::channel::<Event>(64);
+
+    if let Some(publisher) = &publisher {
+        publisher.publish(TaskStatus::Queued);
+    }
+
+    tokio::spawn(async move {
+        let image_before = match &image {
+            Some(image) => image_id(image).await,
+            None => None,
+        };
+        let result = pipe_command(command, &tx, publisher.as_ref()).await;
+        if let (Some(metrics), Ok(Some(status))) = (&metrics, &result) {
+            metrics.record_build(status.success());
+        }
+        if let Some(publisher) = &publisher {
+            let cached = match (&image, &image_before) {
+                (Some(image), Some(_)) => image_id(image).await == image_before,
+                _ => false,
+            };
+            publisher.publish(finished_status(&result, cached));
+        }
+        if let Err(e) = result {
+            let _ = tx
+                .send(Event::default().event("error").data(e.to_string()))
+                .await;
+        }
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok(event), rx))
+    })
+}
+
+/// The status a command is published with once it's finished.
+fn finished_status(result: &std::io::Result<Option<ExitStatus>>, cached: bool) -> TaskStatus {
+    match result {
+        Ok(Some(status)) if status.success() && cached => TaskStatus::Cached,
+        Ok(Some(status)) if status.success() => TaskStatus::Succeeded,
+        Ok(Some(status)) => TaskStatus::Failed {
+            exit_code: status.code(),
+        },
+        _ => TaskStatus::Failed { exit_code: None },
+    }
+}
+
+/// The ID of a local image, or `None` if it doesn't exist (or Docker can't be reached).
+async fn image_id(image: &str) -> Option<String> {
+    let image = image.to_string();
+    tokio::task::spawn_blocking(move || {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        docker_service::image_id("docker", &image, &interrupted)
+    })
+    .await
+    .ok()?
+    .ok()?
+}
+
+/// Returns the command's exit status, or `None` when the client disconnected first.
+async fn pipe_command(
+    mut command: Command,
+    tx: &mpsc::Sender<Event>,
+    publisher: Option<&TaskPublisher>,
+) -> std::io::Result<Option<ExitStatus>> {
+    command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn()?;
+    if let Some(publisher) = publisher {
+        publisher.publish(TaskStatus::Running);
+    }
+    let stdout = child.stdout.take().expect("Failed to capture stdout");
+    let stderr = child.stderr.take().expect("Failed to capture stderr");
+
+    let mut stdout_reader = BufReader::new(stdout).lines();
+    let mut stderr_reader = BufReader::new(stderr).lines();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    while !(stdout_done && stderr_done) {
+        let event = tokio::select! {
+            line = stdout_reader.next_line(), if !stdout_done => match line? {
+                Some(line) => Event::default().event("stdout").data(line),
+                None => {
+                    stdout_done = true;
+                    continue;
+                }
+            },
+            line = stderr_reader.next_line(), if !stderr_done => match line? {
+                Some(line) => Event::default().event("stderr").data(line),
+                None => {
+                    stderr_done = true;
+                    continue;
+                }
+            },
+            // The client went away, no point in finishing the build
+            _ = tx.closed() => {
+                child.kill().await?;
+                return Ok(None);
+            }
+        };
+
+        if tx.send(event).await.is_err() {
+            child.kill().await?;
+            return Ok(None);
+        }
+    }
+
+    let status = child.wait().await?;
+    let _ = tx
+        .send(
+            Event::default()
+                .event("exit")
+                .data(json!({ "code": status.code(), "success": status.success() }).to_string()),
+        )
+        .await;
+
+    Ok(Some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{response::sse::Sse, routing::get, Router};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    fn test_app(repository_url: Option<&str>) -> FpApp {
+        FpApp {
+            id: 1,
+            name: "test-app".to_string(),
+            description: "".to_string(),
+            app_config: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            repository_url: repository_url.map(String::from),
+            branch: Some("main".to_string()),
+            image: None,
+            tag: None,
+        }
+    }
+
+    #[test]
+    fn test_build_command() {
+        let command = build_command(&test_app(Some("https://github.com/auser/app.git"))).unwrap();
+        let args: Vec<_> = command
+            .as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            args,
+            vec![
+                "build",
+                "-t",
+                "test-app:latest",
+                "https://github.com/auser/app.git#main"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_command_without_repository() {
+        assert!(build_command(&test_app(None)).is_err());
+    }
+
+    #[test]
+    fn test_image_reference() {
+        let mut app = test_app(None);
+        assert_eq!(image_reference(&app), "test-app:latest");
+
+        app.image = Some("grid/test-app".to_string());
+        app.tag = Some("v2".to_string());
+        assert_eq!(image_reference(&app), "grid/test-app:v2");
+    }
+
+    #[test]
+    fn test_finished_status() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let success = Ok(Some(ExitStatus::from_raw(0)));
+        assert_eq!(finished_status(&success, false), TaskStatus::Succeeded);
+        assert_eq!(finished_status(&success, true), TaskStatus::Cached);
+
+        // A failed build is a failure even if the image didn't change.
+        let failure = Ok(Some(ExitStatus::from_raw(2 << 8)));
+        assert_eq!(
+            finished_status(&failure, true),
+            TaskStatus::Failed { exit_code: Some(2) }
+        );
+        assert_eq!(
+            finished_status(&Ok(None), false),
+            TaskStatus::Failed { exit_code: None }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_command_over_sse() {
+        let app = Router::new().route(
+            "/logs",
+            get(|| async {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg("echo building; echo warning >&2");
+                Sse::new(stream_command(command, None, None, None))
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /logs HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.contains("text/event-stream"));
+        assert!(response.contains("data: building"));
+        assert!(response.contains("event: exit"));
+        assert!(response.contains(r#""success":true"#));
+    }
+}
@@ -0,0 +1,166 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use sealed_common::settings::CorsArgs;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::error::{SealedServerError, SealedServerResult};
+
+/// Builds the CORS layer from the settings. An empty origin list allows any origin, but only
+/// when `run_mode` is `development`.
+pub fn cors_layer(args: &CorsArgs, run_mode: &str) -> SealedServerResult<CorsLayer> {
+    let origins = if args.allowed_origins.is_empty() {
+        if run_mode != "development" {
+            return Err(SealedServerError::ServerError(format!(
+                "server.cors.allowed_origins must be set when RUN_MODE is {}",
+                run_mode
+            )));
+        }
+        AllowOrigin::from(Any)
+    } else if args.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            args.allowed_origins
+                .iter()
+                .map(|origin| {
+                    HeaderValue::from_str(origin).map_err(|e| invalid("origin", origin, e))
+                })
+                .collect::<SealedServerResult<Vec<_>>>()?,
+        )
+    };
+
+    let methods = args
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|e| invalid("method", method, e))
+        })
+        .collect::<SealedServerResult<Vec<_>>>()?;
+
+    let headers = args
+        .allowed_headers
+        .iter()
+        .map(|header| {
+            HeaderName::from_bytes(header.as_bytes()).map_err(|e| invalid("header", header, e))
+        })
+        .collect::<SealedServerResult<Vec<_>>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers))
+}
+
+fn invalid(kind: &str, value: &str, error: impl std::fmt::Display) -> SealedServerError {
+    SealedServerError::ServerError(format!("Invalid CORS {} {}: {}", kind, value, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn cors_args(origins: &[&str]) -> CorsArgs {
+        CorsArgs {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn allow_origin_header(layer: CorsLayer, origin: &str) -> Option<String> {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(layer);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(header::ORIGIN, origin)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origin() {
+        let layer = cors_layer(&cors_args(&["https://app.example.com"]), "production").unwrap();
+        assert_eq!(
+            allow_origin_header(layer, "https://app.example.com").await,
+            Some("https://app.example.com".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_origin() {
+        let layer = cors_layer(&cors_args(&["https://app.example.com"]), "production").unwrap();
+        assert_eq!(
+            allow_origin_header(layer, "https://evil.example.com").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_any_origin_in_development() {
+        let layer = cors_layer(&cors_args(&[]), "development").unwrap();
+        assert_eq!(
+            allow_origin_header(layer, "https://anything.example.com").await,
+            Some("*".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_methods_allow_updates_and_deletes() {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(cors_layer(&cors_args(&["https://app.example.com"]), "production").unwrap());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("OPTIONS")
+                    .uri("/")
+                    .header(header::ORIGIN, "https://app.example.com")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .to_string();
+        for method in ["GET", "POST", "PUT", "DELETE", "OPTIONS"] {
+            assert!(methods.contains(method), "{methods}");
+        }
+    }
+
+    #[test]
+    fn test_production_requires_origins() {
+        assert!(cors_layer(&cors_args(&[]), "production").is_err());
+    }
+
+    #[test]
+    fn test_invalid_method() {
+        let args = CorsArgs {
+            allowed_methods: vec!["NOT A METHOD".to_string()],
+            ..cors_args(&["https://app.example.com"])
+        };
+        assert!(cors_layer(&args, "production").is_err());
+    }
+}
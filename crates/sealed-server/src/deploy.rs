@@ -0,0 +1,88 @@
+use kube::Client;
+use sealed_database::app::FpApp;
+use sealed_operator::{app_config::AppConfig, error::SealedOperatorError, installer};
+
+use crate::error::{SealedServerError, SealedServerResult};
+
+/// Parse the app's stored `app_config` into an `AppConfig`.
+pub fn app_config(app: &FpApp) -> SealedServerResult<AppConfig> {
+    match &app.app_config {
+        Some(config) if !config.is_null() => serde_json::from_value(config.clone()).map_err(|e| {
+            SealedServerError::BadRequest(format!(
+                "App {} has an invalid app_config: {}",
+                app.id, e
+            ))
+        }),
+        _ => Err(SealedServerError::BadRequest(format!(
+            "App {} has no app_config to deploy",
+            app.id
+        ))),
+    }
+}
+
+/// Apply the app's resources to the cluster in the client's default namespace, returning the
+/// `Kind/name` of each applied resource.
+pub async fn deploy(client: Option<&Client>, app: &FpApp) -> SealedServerResult<Vec<String>> {
+    let config = app_config(app)?;
+    let client = client.ok_or_else(|| {
+        SealedServerError::ClusterError("Not connected to a Kubernetes cluster".to_string())
+    })?;
+
+    installer::deploy(client, &config, client.default_namespace())
+        .await
+        .map_err(|e| match e {
+            SealedOperatorError::Kube { source } => {
+                SealedServerError::ClusterError(source.to_string())
+            }
+            e => SealedServerError::BadRequest(format!(
+                "Unable to build resources for app {}: {}",
+                app.id, e
+            )),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn test_app(app_config: Option<serde_json::Value>) -> FpApp {
+        FpApp {
+            id: 1,
+            name: "test-app".to_string(),
+            description: "".to_string(),
+            app_config,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            repository_url: None,
+            branch: None,
+            image: None,
+            tag: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_app_config() {
+        for config in [None, Some(serde_json::Value::Null)] {
+            let err = deploy(None, &test_app(config)).await.unwrap_err();
+            assert!(matches!(err, SealedServerError::BadRequest(_)));
+            assert!(err.to_string().contains("no app_config"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_app_config() {
+        let err = deploy(None, &test_app(Some(json!({ "image": "nginx" }))))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SealedServerError::BadRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_no_cluster() {
+        let config = json!({ "name": "test-app", "dependencies": [], "ports": [80] });
+        let err = deploy(None, &test_app(Some(config))).await.unwrap_err();
+        assert!(matches!(err, SealedServerError::ClusterError(_)));
+    }
+}
@@ -11,11 +11,53 @@ pub enum SealedServerError {
     ServerError(String),
     #[error("Database error: {0}")]
     DatabaseError(sealed_database::error::SealedDatabaseError),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("No data")]
+    NoData,
+    #[error("Kubernetes error: {0}")]
+    ClusterError(String),
+    #[error("Too many requests from {0}")]
+    TooManyRequests(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+}
+
+impl SealedServerError {
+    /// A stable, machine-readable identifier for the kind of error, included in error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SealedServerError::ServerError(_) => "server_error",
+            SealedServerError::DatabaseError(_) => "db_error",
+            SealedServerError::BadRequest(_) => "bad_request",
+            SealedServerError::NoData => "not_found",
+            SealedServerError::ClusterError(_) => "kube_error",
+            SealedServerError::TooManyRequests(_) => "rate_limited",
+            SealedServerError::Unauthorized(_) => "unauthorized",
+        }
+    }
 }
 
 impl From<SealedServerError> for SealedError {
     fn from(error: SealedServerError) -> Self {
-        SealedError::ServerError(error.to_string())
+        match error {
+            SealedServerError::BadRequest(msg) => SealedError::BadRequest(msg),
+            SealedServerError::NoData => SealedError::NoData,
+            error => SealedError::ServerError(error.to_string()),
+        }
+    }
+}
+
+impl From<SealedError> for SealedServerError {
+    fn from(error: SealedError) -> Self {
+        match error {
+            SealedError::BadRequest(msg) => SealedServerError::BadRequest(msg),
+            SealedError::NoData => SealedServerError::NoData,
+            SealedError::DatabaseError(msg) => SealedServerError::DatabaseError(
+                sealed_database::error::SealedDatabaseError::System(msg, None),
+            ),
+            error => SealedServerError::ServerError(error.to_string()),
+        }
     }
 }
 
@@ -36,3 +78,18 @@ impl From<sealed_database::error::SealedDatabaseError> for SealedServerError {
         SealedServerError::DatabaseError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_sealed_error_keeps_code() {
+        let error = SealedServerError::from(SealedError::DatabaseError("no rows".to_string()));
+        assert_eq!(error.code(), "db_error");
+        assert!(error.to_string().contains("no rows"));
+
+        let error = SealedServerError::from(SealedError::BadRequest("no name".to_string()));
+        assert_eq!(error.code(), "bad_request");
+    }
+}
@@ -1,16 +1,22 @@
-use std::sync::Arc;
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
 
 use app_state::AppState;
-use axum::http::{header::CONTENT_TYPE, Method};
-use sealed_common::{error::SealedResult, settings::ServerArgs};
-use tokio::net::TcpListener;
-use tower_http::cors::{Any, CorsLayer};
+use sealed_common::{error::SealedResult, info, settings::ServerArgs, warn};
+use tokio::{net::TcpListener, sync::oneshot};
 
 mod app_state;
+pub(crate) mod build;
+pub(crate) mod cors;
+pub(crate) mod deploy;
 pub(crate) mod error;
 pub(crate) mod git;
+pub(crate) mod metrics;
+pub(crate) mod rate_limit;
 // pub(crate) mod repo;
+pub(crate) mod request_id;
 mod routes;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub(crate) mod utils;
 
 #[derive(Debug)]
@@ -24,27 +30,139 @@ impl Server {
     }
 
     pub async fn run(&self) -> SealedResult<()> {
-        let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
-            .allow_origin(Any)
-            .allow_headers([CONTENT_TYPE]);
+        let app_state = AppState::new(self.args.skip_migrations, &self.args.database).await?;
+        self.serve(app_state, shutdown_signal()).await
+    }
+
+    /// Serves until `shutdown` completes, then stops accepting connections and gives in-flight
+    /// requests up to `shutdown_timeout_secs` to finish before closing the database pool.
+    async fn serve(
+        &self,
+        mut app_state: AppState,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> SealedResult<()> {
+        let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".to_string());
+        let cors = cors::cors_layer(&self.args.cors, &run_mode)?;
+
+        app_state.webhook = self.args.webhook.clone();
+        app_state.rate_limiter = Arc::new(rate_limit::RateLimiter::new(&self.args.rate_limit));
 
-        let app_state = AppState::new().await?;
+        let db = app_state.db.clone();
         let shared_state = Arc::new(app_state);
 
         let app = routes::routes(shared_state);
         let app = app.layer(cors);
 
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.args.port)).await?;
+
         println!(
             "Server started successfully at http://0.0.0.0:{}",
-            self.args.port
+            listener.local_addr()?.port()
         );
 
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.args.port))
-            .await
-            .unwrap();
-        axum::serve(listener, app).await?;
+        // Start the drain timeout only once the shutdown signal has been received
+        let (draining_tx, draining_rx) = oneshot::channel();
+        let shutdown = async move {
+            shutdown.await;
+            info!("Shutting down, waiting for in-flight requests to finish");
+            let _ = draining_tx.send(());
+        };
+        let drain_timeout = Duration::from_secs(self.args.shutdown_timeout_secs);
+
+        tokio::select! {
+            result = axum::serve(
+                listener,
+                // The rate limiter identifies clients by their address
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown) => result?,
+            _ = async {
+                match draining_rx.await {
+                    Ok(()) => tokio::time::sleep(drain_timeout).await,
+                    Err(_) => std::future::pending().await,
+                }
+            } => {
+                warn!(
+                    "In-flight requests didn't finish within {}s, dropping them",
+                    drain_timeout.as_secs()
+                );
+            }
+        }
+
+        db.get_pool().close().await;
 
         Ok(())
     }
 }
+
+/// Completes on SIGINT or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Unable to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Unable to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_state;
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        let app_state = test_state().await;
+        let pool = app_state.db.get_pool().clone();
+
+        let server = Server::new(ServerArgs {
+            port: 0,
+            skip_migrations: true,
+            shutdown_timeout_secs: 5,
+            cors: Default::default(),
+            webhook: Default::default(),
+            rate_limit: Default::default(),
+            database: Default::default(),
+        })
+        .await;
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            server
+                .serve(app_state, async {
+                    let _ = shutdown_rx.await;
+                })
+                .await
+        });
+
+        shutdown_tx.send(()).unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("the server didn't shut down in time")
+            .unwrap();
+        assert!(result.is_ok());
+        assert!(pool.is_closed());
+    }
+}
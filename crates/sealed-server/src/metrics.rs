@@ -0,0 +1,108 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{
+    app_state::SharedAppState,
+    error::{SealedServerError, SealedServerResult},
+};
+
+/// The path the metrics are served on, excluded from request timing.
+pub const METRICS_PATH: &str = "/metrics";
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub apps_created: IntCounter,
+    pub builds: IntCounterVec,
+    pub request_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> SealedServerResult<Self> {
+        let registry = Registry::new_custom(Some("sealed".to_string()), None)?;
+
+        let apps_created = IntCounter::new("apps_created_total", "Total number of apps created")?;
+        let builds = IntCounterVec::new(
+            Opts::new("builds_total", "Total number of app builds by result"),
+            &["result"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latencies in seconds",
+            ),
+            &["method", "route"],
+        )?;
+
+        registry.register(Box::new(apps_created.clone()))?;
+        registry.register(Box::new(builds.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+
+        Ok(Self {
+            registry,
+            apps_created,
+            builds,
+            request_duration,
+        })
+    }
+
+    pub fn record_build(&self, success: bool) {
+        let result = if success { "success" } else { "failure" };
+        self.builds.with_label_values(&[result]).inc();
+    }
+
+    /// Renders every registered metric in the Prometheus text exposition format.
+    pub fn render(&self) -> SealedServerResult<String> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| SealedServerError::ServerError(e.to_string()))
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl From<prometheus::Error> for SealedServerError {
+    fn from(err: prometheus::Error) -> Self {
+        SealedServerError::ServerError(err.to_string())
+    }
+}
+
+/// Times every request, labelled by method and route template (never the raw path) to keep
+/// label cardinality bounded. Requests that matched no route share the `unmatched` label.
+pub async fn track_requests(
+    State(state): State<SharedAppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => "unmatched".to_string(),
+    };
+    if route.ends_with(METRICS_PATH) {
+        return next.run(request).await;
+    }
+
+    let method = request.method().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    state
+        .metrics
+        .request_duration
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sealed_common::settings::RateLimitArgs;
+
+use crate::{
+    app_state::SharedAppState, error::SealedServerError,
+    utils::server_utils::handle_error_with_status,
+};
+
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Beyond this many clients, buckets which have refilled completely are dropped, since they're
+/// indistinguishable from new ones.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A token bucket per client. Every request takes a token, and tokens refill continuously at
+/// `requests` per `window`, up to `requests`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(args: &RateLimitArgs) -> Self {
+        let capacity = f64::from(args.requests.max(1));
+        Self {
+            capacity,
+            refill_per_sec: capacity / args.window_secs.max(1) as f64,
+            trust_forwarded_for: args.trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `client` at `now`. When the bucket is empty, returns how long until the
+    /// next token is available.
+    fn check(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity)
+    }
+
+    /// Identifies the client by its address, or by the last address in `X-Forwarded-For` when
+    /// that's trusted. The last address is the one the proxy appended; the ones before it come
+    /// from the client, which can send anything.
+    fn client(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+        if self.trust_forwarded_for {
+            if let Some(forwarded) = headers
+                .get_all(FORWARDED_FOR_HEADER)
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+            {
+                return forwarded.to_string();
+            }
+        }
+
+        peer.map_or_else(|| "unknown".to_string(), |peer| peer.ip().to_string())
+    }
+}
+
+/// Rejects requests with `429 Too Many Requests` and a `Retry-After` header once the client has
+/// used up its requests.
+pub async fn rate_limit(
+    State(state): State<SharedAppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| *peer);
+    let client = state.rate_limiter.client(request.headers(), peer);
+
+    match state.rate_limiter.check(&client, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            // Round up, so a client that waits that long is let through
+            let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            let mut response = handle_error_with_status(
+                SealedServerError::TooManyRequests(client),
+                StatusCode::TOO_MANY_REQUESTS,
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.max(1).into());
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests: u32, window_secs: u64, trust_forwarded_for: bool) -> RateLimiter {
+        RateLimiter::new(&RateLimitArgs {
+            requests,
+            window_secs,
+            trust_forwarded_for,
+        })
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let limiter = limiter(2, 10, false);
+        let start = Instant::now();
+
+        assert!(limiter.check("10.0.0.1", start).is_ok());
+        assert!(limiter.check("10.0.0.1", start).is_ok());
+        let retry_after = limiter.check("10.0.0.1", start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(5));
+
+        // Other clients have their own buckets
+        assert!(limiter.check("10.0.0.2", start).is_ok());
+
+        assert!(limiter
+            .check("10.0.0.1", start + Duration::from_secs(5))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_client_address() {
+        let peer = Some("10.0.0.1:4000".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            "203.0.113.7, 10.0.0.9".parse().unwrap(),
+        );
+
+        assert_eq!(limiter(1, 1, false).client(&headers, peer), "10.0.0.1");
+        assert_eq!(limiter(1, 1, true).client(&headers, peer), "10.0.0.9");
+        assert_eq!(
+            limiter(1, 1, true).client(&HeaderMap::new(), peer),
+            "10.0.0.1"
+        );
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for() {
+        let limiter = limiter(1, 60, true);
+        let peer = Some("10.0.0.1:4000".parse().unwrap());
+        let start = Instant::now();
+        let request = |forwarded: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED_FOR_HEADER, forwarded.parse().unwrap());
+            limiter.check(&limiter.client(&headers, peer), start)
+        };
+
+        assert!(request("198.51.100.1, 203.0.113.7").is_ok());
+        // A new leading address per request doesn't get the client a new bucket
+        assert!(request("198.51.100.2, 203.0.113.7").is_err());
+        assert!(request("203.0.113.7").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderName,
+    Router,
+};
+use sealed_common::tracing;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Gives every request an `x-request-id` (keeping one sent by the client), echoes it back on the
+/// response, and runs the request in a span carrying the method, route template and request id,
+/// so anything logged while handling it can be traced back to the request.
+pub fn with_request_tracing<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let header = HeaderName::from_static(REQUEST_ID_HEADER);
+
+    // Layers run outermost-last, so the id is set before the span is made
+    router
+        .layer(PropagateRequestIdLayer::new(header.clone()))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request| {
+                let request_id = request
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("unknown");
+                let route = request
+                    .extensions()
+                    .get::<MatchedPath>()
+                    .map(MatchedPath::as_str)
+                    .unwrap_or("unmatched");
+
+                tracing::info_span!(
+                    "request",
+                    method = %request.method(),
+                    route,
+                    request_id,
+                )
+            }),
+        )
+        .layer(SetRequestIdLayer::new(header, MakeRequestUuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_router() -> Router {
+        with_request_tracing(Router::new().route("/", get(|| async { "ok" })))
+    }
+
+    #[tokio::test]
+    async fn test_generates_request_id() {
+        let response = test_router()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let request_id = response.headers().get(REQUEST_ID_HEADER).unwrap();
+        assert!(!request_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_echoes_request_id() {
+        let response = test_router()
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(REQUEST_ID_HEADER, "abc-123")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+    }
+}
@@ -1,18 +1,22 @@
 use app::FpApp;
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    response::{
+        sse::{KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{get, post, put},
     Json, Router,
 };
-use schema::{Pagination, PaginationParams};
+use schema::{AppFilter, Pagination, PaginationParams};
 use serde_json::Value;
 
 use crate::{
     app_state::SharedAppState,
+    build, deploy,
     error::{SealedServerError, SealedServerResult},
-    utils::server_utils::handle_error,
+    utils::server_utils::{handle_error, handle_error_with_status, not_found},
 };
 use sealed_database::*;
 
@@ -29,6 +33,9 @@ pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
     Router::new()
         .route("/", post(create_new_app))
         .route("/", get(list_apps))
+        .route("/:id", put(update_existing_app).delete(delete_existing_app))
+        .route("/:id/build/logs", get(stream_build_logs))
+        .route("/:id/deploy", post(deploy_app))
         .with_state(app_state)
 }
 
@@ -41,12 +48,19 @@ pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
     ),
     paths(
         list_apps,
-        create_new_app
+        create_new_app,
+        update_existing_app,
+        delete_existing_app,
+        stream_build_logs,
+        deploy_app
     ),
     components(
         schemas(
             FpApp,
+            AppsPage,
+            DeployResponse,
             CreateAppRequest,
+            UpdateAppRequest,
         )
     ),
     tags(
@@ -55,13 +69,23 @@ pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
 )]
 pub struct AppsOpenApi;
 
+/// The number of apps matching the filters across all pages.
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// A page of apps along with the number of apps matching the filters across all pages.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct AppsPage {
+    pub items: Vec<FpApp>,
+    pub total: i64,
+}
+
 #[utoipa::path(
     tag = "Get all apps",
     get,
     path = "/api/apps",
     operation_id = "list_apps",
     responses(
-        (status = 200, description = "List of apps", body = [FpApp], content_type = "application/json")
+        (status = 200, description = "List of apps. The total across all pages is in the `X-Total-Count` header, or in the body with `envelope=true`.", body = [FpApp], content_type = "application/json")
     ),
     // params(
     //     ("limit" = u32, description = "Limit the number of apps returned"),
@@ -72,14 +96,28 @@ pub async fn list_apps(
     Query(opts): Query<PaginationParams>,
     State(state): State<SharedAppState>,
 ) -> impl IntoResponse {
-    let opts = Pagination {
+    let pagination = Pagination {
         offset: opts.offset.unwrap_or(0),
         limit: opts.limit.unwrap_or(10),
     };
+    let filter = AppFilter {
+        name_contains: opts.name_contains.or(opts.search),
+        repository_url: opts.repository_url,
+    };
 
-    match apps_repo::get_apps(&state.db, opts).await {
-        Ok(apps) => Ok(Json(apps)),
-        Err(err) => Err(handle_error(SealedServerError::from(err))),
+    let (apps, total) = match tokio::try_join!(
+        apps_repo::get_apps(&state.db, pagination, filter.clone()),
+        apps_repo::count_apps(&state.db, filter),
+    ) {
+        Ok(result) => result,
+        Err(err) => return Err(handle_error(SealedServerError::from(err))),
+    };
+
+    let headers = [(TOTAL_COUNT_HEADER, total.to_string())];
+    if opts.envelope.unwrap_or(false) {
+        Ok((headers, Json(AppsPage { items: apps, total })).into_response())
+    } else {
+        Ok((headers, Json(apps)).into_response())
     }
 }
 
@@ -98,7 +136,196 @@ pub async fn create_new_app(
     Json(create_app_request): Json<apps_repo::CreateAppRequest>,
 ) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
     match apps_repo::create_app(&state.db, create_app_request).await {
-        Ok(app) => Ok((StatusCode::CREATED, Json(app)).into_response()),
+        Ok(app) => {
+            state.metrics.apps_created.inc();
+            Ok((StatusCode::CREATED, Json(app)).into_response())
+        }
+        Err(err) => Err(handle_error(SealedServerError::from(err))),
+    }
+}
+
+#[utoipa::path(
+    tag = "Update app",
+    put,
+    path = "/api/apps/{id}",
+    request_body = UpdateAppRequest,
+    params(
+        ("id" = i64, Path, description = "The id of the app to update"),
+    ),
+    responses(
+        (status = 200, description = "Update an existing app", body = FpApp),
+        (status = 404, description = "App not found", body = Value),
+        (status = 500, description = "Internal server error", body = Value)
+    ),
+)]
+pub async fn update_existing_app(
+    State(state): State<SharedAppState>,
+    Path(id): Path<i64>,
+    Json(update_app_request): Json<apps_repo::UpdateAppRequest>,
+) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
+    match apps_repo::update_app(&state.db, id, update_app_request).await {
+        Ok(Some(app)) => Ok((StatusCode::OK, Json(app)).into_response()),
+        Ok(None) => Err(not_found(format!("App {} not found", id))),
+        Err(err) => Err(handle_error(SealedServerError::from(err))),
+    }
+}
+
+#[utoipa::path(
+    tag = "Delete app",
+    delete,
+    path = "/api/apps/{id}",
+    params(
+        ("id" = i64, Path, description = "The id of the app to delete"),
+    ),
+    responses(
+        (status = 204, description = "App deleted"),
+        (status = 404, description = "App not found", body = Value),
+        (status = 500, description = "Internal server error", body = Value)
+    ),
+)]
+pub async fn delete_existing_app(
+    State(state): State<SharedAppState>,
+    Path(id): Path<i64>,
+) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
+    match apps_repo::delete_app(&state.db, id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Ok(false) => Err(not_found(format!("App {} not found", id))),
+        Err(err) => Err(handle_error(SealedServerError::from(err))),
+    }
+}
+
+/// The task name build events are published under.
+pub const BUILD_TASK: &str = "build";
+
+#[utoipa::path(
+    tag = "Stream build logs",
+    get,
+    path = "/api/apps/{id}/build/logs",
+    params(
+        ("id" = i64, Path, description = "The id of the app to build"),
+    ),
+    responses(
+        (status = 200, description = "Build output as server-sent events, ending with an `exit` event", content_type = "text/event-stream"),
+        (status = 404, description = "App not found", body = Value),
+        (status = 500, description = "Internal server error", body = Value)
+    ),
+)]
+pub async fn stream_build_logs(
+    State(state): State<SharedAppState>,
+    Path(id): Path<i64>,
+) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
+    match apps_repo::get_app(&state.db, id).await {
+        Ok(Some(app)) => {
+            let command = build::build_command(&app)
+                .map_err(|err| handle_error_with_status(err, StatusCode::UNPROCESSABLE_ENTITY))?;
+            let stream = build::stream_command(
+                command,
+                Some(build::image_reference(&app)),
+                Some(state.metrics.clone()),
+                Some(state.events.publisher(app.id, BUILD_TASK)),
+            );
+            Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+        }
+        Ok(None) => Err(not_found(format!("App {} not found", id))),
         Err(err) => Err(handle_error(SealedServerError::from(err))),
     }
 }
+
+/// The resources applied to the cluster, as `Kind/name`.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct DeployResponse {
+    pub resources: Vec<String>,
+}
+
+#[utoipa::path(
+    tag = "Deploy app",
+    post,
+    path = "/api/apps/{id}/deploy",
+    params(
+        ("id" = i64, Path, description = "The id of the app to deploy"),
+    ),
+    responses(
+        (status = 200, description = "The app's resources were applied to the cluster", body = DeployResponse),
+        (status = 400, description = "The app has no valid app_config", body = Value),
+        (status = 404, description = "App not found", body = Value),
+        (status = 502, description = "The cluster rejected the resources or couldn't be reached", body = Value)
+    ),
+)]
+pub async fn deploy_app(
+    State(state): State<SharedAppState>,
+    Path(id): Path<i64>,
+) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
+    match apps_repo::get_app(&state.db, id).await {
+        Ok(Some(app)) => match deploy::deploy(state.kube.as_ref(), &app).await {
+            Ok(resources) => Ok(Json(DeployResponse { resources })),
+            Err(err) => Err(handle_error(err)),
+        },
+        Ok(None) => Err(not_found(format!("App {} not found", id))),
+        Err(err) => Err(handle_error(SealedServerError::from(err))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_utils::test_state;
+
+    fn params(name_contains: &str, offset: i64, envelope: Option<bool>) -> PaginationParams {
+        PaginationParams {
+            offset: Some(offset),
+            limit: Some(2),
+            search: None,
+            name_contains: Some(name_contains.to_string()),
+            repository_url: None,
+            envelope,
+        }
+    }
+
+    async fn body(response: axum::response::Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_apps_total_count() {
+        let state = Arc::new(test_state().await);
+        let token = format!("total{}", std::process::id());
+        for i in 0..3 {
+            apps_repo::create_app(
+                &state.db,
+                CreateAppRequest {
+                    name: Some(format!("{}-{}", token, i)),
+                    description: None,
+                    app_config: None,
+                    repository_url: None,
+                    branch: None,
+                    image: None,
+                    tag: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        for offset in [0, 2] {
+            let response = list_apps(Query(params(&token, offset, None)), State(state.clone()))
+                .await
+                .into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[TOTAL_COUNT_HEADER], "3");
+            assert!(body(response).await.is_array());
+        }
+
+        let response = list_apps(Query(params(&token, 2, Some(true))), State(state))
+            .await
+            .into_response();
+        assert_eq!(response.headers()[TOTAL_COUNT_HEADER], "3");
+        let page = body(response).await;
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    }
+}
@@ -1,10 +1,11 @@
-use axum::{response::IntoResponse, routing::get, Json, Router};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 
 use crate::app_state::SharedAppState;
 
 pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
     Router::new()
         .route("/health", get(health_check_handler))
+        .route("/livez", get(liveness_handler))
         .with_state(app_state)
 }
 
@@ -16,7 +17,8 @@ pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
         description = "API for Healthcheck"
     ),
     paths(
-        health_check_handler
+        health_check_handler,
+        liveness_handler
     ),
     tags(
         (name = "Healthcheck", description = "Healthcheck related endpoints")
@@ -24,21 +26,74 @@ pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
 )]
 pub struct HealthCheckOpenApi;
 
+/// Readiness: the API is only ready to serve when the database is reachable.
 #[utoipa::path(
     tag = "Healthcheck",
     get,
     path = "/api/health",
     responses(
-        (status = 200, description = "API Services running", body = Value)
+        (status = 200, description = "API Services running", body = Value),
+        (status = 503, description = "The database is unreachable", body = Value)
     ),
 )]
-pub async fn health_check_handler() -> impl IntoResponse {
-    const MESSAGE: &str = "API Services running";
+pub async fn health_check_handler(State(state): State<SharedAppState>) -> impl IntoResponse {
+    match state.db.ping().await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "status": "ok",
+                "message": "API Services running"
+            })),
+        ),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "unavailable",
+                "message": format!("Database unreachable: {}", e)
+            })),
+        ),
+    }
+}
+
+/// Liveness: doesn't touch the database, so a database outage doesn't restart the pod.
+#[utoipa::path(
+    tag = "Healthcheck",
+    get,
+    path = "/api/livez",
+    responses(
+        (status = 200, description = "The process is alive", body = Value)
+    ),
+)]
+pub async fn liveness_handler() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_utils::test_state;
+
+    #[tokio::test]
+    async fn test_health_check_healthy() {
+        let state = Arc::new(test_state().await);
+        let response = health_check_handler(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_database_down() {
+        let state = Arc::new(test_state().await);
+        state.db.get_pool().close().await;
 
-    let json_response = serde_json::json!({
-        "status": "ok",
-        "message": MESSAGE
-    });
+        let response = health_check_handler(State(state.clone()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
 
-    Json(json_response)
+        // Liveness is unaffected
+        let response = liveness_handler().await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
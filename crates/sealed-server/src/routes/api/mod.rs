@@ -7,17 +7,70 @@ pub mod webhook;
 use std::sync::Arc;
 
 use apps::routes as apps_routes;
-use axum::Router;
+use axum::{middleware, Router};
 use healthcheck::routes as healthcheck_routes;
 use webhook::routes as webhook_routes;
 
-use crate::app_state::SharedAppState;
+use crate::{app_state::SharedAppState, rate_limit::rate_limit};
 // use git::routes as git_routes;
 
 pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
     Router::new()
         .nest("/", healthcheck_routes(Arc::clone(&app_state)))
-        .nest("/webhook", webhook_routes(Arc::clone(&app_state)))
+        // The webhook is public, so it's rate limited
+        .nest(
+            "/webhook",
+            webhook_routes(Arc::clone(&app_state)).layer(middleware::from_fn_with_state(
+                Arc::clone(&app_state),
+                rate_limit,
+            )),
+        )
         .nest("/apps", apps_routes(Arc::clone(&app_state)))
     // .nest("/git", git_routes(Arc::clone(&app_state)))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::RETRY_AFTER, Request, StatusCode},
+    };
+    use sealed_common::settings::RateLimitArgs;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{rate_limit::RateLimiter, test_utils::test_state};
+
+    #[tokio::test]
+    async fn test_webhook_rate_limited() {
+        const LIMIT: u32 = 3;
+
+        let mut state = test_state().await;
+        state.rate_limiter = Arc::new(RateLimiter::new(&RateLimitArgs {
+            requests: LIMIT,
+            window_secs: 60,
+            trust_forwarded_for: false,
+        }));
+        let state = Arc::new(state);
+        let app = routes(state.clone()).with_state(state);
+
+        let get_webhook = || {
+            app.clone()
+                .oneshot(Request::get("/webhook").body(Body::empty()).unwrap())
+        };
+        for _ in 0..LIMIT {
+            assert_eq!(get_webhook().await.unwrap().status(), StatusCode::OK);
+        }
+
+        let response = get_webhook().await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "20");
+
+        // Other routes aren't limited
+        let response = app
+            .oneshot(Request::get("/livez").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
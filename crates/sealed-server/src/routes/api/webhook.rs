@@ -1,9 +1,28 @@
 use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
     routing::{get, post},
-    Router,
+    Json, Router,
 };
+use hmac::{Hmac, Mac};
+use sealed_common::settings::WebhookArgs;
+use sealed_database::{
+    app::FpApp,
+    apps_repo::{self, CreateAppRequest, UpdateAppRequest},
+    AppDatabase,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
 
-use crate::app_state::SharedAppState;
+use crate::{
+    app_state::SharedAppState,
+    error::{SealedServerError, SealedServerResult},
+    utils::server_utils::handle_error,
+};
 
 pub fn routes(shared_app_state: SharedAppState) -> Router<SharedAppState> {
     let router = axum::Router::new();
@@ -13,10 +32,492 @@ pub fn routes(shared_app_state: SharedAppState) -> Router<SharedAppState> {
         .route("/", get(webhook_get_handler))
 }
 
-pub async fn webhook_handler() -> impl axum::response::IntoResponse {
-    "Webhook"
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    info(
+        title = "Webhook API",
+        version = "0.1.0",
+        description = "API for Git push webhooks"
+    ),
+    paths(webhook_handler),
+    components(schemas(WebhookResponse)),
+    tags(
+        (name = "Webhook", description = "Git hosting webhook endpoints")
+    )
+)]
+pub struct WebhookOpenApi;
+
+/// The length of the commit SHA used as the image tag of a pushed app.
+const TAG_SHA_LENGTH: usize = 7;
+
+/// The parts of a GitHub or GitLab push event that are needed to track the app. GitHub describes
+/// the repository in `repository` and the pushed commit in `after`, while GitLab uses `project`
+/// and `checkout_sha`.
+#[derive(Debug, Deserialize)]
+pub struct PushPayload {
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub checkout_sha: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub repository: Option<PushRepository>,
+    #[serde(default)]
+    pub project: Option<PushRepository>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushRepository {
+    pub name: String,
+    /// GitHub
+    #[serde(default)]
+    pub clone_url: Option<String>,
+    /// GitLab
+    #[serde(default)]
+    pub git_http_url: Option<String>,
+    #[serde(default)]
+    pub default_branch: Option<String>,
+}
+
+/// Checks that a delivery comes from the git host, before anything else is done with it. GitHub
+/// signs the body with the shared secret (`X-Hub-Signature-256`), while GitLab sends the secret
+/// itself (`X-Gitlab-Token`). Both are compared in constant time.
+fn authenticate(webhook: &WebhookArgs, headers: &HeaderMap, body: &[u8]) -> SealedServerResult {
+    let unauthorized = |reason: &str| SealedServerError::Unauthorized(reason.to_string());
+    let Some(secret) = &webhook.secret else {
+        return Err(unauthorized("No webhook secret is configured"));
+    };
+
+    if let Some(signature) = headers.get("x-hub-signature-256") {
+        let signature = signature
+            .to_str()
+            .ok()
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .and_then(|signature| hex::decode(signature).ok())
+            .ok_or_else(|| unauthorized("The signature is malformed"))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(body);
+        return mac
+            .verify_slice(&signature)
+            .map_err(|_| unauthorized("The signature doesn't match"));
+    }
+
+    match headers.get("x-gitlab-token") {
+        Some(token) if bool::from(token.as_bytes().ct_eq(secret.as_bytes())) => Ok(()),
+        Some(_) => Err(unauthorized("The token doesn't match")),
+        None => Err(unauthorized(
+            "The delivery is neither signed nor has a token",
+        )),
+    }
+}
+
+/// A push to a branch.
+#[derive(Debug, PartialEq, Eq)]
+struct Push {
+    name: String,
+    repository_url: String,
+    branch: String,
+    default_branch: Option<String>,
+    commit: String,
+}
+
+impl PushPayload {
+    /// Returns `Ok(None)` for pushes which don't update a branch, such as tag pushes and branch
+    /// deletions.
+    fn push(self) -> SealedServerResult<Option<Push>> {
+        let Some(branch) = self.git_ref.strip_prefix("refs/heads/") else {
+            return Ok(None);
+        };
+        let commit = match self.checkout_sha.or(self.after) {
+            Some(commit) if !self.deleted && commit.chars().any(|c| c != '0') => commit,
+            _ => return Ok(None),
+        };
+
+        let repository = self.project.or(self.repository).ok_or_else(|| {
+            SealedServerError::BadRequest("The push has no repository".to_string())
+        })?;
+        let repository_url = repository
+            .clone_url
+            .or(repository.git_http_url)
+            .ok_or_else(|| {
+                SealedServerError::BadRequest("The push has no repository URL".to_string())
+            })?;
+
+        Ok(Some(Push {
+            name: repository.name,
+            repository_url,
+            branch: branch.to_string(),
+            default_branch: repository.default_branch,
+            commit,
+        }))
+    }
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct WebhookResponse {
+    /// The app created or updated by the push
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<i64>,
+    /// Why the push didn't affect any app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored: Option<String>,
+}
+
+impl WebhookResponse {
+    fn ignored(reason: impl Into<String>) -> (StatusCode, Json<Self>) {
+        (
+            StatusCode::OK,
+            Json(WebhookResponse {
+                app_id: None,
+                ignored: Some(reason.into()),
+            }),
+        )
+    }
+}
+
+/// Create an app for the pushed repository, or point the existing one at the pushed branch and
+/// commit.
+async fn upsert_app(db: &AppDatabase, push: Push) -> SealedServerResult<FpApp> {
+    let tag = push.commit.chars().take(TAG_SHA_LENGTH).collect::<String>();
+
+    match apps_repo::find_app_by_repository(db, &push.repository_url).await? {
+        Some(app) => apps_repo::update_app(
+            db,
+            app.id,
+            UpdateAppRequest {
+                branch: Some(push.branch),
+                tag: Some(tag),
+                ..Default::default()
+            },
+        )
+        .await?
+        .ok_or(SealedServerError::NoData),
+        None => Ok(apps_repo::create_app(
+            db,
+            CreateAppRequest {
+                name: Some(push.name),
+                description: None,
+                app_config: None,
+                repository_url: Some(push.repository_url),
+                branch: Some(push.branch),
+                image: None,
+                tag: Some(tag),
+            },
+        )
+        .await?),
+    }
+}
+
+#[utoipa::path(
+    tag = "Webhook",
+    post,
+    path = "/api/webhook",
+    responses(
+        (status = 202, description = "The app for the pushed repository was created or updated", body = WebhookResponse),
+        (status = 200, description = "The push didn't affect any app", body = WebhookResponse),
+        (status = 400, description = "The push payload has no repository", body = Value),
+        (status = 401, description = "The delivery isn't signed with the webhook secret", body = Value),
+        (status = 500, description = "Internal server error", body = Value)
+    ),
+)]
+pub async fn webhook_handler(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> SealedServerResult<impl IntoResponse, (StatusCode, Json<Value>)> {
+    authenticate(&state.webhook, &headers, &body).map_err(handle_error)?;
+    let payload: PushPayload = serde_json::from_slice(&body).map_err(|err| {
+        handle_error(SealedServerError::BadRequest(format!(
+            "Invalid push payload: {}",
+            err
+        )))
+    })?;
+
+    let push = match payload.push() {
+        Ok(Some(push)) => push,
+        Ok(None) => return Ok(WebhookResponse::ignored("Not a push to a branch")),
+        Err(err) => return Err(handle_error(err)),
+    };
+
+    if !state.webhook.all_branches {
+        if let Some(default_branch) = &push.default_branch {
+            if &push.branch != default_branch {
+                return Ok(WebhookResponse::ignored(format!(
+                    "{} is not the default branch",
+                    push.branch
+                )));
+            }
+        }
+    }
+
+    match upsert_app(&state.db, push).await {
+        Ok(app) => Ok((
+            StatusCode::ACCEPTED,
+            Json(WebhookResponse {
+                app_id: Some(app.id),
+                ignored: None,
+            }),
+        )),
+        Err(err) => Err(handle_error(err)),
+    }
 }
 
 pub async fn webhook_get_handler() -> impl axum::response::IntoResponse {
     "Webhook GET"
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{app_state::AppState, test_utils};
+
+    async fn test_state() -> SharedAppState {
+        let mut state = test_utils::test_state().await;
+        state.webhook.secret = Some(SECRET.to_string());
+        Arc::new(state)
+    }
+
+    const SECRET: &str = "It's a Sony";
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    // A trimmed-down GitHub push event
+    fn github_push(repository_url: &str, git_ref: &str, after: &str) -> Value {
+        json!({
+            "ref": git_ref,
+            "before": "0000000000000000000000000000000000000000",
+            "after": after,
+            "deleted": false,
+            "repository": {
+                "id": 1296269,
+                "name": "grid",
+                "full_name": "encom/grid",
+                "clone_url": repository_url,
+                "default_branch": "main",
+            },
+            "pusher": { "name": "flynn" },
+        })
+    }
+
+    fn push(payload: Value) -> Option<Push> {
+        serde_json::from_value::<PushPayload>(payload)
+            .unwrap()
+            .push()
+            .unwrap()
+    }
+
+    async fn send(state: &SharedAppState, payload: Value) -> (StatusCode, Value) {
+        let body = payload.to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-hub-signature-256",
+            sign(body.as_bytes()).parse().unwrap(),
+        );
+        send_with(state, headers, body).await
+    }
+
+    async fn send_with(
+        state: &SharedAppState,
+        headers: HeaderMap,
+        body: String,
+    ) -> (StatusCode, Value) {
+        let response = webhook_handler(State(state.clone()), headers, Bytes::from(body))
+            .await
+            .into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[test]
+    fn test_gitlab_push() {
+        let payload: PushPayload = serde_json::from_value(json!({
+            "object_kind": "push",
+            "ref": "refs/heads/main",
+            "checkout_sha": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+            "project": {
+                "name": "grid",
+                "git_http_url": "https://gitlab.com/encom/grid.git",
+                "default_branch": "main",
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            payload.push().unwrap(),
+            Some(Push {
+                name: "grid".to_string(),
+                repository_url: "https://gitlab.com/encom/grid.git".to_string(),
+                branch: "main".to_string(),
+                default_branch: Some("main".to_string()),
+                commit: "da1560886d4f094c3e6c9ef40349f7d38b5d27d7".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_ignored_pushes() {
+        let url = "https://github.com/encom/grid.git";
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(push(github_push(url, "refs/tags/v1.0", sha)), None);
+        assert_eq!(
+            push(github_push(url, "refs/heads/main", &"0".repeat(40))),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_deliveries_are_rejected() {
+        // Nothing listens here, so a delivery which reached the database would fail with a 500
+        let pool =
+            sqlx::postgres::PgPool::connect_lazy("postgresql://postgres@127.0.0.1:9/none").unwrap();
+        let mut state = AppState::with_database(AppDatabase::from_pool(pool)).unwrap();
+        state.webhook.secret = Some(SECRET.to_string());
+        let state = Arc::new(state);
+        let body = github_push(
+            "https://github.com/encom/grid.git",
+            "refs/heads/main",
+            "0123456789abcdef0123456789abcdef01234567",
+        )
+        .to_string();
+
+        let header = |name: &'static str, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            headers
+        };
+        let deliveries = [
+            HeaderMap::new(),
+            header("x-hub-signature-256", &sign(b"some other body")),
+            header("x-hub-signature-256", "sha256=not-hex"),
+            header("x-gitlab-token", "Not a Sony"),
+        ];
+        for headers in deliveries {
+            let (status, body) = send_with(&state, headers, body.clone()).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["code"], "unauthorized");
+        }
+
+        // Without a secret there's nothing to check deliveries against
+        let mut state = AppState::clone(&state);
+        state.webhook.secret = None;
+        let signed = header("x-hub-signature-256", &sign(body.as_bytes()));
+        let (status, _) = send_with(&Arc::new(state), signed, body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_gitlab_token() {
+        let state = test_state().await;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-gitlab-token", SECRET.parse().unwrap());
+
+        let (status, body) = send_with(
+            &state,
+            headers,
+            json!({ "ref": "refs/tags/v1.0", "checkout_sha": "da15608" }).to_string(),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["ignored"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_push_creates_app() {
+        let state = test_state().await;
+        let url = format!("https://github.com/new{}/grid.git", std::process::id());
+
+        let (status, body) = send(
+            &state,
+            github_push(
+                &url,
+                "refs/heads/main",
+                "0123456789abcdef0123456789abcdef01234567",
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let app = apps_repo::get_app(&state.db, body["app_id"].as_i64().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(app.name, "grid");
+        assert_eq!(app.repository_url.as_deref(), Some(url.as_str()));
+        assert_eq!(app.branch.as_deref(), Some("main"));
+        assert_eq!(app.tag.as_deref(), Some("0123456"));
+    }
+
+    #[tokio::test]
+    async fn test_push_updates_existing_app() {
+        let state = test_state().await;
+        let url = format!("https://github.com/existing{}/grid.git", std::process::id());
+        let existing = apps_repo::create_app(
+            &state.db,
+            CreateAppRequest {
+                name: Some("the-grid".to_string()),
+                description: Some("Keep me".to_string()),
+                app_config: None,
+                repository_url: Some(url.clone()),
+                branch: Some("main".to_string()),
+                image: None,
+                tag: Some("old".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let (status, body) = send(
+            &state,
+            github_push(
+                &url,
+                "refs/heads/main",
+                "fedcba9876543210fedcba9876543210fedcba98",
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["app_id"], existing.id);
+
+        let app = apps_repo::get_app(&state.db, existing.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(app.name, "the-grid");
+        assert_eq!(app.description, "Keep me");
+        assert_eq!(app.tag.as_deref(), Some("fedcba9"));
+
+        // Pushes to other branches are ignored
+        let (status, body) = send(
+            &state,
+            github_push(
+                &url,
+                "refs/heads/feature",
+                "1111111111111111111111111111111111111111",
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["ignored"].as_str().unwrap().contains("feature"));
+        let app = apps_repo::get_app(&state.db, existing.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(app.tag.as_deref(), Some("fedcba9"));
+    }
+}
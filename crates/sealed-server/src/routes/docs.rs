@@ -1,10 +1,13 @@
-use axum::{response::IntoResponse, routing::get, Json, Router};
+use axum::Router;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::app_state::SharedAppState;
 
-use super::api::{apps::AppsOpenApi, healthcheck::HealthCheckOpenApi};
+use super::api::{apps::AppsOpenApi, healthcheck::HealthCheckOpenApi, webhook::WebhookOpenApi};
+
+/// Where the merged spec is served.
+pub const OPENAPI_JSON_PATH: &str = "/docs/openapi.json";
 
 #[derive(OpenApi)]
 #[openapi(info(
@@ -14,18 +17,75 @@ use super::api::{apps::AppsOpenApi, healthcheck::HealthCheckOpenApi};
 ))]
 pub struct OpenApiDoc;
 
-pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
-    let swagger_ui = SwaggerUi::new("/swagger-ui").url("/docs/openapi.json", OpenApiDoc::openapi());
+/// The spec of each route module. Register new APIs here to have them show up in the docs.
+fn api_specs() -> Vec<utoipa::openapi::OpenApi> {
+    vec![
+        AppsOpenApi::openapi(),
+        HealthCheckOpenApi::openapi(),
+        WebhookOpenApi::openapi(),
+    ]
+}
 
+/// Every API's spec merged into one.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    api_specs()
+        .into_iter()
+        .fold(OpenApiDoc::openapi(), |mut doc, spec| {
+            doc.merge(spec);
+            doc
+        })
+}
+
+/// Serves the Swagger UI at `/docs` and the spec it's built from at `/docs/openapi.json`.
+pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
     Router::new()
-        .route("/openapi.json", get(openapi_json))
-        .merge(swagger_ui)
+        .merge(SwaggerUi::new("/docs").url(OPENAPI_JSON_PATH, openapi()))
         .with_state(app_state)
 }
 
-async fn openapi_json() -> impl IntoResponse {
-    let mut doc = OpenApiDoc::openapi();
-    doc.merge(AppsOpenApi::openapi());
-    doc.merge(HealthCheckOpenApi::openapi());
-    Json(doc)
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::{routes, test_utils::test_state};
+
+    #[tokio::test]
+    async fn test_openapi_json() {
+        let state = Arc::new(test_state().await);
+        let app = routes::routes(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(super::OPENAPI_JSON_PATH)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let spec: Value = serde_json::from_slice(&bytes).unwrap();
+
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/apps"));
+        assert!(paths.contains_key("/api/apps/{id}"));
+        assert!(paths.contains_key("/api/livez"));
+        assert!(paths.contains_key("/api/webhook"));
+
+        let response = app
+            .oneshot(Request::get("/docs/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
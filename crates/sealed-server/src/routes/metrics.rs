@@ -0,0 +1,77 @@
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde_json::Value;
+
+use crate::{app_state::SharedAppState, metrics::METRICS_PATH, utils::server_utils::handle_error};
+
+pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
+    Router::new()
+        .route(METRICS_PATH, get(metrics_handler))
+        .with_state(app_state)
+}
+
+pub async fn metrics_handler(
+    State(state): State<SharedAppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    match state.metrics.render() {
+        Ok(body) => Ok(([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], body)),
+        Err(err) => Err(handle_error(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use sealed_database::AppDatabase;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{app_state::AppState, routes, test_utils::test_pool};
+
+    async fn test_state() -> SharedAppState {
+        let db = AppDatabase::new(test_pool().await).await.unwrap();
+        Arc::new(AppState::with_database(db).unwrap())
+    }
+
+    async fn scrape(app: axum::Router) -> String {
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_created_apps() {
+        let state = test_state().await;
+        let app = routes::routes(state.clone());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/api/apps")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"name": "metrics-app"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = scrape(app.clone()).await;
+        assert!(body.contains("sealed_apps_created_total 1"));
+        assert!(body.contains(r#"route="/api/apps""#));
+        assert!(!body.contains(r#"route="/metrics""#));
+    }
+}
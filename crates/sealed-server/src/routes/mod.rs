@@ -1,16 +1,23 @@
 pub mod api;
 pub mod docs;
 pub mod frontend;
+pub mod metrics;
+pub mod ws;
 
-use axum::Router;
+use axum::{middleware, Router};
 
-use super::app_state::SharedAppState;
+use super::{app_state::SharedAppState, metrics::track_requests, request_id::with_request_tracing};
 
 pub fn routes(app_state: SharedAppState) -> Router {
-    let router = axum::Router::new();
-    router
+    let router = axum::Router::new()
         .nest("/api", api::routes(app_state.clone()))
         .nest("/", frontend::routes(app_state.clone()))
-        .nest("/docs", docs::routes(app_state.clone()))
-        .with_state(app_state)
+        .merge(docs::routes(app_state.clone()))
+        .merge(metrics::routes(app_state.clone()))
+        .merge(ws::routes(app_state.clone()))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            track_requests,
+        ));
+    with_request_tracing(router).with_state(app_state)
 }
@@ -0,0 +1,117 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use sealed_common::{debug, events::TaskEvent};
+use serde_json::json;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::app_state::SharedAppState;
+
+pub fn routes(app_state: SharedAppState) -> Router<SharedAppState> {
+    Router::new()
+        .route("/ws/apps/:id", get(app_events))
+        .with_state(app_state)
+}
+
+/// Pushes the lifecycle events of the app's tasks to the client as JSON text frames.
+pub async fn app_events(
+    ws: WebSocketUpgrade,
+    State(state): State<SharedAppState>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    // Subscribe before upgrading, so nothing published once the client is connected is missed
+    let events = state.events.subscribe();
+    ws.on_upgrade(move |socket| forward_events(socket, events, id))
+}
+
+async fn forward_events(mut socket: WebSocket, mut events: Receiver<TaskEvent>, app_id: i64) {
+    loop {
+        let frame = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if event.app_id == app_id => match serde_json::to_string(&event) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        debug!("Unable to serialize {:?}: {}", event, e);
+                        continue;
+                    }
+                },
+                Ok(_) => continue,
+                // The client couldn't keep up and the oldest events were dropped. Let it know, so
+                // it can refetch the app's state, rather than slowing down the publishers.
+                Err(RecvError::Lagged(missed)) => {
+                    json!({ "app_id": app_id, "status": "lagged", "missed": missed }).to_string()
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                // Pings are answered by axum, and the client has nothing else to say
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        if socket.send(Message::Text(frame)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use serde_json::Value;
+    use tokio::{net::TcpListener, process::Command};
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    use crate::{build::stream_command, routes, test_utils::test_state};
+
+    #[tokio::test]
+    async fn test_succeeded_frame() {
+        let state = Arc::new(test_state().await);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = routes::routes(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut socket, response) = connect_async(format!("ws://{}/ws/apps/42", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 101);
+
+        // Another app's events aren't sent
+        let mut other = Command::new("sh");
+        other.arg("-c").arg("exit 3");
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo building");
+        for (id, command) in [(7, other), (42, command)] {
+            let events = stream_command(
+                command,
+                None,
+                None,
+                Some(state.events.publisher(id, "build")),
+            );
+            events.collect::<Vec<_>>().await;
+        }
+
+        let mut statuses = vec![];
+        while statuses.last().map(String::as_str) != Some("succeeded") {
+            let Message::Text(frame) = socket.next().await.unwrap().unwrap() else {
+                panic!("expected a text frame");
+            };
+            let event: Value = serde_json::from_str(&frame).unwrap();
+            assert_eq!(event["app_id"], 42);
+            assert_eq!(event["task"], "build");
+            statuses.push(event["status"].as_str().unwrap().to_string());
+        }
+        assert_eq!(statuses, vec!["queued", "running", "succeeded"]);
+    }
+}
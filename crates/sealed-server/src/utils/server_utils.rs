@@ -2,25 +2,47 @@
 use std::fmt::{Display, Formatter};
 
 use axum::http::StatusCode;
+use sealed_common::tracing;
 use serde_json::json;
 
 use crate::error::SealedServerError;
 
-pub fn handle_error(err: SealedServerError) -> (StatusCode, axum::Json<serde_json::Value>) {
-    let msg = axum::Json(json!({ "error": format!("{}", &err) }));
+// Errors are logged inside the request span, which carries the request id
+pub fn handle_error(
+    err: impl Into<SealedServerError>,
+) -> (StatusCode, axum::Json<serde_json::Value>) {
+    let err = err.into();
+    let status = match err {
+        SealedServerError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        SealedServerError::NoData => StatusCode::NOT_FOUND,
+        SealedServerError::ClusterError(_) => StatusCode::BAD_GATEWAY,
+        SealedServerError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+        SealedServerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    handle_error_with_status(err, status)
+}
 
-    match err {
-        SealedServerError::ServerError(_) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-        SealedServerError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-    }
+pub fn not_found(msg: impl Display) -> (StatusCode, axum::Json<serde_json::Value>) {
+    tracing::warn!("{}", msg);
+    let body = json!({ "error": msg.to_string(), "code": SealedServerError::NoData.code() });
+    (StatusCode::NOT_FOUND, axum::Json(body))
 }
 
 pub fn handle_error_with_status(
     err: SealedServerError,
     status: StatusCode,
 ) -> (StatusCode, axum::Json<serde_json::Value>) {
-    let msg = axum::Json(json!({ "error": format!("{}", &err) }));
-    (status, msg)
+    if status.is_server_error() {
+        tracing::error!("{}", err);
+    } else {
+        tracing::warn!("{}", err);
+    }
+    (status, error_body(&err))
+}
+
+fn error_body(err: &SealedServerError) -> axum::Json<serde_json::Value> {
+    axum::Json(json!({ "error": format!("{}", err), "code": err.code() }))
 }
 
 /// Returns early with an error. This macro is similar to the `bail!` macro which can be found in `anyhow`.
@@ -143,3 +165,82 @@ impl WithStatusCode {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sealed_common::error::SealedError;
+
+    use super::*;
+
+    #[test]
+    fn test_sealed_error_mapping() {
+        let (status, body) = handle_error(SealedError::NoData);
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.0["code"], "not_found");
+
+        let (status, body) = handle_error(SealedError::BadRequest("Invalid id".to_string()));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.0["error"], "Bad request: Invalid id");
+
+        let (status, body) = handle_error(SealedError::Interrupted);
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.0["code"], "server_error");
+    }
+
+    #[test]
+    fn test_server_error() {
+        let (status, body) = handle_error(SealedServerError::ServerError("Oops".to_string()));
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body.0,
+            json!({ "error": "Server error: Oops", "code": "server_error" })
+        );
+    }
+
+    #[test]
+    fn test_not_found() {
+        let (status, body) = not_found("App 1 not found");
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body.0,
+            json!({ "error": "App 1 not found", "code": "not_found" })
+        );
+    }
+
+    #[test]
+    fn test_bad_request() {
+        let (status, body) =
+            handle_error(SealedServerError::BadRequest("Missing name".to_string()));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body.0,
+            json!({ "error": "Bad request: Missing name", "code": "bad_request" })
+        );
+    }
+
+    #[test]
+    fn test_no_data() {
+        let (status, body) = handle_error(SealedServerError::NoData);
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.0["code"], "not_found");
+    }
+
+    #[test]
+    fn test_cluster_error() {
+        let (status, body) = handle_error(SealedServerError::ClusterError(
+            "connection refused".to_string(),
+        ));
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body.0["code"], "kube_error");
+    }
+
+    #[test]
+    fn test_with_status_includes_code() {
+        let (status, body) = handle_error_with_status(
+            SealedServerError::ServerError("Invalid app".to_string()),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        );
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body.0["code"], "server_error");
+    }
+}
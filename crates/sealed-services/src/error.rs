@@ -14,9 +14,11 @@ pub enum SealedServicesError {
     #[error("Interrupted")]
     Interrupted,
     #[error("Failed to run command: {0} {1:?}")]
-    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error>>),
+    FailedToRunUserCommand(String, Option<Box<dyn std::error::Error + Send + Sync>>),
     #[error("System error: {0} {1:?}")]
-    System(String, Option<Box<dyn std::error::Error>>),
+    System(String, Option<Box<dyn std::error::Error + Send + Sync>>),
+    #[error("{message} The command {}.", sealed_common::error::describe_exit_code(*.code))]
+    TaskExited { message: String, code: i32 },
 
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
@@ -26,6 +28,13 @@ pub enum SealedServicesError {
 
     #[error("Git error: {0}")]
     GitError(#[from] git2::Error),
+
+    #[error("Merging {commit} into {branch} conflicts in: {}", .paths.join(", "))]
+    MergeConflict {
+        branch: String,
+        commit: String,
+        paths: Vec<String>,
+    },
 }
 
 impl From<SealedServicesError> for SealedError {
@@ -40,9 +49,15 @@ impl From<SealedServicesError> for SealedError {
                 SealedError::FailedToRunUserCommand(e, None)
             }
             SealedServicesError::System(e, _) => SealedError::System(e, None),
+            SealedServicesError::TaskExited { message, code } => {
+                SealedError::TaskExited { message, code }
+            }
             SealedServicesError::IOError(e) => SealedError::IOError(e),
             SealedServicesError::RuntimeError(e) => SealedError::Runtime(anyhow::anyhow!(e)),
             SealedServicesError::GitError(e) => SealedError::GitOperationFailed(e.to_string()),
+            e @ SealedServicesError::MergeConflict { .. } => {
+                SealedError::GitOperationFailed(e.to_string())
+            }
         }
     }
 }
@@ -56,6 +71,9 @@ impl From<sealed_common::error::SealedError> for SealedServicesError {
                 SealedServicesError::FailedToRunUserCommand(e, None)
             }
             SealedError::System(e, _) => SealedServicesError::System(e, None),
+            SealedError::TaskExited { message, code } => {
+                SealedServicesError::TaskExited { message, code }
+            }
             SealedError::IOError(e) => SealedServicesError::IOError(e),
             SealedError::Runtime(e) => SealedServicesError::RuntimeError(anyhow::anyhow!(e)),
             _ => SealedServicesError::RuntimeError(anyhow::anyhow!("unknown error")),
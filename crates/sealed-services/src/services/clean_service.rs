@@ -0,0 +1,301 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use console::style;
+use sealed_common::debug;
+
+use crate::{
+    docker_service::{
+        delete_container, delete_image, list_containers, list_images, ContainerSummary,
+        ImageSummary,
+    },
+    error::{SealedServicesError, SealedServicesResult},
+    kept_container_service::{forget_kept_containers, kept_containers},
+    prune_service::{in_use, TASK_TAG_PREFIX},
+};
+
+// What `clean` deleted, or would have deleted for a dry run.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cleaned {
+    // Container IDs
+    pub containers: Vec<String>,
+    // Image references
+    pub images: Vec<String>,
+}
+
+// Choose the containers to delete: those created by this tool or kept for debugging (`kept`).
+// Running containers may belong to a task which is still in progress, so they're only chosen if
+// `all` is set.
+pub fn select_containers<'a>(
+    containers: &'a [ContainerSummary],
+    kept: &HashSet<String>,
+    all: bool,
+) -> Vec<&'a ContainerSummary> {
+    containers
+        .iter()
+        .filter(|container| {
+            (container.managed || kept.contains(&container.id)) && (all || !container.running)
+        })
+        .collect()
+}
+
+// Choose the task images to delete: every one in `docker_repo` which isn't used by a running
+// container that is being left alone. Images in other repositories weren't created by this tool,
+// even if their tags look like task images.
+pub fn select_images<'a>(
+    images: &'a [ImageSummary],
+    docker_repo: &str,
+    containers: &[ContainerSummary],
+    deleted_containers: &[&ContainerSummary],
+) -> Vec<&'a ImageSummary> {
+    let deleted = deleted_containers
+        .iter()
+        .map(|container| container.id.as_str())
+        .collect::<HashSet<_>>();
+    let running = containers
+        .iter()
+        .filter(|container| container.running && !deleted.contains(container.id.as_str()))
+        .map(|container| container.image.clone())
+        .collect::<HashSet<_>>();
+
+    images
+        .iter()
+        .filter(|image| image.repository == docker_repo && !in_use(image, &running))
+        .collect()
+}
+
+// Delete the containers this tool leaked, e.g., because it crashed or a failed container was kept
+// for debugging. With `all`, running containers are deleted too, as are the task images in
+// `docker_repo`, which are otherwise kept since they cache the tasks. With `dry_run`, nothing is
+// deleted, but the result says what would have been.
+pub fn clean(
+    docker_cli: &str,
+    docker_repo: &str,
+    kept_containers_path: &Path,
+    all: bool,
+    dry_run: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Cleaned> {
+    let containers = list_containers(docker_cli, interrupted)?;
+    let images = if all {
+        list_images(docker_cli, TASK_TAG_PREFIX, interrupted)?
+    } else {
+        vec![]
+    };
+    let kept = kept_containers(kept_containers_path)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let doomed_containers = select_containers(&containers, &kept, all);
+    let doomed_images = select_images(&images, docker_repo, &containers, &doomed_containers);
+
+    let mut cleaned = Cleaned::default();
+    for container in doomed_containers {
+        if dry_run {
+            debug!(
+                "Would delete container {}",
+                style(&container.id).bold().dim()
+            );
+        } else {
+            delete_container(docker_cli, &container.id, interrupted)?;
+        }
+        cleaned.containers.push(container.id.clone());
+    }
+
+    for image in doomed_images {
+        let reference = image.reference();
+        if dry_run {
+            debug!("Would delete image {}", style(&reference).bold().dim());
+        } else {
+            // Another image may still depend on this one, in which case Docker refuses to delete
+            // it. That's not worth failing over.
+            match delete_image(docker_cli, &reference, interrupted) {
+                Ok(()) => {}
+                Err(SealedServicesError::Interrupted) => {
+                    return Err(SealedServicesError::Interrupted);
+                }
+                Err(error) => {
+                    debug!("Unable to delete image {}: {}", reference, error);
+                    continue;
+                }
+            }
+        }
+        cleaned.images.push(reference);
+    }
+
+    if !dry_run {
+        forget_kept_containers(kept_containers_path)?;
+    }
+
+    Ok(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{kept_container_service::kept_containers_path, test_utils::FakeDocker};
+
+    fn container(id: &str, image: &str, running: bool, managed: bool) -> ContainerSummary {
+        ContainerSummary {
+            id: id.to_owned(),
+            image: image.to_owned(),
+            running,
+            managed,
+        }
+    }
+
+    fn image(tag: &str, id: &str) -> ImageSummary {
+        ImageSummary {
+            repository: "sealed".to_owned(),
+            tag: tag.to_owned(),
+            id: id.to_owned(),
+        }
+    }
+
+    fn ids(containers: &[&ContainerSummary]) -> Vec<String> {
+        containers
+            .iter()
+            .map(|container| container.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_containers() {
+        let containers = vec![
+            container("c1", "sealed:task-01", false, true),
+            container("c2", "sealed:task-02", true, true),
+            container("c3", "postgres:16", false, false),
+            container("c4", "sealed:task-03", false, false),
+        ];
+        let kept = HashSet::from(["c4".to_owned()]);
+
+        assert_eq!(
+            ids(&select_containers(&containers, &kept, false)),
+            vec!["c1", "c4"]
+        );
+        assert_eq!(
+            ids(&select_containers(&containers, &kept, true)),
+            vec!["c1", "c2", "c4"],
+        );
+        assert!(select_containers(&containers, &HashSet::new(), false)
+            .iter()
+            .all(|container| container.managed));
+    }
+
+    #[test]
+    fn test_select_images_skips_images_in_use() {
+        let images = vec![
+            image("task-01", "sha256:01"),
+            image("task-02", "sha256:02"),
+            image("task-03", "sha256:03"),
+            ImageSummary {
+                repository: "postgres".to_owned(),
+                tag: "task-04".to_owned(),
+                id: "sha256:04".to_owned(),
+            },
+        ];
+        let containers = vec![
+            container("c1", "sealed:task-01", true, true),
+            container("c2", "sha256:02", true, false),
+            container("c3", "sealed:task-03", false, true),
+        ];
+
+        let references = |deleted: &[&ContainerSummary]| {
+            select_images(&images, "sealed", &containers, deleted)
+                .iter()
+                .map(|image| image.reference())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(references(&[]), vec!["sealed:task-03"]);
+        assert_eq!(
+            references(&[&containers[0]]),
+            vec!["sealed:task-01", "sealed:task-03"],
+        );
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = tempdir().unwrap();
+        let path = kept_containers_path(dir.path());
+        std::fs::write(&path, "c3\n").unwrap();
+
+        let docker = FakeDocker::new(
+            r#"case "$1" in
+  ps)
+    printf 'c1\tsealed:task-01\texited\ttrue\n'
+    printf 'c2\tsealed:task-02\trunning\ttrue\n'
+    printf 'c3\tsealed:task-03\texited\t\n'
+    printf 'c4\tpostgres:16\trunning\t\n'
+    ;;
+  images)
+    printf 'sealed\ttask-01\tsha256:01\n'
+    printf 'sealed\ttask-02\tsha256:02\n'
+    printf 'mysql\ttask-05\tsha256:05\n'
+    ;;
+esac"#,
+        );
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        // The task images cache the tasks, so they're kept.
+        let expected = Cleaned {
+            containers: vec!["c1".to_owned(), "c3".to_owned()],
+            images: vec![],
+        };
+
+        // A dry run deletes nothing.
+        assert_eq!(
+            clean(docker.cli(), "sealed", &path, false, true, &interrupted).unwrap(),
+            expected,
+        );
+        assert!(docker.calls().iter().all(|call| !call.contains(" rm ")));
+        assert_eq!(kept_containers(&path).unwrap(), vec!["c3"]);
+
+        assert_eq!(
+            clean(docker.cli(), "sealed", &path, false, false, &interrupted).unwrap(),
+            expected,
+        );
+        let calls = docker.calls();
+        assert!(calls.contains(&"container rm --force c1".to_owned()));
+        assert!(calls.contains(&"container rm --force c3".to_owned()));
+        assert!(calls.iter().all(|call| !call.starts_with("image rm")));
+        assert!(kept_containers(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_clean_all() {
+        let dir = tempdir().unwrap();
+        let path = kept_containers_path(dir.path());
+
+        let docker = FakeDocker::new(
+            r#"case "$1" in
+  ps)
+    printf 'c1\tsealed:task-01\trunning\ttrue\n'
+    printf 'c4\tpostgres:16\trunning\t\n'
+    ;;
+  images)
+    printf 'sealed\ttask-01\tsha256:01\n'
+    printf 'sealed\ttask-02\tsha256:02\n'
+    printf 'mysql\ttask-05\tsha256:05\n'
+    ;;
+esac"#,
+        );
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        assert_eq!(
+            clean(docker.cli(), "sealed", &path, true, false, &interrupted).unwrap(),
+            Cleaned {
+                containers: vec!["c1".to_owned()],
+                images: vec!["sealed:task-01".to_owned(), "sealed:task-02".to_owned()],
+            },
+        );
+        let calls = docker.calls();
+        assert!(calls.contains(&"image rm --force sealed:task-02".to_owned()));
+        assert!(!calls.contains(&"image rm --force mysql:task-05".to_owned()));
+    }
+}
@@ -3,11 +3,16 @@ use std::{
     fs::{copy, create_dir_all, read_link, rename, symlink_metadata, Metadata},
     io::{self, Read},
     path::Path,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{atomic::AtomicBool, Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use console::style;
-use sealed_common::{debug, error::SealedError, fs_utils::make_dirs};
+use sealed_common::{
+    debug,
+    error::SealedError,
+    fs_utils::make_dirs,
+};
 use sealed_database::task::MappingPath;
 use tempfile::tempdir;
 use typed_path::{TryAsRef, UnixPath, UnixPathBuf};
@@ -16,6 +21,7 @@ use walkdir::WalkDir;
 use crate::{
     error::{SealedServicesError, SealedServicesResult},
     exec_service::{run_attach, run_loud, run_quiet, run_quiet_stdin},
+    retry_service::{with_retries, with_task_retries, RetryPolicy},
 };
 
 pub fn image_exists(
@@ -43,24 +49,136 @@ pub fn image_exists(
     }
 }
 
-pub fn push_image(
+// Remembers which images exist, so a run doesn't ask Docker about the same image twice. Images
+// can come and go between runs, so it shouldn't outlive one.
+#[derive(Debug, Default)]
+pub struct ExistingImages {
+    images: Mutex<HashMap<String, bool>>,
+}
+
+impl ExistingImages {
+    // Like `image_exists`, but only the first check of each image goes to Docker.
+    pub fn image_exists(
+        &self,
+        docker_cli: &str,
+        image: &str,
+        interrupted: &Arc<AtomicBool>,
+    ) -> SealedServicesResult<bool> {
+        if let Some(exists) = self.lock().get(image) {
+            return Ok(*exists);
+        }
+
+        let exists = image_exists(docker_cli, image, interrupted)?;
+        self.lock().insert(image.to_owned(), exists);
+        Ok(exists)
+    }
+
+    // Like `commit_container`, but forgets what's known about `image`, since the commit creates it.
+    pub fn commit_container(
+        &self,
+        docker_cli: &str,
+        container: &str,
+        image: &str,
+        interrupted: &Arc<AtomicBool>,
+    ) -> SealedServicesResult<()> {
+        commit_container(docker_cli, container, image, interrupted)?;
+        self.lock().remove(image);
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, bool>> {
+        self.images
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+// Resolve an image to its ID, which changes whenever a mutable tag is pushed with new contents.
+// Returns `None` if the image isn't present locally.
+pub fn image_id(
     docker_cli: &str,
     image: &str,
     interrupted: &Arc<AtomicBool>,
-) -> SealedServicesResult<()> {
-    debug!("Pushing image {}", style(image).bold().dim());
+) -> SealedServicesResult<Option<String>> {
+    debug!("Resolving the ID of image {}", style(image).bold().dim());
 
-    run_quiet(
+    match run_quiet(
         docker_cli,
-        "Pushing image\u{2026}",
-        "Unable to push image.",
-        &vec!["image", "push", image]
+        "Inspecting image\u{2026}",
+        "Image doesn't exist",
+        &vec!["image", "inspect", "--format", "{{.Id}}", image]
             .into_iter()
             .map(std::borrow::ToOwned::to_owned)
             .collect::<Vec<_>>(),
         false,
         interrupted,
-    )
+    ) {
+        Ok(id) => Ok(Some(id.trim().to_owned()).filter(|id| !id.is_empty())),
+        Err(SealedError::Interrupted) => Err(SealedServicesError::Interrupted),
+        Err(SealedError::System(_, _) | SealedError::FailedToRunUserCommand(_, _)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Resolve an image reference to the digest of its manifest in the registry, without pulling the
+// image. Returns `None` if the digest can't be determined (e.g., if the registry is unreachable).
+pub fn registry_digest(
+    docker_cli: &str,
+    image: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Option<String>> {
+    debug!(
+        "Resolving the digest of image {}",
+        style(image).bold().dim()
+    );
+
+    match run_quiet(
+        docker_cli,
+        "Resolving image\u{2026}",
+        "Unable to resolve image.",
+        &vec![
+            "buildx",
+            "imagetools",
+            "inspect",
+            "--format",
+            "{{.Manifest.Digest}}",
+            image,
+        ]
+        .into_iter()
+        .map(std::borrow::ToOwned::to_owned)
+        .collect::<Vec<_>>(),
+        false,
+        interrupted,
+    ) {
+        Ok(digest) => Ok(Some(digest.trim().to_owned()).filter(|digest| digest.contains(':'))),
+        Err(SealedError::Interrupted) => Err(SealedServicesError::Interrupted),
+        Err(SealedError::System(_, _) | SealedError::FailedToRunUserCommand(_, _)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn push_image(
+    docker_cli: &str,
+    image: &str,
+    retry: &RetryPolicy,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    debug!("Pushing image {}", style(image).bold().dim());
+
+    // Registries fail transiently (e.g., rate limits), so retry those failures.
+    with_retries(retry, interrupted, || {
+        run_quiet(
+            docker_cli,
+            "Pushing image\u{2026}",
+            "Unable to push image.",
+            &vec!["image", "push", image]
+                .into_iter()
+                .map(std::borrow::ToOwned::to_owned)
+                .collect::<Vec<_>>(),
+            false,
+            interrupted,
+        )
+    })
     .map(|_| ())?;
     Ok(())
 }
@@ -69,18 +187,81 @@ pub fn push_image(
 pub fn pull_image(
     docker_cli: &str,
     image: &str,
+    retry: &RetryPolicy,
     interrupted: &Arc<AtomicBool>,
 ) -> SealedServicesResult<()> {
     debug!("Pulling image {}", style(image).bold().dim());
 
+    // Registries fail transiently (e.g., rate limits), so retry those failures.
+    with_retries(retry, interrupted, || {
+        run_quiet(
+            docker_cli,
+            "Pulling image\u{2026}",
+            "Unable to pull image.",
+            &vec!["image", "pull", image]
+                .into_iter()
+                .map(std::borrow::ToOwned::to_owned)
+                .collect::<Vec<_>>(),
+            false,
+            interrupted,
+        )
+    })
+    .map(|_| ())?;
+    Ok(())
+}
+
+// Load an image from a tarball produced by `docker image save`.
+pub fn load_image(
+    docker_cli: &str,
+    tarball: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    debug!(
+        "Loading image from {}",
+        style(tarball.to_string_lossy()).bold().dim()
+    );
+
     run_quiet(
         docker_cli,
-        "Pulling image\u{2026}",
-        "Unable to pull image.",
-        &vec!["image", "pull", image]
-            .into_iter()
-            .map(std::borrow::ToOwned::to_owned)
-            .collect::<Vec<_>>(),
+        "Loading image\u{2026}",
+        "Unable to load image.",
+        &[
+            "image".to_owned(),
+            "load".to_owned(),
+            "--input".to_owned(),
+            tarball.to_string_lossy().into_owned(),
+        ],
+        false,
+        interrupted,
+    )
+    .map(|_| ())?;
+    Ok(())
+}
+
+// Save an image to a tarball which can be restored with `load_image`.
+pub fn save_image(
+    docker_cli: &str,
+    image: &str,
+    tarball: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    debug!(
+        "Saving image {} to {}",
+        style(image).bold().dim(),
+        tarball.to_string_lossy()
+    );
+
+    run_quiet(
+        docker_cli,
+        "Saving image\u{2026}",
+        "Unable to save image.",
+        &[
+            "image".to_owned(),
+            "save".to_owned(),
+            "--output".to_owned(),
+            tarball.to_string_lossy().into_owned(),
+            image.to_owned(),
+        ],
         false,
         interrupted,
     )
@@ -111,6 +292,187 @@ pub fn delete_image(
     Ok(())
 }
 
+// An image as reported by `docker images`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSummary {
+    pub repository: String,
+    pub tag: String,
+    pub id: String,
+}
+
+impl ImageSummary {
+    pub fn reference(&self) -> String {
+        format!("{}:{}", self.repository, self.tag)
+    }
+}
+
+// List the tagged images whose tag starts with `tag_prefix`. Docker lists images from newest to
+// oldest, and that order is preserved.
+pub fn list_images(
+    docker_cli: &str,
+    tag_prefix: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<ImageSummary>> {
+    debug!(
+        "Listing images tagged {}",
+        style(format!("{tag_prefix}*")).bold().dim(),
+    );
+
+    let output = run_quiet(
+        docker_cli,
+        "Listing images\u{2026}",
+        "Unable to list images.",
+        &vec![
+            "images",
+            "--no-trunc",
+            "--filter",
+            "dangling=false",
+            "--format",
+            "{{.Repository}}\t{{.Tag}}\t{{.ID}}",
+        ]
+        .into_iter()
+        .map(std::borrow::ToOwned::to_owned)
+        .collect::<Vec<_>>(),
+        false,
+        interrupted,
+    )?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().split('\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(repository), Some(tag), Some(id)) if tag.starts_with(tag_prefix) => {
+                    Some(ImageSummary {
+                        repository: repository.to_owned(),
+                        tag: tag.to_owned(),
+                        id: id.to_owned(),
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+// Containers created by this tool carry this label, with the value `MANAGED_LABEL_VALUE`.
+pub const MANAGED_LABEL: &str = "sealed.managed";
+pub const MANAGED_LABEL_VALUE: &str = "true";
+
+// The other labels on the containers this tool creates
+pub const VERSION_LABEL: &str = "sealed.version";
+pub const TASK_LABEL: &str = "sealed.task";
+pub const RUN_ID_LABEL: &str = "sealed.run-id";
+
+// What to label a container with, so it can be traced back to the task and run which created it.
+// Labels are metadata only, so they don't affect the cache key of the task's image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ContainerLabels<'a> {
+    pub task: &'a str,
+    pub run_id: &'a str,
+}
+
+impl ContainerLabels<'_> {
+    fn args(&self) -> Vec<String> {
+        [
+            (MANAGED_LABEL, MANAGED_LABEL_VALUE),
+            (VERSION_LABEL, env!("CARGO_PKG_VERSION")),
+            (TASK_LABEL, self.task),
+            (RUN_ID_LABEL, self.run_id),
+        ]
+        .iter()
+        .flat_map(|(label, value)| vec!["--label".to_owned(), format!("{label}={value}")])
+        .collect()
+    }
+}
+
+// A fresh ID for the containers of one run, unique enough to tell runs apart.
+pub fn new_run_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    format!("{:x}-{:x}", std::process::id(), nanos)
+}
+
+// A container as reported by `docker ps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerSummary {
+    pub id: String,
+    pub image: String,
+    pub running: bool,
+    // Whether the container has `MANAGED_LABEL`
+    pub managed: bool,
+}
+
+// List every container, whether it's running or not.
+pub fn list_containers(
+    docker_cli: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<ContainerSummary>> {
+    debug!("Listing containers");
+
+    let output = run_quiet(
+        docker_cli,
+        "Listing containers\u{2026}",
+        "Unable to list containers.",
+        &[
+            "ps".to_owned(),
+            "--all".to_owned(),
+            "--no-trunc".to_owned(),
+            "--format".to_owned(),
+            format!(
+                "{{{{.ID}}}}\t{{{{.Image}}}}\t{{{{.State}}}}\t{{{{.Label \"{MANAGED_LABEL}\"}}}}"
+            ),
+        ],
+        false,
+        interrupted,
+    )?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().split('\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(id), Some(image), Some(state)) if !id.is_empty() => Some(ContainerSummary {
+                    id: id.to_owned(),
+                    image: image.to_owned(),
+                    running: state == "running",
+                    managed: fields.next() == Some(MANAGED_LABEL_VALUE),
+                }),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+// The images (as references or IDs, depending on how the containers were created) used by the
+// running containers.
+pub fn running_container_images(
+    docker_cli: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<String>> {
+    debug!("Listing the images of running containers");
+
+    let output = run_quiet(
+        docker_cli,
+        "Listing containers\u{2026}",
+        "Unable to list containers.",
+        &vec!["ps", "--no-trunc", "--format", "{{.Image}}"]
+            .into_iter()
+            .map(std::borrow::ToOwned::to_owned)
+            .collect::<Vec<_>>(),
+        false,
+        interrupted,
+    )?;
+
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|image| !image.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
 // Create a container and return its ID.
 #[allow(clippy::too_many_arguments)]
 pub fn create_container(
@@ -123,7 +485,10 @@ pub fn create_container(
     ports: &[String],
     location: &UnixPath,
     user: &str,
+    shell: &str,
     command: &str,
+    command_args: &[String],
+    labels: &ContainerLabels,
     extra_args: &[String],
     interrupted: &Arc<AtomicBool>,
 ) -> SealedServicesResult<String> {
@@ -144,15 +509,12 @@ pub fn create_container(
         mount_paths,
         mount_readonly,
         ports,
+        labels,
         extra_args,
     )?);
 
-    args.extend(
-        vec![image, "/bin/su", "-c", command, user]
-            .into_iter()
-            .map(std::borrow::ToOwned::to_owned)
-            .collect::<Vec<_>>(),
-    );
+    args.push(image.to_owned());
+    args.extend(su_command(user, shell, command, command_args));
 
     Ok(run_quiet(
         docker_cli,
@@ -166,7 +528,35 @@ pub fn create_container(
     .to_owned())
 }
 
-// Copy files into a container.
+// The script which runs `command_args` after the command prefix. The arguments are the shell's
+// positional parameters, so they reach the program exactly as given.
+const EXEC_COMMAND_ARGS: &str = r#"exec "$@""#;
+
+// The `su` invocation which runs a task's command as `user`. With `command_args`, `command` is
+// only the command prefix, and the arguments are passed after the user (the first one becomes
+// `$0`, so the shell goes there) rather than being spliced into the script.
+fn su_command(user: &str, shell: &str, command: &str, command_args: &[String]) -> Vec<String> {
+    let script = if command_args.is_empty() {
+        command.to_owned()
+    } else if command.is_empty() {
+        EXEC_COMMAND_ARGS.to_owned()
+    } else {
+        format!("{command}\n{EXEC_COMMAND_ARGS}")
+    };
+
+    let mut args = vec!["/bin/su", "-s", shell, "-c", &script, user]
+        .into_iter()
+        .map(std::borrow::ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if !command_args.is_empty() {
+        args.push(shell.to_owned());
+        args.extend(command_args.iter().cloned());
+    }
+    args
+}
+
+// Copy files into a container. `tar` is an archive, which may be compressed in any format Docker
+// extracts itself (see `Compression`).
 pub fn copy_into_container<R: Read>(
     docker_cli: &str,
     container: &str,
@@ -440,6 +830,125 @@ pub fn start_container(
     Ok(())
 }
 
+// Start a container, starting it again up to `retries` more times if its command fails. Each
+// attempt reruns the command in the same container.
+pub fn start_container_with_retries(
+    docker_cli: &str,
+    container: &str,
+    retries: u32,
+    retry_delay: Duration,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    with_task_retries(retries, retry_delay, interrupted, || {
+        start_container(docker_cli, container, interrupted).map_err(SealedError::from)
+    })
+    .map_err(SealedServicesError::from)
+}
+
+// Docker reports this start time for containers which have never been started.
+const NEVER_STARTED: &str = "0001-01-01T00:00:00Z";
+
+// Determine whether a container has ever been started.
+pub fn container_started(
+    docker_cli: &str,
+    container: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<bool> {
+    let started_at = run_quiet(
+        docker_cli,
+        "Inspecting container\u{2026}",
+        "Unable to inspect container.",
+        &vec![
+            "container",
+            "inspect",
+            "--format",
+            "{{.State.StartedAt}}",
+            container,
+        ]
+        .into_iter()
+        .map(std::borrow::ToOwned::to_owned)
+        .collect::<Vec<_>>(),
+        false,
+        interrupted,
+    )?;
+
+    Ok(started_at.trim() != NEVER_STARTED)
+}
+
+// Run a container and copy its outputs to the host. If the command succeeds, `output_paths` are
+// copied. If it fails, `output_paths_on_failure` are copied instead (e.g., logs or coverage reports)
+// and then the failure is reported. Nothing is copied if the container never started or the user
+// interrupted the program, and a failure to copy doesn't mask the original failure.
+#[allow(clippy::too_many_arguments)]
+pub fn run_container(
+    docker_cli: &str,
+    container: &str,
+    retries: u32,
+    retry_delay: Duration,
+    output_paths: &[UnixPathBuf],
+    output_paths_on_failure: &[UnixPathBuf],
+    location: &UnixPath,
+    destination_dir: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    let error = match start_container_with_retries(
+        docker_cli,
+        container,
+        retries,
+        retry_delay,
+        interrupted,
+    ) {
+        Ok(()) => {
+            return copy_from_container(
+                docker_cli,
+                container,
+                output_paths,
+                location,
+                destination_dir,
+                interrupted,
+            );
+        }
+        Err(SealedServicesError::Interrupted) => return Err(SealedServicesError::Interrupted),
+        Err(error) => error,
+    };
+
+    if output_paths_on_failure.is_empty() {
+        return Err(error);
+    }
+
+    match container_started(docker_cli, container, interrupted) {
+        Ok(true) => {
+            if let Err(copy_error) = copy_from_container(
+                docker_cli,
+                container,
+                output_paths_on_failure,
+                location,
+                destination_dir,
+                interrupted,
+            ) {
+                debug!(
+                    "Unable to copy the failure outputs from container {}: {}",
+                    container, copy_error,
+                );
+            }
+        }
+        Ok(false) => {
+            debug!(
+                "Container {} never started, so there are no failure outputs to copy.",
+                container,
+            );
+        }
+        Err(inspect_error) => {
+            debug!(
+                "Unable to inspect container {}: {}",
+                container, inspect_error
+            );
+        }
+    }
+
+    Err(error)
+}
+
 // Stop a container.
 pub fn stop_container(
     docker_cli: &str,
@@ -526,6 +1035,8 @@ pub fn spawn_shell(
     mount_readonly: bool,
     ports: &[String],
     user: &str,
+    shell: &str,
+    labels: &ContainerLabels,
     extra_args: &[String],
     interrupted: &Arc<AtomicBool>,
 ) -> SealedServicesResult<()> {
@@ -543,11 +1054,12 @@ pub fn spawn_shell(
         mount_paths,
         mount_readonly,
         ports,
+        labels,
         extra_args,
     )?);
 
     args.extend(
-        vec![image, "/bin/su", user]
+        vec![image, "/bin/su", "-s", shell, user]
             .into_iter()
             .map(std::borrow::ToOwned::to_owned)
             .collect::<Vec<_>>(),
@@ -565,6 +1077,7 @@ pub fn spawn_shell(
 }
 
 // This function returns arguments for `docker create` or `docker run`.
+#[allow(clippy::too_many_arguments)]
 fn container_args(
     source_dir: &Path,
     environment: &HashMap<String, String>,
@@ -572,6 +1085,7 @@ fn container_args(
     mount_paths: &[MappingPath],
     mount_readonly: bool,
     ports: &[String],
+    labels: &ContainerLabels,
     extra_args: &[String],
 ) -> SealedServicesResult<Vec<String>> {
     // Why `--init`? (1) PID 1 is supposed to reap orphaned zombie processes, otherwise they can
@@ -589,6 +1103,9 @@ fn container_args(
     // all.
     args.extend(vec!["--user".to_owned(), "root".to_owned()]);
 
+    // Labels, so `sealed clean` can find the container if it's leaked
+    args.extend(labels.args());
+
     // Environment
     args.extend(
         environment.iter().flat_map(|(variable, value)| {
@@ -620,7 +1137,7 @@ fn container_args(
         // [ref:mount_paths_no_commas]
         vec![
             "--mount".to_owned(),
-            if mount_readonly {
+            if mount_path.readonly.unwrap_or(mount_readonly) {
                 format!(
                     "type=bind,source={},target={},readonly",
                     absolute_source_dir
@@ -658,3 +1175,475 @@ fn container_args(
 
     Ok(args)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    const LABELS: ContainerLabels<'static> = ContainerLabels {
+        task: "build",
+        run_id: "run-1",
+    };
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    #[test]
+    fn test_pull_image_retries_rate_limits() {
+        // Fail with a rate limit on the first two attempts
+        let docker = FakeDocker::new(
+            r#"attempts=$(wc -l < "$(dirname "$0")/calls.log")
+if [ "$attempts" -le 2 ]; then
+  echo "toomanyrequests: You have reached your pull rate limit." >&2
+  exit 1
+fi"#,
+        );
+
+        pull_image(
+            docker.cli(),
+            "alpine:3.20",
+            &fast_retries(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(docker.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_image_id() {
+        let docker = FakeDocker::new(
+            r#"[ "$5" = "alpine:3.20" ] || exit 1
+echo sha256:0123abcd"#,
+        );
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        assert_eq!(
+            image_id(docker.cli(), "alpine:3.20", &interrupted).unwrap(),
+            Some("sha256:0123abcd".to_owned()),
+        );
+        assert_eq!(
+            image_id(docker.cli(), "alpine:nope", &interrupted).unwrap(),
+            None
+        );
+        assert_eq!(
+            docker.calls()[0],
+            "image inspect --format {{.Id}} alpine:3.20"
+        );
+    }
+
+    #[test]
+    fn test_existing_images_asks_docker_once() {
+        let docker = FakeDocker::new(r#"[ "$1" = container ] || [ "$3" = "sealed:task-01" ]"#);
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let existing_images = ExistingImages::default();
+
+        for _ in 0..2 {
+            assert!(existing_images
+                .image_exists(docker.cli(), "sealed:task-01", &interrupted)
+                .unwrap());
+            assert!(!existing_images
+                .image_exists(docker.cli(), "sealed:task-02", &interrupted)
+                .unwrap());
+        }
+        assert_eq!(
+            docker.calls(),
+            vec![
+                "image inspect sealed:task-01",
+                "image inspect sealed:task-02"
+            ],
+        );
+
+        // Committing the image means asking again.
+        existing_images
+            .commit_container(docker.cli(), "abc123", "sealed:task-02", &interrupted)
+            .unwrap();
+        existing_images
+            .image_exists(docker.cli(), "sealed:task-02", &interrupted)
+            .unwrap();
+        assert_eq!(docker.calls().len(), 4);
+    }
+
+    #[test]
+    fn test_list_images() {
+        let docker = FakeDocker::new(
+            r"printf 'sealed\ttask-02\tsha256:02\n'
+printf 'alpine\t3.20\tsha256:aa\n'
+printf 'sealed\ttask-01\tsha256:01\n'",
+        );
+
+        let images = list_images(docker.cli(), "task-", &Arc::new(AtomicBool::new(false))).unwrap();
+        assert_eq!(
+            images
+                .iter()
+                .map(ImageSummary::reference)
+                .collect::<Vec<_>>(),
+            vec!["sealed:task-02", "sealed:task-01"],
+        );
+        assert_eq!(images[0].id, "sha256:02");
+    }
+
+    #[test]
+    fn test_list_containers() {
+        let docker = FakeDocker::new(
+            r"printf 'c1\tsealed:task-01\trunning\ttrue\n'
+printf 'c2\tpostgres:16\texited\t\n'",
+        );
+
+        let containers = list_containers(docker.cli(), &Arc::new(AtomicBool::new(false))).unwrap();
+        assert_eq!(
+            containers,
+            vec![
+                ContainerSummary {
+                    id: "c1".to_owned(),
+                    image: "sealed:task-01".to_owned(),
+                    running: true,
+                    managed: true,
+                },
+                ContainerSummary {
+                    id: "c2".to_owned(),
+                    image: "postgres:16".to_owned(),
+                    running: false,
+                    managed: false,
+                },
+            ],
+        );
+        assert!(docker.calls()[0].starts_with("ps --all --no-trunc --format"));
+    }
+
+    // The labels `LABELS` turns into, as they appear in a logged invocation
+    fn label_args() -> String {
+        format!(
+            "--label sealed.managed=true --label sealed.version={} --label sealed.task=build \
+             --label sealed.run-id=run-1",
+            env!("CARGO_PKG_VERSION"),
+        )
+    }
+
+    #[test]
+    fn test_create_container_labels() {
+        let docker = FakeDocker::new("echo container-id");
+
+        create_container(
+            docker.cli(),
+            "alpine:3.20",
+            Path::new("."),
+            &HashMap::new(),
+            &[],
+            false,
+            &[],
+            UnixPath::new("/scratch"),
+            "root",
+            "/bin/sh",
+            "make",
+            &[],
+            &LABELS,
+            &[],
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert!(docker.calls()[0].contains(&label_args()));
+    }
+
+    #[test]
+    fn test_spawn_shell_labels() {
+        let docker = FakeDocker::new("exit 0");
+
+        spawn_shell(
+            docker.cli(),
+            "alpine:3.20",
+            Path::new("."),
+            &HashMap::new(),
+            UnixPath::new("/scratch"),
+            &[],
+            false,
+            &[],
+            "root",
+            "/bin/sh",
+            &LABELS,
+            &[],
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert!(docker.calls()[0].contains(&label_args()));
+    }
+
+    #[test]
+    fn test_copy_into_container_compression() {
+        use std::io::Write;
+
+        use sealed_common::tar::{Compression, Compressor};
+
+        let interrupted = Arc::new(AtomicBool::new(false));
+        // What Docker receives on stdin
+        let piped = |compression: Compression| {
+            let docker = FakeDocker::new(r#"cat > "$(dirname "$0")/stdin""#);
+            let mut compressor = Compressor::new(vec![], compression).unwrap();
+            compressor
+                .write_all(b"pretend this is a tar archive")
+                .unwrap();
+            let data = compressor.finish().unwrap();
+
+            copy_into_container(docker.cli(), "abc123", data.as_slice(), &interrupted).unwrap();
+            std::fs::read(Path::new(docker.cli()).with_file_name("stdin")).unwrap()
+        };
+
+        // The archive is piped to Docker as is.
+        assert!(piped(Compression::Gzip).starts_with(&[0x1f, 0x8b]));
+        assert_eq!(piped(Compression::None), b"pretend this is a tar archive");
+    }
+
+    #[test]
+    fn test_pull_image_fails_fast_on_unknown_manifest() {
+        let docker = FakeDocker::new(
+            r#"echo "manifest unknown" >&2
+exit 1"#,
+        );
+
+        assert!(pull_image(
+            docker.cli(),
+            "alpine:nope",
+            &fast_retries(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .is_err());
+
+        assert_eq!(docker.calls().len(), 1);
+    }
+
+    #[test]
+    fn test_container_args_mount_readonly_override() {
+        let mount_paths = vec![
+            MappingPath {
+                host_path: "src".into(),
+                container_path: UnixPath::new("src").to_owned(),
+                readonly: None,
+            },
+            MappingPath {
+                host_path: "cache".into(),
+                container_path: UnixPath::new("/cache").to_owned(),
+                readonly: Some(false),
+            },
+        ];
+
+        let args = container_args(
+            Path::new("."),
+            &HashMap::new(),
+            UnixPath::new("/scratch"),
+            &mount_paths,
+            true,
+            &[],
+            &LABELS,
+            &[],
+        )
+        .unwrap();
+
+        let mounts = args
+            .iter()
+            .filter(|arg| arg.starts_with("type=bind"))
+            .collect::<Vec<_>>();
+        assert_eq!(mounts.len(), 2);
+        assert!(mounts[0].ends_with("target=/scratch/src,readonly"));
+        assert!(mounts[1].ends_with("target=/cache"));
+    }
+
+    #[test]
+    fn test_create_container_uses_shell() {
+        let docker = FakeDocker::new("echo container-id");
+
+        let container = create_container(
+            docker.cli(),
+            "alpine:3.20",
+            Path::new("."),
+            &HashMap::new(),
+            &[],
+            false,
+            &[],
+            UnixPath::new("/scratch"),
+            "root",
+            "/bin/bash",
+            "make test",
+            &[],
+            &LABELS,
+            &[],
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert_eq!(container, "container-id");
+
+        let calls = docker.calls();
+        assert!(calls[0].contains("alpine:3.20 /bin/su -s /bin/bash -c make test root"));
+    }
+
+    #[test]
+    fn test_su_command_exec_form() {
+        let command_args = vec![
+            "grep".to_owned(),
+            "two words".to_owned(),
+            "$HOME".to_owned(),
+        ];
+
+        assert_eq!(
+            su_command("flynn", "/bin/sh", "", &command_args),
+            vec![
+                "/bin/su",
+                "-s",
+                "/bin/sh",
+                "-c",
+                r#"exec "$@""#,
+                "flynn",
+                "/bin/sh",
+                "grep",
+                "two words",
+                "$HOME",
+            ],
+        );
+
+        // The command prefix runs first
+        assert_eq!(
+            su_command("flynn", "/bin/sh", "set -e", &command_args)[4],
+            "set -e\nexec \"$@\"",
+        );
+    }
+
+    #[test]
+    fn test_create_container_exec_form() {
+        let docker = FakeDocker::new("echo container-id");
+
+        create_container(
+            docker.cli(),
+            "alpine:3.20",
+            Path::new("."),
+            &HashMap::new(),
+            &[],
+            false,
+            &[],
+            UnixPath::new("/scratch"),
+            "root",
+            "/bin/sh",
+            "",
+            &["echo".to_owned(), "hello world".to_owned()],
+            &LABELS,
+            &[],
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let calls = docker.calls();
+        assert!(calls[0].ends_with(
+            r#"alpine:3.20 /bin/su -s /bin/sh -c exec "$@" root /bin/sh echo hello world"#
+        ));
+    }
+
+    #[test]
+    fn test_start_container_with_retries() {
+        // Fail the first attempt only.
+        let docker =
+            FakeDocker::new(r#"[ $(( $(wc -l < "$(dirname "$0")/calls.log") )) -ge 2 ] || exit 1"#);
+        start_container_with_retries(
+            docker.cli(),
+            "container-id",
+            3,
+            Duration::ZERO,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert_eq!(docker.calls().len(), 2);
+
+        // Always fail.
+        let docker = FakeDocker::new("exit 1");
+        let result = start_container_with_retries(
+            docker.cli(),
+            "container-id",
+            1,
+            Duration::ZERO,
+            &Arc::new(AtomicBool::new(false)),
+        );
+        assert_eq!(docker.calls().len(), 2);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Failed after 2 attempts"));
+    }
+
+    // `container start` runs a command which fails, `container inspect` reports when the container
+    // started, and `container cp` writes the source path into the destination.
+    fn failing_container(started_at: &str) -> FakeDocker {
+        FakeDocker::new(&format!(
+            r#"case "$1 $2" in
+  "container start") exit 1 ;;
+  "container inspect") echo {started_at} ;;
+  "container cp") echo "$3" > "$4" ;;
+esac"#
+        ))
+    }
+
+    #[test]
+    fn test_run_container_copies_failure_outputs() {
+        let docker = failing_container("2024-01-01T00:00:00Z");
+        let dir = tempdir().unwrap();
+
+        let result = run_container(
+            docker.cli(),
+            "container-id",
+            0,
+            Duration::ZERO,
+            &[UnixPathBuf::from("dist")],
+            &[UnixPathBuf::from("test.log")],
+            UnixPath::new("/scratch"),
+            dir.path(),
+            &Arc::new(AtomicBool::new(false)),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SealedServicesError::TaskExited { code: 1, .. })
+        ));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("test.log")).unwrap(),
+            "container-id:/scratch/test.log\n",
+        );
+        assert!(!dir.path().join("dist").exists());
+        assert_eq!(
+            docker
+                .calls()
+                .iter()
+                .filter(|call| call.starts_with("container cp"))
+                .count(),
+            1,
+        );
+    }
+
+    #[test]
+    fn test_run_container_never_started() {
+        let docker = failing_container(NEVER_STARTED);
+        let dir = tempdir().unwrap();
+
+        let result = run_container(
+            docker.cli(),
+            "container-id",
+            0,
+            Duration::ZERO,
+            &[],
+            &[UnixPathBuf::from("test.log")],
+            UnixPath::new("/scratch"),
+            dir.path(),
+            &Arc::new(AtomicBool::new(false)),
+        );
+
+        assert!(result.is_err());
+        assert!(!dir.path().join("test.log").exists());
+        assert!(docker
+            .calls()
+            .iter()
+            .all(|call| !call.starts_with("container cp")));
+    }
+}
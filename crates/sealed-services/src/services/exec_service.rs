@@ -1,9 +1,12 @@
 use std::{
-    process::{ChildStdin, Command, Stdio},
+    io::{self, Read},
+    process::{Child, ChildStdin, Command, ExitStatus, Output, Stdio},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use sealed_common::error::{SealedError, SealedResult};
@@ -26,35 +29,30 @@ pub fn run_quiet(
     let was_interrupted = interrupted.load(Ordering::SeqCst);
 
     // Run the child process.
-    let child = command(docker_cli, args).output().map_err(|error| {
-        SealedError::System(
-            format!("{error} Perhaps you don't have Docker installed.",),
-            None,
-        )
-    })?;
+    let child = command(docker_cli, args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|child| wait_with_output(child, was_interrupted, interrupted))
+        .map_err(|error| {
+            SealedError::System(
+                format!("{error} Perhaps you don't have Docker installed.",),
+                None,
+            )
+        })?;
 
     // Handle the result.
     if child.status.success() {
         Ok(String::from_utf8_lossy(&child.stdout).to_string())
     } else {
-        Err(
-            if child.status.code().is_none()
-                || (!was_interrupted && interrupted.load(Ordering::SeqCst))
-            {
-                interrupted.store(true, Ordering::SeqCst);
-                SealedError::Interrupted
-            } else if user_command {
-                SealedError::FailedToRunUserCommand(
-                    format!("{}\n{}", error, String::from_utf8_lossy(&child.stderr)),
-                    None,
-                )
-            } else {
-                SealedError::System(
-                    format!("{}\n{}", error, String::from_utf8_lossy(&child.stderr)),
-                    None,
-                )
-            },
-        )
+        Err(failure(
+            child.status,
+            format!("{}\n{}", error, String::from_utf8_lossy(&child.stderr)),
+            user_command,
+            was_interrupted,
+            interrupted,
+        ))
     }
 }
 
@@ -93,7 +91,7 @@ pub fn run_quiet_stdin<W: FnOnce(&mut ChildStdin) -> SealedResult<()>>(
     writer(child.stdin.as_mut().unwrap())?; // [ref:run_quiet_stdin_piped]
 
     // Wait for the child to terminate.
-    let output = child.wait_with_output().map_err(|error| {
+    let output = wait_with_output(child, was_interrupted, interrupted).map_err(|error| {
         SealedError::System(
             format!("{error} Perhaps you don't have Docker installed.",),
             None,
@@ -104,24 +102,13 @@ pub fn run_quiet_stdin<W: FnOnce(&mut ChildStdin) -> SealedResult<()>>(
     if output.status.success() {
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     } else {
-        Err(
-            if output.status.code().is_none()
-                || (!was_interrupted && interrupted.load(Ordering::SeqCst))
-            {
-                interrupted.store(true, Ordering::SeqCst);
-                SealedError::Interrupted
-            } else if user_command {
-                SealedError::FailedToRunUserCommand(
-                    format!("{}\n{}", error, String::from_utf8_lossy(&output.stderr)),
-                    None,
-                )
-            } else {
-                SealedError::System(
-                    format!("{}\n{}", error, String::from_utf8_lossy(&output.stderr)),
-                    None,
-                )
-            },
-        )
+        Err(failure(
+            output.status,
+            format!("{}\n{}", error, String::from_utf8_lossy(&output.stderr)),
+            user_command,
+            was_interrupted,
+            interrupted,
+        ))
     }
 }
 
@@ -148,7 +135,7 @@ pub fn run_loud(
             )
         })?;
     // Wait for the child to terminate.
-    let status = child.wait().map_err(|error| {
+    let status = wait(&mut child, was_interrupted, interrupted).map_err(|error| {
         SealedError::System(
             format!("{error} Perhaps you don't have Docker installed."),
             None,
@@ -159,16 +146,13 @@ pub fn run_loud(
     if status.success() {
         Ok(())
     } else {
-        Err(
-            if status.code().is_none() || (!was_interrupted && interrupted.load(Ordering::SeqCst)) {
-                interrupted.store(true, Ordering::SeqCst);
-                SealedError::Interrupted
-            } else if user_command {
-                SealedError::FailedToRunUserCommand(error.to_owned(), None)
-            } else {
-                SealedError::System(error.to_owned(), None)
-            },
-        )
+        Err(failure(
+            status,
+            error.to_owned(),
+            user_command,
+            was_interrupted,
+            interrupted,
+        ))
     }
 }
 
@@ -196,16 +180,99 @@ pub fn run_attach(
     if child.success() {
         Ok(())
     } else {
-        Err(
-            if child.code().is_none() || (!was_interrupted && interrupted.load(Ordering::SeqCst)) {
-                interrupted.store(true, Ordering::SeqCst);
-                SealedError::Interrupted
-            } else if user_command {
-                SealedError::FailedToRunUserCommand(error.to_owned(), None)
+        Err(failure(
+            child,
+            error.to_owned(),
+            user_command,
+            was_interrupted,
+            interrupted,
+        ))
+    }
+}
+
+// How often a running child process checks whether the user interrupted the program
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Wait for a child process to exit. If the user interrupts the program in the meantime, the child
+// is killed rather than left to finish on its own, e.g., so a long `docker build` stops on Ctrl-C.
+// Children started after the interruption (`was_interrupted`), like the ones cleaning up, are left
+// alone. Either way the child is reaped, so no zombie remains.
+fn wait(
+    child: &mut Child,
+    was_interrupted: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if !was_interrupted && interrupted.load(Ordering::SeqCst) {
+            // This only fails if the child has exited in the meantime, which is what we want.
+            let _ = child.kill();
+            return child.wait();
+        }
+        thread::sleep(INTERRUPT_POLL_INTERVAL);
+    }
+}
+
+// Like `Child::wait_with_output`, but the child is killed when the user interrupts the program, as
+// for `wait`. The pipes are drained on their own threads, so a chatty child can't fill them up and
+// block.
+fn wait_with_output(
+    mut child: Child,
+    was_interrupted: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> io::Result<Output> {
+    // Close standard input, so a child reading it doesn't wait forever.
+    drop(child.stdin.take());
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = wait(&mut child, was_interrupted, interrupted)?;
+
+    let join = |drained: JoinHandle<io::Result<Vec<u8>>>| {
+        drained
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Unable to read the output")))
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
+    })
+}
+
+// Turn the status of a failed child process into an error. If the child was killed by a signal or
+// the user interrupted the program while it was running, that's an interruption. Otherwise, a
+// failed user command keeps its exit code so callers can tell, e.g., a failure from an OOM kill.
+fn failure(
+    status: ExitStatus,
+    message: String,
+    user_command: bool,
+    was_interrupted: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedError {
+    match status.code() {
+        Some(code) if was_interrupted || !interrupted.load(Ordering::SeqCst) => {
+            if user_command {
+                SealedError::TaskExited { message, code }
             } else {
-                SealedError::System(error.to_owned(), None)
-            },
-        )
+                SealedError::System(message, None)
+            }
+        }
+        _ => {
+            interrupted.store(true, Ordering::SeqCst);
+            SealedError::Interrupted
+        }
     }
 }
 
@@ -217,3 +284,143 @@ pub fn command(docker_cli: &str, args: &[String]) -> Command {
     }
     command
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_owned(), script.to_owned()]
+    }
+
+    #[test]
+    fn test_run_loud_exit_code() {
+        let result = run_loud(
+            "sh",
+            "The task failed.",
+            &sh("exit 42"),
+            true,
+            &Arc::new(AtomicBool::new(false)),
+        );
+
+        match result {
+            Err(SealedError::TaskExited { code, .. }) => assert_eq!(code, 42),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_run_quiet_exit_code() {
+        let result = run_quiet(
+            "sh",
+            "Running\u{2026}",
+            "The task failed.",
+            &sh("echo oops >&2; exit 42"),
+            true,
+            &Arc::new(AtomicBool::new(false)),
+        );
+
+        match result {
+            Err(SealedError::TaskExited { message, code }) => {
+                assert_eq!(code, 42);
+                assert!(message.contains("oops"));
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_oom_kill_is_distinguishable() {
+        let error = run_loud(
+            "sh",
+            "The task failed.",
+            &sh("exit 137"),
+            true,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, SealedError::TaskExited { code: 137, .. }));
+        assert!(error.to_string().contains("out of memory"));
+
+        let error = run_loud(
+            "sh",
+            "The task failed.",
+            &sh("exit 143"),
+            true,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("signal 15"));
+    }
+
+    #[test]
+    fn test_system_command_failure() {
+        let result = run_loud(
+            "sh",
+            "Unable to start.",
+            &sh("exit 1"),
+            false,
+            &Arc::new(AtomicBool::new(false)),
+        );
+
+        assert!(matches!(result, Err(SealedError::System(_, _))));
+    }
+
+    #[test]
+    fn test_interruption_kills_child() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let interrupter = {
+            let interrupted = interrupted.clone();
+            let pid_file = pid_file.clone();
+            thread::spawn(move || {
+                while !pid_file.exists() {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                interrupted.store(true, Ordering::SeqCst);
+            })
+        };
+
+        let start = std::time::Instant::now();
+        let result = run_quiet(
+            "sh",
+            "Building\u{2026}",
+            "The build failed.",
+            &sh(&format!("echo $$ > {}; exec sleep 30", pid_file.display())),
+            false,
+            &interrupted,
+        );
+        interrupter.join().unwrap();
+
+        assert!(matches!(result, Err(SealedError::Interrupted)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // The child was killed and reaped.
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let alive = Command::new("kill")
+            .args(["-0", pid.trim()])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!alive.success());
+    }
+
+    #[test]
+    fn test_commands_after_interruption_run() {
+        // Cleaning up after an interruption still works.
+        let interrupted = Arc::new(AtomicBool::new(true));
+        let output = run_quiet(
+            "sh",
+            "Cleaning up\u{2026}",
+            "Unable to clean up.",
+            &sh("sleep 0.2; echo done"),
+            false,
+            &interrupted,
+        )
+        .unwrap();
+        assert_eq!(output, "done\n");
+    }
+}
@@ -1,21 +1,54 @@
 use anyhow::Context;
 use git2::{
-    build::RepoBuilder, BranchType, Cred, ErrorCode, FetchOptions, RemoteCallbacks, Repository,
+    build::RepoBuilder, BranchType, Cred, ErrorClass, ErrorCode, FetchOptions, Index,
+    RemoteCallbacks, Repository, ResetType, SubmoduleUpdateOptions,
 };
 use resolve_path::PathResolveExt;
 use sealed_common::{
-    fs_utils::make_dirs, git_ops::parse_repo_name, info, settings::Settings, warn,
+    fs_utils::make_dirs,
+    git_ops::{parse_repo_name, parse_repo_path},
+    info,
+    settings::Settings,
+    warn,
+};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
 };
-use std::path::{Path, PathBuf};
 
 use crate::error::{SealedServicesError, SealedServicesResult};
 
+// Git never abbreviates an object ID to fewer than this many characters.
+const MIN_ABBREV_LENGTH: usize = 4;
+
+// The length of a full (SHA-1) object ID.
+const FULL_SHA_LENGTH: usize = 40;
+
+// What to check out. A branch is kept up to date with the remote, while a tag or a commit pins
+// the repository to that commit (with a detached HEAD).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitRef {
+    Branch(String),
+    Tag(String),
+    Commit(String),
+}
+
+impl Display for GitRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitRef::Branch(branch) => write!(f, "branch {}", branch),
+            GitRef::Tag(tag) => write!(f, "tag {}", tag),
+            GitRef::Commit(sha) => write!(f, "commit {}", sha),
+        }
+    }
+}
+
 pub struct GitRepoService;
 
 impl GitRepoService {
     pub fn fetch(
         repo: &str,
-        branch_name: &str,
+        git_ref: &GitRef,
         settings: &Settings,
     ) -> SealedServicesResult<Repository> {
         let repo = match GitRepoService::has_repo_been_cloned(repo, settings) {
@@ -24,11 +57,43 @@ impl GitRepoService {
             Err(e) => return Err(e),
         };
 
-        GitRepoService::checkout_branch(&repo, branch_name, settings)?;
-        GitRepoService::update_from_remote(&repo, branch_name, settings)?;
+        match git_ref {
+            GitRef::Branch(branch_name) => {
+                GitRepoService::checkout(&repo, git_ref, settings)?;
+                GitRepoService::update_from_remote(&repo, branch_name, settings)?;
+            }
+            GitRef::Tag(_) | GitRef::Commit(_) => {
+                // A repository cloned earlier might not have the tag or commit yet.
+                GitRepoService::fetch_tags(&repo, settings)?;
+                GitRepoService::checkout(&repo, git_ref, settings)?;
+            }
+        }
+
+        if settings.submodules {
+            GitRepoService::update_submodules(&repo, settings)?;
+        }
         Ok(repo)
     }
 
+    // Initialize and update the submodules of a repository, and theirs in turn. They're fetched
+    // with the same credentials as the repository itself.
+    fn update_submodules(repo: &Repository, settings: &Settings) -> SealedServicesResult<()> {
+        for mut submodule in repo.submodules()? {
+            info!(
+                "Updating submodule: {}",
+                submodule.name().unwrap_or_default()
+            );
+
+            let mut options = SubmoduleUpdateOptions::new();
+            options.fetch(Self::get_fetch_options(settings)?);
+            submodule.update(true, Some(&mut options))?;
+
+            GitRepoService::update_submodules(&submodule.open()?, settings)?;
+        }
+
+        Ok(())
+    }
+
     fn clone_from_remote(repo: &str, settings: &Settings) -> SealedServicesResult<Repository> {
         let mut builder = Self::get_repo_builder(settings)?;
 
@@ -48,7 +113,8 @@ impl GitRepoService {
             callbacks.credentials(|_url, username_from_url, _allowed_types| {
                 let resolved_ssh_key = ssh_key.resolve();
                 Cred::ssh_key(
-                    username_from_url.unwrap(),
+                    // Submodule URLs don't necessarily name a user
+                    username_from_url.unwrap_or("git"),
                     None,
                     Path::new(resolved_ssh_key.to_str().unwrap()),
                     None,
@@ -70,6 +136,53 @@ impl GitRepoService {
         Ok(builder)
     }
 
+    // Check out a branch, a tag, or a commit. Tags and commits are checked out with a detached HEAD.
+    pub fn checkout(
+        repo: &Repository,
+        git_ref: &GitRef,
+        settings: &Settings,
+    ) -> SealedServicesResult<()> {
+        match git_ref {
+            GitRef::Branch(branch_name) => {
+                GitRepoService::checkout_branch(repo, branch_name, settings)
+            }
+            GitRef::Tag(tag) => {
+                GitRepoService::checkout_detached(repo, &format!("refs/tags/{}", tag))
+            }
+            GitRef::Commit(sha) => GitRepoService::checkout_detached(repo, sha),
+        }
+    }
+
+    // Check out the commit that `spec` resolves to, leaving HEAD detached
+    fn checkout_detached(repo: &Repository, spec: &str) -> SealedServicesResult<()> {
+        let commit = repo.revparse_single(spec)?.peel_to_commit()?;
+
+        let mut checkout_builder = git2::build::CheckoutBuilder::new();
+        checkout_builder.force();
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout_builder))?;
+        repo.set_head_detached(commit.id())?;
+        info!("Checked out {} at {}", spec, commit.id());
+
+        Ok(())
+    }
+
+    // Fetch the branches and tags from the remote, without updating any local branch
+    fn fetch_tags(repo: &Repository, settings: &Settings) -> SealedServicesResult<()> {
+        let mut fo = Self::get_fetch_options(settings)?;
+
+        info!("Fetching tags from remote: origin");
+        repo.find_remote("origin")?.fetch(
+            &[
+                "refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*",
+            ],
+            Some(&mut fo),
+            None,
+        )?;
+
+        Ok(())
+    }
+
     // Checkout a branch and set it as the current branch
     fn checkout_branch(
         repo: &Repository,
@@ -204,11 +317,20 @@ impl GitRepoService {
             let mut idx = repo.merge_trees(&ancestor.tree()?, &local_tree, &remote_tree, None)?;
 
             if idx.has_conflicts() {
+                let conflicts = Self::conflicted_paths(&idx)?;
                 warn!("Merge conflicts detected. Aborting.");
-                repo.checkout_index(Some(&mut idx), None)?;
-                return Err(SealedServicesError::GitError(git2::Error::from_str(
-                    "Merge conflicts detected",
-                )));
+
+                // The merge was only done in memory, but put HEAD, the index, and the working
+                // tree back the way they were in case anything was left behind.
+                let head = repo.find_commit(local_commit.id())?;
+                repo.reset(head.as_object(), ResetType::Hard, None)?;
+                repo.cleanup_state()?;
+
+                return Err(SealedServicesError::MergeConflict {
+                    branch: clean_branch_name.to_string(),
+                    commit: remote_commit.id().to_string(),
+                    paths: conflicts,
+                });
             }
 
             let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
@@ -238,6 +360,25 @@ impl GitRepoService {
         Ok(())
     }
 
+    // The paths with conflicts in a merged index, in order and without duplicates.
+    fn conflicted_paths(index: &Index) -> SealedServicesResult<Vec<String>> {
+        let mut paths = vec![];
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let entry = conflict
+                .our
+                .or(conflict.their)
+                .or(conflict.ancestor)
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned());
+            if let Some(path) = entry {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+
     pub fn open_locally(repo: &str, settings: &Settings) -> SealedServicesResult<Repository> {
         let path = Self::resolve_repo_local(repo, settings)?;
         info!("Resolved git repository to: {}", path.display());
@@ -248,12 +389,35 @@ impl GitRepoService {
         }
     }
 
-    pub fn short_sha(repo: &Repository) -> SealedServicesResult<String> {
-        let head = repo.head()?;
-        let commit = head.peel_to_commit()?;
-        let short_sha = commit.id().to_string()[..7].to_string();
+    // The SHA of the commit at HEAD. With a `len`, it's abbreviated the way git does it: to at
+    // least `len` characters, but longer if that's needed for the prefix to be unambiguous.
+    pub fn commit_sha(repo: &Repository, len: Option<usize>) -> SealedServicesResult<String> {
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => {
+                return Err(SealedServicesError::GitError(git2::Error::new(
+                    ErrorCode::UnbornBranch,
+                    ErrorClass::Reference,
+                    "HEAD doesn't point to a commit yet, so there is no SHA to use",
+                )));
+            }
+            Err(e) => return Err(SealedServicesError::GitError(e)),
+        };
+        let sha = head.peel_to_commit()?.id().to_string();
 
-        Ok(short_sha)
+        let Some(len) = len else {
+            return Ok(sha);
+        };
+
+        for len in len.clamp(MIN_ABBREV_LENGTH, FULL_SHA_LENGTH)..FULL_SHA_LENGTH {
+            match repo.find_object_by_prefix(&sha[..len], None) {
+                Ok(_) => return Ok(sha[..len].to_string()),
+                Err(ref e) if e.code() == ErrorCode::Ambiguous => continue,
+                Err(e) => return Err(SealedServicesError::GitError(e)),
+            }
+        }
+
+        Ok(sha)
     }
 
     fn current_branch(repo: &Repository) -> SealedServicesResult<String> {
@@ -277,10 +441,258 @@ impl GitRepoService {
         Ok(dot_git_path.exists() && dot_git_path.is_dir())
     }
 
+    // Clones live under the working directory at `host/owner/name`. They used to live at just
+    // `name`, so a clone which only exists there is still used.
     fn resolve_repo_local(repo: &str, settings: &Settings) -> SealedServicesResult<PathBuf> {
         let root_dir = &settings.working_directory;
-        let repo_name = parse_repo_name(repo)?;
-        let path = root_dir.join(repo_name);
+        let path = root_dir.join(parse_repo_path(repo)?);
+
+        let legacy_path = root_dir.join(parse_repo_name(repo)?);
+        if !path.join(".git").is_dir() && legacy_path.join(".git").is_dir() {
+            warn!(
+                "Using the existing clone at {} (new clones go in {})",
+                legacy_path.display(),
+                path.display()
+            );
+            return Ok(legacy_path);
+        }
+
         Ok(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use git2::Signature;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn commit_file(repo: &Repository, name: &str, contents: &str) -> git2::Oid {
+        std::fs::write(repo.workdir().unwrap().join(name), contents).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Flynn", "flynn@encom.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &format!("Update {}", name),
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    fn repo_with_commit(path: &Path) -> Repository {
+        let repo = Repository::init(path).unwrap();
+        commit_file(&repo, "README.md", "# Encom\n");
+        repo
+    }
+
+    #[test]
+    fn test_commit_sha_full() {
+        let dir = tempdir().unwrap();
+        let repo = repo_with_commit(dir.path());
+
+        let sha = GitRepoService::commit_sha(&repo, None).unwrap();
+        assert_eq!(sha.len(), FULL_SHA_LENGTH);
+        assert_eq!(
+            sha,
+            repo.head()
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+                .id()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_commit_sha_abbreviated() {
+        let dir = tempdir().unwrap();
+        let repo = repo_with_commit(dir.path());
+        let full = GitRepoService::commit_sha(&repo, None).unwrap();
+
+        let sha = GitRepoService::commit_sha(&repo, Some(12)).unwrap();
+        assert_eq!(sha, full[..12]);
+
+        // Too short, so it's lengthened to the minimum
+        let sha = GitRepoService::commit_sha(&repo, Some(1)).unwrap();
+        assert_eq!(sha, full[..MIN_ABBREV_LENGTH]);
+    }
+
+    #[test]
+    fn test_commit_sha_unborn_head() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        match GitRepoService::commit_sha(&repo, Some(7)) {
+            Err(SealedServicesError::GitError(e)) => assert_eq!(e.code(), ErrorCode::UnbornBranch),
+            result => panic!("Expected an error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_update_from_remote_aborts_on_conflict() {
+        let dir = tempdir().unwrap();
+        let upstream = repo_with_commit(&dir.path().join("upstream"));
+        commit_file(&upstream, "grid.txt", "base\n");
+
+        let local = Repository::clone(
+            dir.path().join("upstream").to_str().unwrap(),
+            dir.path().join("local"),
+        )
+        .unwrap();
+        let branch = local.head().unwrap().shorthand().unwrap().to_owned();
+
+        // Diverge with conflicting changes to the same file
+        commit_file(&upstream, "grid.txt", "theirs\n");
+        let ours = commit_file(&local, "grid.txt", "ours\n");
+
+        let settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        match GitRepoService::update_from_remote(&local, &branch, &settings) {
+            Err(SealedServicesError::MergeConflict {
+                branch: merged_into,
+                paths,
+                ..
+            }) => {
+                assert_eq!(merged_into, branch);
+                assert_eq!(paths, vec!["grid.txt"]);
+            }
+            result => panic!("Expected a merge conflict, got {:?}", result),
+        }
+
+        // Nothing was committed and the working tree is untouched
+        assert_eq!(local.head().unwrap().target(), Some(ours));
+        assert_eq!(local.state(), git2::RepositoryState::Clean);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("local/grid.txt")).unwrap(),
+            "ours\n"
+        );
+        assert!(local.statuses(None).unwrap().is_empty());
+        assert!(!local.index().unwrap().has_conflicts());
+    }
+
+    #[test]
+    fn test_checkout_tag() {
+        let dir = tempdir().unwrap();
+        let repo = repo_with_commit(dir.path());
+        let tagged = commit_file(&repo, "grid.txt", "v1\n");
+        let signature = Signature::now("Flynn", "flynn@encom.com").unwrap();
+        repo.tag(
+            "v1.0",
+            &repo.find_object(tagged, None).unwrap(),
+            &signature,
+            "Version 1.0",
+            false,
+        )
+        .unwrap();
+        commit_file(&repo, "grid.txt", "v2\n");
+
+        let settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        GitRepoService::checkout(&repo, &GitRef::Tag("v1.0".to_owned()), &settings).unwrap();
+
+        assert!(repo.head_detached().unwrap());
+        assert_eq!(repo.head().unwrap().target(), Some(tagged));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("grid.txt")).unwrap(),
+            "v1\n"
+        );
+    }
+
+    #[test]
+    fn test_checkout_commit() {
+        let dir = tempdir().unwrap();
+        let repo = repo_with_commit(dir.path());
+        let first = commit_file(&repo, "grid.txt", "first\n");
+        commit_file(&repo, "grid.txt", "second\n");
+
+        let settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        let sha = first.to_string()[..12].to_owned();
+        GitRepoService::checkout(&repo, &GitRef::Commit(sha), &settings).unwrap();
+
+        assert!(repo.head_detached().unwrap());
+        assert_eq!(repo.head().unwrap().target(), Some(first));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("grid.txt")).unwrap(),
+            "first\n"
+        );
+
+        // An unknown commit is an error
+        assert!(GitRepoService::checkout(
+            &repo,
+            &GitRef::Commit("0123456789ab".to_owned()),
+            &settings
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fetch_updates_submodules() {
+        let dir = tempdir().unwrap();
+        let library = repo_with_commit(&dir.path().join("library"));
+        commit_file(&library, "lib.txt", "light cycles\n");
+
+        // A superproject with the library as a submodule
+        let superproject = repo_with_commit(&dir.path().join("superproject"));
+        let library_url = format!("file://{}", dir.path().join("library").display());
+        let mut submodule = superproject
+            .submodule(&library_url, Path::new("vendor/library"), true)
+            .unwrap();
+        submodule.clone(None).unwrap();
+        submodule.add_finalize().unwrap();
+        commit_file(&superproject, "README.md", "# Encom (with a library)\n");
+        let branch = superproject.head().unwrap().shorthand().unwrap().to_owned();
+
+        let mut settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        settings.working_directory = dir.path().join("work");
+        settings.submodules = true;
+
+        let repo = GitRepoService::fetch(
+            &format!("file://{}", dir.path().join("superproject").display()),
+            &GitRef::Branch(branch),
+            &settings,
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(repo.workdir().unwrap().join("vendor/library/lib.txt"))
+                .unwrap(),
+            "light cycles\n"
+        );
+    }
+
+    #[test]
+    fn test_resolve_repo_local_by_owner() {
+        let dir = tempdir().unwrap();
+        let mut settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        settings.working_directory = dir.path().to_owned();
+
+        let encom =
+            GitRepoService::resolve_repo_local("git@github.com:encom/grid.git", &settings).unwrap();
+        let flynn =
+            GitRepoService::resolve_repo_local("git@github.com:flynn/grid.git", &settings).unwrap();
+
+        assert_ne!(encom, flynn);
+        assert_eq!(encom, dir.path().join("github.com/encom/grid"));
+        assert_eq!(flynn, dir.path().join("github.com/flynn/grid"));
+    }
+
+    #[test]
+    fn test_resolve_repo_local_legacy_clone() {
+        let dir = tempdir().unwrap();
+        let mut settings: Settings = serde_yaml::from_str("ssh_key: null").unwrap();
+        settings.working_directory = dir.path().to_owned();
+        std::fs::create_dir_all(dir.path().join("grid/.git")).unwrap();
+
+        assert_eq!(
+            GitRepoService::resolve_repo_local("git@github.com:encom/grid.git", &settings).unwrap(),
+            dir.path().join("grid")
+        );
+    }
+}
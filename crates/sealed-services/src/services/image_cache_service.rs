@@ -0,0 +1,411 @@
+use std::{
+    fs::{read_dir, remove_file, rename},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    time::SystemTime,
+};
+
+use console::style;
+use sealed_common::{
+    cache::{combine, CACHE_VERSION},
+    debug,
+    fs_utils::make_dirs,
+    settings::Settings,
+};
+
+use crate::{
+    docker_service::{load_image, pull_image, registry_digest, save_image},
+    error::{SealedServicesError, SealedServicesResult},
+    retry_service::RetryPolicy,
+};
+
+// The extension of the tarballs in the cache directory. Anything else in there is left alone.
+const TARBALL_EXTENSION: &str = "tar";
+
+// A local cache of `docker image save` tarballs, so images can be restored with `docker image load`
+// instead of being pulled from a registry again. The tarballs are named after a hash of the image's
+// manifest digest rather than its reference, so a tag that has moved never serves a stale tarball.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageCache {
+    directory: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl ImageCache {
+    pub fn new(directory: PathBuf, max_size: Option<u64>) -> Self {
+        ImageCache {
+            directory,
+            max_size,
+        }
+    }
+
+    // Returns `None` if the image cache has been disabled (e.g., with `--no-image-cache`).
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        if !settings.image_cache.enabled {
+            return None;
+        }
+
+        let directory = settings
+            .image_cache
+            .directory
+            .clone()
+            .unwrap_or_else(|| settings.working_directory.join("image-cache"));
+
+        Some(ImageCache::new(directory, settings.image_cache.max_size))
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // The location of the tarball for an image digest, whether or not it exists.
+    pub fn tarball_path(&self, digest: &str) -> PathBuf {
+        let key = combine(&format!("{}", CACHE_VERSION), digest);
+        self.directory
+            .join(format!("{}.{}", key, TARBALL_EXTENSION))
+    }
+
+    // The total size of the cached tarballs in bytes.
+    pub fn size(&self) -> SealedServicesResult<u64> {
+        Ok(self.tarballs()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    // Delete the least recently used tarballs until the cache fits in its maximum size.
+    pub fn evict(&self) -> SealedServicesResult<()> {
+        let Some(max_size) = self.max_size else {
+            return Ok(());
+        };
+
+        let mut tarballs = self.tarballs()?;
+        let mut size: u64 = tarballs.iter().map(|(_, size, _)| size).sum();
+
+        // Oldest first
+        tarballs.sort_by_key(|(_, _, modified)| *modified);
+
+        for (path, tarball_size, _) in tarballs {
+            if size <= max_size {
+                break;
+            }
+
+            debug!("Evicting {} from the image cache", path.to_string_lossy());
+            remove_file(&path)?;
+            size -= tarball_size;
+        }
+
+        Ok(())
+    }
+
+    // The path, size, and modification time of every tarball in the cache.
+    fn tarballs(&self) -> SealedServicesResult<Vec<(PathBuf, u64, SystemTime)>> {
+        if !self.directory.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut tarballs = vec![];
+        for entry in read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(TARBALL_EXTENSION)
+            {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                tarballs.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+
+        Ok(tarballs)
+    }
+
+    // Save an image into the cache. The tarball is written under a temporary name and then renamed,
+    // so an interrupted save never leaves a truncated tarball behind to be loaded later.
+    fn store(
+        &self,
+        docker_cli: &str,
+        image: &str,
+        digest: &str,
+        interrupted: &Arc<AtomicBool>,
+    ) -> SealedServicesResult<()> {
+        make_dirs(&self.directory)?;
+
+        let tarball = self.tarball_path(digest);
+        let partial = tarball.with_extension("partial");
+        if let Err(error) = save_image(docker_cli, image, &partial, interrupted) {
+            let _ = remove_file(&partial);
+            return Err(error);
+        }
+        rename(&partial, &tarball)?;
+
+        self.evict()
+    }
+}
+
+// Pull an image, going through the image cache if one is given. On a cache hit, the image is loaded
+// from the cached tarball instead. On a miss, the image is pulled and then saved into the cache. The
+// digest comes from the reference itself if it's pinned, and from the registry otherwise. If it can't
+// be resolved, the cache is bypassed since there's no telling which content the tag refers to.
+pub fn pull_image_cached(
+    docker_cli: &str,
+    image: &str,
+    cache: Option<&ImageCache>,
+    retry: &RetryPolicy,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    let Some(cache) = cache else {
+        return pull_image(docker_cli, image, retry, interrupted);
+    };
+
+    let digest = match image.split_once('@') {
+        Some((_, digest)) => Some(digest.to_owned()),
+        None => registry_digest(docker_cli, image, interrupted)?,
+    };
+    let Some(digest) = digest else {
+        debug!(
+            "Unable to resolve the digest of image {}; bypassing the image cache",
+            style(image).bold().dim()
+        );
+        return pull_image(docker_cli, image, retry, interrupted);
+    };
+
+    let tarball = cache.tarball_path(&digest);
+    if tarball.is_file() {
+        debug!(
+            "Found image {} in the image cache",
+            style(image).bold().dim()
+        );
+
+        match load_image(docker_cli, &tarball, interrupted) {
+            Ok(()) => return Ok(()),
+            Err(SealedServicesError::Interrupted) => return Err(SealedServicesError::Interrupted),
+            Err(error) => {
+                // The tarball is probably corrupt. Drop it and pull the image instead.
+                debug!("Unable to load image from the image cache: {}", error);
+                let _ = remove_file(&tarball);
+            }
+        }
+    }
+
+    pull_image(docker_cli, image, retry, interrupted)?;
+
+    // The pull succeeded, so failing to populate the cache isn't worth failing for.
+    match cache.store(docker_cli, image, &digest, interrupted) {
+        Err(SealedServicesError::Interrupted) => Err(SealedServicesError::Interrupted),
+        Err(error) => {
+            debug!("Unable to save image to the image cache: {}", error);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    const DIGEST: &str = "sha256:3f9c2ab";
+
+    const RESOLVE: &str = "buildx imagetools inspect --format {{.Manifest.Digest}} alpine:3.20";
+
+    // The registry resolves every tag to `DIGEST`.
+    const RESOLVING_DOCKER: &str =
+        r#"if [ "$1 $2" = "buildx imagetools" ]; then echo sha256:3f9c2ab; fi"#;
+
+    // `docker image save --output <path> <image>` writes a dummy tarball.
+    const SAVING_DOCKER: &str = r#"if [ "$1 $2" = "buildx imagetools" ]; then echo sha256:3f9c2ab; fi
+if [ "$1 $2" = "image save" ]; then echo tarball > "$4"; fi"#;
+
+    #[test]
+    fn test_cache_hit_loads_instead_of_pulling() {
+        let docker = FakeDocker::new(RESOLVING_DOCKER);
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), None);
+        let tarball = cache.tarball_path(DIGEST);
+        write(&tarball, "tarball").unwrap();
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            docker.calls(),
+            vec![
+                RESOLVE.to_owned(),
+                format!("image load --input {}", tarball.to_string_lossy()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_cache_miss_pulls_and_saves() {
+        let docker = FakeDocker::new(SAVING_DOCKER);
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().join("cache"), None);
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let calls = docker.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0], RESOLVE);
+        assert_eq!(calls[1], "image pull alpine:3.20");
+        assert!(calls[2].starts_with("image save --output "));
+        assert!(cache.tarball_path(DIGEST).is_file());
+        assert!(cache.size().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_no_cache_only_pulls() {
+        let docker = FakeDocker::new(SAVING_DOCKER);
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            None,
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(docker.calls(), vec!["image pull alpine:3.20"]);
+    }
+
+    #[test]
+    fn test_corrupt_tarball_falls_back_to_pull() {
+        let docker = FakeDocker::new(
+            r#"if [ "$1 $2" = "buildx imagetools" ]; then echo sha256:3f9c2ab; fi
+if [ "$1 $2" = "image load" ]; then exit 1; fi
+if [ "$1 $2" = "image save" ]; then echo tarball > "$4"; fi"#,
+        );
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), None);
+        write(cache.tarball_path(DIGEST), "garbage").unwrap();
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let calls = docker.calls();
+        assert!(calls[1].starts_with("image load"));
+        assert_eq!(calls[2], "image pull alpine:3.20");
+    }
+
+    #[test]
+    fn test_moved_tag_misses() {
+        let docker = FakeDocker::new(SAVING_DOCKER);
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), None);
+        write(cache.tarball_path("sha256:0000000"), "stale").unwrap();
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let calls = docker.calls();
+        assert_eq!(calls[0], RESOLVE);
+        assert_eq!(calls[1], "image pull alpine:3.20");
+    }
+
+    #[test]
+    fn test_pinned_image_skips_resolving() {
+        let docker = FakeDocker::new("");
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), None);
+        let tarball = cache.tarball_path(DIGEST);
+        write(&tarball, "tarball").unwrap();
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine@sha256:3f9c2ab",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            docker.calls(),
+            vec![format!("image load --input {}", tarball.to_string_lossy())],
+        );
+    }
+
+    #[test]
+    fn test_unresolvable_digest_bypasses_cache() {
+        let docker = FakeDocker::new(
+            r#"if [ "$1 $2" = "buildx imagetools" ]; then exit 1; fi
+if [ "$1 $2" = "image save" ]; then echo tarball > "$4"; fi"#,
+        );
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), None);
+
+        pull_image_cached(
+            docker.cli(),
+            "alpine:3.20",
+            Some(&cache),
+            &RetryPolicy::none(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(docker.calls(), vec![RESOLVE, "image pull alpine:3.20"]);
+        assert_eq!(cache.size().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_evict_removes_oldest_tarballs() {
+        let dir = tempdir().unwrap();
+        let cache = ImageCache::new(dir.path().to_owned(), Some(10));
+        let old = cache.tarball_path("old");
+        let new = cache.tarball_path("new");
+        write(&old, "0123456789").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        write(&new, "0123456789").unwrap();
+        write(dir.path().join("unrelated.txt"), "0123456789").unwrap();
+
+        assert_eq!(cache.size().unwrap(), 20);
+        cache.evict().unwrap();
+
+        assert!(!old.exists());
+        assert!(new.exists());
+        assert_eq!(cache.size().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_from_settings() {
+        let mut settings: Settings = serde_yaml::from_str("working_directory: /work").unwrap();
+        assert_eq!(
+            ImageCache::from_settings(&settings).unwrap().directory(),
+            Path::new("/work/image-cache"),
+        );
+
+        settings.image_cache.enabled = false;
+        assert_eq!(ImageCache::from_settings(&settings), None);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use console::style;
+use sealed_common::info;
+
+use crate::{
+    docker_service::delete_container,
+    error::{SealedServicesError, SealedServicesResult},
+};
+
+// Containers kept for post-mortem debugging are recorded in this file in the working directory,
+// one ID per line, so they can be cleaned up later.
+const KEPT_CONTAINERS_FILE: &str = "kept-containers";
+
+// Where the kept containers are recorded.
+pub fn kept_containers_path(working_directory: &Path) -> PathBuf {
+    working_directory.join(KEPT_CONTAINERS_FILE)
+}
+
+// Delete a task's container once it's done with. If the task failed and `keep_failed` is set, the
+// container is kept instead so it can be inspected: its ID is recorded in `kept_containers` and a
+// command to get a shell in it is printed.
+pub fn release_container(
+    docker_cli: &str,
+    container: &str,
+    failed: bool,
+    keep_failed: bool,
+    kept_containers: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    if !(failed && keep_failed) {
+        return delete_container(docker_cli, container, interrupted);
+    }
+
+    record(kept_containers, container)?;
+    info!(
+        "Kept container {} for debugging. To get a shell in it, run: {}",
+        style(container).bold(),
+        style(format!("{docker_cli} exec -it {container} sh")).bold(),
+    );
+    Ok(())
+}
+
+fn record(kept_containers: &Path, container: &str) -> SealedServicesResult<()> {
+    if let Some(parent) = kept_containers.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(kept_containers)?;
+    writeln!(file, "{container}")?;
+    Ok(())
+}
+
+// The IDs of the containers which have been kept, oldest first.
+pub fn kept_containers(kept_containers: &Path) -> SealedServicesResult<Vec<String>> {
+    match read_to_string(kept_containers) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(SealedServicesError::IOError(error)),
+    }
+}
+
+// Forget about the kept containers, once they've been deleted.
+pub fn forget_kept_containers(kept_containers: &Path) -> SealedServicesResult<()> {
+    match remove_file(kept_containers) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(SealedServicesError::IOError(error))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    #[test]
+    fn test_failed_container_kept() {
+        let dir = tempdir().unwrap();
+        let path = kept_containers_path(dir.path());
+        let docker = FakeDocker::new("exit 0");
+
+        release_container(
+            docker.cli(),
+            "abc123",
+            true,
+            true,
+            &path,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert!(docker.calls().is_empty());
+        assert_eq!(kept_containers(&path).unwrap(), vec!["abc123"]);
+    }
+
+    #[test]
+    fn test_containers_deleted_otherwise() {
+        let dir = tempdir().unwrap();
+        let path = kept_containers_path(dir.path());
+        let docker = FakeDocker::new("exit 0");
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        // Successful tasks are cleaned up even if failed ones are kept.
+        release_container(docker.cli(), "abc123", false, true, &path, &interrupted).unwrap();
+        release_container(docker.cli(), "def456", true, false, &path, &interrupted).unwrap();
+
+        assert_eq!(
+            docker.calls(),
+            vec!["container rm --force abc123", "container rm --force def456"],
+        );
+        assert!(kept_containers(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_forget_kept_containers() {
+        let dir = tempdir().unwrap();
+        let path = kept_containers_path(dir.path());
+        let interrupted = Arc::new(AtomicBool::new(false));
+        release_container(
+            FakeDocker::new("exit 0").cli(),
+            "abc123",
+            true,
+            true,
+            &path,
+            &interrupted,
+        )
+        .unwrap();
+
+        forget_kept_containers(&path).unwrap();
+        assert!(kept_containers(&path).unwrap().is_empty());
+
+        // Forgetting nothing is fine too.
+        forget_kept_containers(&path).unwrap();
+    }
+}
@@ -1,5 +1,18 @@
 // pub mod postgres;
 
+pub mod clean_service;
 pub mod docker_service;
 pub mod exec_service;
 pub mod git_repo_service;
+pub mod image_cache_service;
+pub mod kept_container_service;
+pub mod plan_service;
+pub mod prune_service;
+pub mod remote_cache_service;
+pub mod retry_service;
+pub mod run_service;
+pub mod shell_service;
+pub mod taskfile_service;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -0,0 +1,460 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use sealed_common::cache::HashAlgorithm;
+use sealed_database::{
+    task::image_name,
+    taskfile::{environment, TaskFile},
+};
+
+use crate::{
+    docker_service::{image_id, ExistingImages},
+    error::{SealedServicesError, SealedServicesResult},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TaskStatus {
+    // The image for the task already exists, so the task would be skipped.
+    Cached,
+    // The task would run.
+    Run,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlannedTask {
+    pub name: String,
+    pub image: String,
+    pub status: TaskStatus,
+}
+
+// Tasks to run even if their image is cached, as chosen on the command line. This only ever
+// disables the cache: tasks which can't be cached (e.g., because they mount paths) never are.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum NoCache {
+    #[default]
+    None,
+    All,
+    Tasks(HashSet<String>),
+}
+
+impl NoCache {
+    pub fn applies_to(&self, task: &str) -> bool {
+        match self {
+            NoCache::None => false,
+            NoCache::All => true,
+            NoCache::Tasks(tasks) => tasks.contains(task),
+        }
+    }
+}
+
+// Compute the order in which tasks run: every task comes after its dependencies. If `roots` is
+// empty, the default tasks are used in order, or every task if there is no default. The dependency
+// graph must be valid, which `taskfile::validate` guarantees.
+pub fn schedule<'a>(task_file: &'a TaskFile, roots: &[&'a str]) -> Vec<&'a str> {
+    let mut roots = roots.to_vec();
+    if roots.is_empty() {
+        if let Some(default) = &task_file.default {
+            roots = default.names().iter().map(String::as_str).collect();
+        } else {
+            roots = task_file.tasks.keys().map(String::as_str).collect();
+            roots.sort_unstable();
+        }
+    }
+
+    let mut schedule = vec![];
+    let mut visited = HashSet::new();
+    for root in roots {
+        visit(task_file, root, &mut visited, &mut schedule);
+    }
+    schedule
+}
+
+fn visit<'a>(
+    task_file: &'a TaskFile,
+    task: &'a str,
+    visited: &mut HashSet<&'a str>,
+    schedule: &mut Vec<&'a str>,
+) {
+    if !visited.insert(task) {
+        return;
+    }
+
+    for dependency in &task_file.tasks[task].dependencies {
+        visit(task_file, dependency, visited, schedule);
+    }
+
+    schedule.push(task);
+}
+
+// Work out what running `roots` would do without creating any containers: walk the schedule
+// computing the image for each task and check whether that image already exists. Tasks without an
+// entry in `input_files_hashes` (computed with `hash_algorithm`) are treated as having no input
+// files. Variables missing from the process's environment are taken from `file_environment`. Tasks
+// chosen by `no_cache` run without checking for their image. What's learned about which images
+// exist is kept in `existing_images`, which the caller can share with the rest of the run.
+#[allow(clippy::too_many_arguments)]
+pub fn explain(
+    docker_cli: &str,
+    docker_repo: &str,
+    task_file: &TaskFile,
+    roots: &[&str],
+    input_files_hashes: &HashMap<String, String>,
+    file_environment: &HashMap<String, String>,
+    no_cache: &NoCache,
+    existing_images: &ExistingImages,
+    hash_algorithm: HashAlgorithm,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<PlannedTask>> {
+    let mut plan = vec![];
+
+    // Seed the cache keys with the ID of the base image rather than its tag, so repushing the tag
+    // with new contents invalidates the cache. If the image hasn't been pulled, fall back to the tag.
+    let mut previous_image = image_id(docker_cli, &task_file.image, interrupted)?
+        .unwrap_or_else(|| task_file.image.clone());
+
+    // Once a task disables the cache (or `no_cache` disables it for the task), the tasks after it
+    // can't be restored from the cache either, because they build on the results of a task which
+    // always runs.
+    let mut caching = true;
+
+    for name in schedule(task_file, roots) {
+        let task = &task_file.tasks[name];
+
+        let environment = environment(task, file_environment).map_err(|error| {
+            SealedServicesError::FailedToRunUserCommand(format!("Task {name} {error}."), None)
+        })?;
+
+        let image = image_name(
+            &previous_image,
+            docker_repo,
+            task_file,
+            task,
+            input_files_hashes.get(name).map_or("", String::as_str),
+            &environment,
+            hash_algorithm,
+        );
+
+        caching = caching && task.cache && !no_cache.applies_to(name);
+        let status = if caching && existing_images.image_exists(docker_cli, &image, interrupted)? {
+            TaskStatus::Cached
+        } else {
+            TaskStatus::Run
+        };
+
+        plan.push(PlannedTask {
+            name: name.to_owned(),
+            image: image.clone(),
+            status,
+        });
+        previous_image = image;
+    }
+
+    Ok(plan)
+}
+
+// Render a plan as a numbered list, one task per line.
+pub fn format_plan(plan: &[PlannedTask]) -> String {
+    let mut output = String::new();
+    for (index, task) in plan.iter().enumerate() {
+        let status = match task.status {
+            TaskStatus::Cached => "cached",
+            TaskStatus::Run => "run",
+        };
+        let _ = writeln!(
+            output,
+            "{}. {} ({}) {}",
+            index + 1,
+            task.name,
+            status,
+            task.image,
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use sealed_database::taskfile::parse;
+
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    const TASK_FILE: &str = r"
+image: alpine:3.20
+tasks:
+  install:
+    command: apk add make
+  build:
+    dependencies:
+      - install
+    command: make build
+  test:
+    dependencies:
+      - build
+    command: make test
+";
+
+    // A Docker CLI for which only the given images exist.
+    fn docker_with_images(images: &[&str]) -> FakeDocker {
+        let mut script = String::from("if [ \"$1 $2\" = \"image inspect\" ]; then\n");
+        for image in images {
+            script.push_str(&format!("  [ \"$3\" = \"{image}\" ] && exit 0\n"));
+        }
+        script.push_str("  exit 1\nfi");
+        FakeDocker::new(&script)
+    }
+
+    #[test]
+    fn test_schedule_honors_dependencies() {
+        let task_file = parse(TASK_FILE).unwrap();
+        assert_eq!(
+            schedule(&task_file, &["test"]),
+            vec!["install", "build", "test"],
+        );
+        assert_eq!(schedule(&task_file, &["build"]), vec!["install", "build"]);
+    }
+
+    #[test]
+    fn test_schedule_default_tasks_in_order() {
+        let task_file = parse(&format!("default: [test, install]\n{TASK_FILE}")).unwrap();
+        assert_eq!(schedule(&task_file, &[]), vec!["install", "build", "test"]);
+
+        let task_file = parse(
+            r"
+image: alpine:3.20
+default: [lint, build]
+tasks:
+  install: {}
+  lint:
+    dependencies: [install]
+  build:
+    dependencies: [install]
+",
+        )
+        .unwrap();
+        assert_eq!(schedule(&task_file, &[]), vec!["install", "lint", "build"],);
+    }
+
+    #[test]
+    fn test_explain_mixed_cache() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        // Find out the image names with nothing cached.
+        let uncached = explain(
+            docker_with_images(&[]).cli(),
+            "sealed",
+            &task_file,
+            &["test"],
+            &HashMap::new(),
+            &HashMap::new(),
+            &NoCache::None,
+            &ExistingImages::default(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+        assert!(uncached.iter().all(|task| task.status == TaskStatus::Run));
+
+        // With the first two images present, only the last task runs.
+        let docker = docker_with_images(&[uncached[0].image.as_str(), uncached[1].image.as_str()]);
+        let plan = explain(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["test"],
+            &HashMap::new(),
+            &HashMap::new(),
+            &NoCache::None,
+            &ExistingImages::default(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.iter()
+                .map(|task| (task.name.as_str(), task.status))
+                .collect::<Vec<_>>(),
+            vec![
+                ("install", TaskStatus::Cached),
+                ("build", TaskStatus::Cached),
+                ("test", TaskStatus::Run),
+            ],
+        );
+        assert!(docker
+            .calls()
+            .iter()
+            .all(|call| call.starts_with("image inspect")));
+        assert!(format_plan(&plan).starts_with("1. install (cached) sealed:task-"));
+    }
+
+    #[test]
+    fn test_explain_shares_existing_images() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let docker = docker_with_images(&[]);
+        let existing_images = ExistingImages::default();
+
+        for _ in 0..2 {
+            explain(
+                docker.cli(),
+                "sealed",
+                &task_file,
+                &["test"],
+                &HashMap::new(),
+                &HashMap::new(),
+                &NoCache::None,
+                &existing_images,
+                HashAlgorithm::default(),
+                &interrupted,
+            )
+            .unwrap();
+        }
+
+        // The base image is resolved each time, but each task image is only looked up once.
+        let lookups = docker
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("image inspect sealed:"))
+            .collect::<Vec<_>>();
+        assert_eq!(lookups.len(), 3, "{lookups:#?}");
+    }
+
+    #[test]
+    fn test_explain_seeds_cache_keys_with_base_image_id() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let plan_with_base_image_id = |id: &str| {
+            let docker = FakeDocker::new(&format!(
+                "[ \"$3\" = \"--format\" ] && echo {id} && exit 0\nexit 1"
+            ));
+            explain(
+                docker.cli(),
+                "sealed",
+                &task_file,
+                &["install"],
+                &HashMap::new(),
+                &HashMap::new(),
+                &NoCache::None,
+                &ExistingImages::default(),
+                HashAlgorithm::default(),
+                &interrupted,
+            )
+            .unwrap()
+        };
+
+        assert_ne!(
+            plan_with_base_image_id("sha256:0123")[0].image,
+            plan_with_base_image_id("sha256:4567")[0].image,
+        );
+    }
+
+    #[test]
+    fn test_explain_uncached_task() {
+        let task_file = parse(&TASK_FILE.replace(
+            "    command: make build",
+            "    command: make build\n    cache: false",
+        ))
+        .unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let uncached = explain(
+            docker_with_images(&[]).cli(),
+            "sealed",
+            &task_file,
+            &["test"],
+            &HashMap::new(),
+            &HashMap::new(),
+            &NoCache::None,
+            &ExistingImages::default(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+
+        // Every image is present, but `build` disables the cache, so it and `test` have to run.
+        let images = uncached
+            .iter()
+            .map(|task| task.image.as_str())
+            .collect::<Vec<_>>();
+        let plan = explain(
+            docker_with_images(&images).cli(),
+            "sealed",
+            &task_file,
+            &["test"],
+            &HashMap::new(),
+            &HashMap::new(),
+            &NoCache::None,
+            &ExistingImages::default(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.iter().map(|task| task.status).collect::<Vec<_>>(),
+            vec![TaskStatus::Cached, TaskStatus::Run, TaskStatus::Run],
+        );
+    }
+
+    #[test]
+    fn test_explain_no_cache_override() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let plan_with = |docker: &FakeDocker, no_cache: &NoCache| {
+            explain(
+                docker.cli(),
+                "sealed",
+                &task_file,
+                &["test"],
+                &HashMap::new(),
+                &HashMap::new(),
+                no_cache,
+                &ExistingImages::default(),
+                HashAlgorithm::default(),
+                &interrupted,
+            )
+            .unwrap()
+        };
+
+        let uncached = plan_with(&docker_with_images(&[]), &NoCache::None);
+        let images = uncached
+            .iter()
+            .map(|task| task.image.as_str())
+            .collect::<Vec<_>>();
+        let docker = docker_with_images(&images);
+        let statuses =
+            |plan: Vec<PlannedTask>| plan.iter().map(|task| task.status).collect::<Vec<_>>();
+
+        // Everything is cached normally.
+        assert_eq!(
+            statuses(plan_with(&docker, &NoCache::None)),
+            vec![TaskStatus::Cached, TaskStatus::Cached, TaskStatus::Cached],
+        );
+
+        // Rebuilding `build` rebuilds the tasks after it too.
+        assert_eq!(
+            statuses(plan_with(
+                &docker,
+                &NoCache::Tasks(HashSet::from(["build".to_owned()])),
+            )),
+            vec![TaskStatus::Cached, TaskStatus::Run, TaskStatus::Run],
+        );
+
+        // The images aren't even looked up when every task is rebuilt.
+        let docker = docker_with_images(&images);
+        let plan = plan_with(&docker, &NoCache::All);
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Run));
+        assert_eq!(plan[0].image, uncached[0].image);
+        assert_eq!(
+            docker
+                .calls()
+                .iter()
+                .filter(|call| !call.contains("--format"))
+                .count(),
+            0,
+        );
+    }
+}
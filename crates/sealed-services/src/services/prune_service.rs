@@ -0,0 +1,180 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use console::style;
+use sealed_common::debug;
+
+use crate::{
+    docker_service::{delete_image, list_images, running_container_images, ImageSummary},
+    error::SealedServicesResult,
+};
+
+// Task images are tagged `repo:task-<cache key>` (see `task_image`).
+pub(crate) const TASK_TAG_PREFIX: &str = "task-";
+
+// Whether any of the running containers uses the image. Containers may refer to their image by
+// reference, by full ID, or by a prefix of the ID.
+pub(crate) fn in_use(image: &ImageSummary, running: &HashSet<String>) -> bool {
+    let id = image.id.trim_start_matches("sha256:");
+    running.contains(&image.reference())
+        || running.iter().any(|used| {
+            let used = used.trim_start_matches("sha256:");
+            !used.is_empty() && id.starts_with(used)
+        })
+}
+
+// Choose the images to delete. `images` must be ordered from most to least recently used, as
+// `docker images` does. The first `keep` images of each repository are kept, as is any image used
+// by a running container (which still counts towards `keep`).
+pub fn select_images_to_prune<'a>(
+    images: &'a [ImageSummary],
+    keep: usize,
+    running: &HashSet<String>,
+) -> Vec<&'a ImageSummary> {
+    let mut seen = HashMap::<&str, usize>::new();
+
+    images
+        .iter()
+        .filter(|image| {
+            let count = seen.entry(image.repository.as_str()).or_insert(0);
+            *count += 1;
+            *count > keep && !in_use(image, running)
+        })
+        .collect()
+}
+
+// Delete all but the `keep` most recently used task images of each repository. Returns the
+// references of the images which were deleted, or which would have been if `dry_run` is set.
+pub fn prune(
+    docker_cli: &str,
+    keep: usize,
+    dry_run: bool,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<String>> {
+    let images = list_images(docker_cli, TASK_TAG_PREFIX, interrupted)?;
+    let running = running_container_images(docker_cli, interrupted)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut pruned = vec![];
+    for image in select_images_to_prune(&images, keep, &running) {
+        let reference = image.reference();
+        if dry_run {
+            debug!("Would prune {}", style(&reference).bold().dim());
+        } else {
+            debug!("Pruning {}", style(&reference).bold().dim());
+            delete_image(docker_cli, &reference, interrupted)?;
+        }
+        pruned.push(reference);
+    }
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    fn image(repository: &str, tag: &str, id: &str) -> ImageSummary {
+        ImageSummary {
+            repository: repository.to_owned(),
+            tag: tag.to_owned(),
+            id: id.to_owned(),
+        }
+    }
+
+    fn references(images: &[&ImageSummary]) -> Vec<String> {
+        images.iter().map(|image| image.reference()).collect()
+    }
+
+    #[test]
+    fn test_select_keeps_most_recent_per_repository() {
+        let images = vec![
+            image("sealed", "task-03", "sha256:03"),
+            image("encom", "task-b2", "sha256:b2"),
+            image("sealed", "task-02", "sha256:02"),
+            image("sealed", "task-01", "sha256:01"),
+            image("encom", "task-b1", "sha256:b1"),
+        ];
+
+        assert_eq!(
+            references(&select_images_to_prune(&images, 1, &HashSet::new())),
+            vec!["sealed:task-02", "sealed:task-01", "encom:task-b1"],
+        );
+        assert_eq!(
+            references(&select_images_to_prune(&images, 2, &HashSet::new())),
+            vec!["sealed:task-01"],
+        );
+        assert!(select_images_to_prune(&images, 3, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn test_select_skips_images_in_use() {
+        let images = vec![
+            image("sealed", "task-03", "sha256:0303"),
+            image("sealed", "task-02", "sha256:0202"),
+            image("sealed", "task-01", "sha256:0101"),
+        ];
+
+        // By reference
+        let running = HashSet::from(["sealed:task-02".to_owned()]);
+        assert_eq!(
+            references(&select_images_to_prune(&images, 0, &running)),
+            vec!["sealed:task-03", "sealed:task-01"],
+        );
+
+        // By (abbreviated) ID
+        let running = HashSet::from(["01".to_owned()]);
+        assert_eq!(
+            references(&select_images_to_prune(&images, 0, &running)),
+            vec!["sealed:task-03", "sealed:task-02"],
+        );
+    }
+
+    // `images` prints the image list and `ps` reports a container using `sealed:task-01`.
+    fn docker_with_images() -> FakeDocker {
+        FakeDocker::new(
+            r"case $1 in
+  images)
+    printf 'sealed\ttask-03\tsha256:03\n'
+    printf 'sealed\ttask-02\tsha256:02\n'
+    printf 'sealed\ttask-01\tsha256:01\n'
+    ;;
+  ps) echo sealed:task-01 ;;
+esac",
+        )
+    }
+
+    #[test]
+    fn test_prune() {
+        let docker = docker_with_images();
+
+        let pruned = prune(docker.cli(), 1, false, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        assert_eq!(pruned, vec!["sealed:task-02"]);
+        assert_eq!(
+            docker
+                .calls()
+                .iter()
+                .filter(|call| call.starts_with("image rm"))
+                .collect::<Vec<_>>(),
+            vec!["image rm --force sealed:task-02"],
+        );
+    }
+
+    #[test]
+    fn test_prune_dry_run() {
+        let docker = docker_with_images();
+
+        let pruned = prune(docker.cli(), 1, true, &Arc::new(AtomicBool::new(false))).unwrap();
+
+        assert_eq!(pruned, vec!["sealed:task-02"]);
+        assert!(!docker
+            .calls()
+            .iter()
+            .any(|call| call.starts_with("image rm")));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use console::style;
+use s3::{creds::Credentials, Bucket, Region};
+use sealed_common::{debug, settings::RemoteCacheSettings, warn};
+use tempfile::tempdir;
+
+use crate::{
+    docker_service::{load_image, save_image},
+    error::{SealedServicesError, SealedServicesResult},
+};
+
+// A store for task images shared between machines, keyed by the cache key of a task (see
+// `image_name` in `sealed-database`). The values are `docker image save` tarballs, which can be
+// large, so they're streamed rather than held in memory.
+pub trait RemoteCache {
+    // Write the value stored under `key` to `value` as it's downloaded. Returns whether there was
+    // one; if not, `value` may have been written to anyway.
+    fn get(&self, key: &str, value: &mut (dyn Write + Send)) -> SealedServicesResult<bool>;
+
+    // Store `value` under `key` as it's read.
+    fn put(&self, key: &str, value: &mut dyn Read) -> SealedServicesResult<()>;
+}
+
+// A remote cache backed by an S3-compatible bucket.
+pub struct S3Cache {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Cache {
+    pub fn new(settings: &RemoteCacheSettings) -> SealedServicesResult<Self> {
+        let region = match &settings.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: settings.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => settings.region.parse().map_err(|error| {
+                SealedServicesError::System(
+                    format!("Invalid region {}.", settings.region),
+                    Some(Box::new(error)),
+                )
+            })?,
+        };
+
+        let credentials = match (&settings.access_key_id, &settings.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Credentials::new(
+                Some(access_key_id),
+                Some(secret_access_key),
+                None,
+                None,
+                None,
+            ),
+            _ => Credentials::default(),
+        }
+        .map_err(|error| {
+            SealedServicesError::System(
+                "Unable to load credentials for the remote cache.".to_owned(),
+                Some(Box::new(error)),
+            )
+        })?;
+
+        let mut bucket = Bucket::new(&settings.bucket, region, credentials).map_err(|error| {
+            SealedServicesError::System(
+                format!("Unable to open bucket {}.", settings.bucket),
+                Some(Box::new(error)),
+            )
+        })?;
+
+        // Custom endpoints are usually self-hosted stores, which rarely support virtual-hosted
+        // style requests.
+        if settings.endpoint.is_some() {
+            bucket = bucket.with_path_style();
+        }
+
+        Ok(S3Cache {
+            bucket,
+            prefix: settings.prefix.clone(),
+        })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("{}{}.tar", self.prefix, key)
+    }
+}
+
+impl RemoteCache for S3Cache {
+    fn get(&self, key: &str, value: &mut (dyn Write + Send)) -> SealedServicesResult<bool> {
+        let path = self.object_path(key);
+        let status = self
+            .bucket
+            .get_object_to_writer(&path, value)
+            .map_err(|error| {
+                SealedServicesError::System(
+                    format!("Unable to fetch {} from the remote cache.", path),
+                    Some(Box::new(error)),
+                )
+            })?;
+
+        match status {
+            200 => Ok(true),
+            404 => Ok(false),
+            status => Err(SealedServicesError::System(
+                format!(
+                    "Unable to fetch {} from the remote cache (status {}).",
+                    path, status
+                ),
+                None,
+            )),
+        }
+    }
+
+    // Large values are uploaded in parts, so only one part is held in memory at a time.
+    fn put(&self, key: &str, mut value: &mut dyn Read) -> SealedServicesResult<()> {
+        let path = self.object_path(key);
+        let status = self
+            .bucket
+            .put_object_stream(&mut value, &path)
+            .map_err(|error| {
+                SealedServicesError::System(
+                    format!("Unable to store {} in the remote cache.", path),
+                    Some(Box::new(error)),
+                )
+            })?;
+
+        match status {
+            200..=299 => Ok(()),
+            status => Err(SealedServicesError::System(
+                format!(
+                    "Unable to store {} in the remote cache (status {}).",
+                    path, status
+                ),
+                None,
+            )),
+        }
+    }
+}
+
+// The key for a task image in the remote cache. Task images are named `repo:task-<cache key>`, and
+// only the cache key identifies the contents.
+fn cache_key(image: &str) -> &str {
+    image
+        .rsplit_once(":task-")
+        .map_or(image, |(_, cache_key)| cache_key)
+}
+
+// Try to load a task image from the remote cache. Returns whether the image was loaded, in which case
+// the caller can skip creating and committing a container for the task. The remote cache is only an
+// optimization, so errors talking to it are logged and treated as a miss.
+pub fn fetch_task_image(
+    docker_cli: &str,
+    remote_cache: Option<&dyn RemoteCache>,
+    image: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<bool> {
+    let Some(remote_cache) = remote_cache else {
+        return Ok(false);
+    };
+
+    // The image is downloaded straight to disk.
+    let temp_dir = tempdir()?;
+    let tarball = temp_dir.path().join("image.tar");
+    match remote_cache.get(cache_key(image), &mut File::create(&tarball)?) {
+        Ok(true) => {}
+        Ok(false) => return Ok(false),
+        Err(error) => {
+            warn!("Unable to use the remote cache: {}", error);
+            return Ok(false);
+        }
+    }
+
+    debug!(
+        "Found image {} in the remote cache",
+        style(image).bold().dim()
+    );
+
+    match load_image(docker_cli, &tarball, interrupted) {
+        Ok(()) => Ok(true),
+        Err(SealedServicesError::Interrupted) => Err(SealedServicesError::Interrupted),
+        Err(error) => {
+            warn!("Unable to load image from the remote cache: {}", error);
+            Ok(false)
+        }
+    }
+}
+
+// Upload a task image to the remote cache so other machines can skip building it. Like
+// `fetch_task_image`, this never fails because of the remote cache itself.
+pub fn publish_task_image(
+    docker_cli: &str,
+    remote_cache: Option<&dyn RemoteCache>,
+    image: &str,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    let Some(remote_cache) = remote_cache else {
+        return Ok(());
+    };
+
+    let temp_dir = tempdir()?;
+    let tarball = temp_dir.path().join("image.tar");
+    save_image(docker_cli, image, &tarball, interrupted)?;
+
+    if let Err(error) = remote_cache.put(cache_key(image), &mut File::open(&tarball)?) {
+        warn!("Unable to upload to the remote cache: {}", error);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeDocker, MemoryCache};
+
+    const IMAGE: &str = "sealed:task-0123abcd";
+
+    // A remote cache which can't be reached.
+    struct OfflineCache;
+
+    impl RemoteCache for OfflineCache {
+        fn get(&self, _key: &str, _value: &mut (dyn Write + Send)) -> SealedServicesResult<bool> {
+            Err(SealedServicesError::System(
+                "Network is down.".to_owned(),
+                None,
+            ))
+        }
+
+        fn put(&self, _key: &str, _value: &mut dyn Read) -> SealedServicesResult<()> {
+            Err(SealedServicesError::System(
+                "Network is down.".to_owned(),
+                None,
+            ))
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key(IMAGE), "0123abcd");
+        assert_eq!(cache_key("alpine:3.20"), "alpine:3.20");
+    }
+
+    #[test]
+    fn test_fetch_hit_loads_image() {
+        let docker = FakeDocker::new("");
+        let cache = MemoryCache::default();
+        cache
+            .objects
+            .borrow_mut()
+            .insert("0123abcd".to_owned(), b"tarball".to_vec());
+
+        assert!(fetch_task_image(
+            docker.cli(),
+            Some(&cache),
+            IMAGE,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap());
+
+        let calls = docker.calls();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].starts_with("image load --input "));
+    }
+
+    #[test]
+    fn test_fetch_miss() {
+        let docker = FakeDocker::new("");
+
+        assert!(!fetch_task_image(
+            docker.cli(),
+            Some(&MemoryCache::default()),
+            IMAGE,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap());
+        assert!(docker.calls().is_empty());
+    }
+
+    #[test]
+    fn test_publish_then_fetch() {
+        let docker =
+            FakeDocker::new(r#"if [ "$1 $2" = "image save" ]; then echo tarball > "$4"; fi"#);
+        let cache = MemoryCache::default();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        publish_task_image(docker.cli(), Some(&cache), IMAGE, &interrupted).unwrap();
+        assert_eq!(cache.objects.borrow()["0123abcd"], b"tarball\n");
+
+        assert!(fetch_task_image(docker.cli(), Some(&cache), IMAGE, &interrupted).unwrap());
+    }
+
+    #[test]
+    fn test_network_errors_degrade_to_local() {
+        let docker =
+            FakeDocker::new(r#"if [ "$1 $2" = "image save" ]; then echo tarball > "$4"; fi"#);
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        assert!(!fetch_task_image(docker.cli(), Some(&OfflineCache), IMAGE, &interrupted).unwrap());
+        publish_task_image(docker.cli(), Some(&OfflineCache), IMAGE, &interrupted).unwrap();
+    }
+
+    // Answers each connection with the next of `responses`, and returns the address to reach it
+    // at and the requests it received.
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::{io::BufRead, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                requests.push(request_line.trim().to_owned());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn test_s3_get_streams_to_writer() {
+        let (endpoint, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\ntarball",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let cache = S3Cache::new(&RemoteCacheSettings {
+            bucket: "grid".to_owned(),
+            prefix: "tasks/".to_owned(),
+            region: "us-east-1".to_owned(),
+            endpoint: Some(endpoint),
+            access_key_id: Some("flynn".to_owned()),
+            secret_access_key: Some("reindeer flotilla".to_owned()),
+        })
+        .unwrap();
+
+        let mut tarball = vec![];
+        assert!(cache.get("0123abcd", &mut tarball).unwrap());
+        assert_eq!(tarball, b"tarball");
+        assert!(!cache.get("4567ef01", &mut vec![]).unwrap());
+
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "GET /grid/tasks/0123abcd.tar HTTP/1.1",
+                "GET /grid/tasks/4567ef01.tar HTTP/1.1",
+            ],
+        );
+    }
+}
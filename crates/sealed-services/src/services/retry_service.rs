@@ -0,0 +1,318 @@
+use std::{
+    cmp::min,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use sealed_common::{debug, error::SealedError, settings::Settings};
+
+// Fragments of Docker error output which indicate the failure is worth retrying. These are matched
+// case-insensitively.
+const RETRYABLE_PATTERNS: &[&str] = &[
+    "toomanyrequests",
+    "too many requests",
+    "tls handshake timeout",
+    "i/o timeout",
+    "timed out",
+    "timeout exceeded",
+    "connection reset",
+    "connection refused",
+    "temporary failure in name resolution",
+    "unexpected eof",
+    "502 bad gateway",
+    "503 service unavailable",
+    "504 gateway timeout",
+];
+
+// How often a sleeping retry loop checks whether the user interrupted the program.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // The number of retries after the first attempt
+    pub max_retries: u32,
+    // The delay before the first retry. It doubles after every retry, up to `max_delay`.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Never retry.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        RetryPolicy {
+            max_retries: settings.docker_retries,
+            ..RetryPolicy::default()
+        }
+    }
+}
+
+// Determine whether an error is transient, such as a registry rate limit or a network hiccup.
+// Everything else (e.g., `manifest unknown`) is considered permanent.
+pub fn is_retryable(error: &SealedError) -> bool {
+    let message = match error {
+        SealedError::System(message, _) | SealedError::FailedToRunUserCommand(message, _) => {
+            message.to_lowercase()
+        }
+        _ => return false,
+    };
+
+    RETRYABLE_PATTERNS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+// Run `operation` until it succeeds, fails with an error which isn't retryable, or runs out of
+// retries. The delays between attempts grow exponentially. If the user interrupts the program, the
+// loop stops immediately.
+pub fn with_retries<T, F: FnMut() -> Result<T, SealedError>>(
+    policy: &RetryPolicy,
+    interrupted: &Arc<AtomicBool>,
+    mut operation: F,
+) -> Result<T, SealedError> {
+    let mut delay = policy.initial_delay;
+    let mut retries = 0;
+
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(error) if retries < policy.max_retries && is_retryable(&error) => {
+                retries += 1;
+                debug!(
+                    "Retrying ({}/{}) in {:?} after a transient failure: {}",
+                    retries, policy.max_retries, delay, error,
+                );
+                sleep_unless_interrupted(delay, interrupted)?;
+                delay = min(delay * 2, policy.max_delay);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Run a task's command, running it again up to `retries` more times if it fails. Unlike
+// `with_retries`, every failure except an interruption is retried, since flaky tasks fail in ways we
+// can't recognize. The delay between attempts is constant. If every attempt fails, the last error is
+// returned along with the number of attempts.
+pub fn with_task_retries<T, F: FnMut() -> Result<T, SealedError>>(
+    retries: u32,
+    delay: Duration,
+    interrupted: &Arc<AtomicBool>,
+    mut operation: F,
+) -> Result<T, SealedError> {
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(SealedError::Interrupted) => return Err(SealedError::Interrupted),
+            Err(error) if attempts <= retries => {
+                debug!(
+                    "Running the task again ({}/{}) in {:?} after a failure: {}",
+                    attempts, retries, delay, error,
+                );
+                sleep_unless_interrupted(delay, interrupted)?;
+            }
+            Err(error) if retries == 0 => return Err(error),
+            Err(error) => return Err(with_attempts(error, attempts)),
+        }
+    }
+}
+
+// Note the number of attempts in an error message.
+fn with_attempts(error: SealedError, attempts: u32) -> SealedError {
+    let note = format!("(Failed after {attempts} attempts.)");
+    match error {
+        SealedError::TaskExited { message, code } => SealedError::TaskExited {
+            message: format!("{message} {note}"),
+            code,
+        },
+        SealedError::FailedToRunUserCommand(message, source) => {
+            SealedError::FailedToRunUserCommand(format!("{message} {note}"), source)
+        }
+        SealedError::System(message, source) => {
+            SealedError::System(format!("{message} {note}"), source)
+        }
+        error => error,
+    }
+}
+
+fn sleep_unless_interrupted(
+    duration: Duration,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<(), SealedError> {
+    let deadline = Instant::now() + duration;
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            return Err(SealedError::Interrupted);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(());
+        }
+        sleep(min(deadline - now, INTERRUPT_POLL_INTERVAL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    fn failure(stderr: &str) -> SealedError {
+        SealedError::System(format!("Unable to pull image.\n{}", stderr), None)
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&failure(
+            "Error response from daemon: toomanyrequests: You have reached your pull rate limit."
+        )));
+        assert!(is_retryable(&failure("net/http: TLS handshake timeout")));
+        assert!(is_retryable(&failure("read: connection reset by peer")));
+        assert!(!is_retryable(&failure(
+            "Error response from daemon: manifest unknown"
+        )));
+        assert!(!is_retryable(&SealedError::Interrupted));
+    }
+
+    #[test]
+    fn test_fails_twice_then_succeeds() {
+        let mut attempts = 0;
+        let result = with_retries(&fast_policy(), &Arc::new(AtomicBool::new(false)), || {
+            attempts += 1;
+            if attempts <= 2 {
+                Err(failure("toomanyrequests"))
+            } else {
+                Ok("pulled")
+            }
+        });
+
+        assert_eq!(result.unwrap(), "pulled");
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_permanent_failure_fails_fast() {
+        let mut attempts = 0;
+        let result: Result<(), _> =
+            with_retries(&fast_policy(), &Arc::new(AtomicBool::new(false)), || {
+                attempts += 1;
+                Err(failure("manifest unknown"))
+            });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let mut attempts = 0;
+        let result: Result<(), _> =
+            with_retries(&fast_policy(), &Arc::new(AtomicBool::new(false)), || {
+                attempts += 1;
+                Err(failure("i/o timeout"))
+            });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+    }
+
+    #[test]
+    fn test_interrupt_aborts_retries() {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let mut attempts = 0;
+        let result: Result<(), _> = with_retries(&fast_policy(), &interrupted, || {
+            attempts += 1;
+            interrupted.store(true, Ordering::SeqCst);
+            Err(failure("i/o timeout"))
+        });
+
+        assert!(matches!(result, Err(SealedError::Interrupted)));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_task_retries_stop_at_first_success() {
+        let mut attempts = 0;
+        let result =
+            with_task_retries(5, Duration::ZERO, &Arc::new(AtomicBool::new(false)), || {
+                attempts += 1;
+                if attempts < 2 {
+                    Err(SealedError::TaskExited {
+                        message: "Task failed.".to_owned(),
+                        code: 1,
+                    })
+                } else {
+                    Ok("passed")
+                }
+            });
+
+        assert_eq!(result.unwrap(), "passed");
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_task_retries_respect_cap() {
+        let mut attempts = 0;
+        let result: Result<(), _> =
+            with_task_retries(2, Duration::ZERO, &Arc::new(AtomicBool::new(false)), || {
+                attempts += 1;
+                Err(SealedError::TaskExited {
+                    message: "Task failed.".to_owned(),
+                    code: 1,
+                })
+            });
+
+        assert_eq!(attempts, 3);
+        match result {
+            Err(SealedError::TaskExited { message, code }) => {
+                assert_eq!(message, "Task failed. (Failed after 3 attempts.)");
+                assert_eq!(code, 1);
+            }
+            _ => panic!("expected the task to fail"),
+        }
+    }
+
+    #[test]
+    fn test_task_retries_not_after_interrupt() {
+        let mut attempts = 0;
+        let result: Result<(), _> =
+            with_task_retries(2, Duration::ZERO, &Arc::new(AtomicBool::new(false)), || {
+                attempts += 1;
+                Err(SealedError::Interrupted)
+            });
+
+        assert!(matches!(result, Err(SealedError::Interrupted)));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,1223 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Seek, SeekFrom},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use console::style;
+use sealed_common::{
+    cache::{hash_manifest_path, HashAlgorithm, HashManifest},
+    debug,
+    error::SealedError,
+    events::{AppPublisher, TaskStatus as TaskEventStatus},
+    info,
+    settings::Settings,
+    tar::{create, Compression, Compressor},
+};
+use sealed_database::taskfile::{
+    command, environment, location, retry_delay, shell, user, TaskFile,
+};
+use tempfile::tempfile;
+
+use crate::{
+    docker_service::{
+        copy_from_container, copy_into_container, create_container, delete_container, new_run_id,
+        run_container, ContainerLabels, ExistingImages,
+    },
+    error::{SealedServicesError, SealedServicesResult},
+    image_cache_service::{pull_image_cached, ImageCache},
+    kept_container_service::release_container,
+    plan_service::{explain, schedule, NoCache, PlannedTask, TaskStatus},
+    remote_cache_service::{fetch_task_image, publish_task_image, RemoteCache},
+    retry_service::RetryPolicy,
+};
+
+// The input paths of a task, archived and ready to copy into its container.
+struct Inputs {
+    archive: File,
+    hash: String,
+}
+
+// Archive the input paths of each task in `names`, working on up to `jobs` tasks at a time. Tasks
+// without input paths get no archive, which is how `explain` treats them too. With
+// `hash_manifests`, the hashes of each task's input files are kept in that directory, so unchanged
+// files aren't hashed again by the next run. The files are hashed with `hash_algorithm`, and the
+// archives are compressed with `compression`.
+#[allow(clippy::too_many_arguments)]
+fn collect_inputs(
+    task_file: &TaskFile,
+    names: &[&str],
+    source_dir: &Path,
+    hash_manifests: Option<&Path>,
+    hash_algorithm: HashAlgorithm,
+    compression: Compression,
+    jobs: usize,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<HashMap<String, Inputs>> {
+    let names = names
+        .iter()
+        .copied()
+        .filter(|name| !task_file.tasks[*name].input_paths.is_empty())
+        .collect::<Vec<_>>();
+    let next = AtomicUsize::new(0);
+    let jobs = jobs.clamp(1, names.len().max(1));
+
+    let results = thread::scope(|scope| {
+        let workers = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    while !interrupted.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(name) = names.get(index) else {
+                            break;
+                        };
+                        let task = &task_file.tasks[*name];
+                        let manifest_path =
+                            hash_manifests.map(|directory| hash_manifest_path(directory, name));
+                        let mut manifest = manifest_path
+                            .as_deref()
+                            .map(|path| HashManifest::load(path, hash_algorithm));
+                        let archived = (|| {
+                            let compressor = Compressor::new(tempfile()?, compression)?;
+                            let (compressor, hash) = create(
+                                &format!("Archiving the inputs of {name}\u{2026}"),
+                                compressor,
+                                &task.input_paths,
+                                &task.excluded_input_paths,
+                                source_dir,
+                                &location(task_file, task),
+                                manifest.as_mut(),
+                                hash_algorithm,
+                                interrupted,
+                            )?;
+                            Ok::<_, SealedError>((compressor.finish()?, hash))
+                        })();
+                        if let (Some(path), Some(manifest), Ok(_)) =
+                            (&manifest_path, &manifest, &archived)
+                        {
+                            // Losing the manifest only costs time on the next run.
+                            if let Err(error) = manifest.save(path) {
+                                debug!("Unable to save the input hashes of {name}: {error}");
+                            }
+                        }
+                        results.push((index, archived));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("An archiving thread panicked."))
+            .collect::<Vec<_>>()
+    });
+
+    if interrupted.load(Ordering::SeqCst) {
+        return Err(SealedServicesError::Interrupted);
+    }
+
+    // Report the error for the first failing task, so the error doesn't depend on scheduling.
+    let mut results = results;
+    results.sort_by_key(|(index, _)| *index);
+    let mut inputs = HashMap::new();
+    for (index, result) in results {
+        let (archive, hash) = result.map_err(|error| {
+            SealedServicesError::System(
+                format!(
+                    "Unable to archive the inputs of task {}: {error}",
+                    names[index]
+                ),
+                None,
+            )
+        })?;
+        inputs.insert(names[index].to_owned(), Inputs { archive, hash });
+    }
+
+    Ok(inputs)
+}
+
+// Pull the base image unless it's already present. Docker would pull it when the first container
+// is created, but without retrying transient registry failures, and the plan is computed before
+// that from the ID of the base image, which is only known once it's been pulled.
+fn pull_base_image(
+    docker_cli: &str,
+    image: &str,
+    settings: &Settings,
+    existing_images: &ExistingImages,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    if existing_images.image_exists(docker_cli, image, interrupted)? {
+        return Ok(());
+    }
+    pull_image_cached(
+        docker_cli,
+        image,
+        ImageCache::from_settings(settings).as_ref(),
+        &RetryPolicy::from_settings(settings),
+        interrupted,
+    )
+}
+
+// A cached task isn't run, so its outputs are copied out of its image instead, through a container
+// which is created but never started.
+fn copy_cached_outputs(
+    docker_cli: &str,
+    task_file: &TaskFile,
+    planned: &PlannedTask,
+    run_id: &str,
+    source_dir: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    let task = &task_file.tasks[&planned.name];
+    if task.output_paths.is_empty() {
+        return Ok(());
+    }
+
+    let location = location(task_file, task);
+    let container = create_container(
+        docker_cli,
+        &planned.image,
+        source_dir,
+        &HashMap::new(),
+        &[],
+        false,
+        &[],
+        &location,
+        &user(task_file, task),
+        shell(task),
+        &command(task_file, task),
+        &task.command_args,
+        &ContainerLabels {
+            task: &planned.name,
+            run_id,
+        },
+        &[],
+        interrupted,
+    )?;
+    let copied = copy_from_container(
+        docker_cli,
+        &container,
+        &task.output_paths,
+        &location,
+        source_dir,
+        interrupted,
+    );
+    let deleted = delete_container(docker_cli, &container, interrupted);
+    copied?;
+    deleted
+}
+
+// Run `roots` and their dependencies, each after the tasks it depends on. A task runs in a
+// container created from the image the task before it left behind: its inputs are copied in, its
+// command is run, its outputs are copied out to `source_dir`, and the container is committed to the
+// task's image. Tasks whose image already exists aren't run, but their outputs are still copied
+// out of the image. Tasks run one at a time; `jobs` is how many tasks' inputs are archived at once
+// beforehand. `extra_args` are passed to Docker for
+// every task which runs. Input file hashes are kept in `hash_manifests`, if given, as for
+// `collect_inputs`. Tasks chosen by `no_cache` run even if their image exists. The base image is
+// pulled (through the image cache) if necessary, and inputs are compressed, according to
+// `settings`. Cacheable tasks whose image isn't present locally are fetched from `remote_cache`, if
+// given, instead of being run, and the images of those which run are published to it. With
+// `keep_failed`, the container of a failing task is kept for debugging and recorded in
+// `kept_containers`. The lifecycle of each task is published to `events`, if given. Returns the
+// plan which was carried out.
+#[allow(clippy::too_many_arguments)]
+pub fn run_tasks(
+    docker_cli: &str,
+    docker_repo: &str,
+    task_file: &TaskFile,
+    roots: &[&str],
+    source_dir: &Path,
+    hash_manifests: Option<&Path>,
+    file_environment: &HashMap<String, String>,
+    extra_args: &[String],
+    no_cache: &NoCache,
+    settings: &Settings,
+    remote_cache: Option<&dyn RemoteCache>,
+    jobs: usize,
+    keep_failed: bool,
+    kept_containers: &Path,
+    events: Option<&AppPublisher>,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<Vec<PlannedTask>> {
+    let publish = |task: &str, status| {
+        if let Some(events) = events {
+            events.publish(task, status);
+        }
+    };
+    let compression = settings.tar_compression;
+    // Which images exist is only asked once per image for the whole run.
+    let existing_images = ExistingImages::default();
+    pull_base_image(
+        docker_cli,
+        &task_file.image,
+        settings,
+        &existing_images,
+        interrupted,
+    )?;
+
+    let mut inputs = collect_inputs(
+        task_file,
+        &schedule(task_file, roots),
+        source_dir,
+        hash_manifests,
+        settings.hash_algorithm,
+        compression,
+        jobs,
+        interrupted,
+    )?;
+    let input_files_hashes = inputs
+        .iter()
+        .map(|(name, inputs)| (name.clone(), inputs.hash.clone()))
+        .collect();
+
+    // Extra arguments can change what a task does without changing its image name, so, like a
+    // task's own `extra_docker_arguments` [ref:extra_docker_arguments_nand_cache], they disable the
+    // cache.
+    let no_cache = if extra_args.is_empty() {
+        no_cache.clone()
+    } else {
+        NoCache::All
+    };
+
+    let mut plan = explain(
+        docker_cli,
+        docker_repo,
+        task_file,
+        roots,
+        &input_files_hashes,
+        file_environment,
+        &no_cache,
+        &existing_images,
+        settings.hash_algorithm,
+        interrupted,
+    )?;
+    for planned in &plan {
+        publish(&planned.name, TaskEventStatus::Queued);
+    }
+
+    let run_id = new_run_id();
+    let mut previous_image = task_file.image.clone();
+    // As in `explain`, once a task can't be cached, neither can the tasks after it.
+    let mut caching = true;
+    for planned in &mut plan {
+        let task = &task_file.tasks[&planned.name];
+        caching = caching && task.cache && !no_cache.applies_to(&planned.name);
+
+        if planned.status == TaskStatus::Cached {
+            info!("Task {} is cached.", style(&planned.name).bold());
+            publish(&planned.name, TaskEventStatus::Cached);
+            copy_cached_outputs(
+                docker_cli,
+                task_file,
+                planned,
+                &run_id,
+                source_dir,
+                interrupted,
+            )?;
+            previous_image.clone_from(&planned.image);
+            continue;
+        }
+
+        if caching && fetch_task_image(docker_cli, remote_cache, &planned.image, interrupted)? {
+            info!("Task {} is cached remotely.", style(&planned.name).bold());
+            planned.status = TaskStatus::Cached;
+            publish(&planned.name, TaskEventStatus::Cached);
+            copy_cached_outputs(
+                docker_cli,
+                task_file,
+                planned,
+                &run_id,
+                source_dir,
+                interrupted,
+            )?;
+            previous_image.clone_from(&planned.image);
+            continue;
+        }
+
+        info!("Running task {}\u{2026}", style(&planned.name).bold());
+        publish(&planned.name, TaskEventStatus::Running);
+        let result = run_task(
+            docker_cli,
+            task_file,
+            planned,
+            &previous_image,
+            source_dir,
+            file_environment,
+            extra_args,
+            inputs.get_mut(&planned.name),
+            &existing_images,
+            &run_id,
+            keep_failed,
+            kept_containers,
+            interrupted,
+        );
+        publish(&planned.name, finished_status(&result));
+        result?;
+
+        if caching {
+            publish_task_image(docker_cli, remote_cache, &planned.image, interrupted)?;
+        }
+
+        previous_image.clone_from(&planned.image);
+    }
+
+    Ok(plan)
+}
+
+// Run `planned` in a container created from `previous_image`, copying its inputs in and its
+// outputs out, and commit the container to the task's image. The container is deleted afterwards,
+// unless the task fails and `keep_failed` asks for it to be kept.
+#[allow(clippy::too_many_arguments)]
+fn run_task(
+    docker_cli: &str,
+    task_file: &TaskFile,
+    planned: &PlannedTask,
+    previous_image: &str,
+    source_dir: &Path,
+    file_environment: &HashMap<String, String>,
+    extra_args: &[String],
+    inputs: Option<&mut Inputs>,
+    existing_images: &ExistingImages,
+    run_id: &str,
+    keep_failed: bool,
+    kept_containers: &Path,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    let task = &task_file.tasks[&planned.name];
+    let environment = environment(task, file_environment).map_err(|error| {
+        SealedServicesError::FailedToRunUserCommand(format!("Task {} {error}.", planned.name), None)
+    })?;
+    let location = location(task_file, task);
+    let mut task_extra_args = task.extra_docker_arguments.clone();
+    task_extra_args.extend(extra_args.iter().cloned());
+
+    let container = create_container(
+        docker_cli,
+        previous_image,
+        source_dir,
+        &environment,
+        &task.mount_paths,
+        task.mount_readonly,
+        &task.ports,
+        &location,
+        &user(task_file, task),
+        shell(task),
+        &command(task_file, task),
+        &task.command_args,
+        &ContainerLabels {
+            task: &planned.name,
+            run_id,
+        },
+        &task_extra_args,
+        interrupted,
+    )?;
+
+    // The container is deleted whether or not the task succeeds, unless it's kept for debugging.
+    let result = (|| {
+        if let Some(Inputs { archive, .. }) = inputs {
+            archive.seek(SeekFrom::Start(0))?;
+            copy_into_container(docker_cli, &container, &*archive, interrupted)?;
+        }
+
+        run_container(
+            docker_cli,
+            &container,
+            task.retries,
+            retry_delay(task),
+            &task.output_paths,
+            &task.output_paths_on_failure,
+            &location,
+            source_dir,
+            interrupted,
+        )?;
+
+        existing_images.commit_container(docker_cli, &container, &planned.image, interrupted)
+    })();
+    let released = release_container(
+        docker_cli,
+        &container,
+        result.is_err(),
+        keep_failed,
+        kept_containers,
+        interrupted,
+    );
+    result?;
+    released
+}
+
+// The status a task which ran is published with.
+fn finished_status(result: &SealedServicesResult<()>) -> TaskEventStatus {
+    match result {
+        Ok(()) => TaskEventStatus::Succeeded,
+        Err(SealedServicesError::TaskExited { code, .. }) => TaskEventStatus::Failed {
+            exit_code: Some(*code),
+        },
+        Err(_) => TaskEventStatus::Failed { exit_code: None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, write};
+
+    use sealed_common::{events::TaskEvents, fs_utils::make_dirs};
+    use sealed_database::taskfile::parse;
+
+    use super::*;
+    use crate::{
+        kept_container_service::{kept_containers, kept_containers_path},
+        test_utils::{FakeDocker, MemoryCache},
+    };
+
+    const TASK_FILE: &str = r"
+image: alpine:3.20
+default: greet
+tasks:
+  install:
+    input_paths:
+      - name.txt
+    command: cp name.txt greeting.txt
+  greet:
+    dependencies:
+      - install
+    output_paths:
+      - greeting.txt
+    command: sed -i 's/^/hello /' greeting.txt
+";
+
+    // Settings which keep the tests away from the user's working directory.
+    fn test_settings() -> Settings {
+        serde_yaml::from_str("ssh_key: null\nimage_cache:\n  enabled: false\n").unwrap()
+    }
+
+    // Nothing is cached, every container is called `container`, and copying a path out of the
+    // container writes `hello flynn` to the destination.
+    const DOCKER_SCRIPT: &str = r#"
+case "$1 $2" in
+  "image inspect") exit 1 ;;
+  "container create") echo container ;;
+  "container cp") [ "$3" = - ] && cat > /dev/null || echo "hello flynn" > "$4" ;;
+esac
+exit 0
+"#;
+
+    #[test]
+    fn test_run_tasks_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let docker = FakeDocker::new(DOCKER_SCRIPT);
+        let plan = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &[],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            2,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &interrupted,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.iter()
+                .map(|task| task.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["install", "greet"],
+        );
+        assert_eq!(
+            read_to_string(dir.path().join("greeting.txt")).unwrap(),
+            "hello flynn\n",
+        );
+
+        // The base image is pulled first, and the second task starts from the image the first one
+        // was committed to.
+        let calls = docker
+            .calls()
+            .into_iter()
+            .filter(|call| !call.starts_with("image inspect"))
+            .skip(1)
+            .collect::<Vec<_>>();
+        assert_eq!(docker.calls()[1], "image pull alpine:3.20");
+        assert_eq!(calls.len(), 10, "{calls:#?}");
+        assert!(calls[0].starts_with("container create"));
+        assert!(calls[0].contains(" alpine:3.20 /bin/su"));
+        assert!(calls[0].contains("--label sealed.task=install"));
+        assert_eq!(calls[1], "container cp - container:/");
+        assert_eq!(calls[2], "container start --attach container");
+        assert_eq!(
+            calls[3],
+            format!("container commit container {}", plan[0].image),
+        );
+        assert_eq!(calls[4], "container rm --force container");
+        assert!(calls[5].contains(&format!(" {} /bin/su", plan[0].image)));
+        assert_eq!(calls[6], "container start --attach container");
+        assert!(calls[7].starts_with("container cp container:/scratch/greeting.txt "));
+        assert_eq!(
+            calls[8],
+            format!("container commit container {}", plan[1].image),
+        );
+        assert_eq!(calls[9], "container rm --force container");
+    }
+
+    #[test]
+    fn test_run_tasks_pulls_base_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let task_file =
+            parse("image: alpine:3.20\ntasks:\n  greet:\n    command: echo hello\n").unwrap();
+        let attempts = dir.path().join("attempts");
+        let run = |script: &str| {
+            let docker = FakeDocker::new(&format!("{script}\n{DOCKER_SCRIPT}"));
+            run_tasks(
+                docker.cli(),
+                "sealed",
+                &task_file,
+                &[],
+                dir.path(),
+                None,
+                &HashMap::new(),
+                &[],
+                &NoCache::None,
+                &Settings {
+                    docker_retries: 1,
+                    ..test_settings()
+                },
+                None,
+                1,
+                false,
+                Path::new("kept-containers"),
+                None,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .map(|_| docker.calls())
+        };
+
+        // A rate limit is retried.
+        let calls = run(&format!(
+            "if [ \"$1 $2\" = \"image pull\" ] && [ ! -e '{0}' ]; then\n\
+               touch '{0}'; echo 'toomanyrequests: slow down' >&2; exit 1\n\
+             fi",
+            attempts.display(),
+        ))
+        .unwrap();
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| *call == "image pull alpine:3.20")
+                .count(),
+            2,
+        );
+
+        // A present base image isn't pulled.
+        let calls = run("[ \"$1 $2 $3\" = \"image inspect alpine:3.20\" ] && exit 0").unwrap();
+        assert!(!calls.iter().any(|call| call.starts_with("image pull")));
+    }
+
+    #[test]
+    fn test_run_tasks_loads_base_image_from_image_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let task_file =
+            parse("image: alpine:3.20\ntasks:\n  greet:\n    command: echo hello\n").unwrap();
+        let settings: Settings = serde_yaml::from_str(&format!(
+            "ssh_key: null\nimage_cache:\n  directory: {}\n",
+            dir.path().join("image-cache").display(),
+        ))
+        .unwrap();
+        let cache = ImageCache::from_settings(&settings).unwrap();
+        make_dirs(cache.directory()).unwrap();
+        write(cache.tarball_path("sha256:3f9c2ab"), "tarball").unwrap();
+
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"buildx imagetools\" ] && echo sha256:3f9c2ab\n{DOCKER_SCRIPT}"
+        ));
+        run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &[],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &settings,
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        let calls = docker.calls();
+        assert!(calls.iter().any(|call| call.starts_with("image load")));
+        assert!(!calls.iter().any(|call| call.starts_with("image pull")));
+    }
+
+    #[test]
+    fn test_run_tasks_remote_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let remote_cache = MemoryCache::default();
+        let run = |no_cache: &NoCache| {
+            let docker = FakeDocker::new(&format!(
+                "[ \"$1 $2\" = \"image save\" ] && echo tarball > \"$4\"\n{DOCKER_SCRIPT}"
+            ));
+            let plan = run_tasks(
+                docker.cli(),
+                "sealed",
+                &task_file,
+                &[],
+                dir.path(),
+                None,
+                &HashMap::new(),
+                &[],
+                no_cache,
+                &test_settings(),
+                Some(&remote_cache),
+                1,
+                false,
+                Path::new("kept-containers"),
+                None,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap();
+            (plan, docker.calls())
+        };
+
+        // The first run builds both tasks and publishes their images.
+        let (plan, _) = run(&NoCache::None);
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Run));
+        assert_eq!(remote_cache.objects.borrow().len(), 2);
+
+        // The next one loads them instead of running anything, and copies the outputs out of
+        // the loaded image.
+        std::fs::remove_file(dir.path().join("greeting.txt")).unwrap();
+        let (plan, calls) = run(&NoCache::None);
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Cached));
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| call.starts_with("image load"))
+                .count(),
+            2,
+        );
+        assert!(!calls.iter().any(|call| call.starts_with("container start")));
+        assert!(dir.path().join("greeting.txt").exists());
+
+        // Tasks which aren't cached are neither fetched nor published.
+        remote_cache.objects.borrow_mut().clear();
+        let (plan, calls) = run(&NoCache::All);
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Run));
+        assert!(!calls.iter().any(|call| call.starts_with("image save")));
+        assert!(remote_cache.objects.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_run_tasks_skips_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let plan = run_tasks(
+            FakeDocker::new(DOCKER_SCRIPT).cli(),
+            "sealed",
+            &task_file,
+            &["install"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &interrupted,
+        )
+        .unwrap();
+        assert_eq!(plan.len(), 1);
+
+        // With the image of `install` present, only `greet` runs, starting from that image.
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2 $3\" = \"image inspect {}\" ] && exit 0\n{DOCKER_SCRIPT}",
+            plan[0].image,
+        ));
+        let plan = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &interrupted,
+        )
+        .unwrap();
+        assert_eq!(plan[0].status, TaskStatus::Cached);
+        assert_eq!(plan[1].status, TaskStatus::Run);
+
+        let creates = docker
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("container create"))
+            .collect::<Vec<_>>();
+        assert_eq!(creates.len(), 1);
+        assert!(creates[0].contains("--label sealed.task=greet"));
+        assert!(creates[0].contains(&format!(" {} /bin/su", plan[0].image)));
+    }
+
+    #[test]
+    fn test_run_tasks_copies_cached_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+
+        // Every image exists, so nothing runs, but the output of `greet` is still copied out.
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"image inspect\" ] && exit 0\n{DOCKER_SCRIPT}",
+        ));
+        let plan = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Cached));
+        assert_eq!(
+            read_to_string(dir.path().join("greeting.txt")).unwrap(),
+            "hello flynn\n",
+        );
+
+        // Only `greet` has outputs, and its container is never started.
+        let calls = docker
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("container "))
+            .collect::<Vec<_>>();
+        assert_eq!(calls.len(), 3, "{calls:#?}");
+        assert!(calls[0].starts_with("container create"));
+        assert!(calls[0].contains("--label sealed.task=greet"));
+        assert!(calls[0].contains(&format!(" {} /bin/su", plan[1].image)));
+        assert!(calls[1].starts_with("container cp container:/scratch/greeting.txt "));
+        assert_eq!(calls[2], "container rm --force container");
+    }
+
+    #[test]
+    fn test_run_tasks_publishes_events() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let events = TaskEvents::default();
+        let mut receiver = events.subscribe();
+
+        // `install` succeeds, and `greet`, the second task to be started, fails.
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"container start\" ] && \
+             [ \"$(grep -c '^container start' \"$(dirname \"$0\")/calls.log\")\" = 2 ] && exit 3\n\
+             {DOCKER_SCRIPT}",
+        ));
+        let result = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            Some(&events.app(7)),
+            &Arc::new(AtomicBool::new(false)),
+        );
+        assert!(result.is_err());
+
+        let published = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|event| {
+                assert_eq!(event.app_id, 7);
+                (event.task, event.status)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            published,
+            vec![
+                ("install".to_owned(), TaskEventStatus::Queued),
+                ("greet".to_owned(), TaskEventStatus::Queued),
+                ("install".to_owned(), TaskEventStatus::Running),
+                ("install".to_owned(), TaskEventStatus::Succeeded),
+                ("greet".to_owned(), TaskEventStatus::Running),
+                (
+                    "greet".to_owned(),
+                    TaskEventStatus::Failed { exit_code: Some(3) }
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_run_tasks_failure_deletes_container() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"container start\" ] && exit 3\n{DOCKER_SCRIPT}",
+        ));
+        let result = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        );
+        assert!(matches!(
+            result,
+            Err(SealedServicesError::TaskExited { code: 3, .. })
+        ));
+
+        let calls = docker.calls();
+        assert!(!calls
+            .iter()
+            .any(|call| call.starts_with("container commit")));
+        assert_eq!(calls.last().unwrap(), "container rm --force container");
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| call.starts_with("container create"))
+                .count(),
+            1,
+        );
+    }
+
+    #[test]
+    fn test_run_tasks_keeps_failed_container() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let kept = kept_containers_path(work.path());
+
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"container start\" ] && exit 3\n{DOCKER_SCRIPT}",
+        ));
+        let result = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            true,
+            &kept,
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        );
+        assert!(matches!(
+            result,
+            Err(SealedServicesError::TaskExited { code: 3, .. })
+        ));
+
+        assert!(!docker
+            .calls()
+            .iter()
+            .any(|call| call.starts_with("container rm")));
+        assert_eq!(kept_containers(&kept).unwrap(), vec!["container"]);
+    }
+
+    #[test]
+    fn test_run_tasks_extra_args_disable_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+
+        // Every image exists, but the extra arguments mean every task runs anyway.
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"image inspect\" ] && exit 0\n{DOCKER_SCRIPT}",
+        ));
+        let plan = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &["--network=none".to_owned()],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Run));
+
+        let creates = docker
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("container create"))
+            .collect::<Vec<_>>();
+        assert_eq!(creates.len(), 2);
+        assert!(creates.iter().all(|call| call.contains("--network=none")));
+    }
+
+    #[test]
+    fn test_run_tasks_no_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+
+        // Every image exists, but `install` is run anyway.
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2\" = \"image inspect\" ] && exit 0\n{DOCKER_SCRIPT}",
+        ));
+        let plan = run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["greet"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::Tasks(["install".to_owned()].into_iter().collect()),
+            &test_settings(),
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert_eq!(plan[0].status, TaskStatus::Run);
+
+        let creates = docker
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("container create"))
+            .collect::<Vec<_>>();
+        assert!(creates[0].contains("--label sealed.task=install"));
+    }
+
+    #[test]
+    fn test_run_tasks_compresses_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let copied = dir.path().join("copied.tar.gz");
+
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2 $3\" = \"container cp -\" ] && cat > '{}' && exit 0\n{DOCKER_SCRIPT}",
+            copied.display(),
+        ));
+        run_tasks(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            &["install"],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &Settings {
+                tar_compression: Compression::Gzip,
+                ..test_settings()
+            },
+            None,
+            1,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        // Docker extracts gzip archives itself, so the archive is sent as it is.
+        assert!(std::fs::read(&copied).unwrap().starts_with(&[0x1f, 0x8b]));
+    }
+
+    #[test]
+    fn test_run_tasks_keeps_input_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let run = |hash_manifests: Option<&Path>| {
+            run_tasks(
+                FakeDocker::new(DOCKER_SCRIPT).cli(),
+                "sealed",
+                &task_file,
+                &["install"],
+                dir.path(),
+                hash_manifests,
+                &HashMap::new(),
+                &[],
+                &NoCache::None,
+                &test_settings(),
+                None,
+                1,
+                false,
+                Path::new("kept-containers"),
+                None,
+                &interrupted,
+            )
+            .unwrap()
+        };
+
+        // The manifest doesn't change the images.
+        let without = run(None);
+        assert_eq!(run(Some(work.path())), without);
+        assert_eq!(run(Some(work.path())), without);
+        assert!(hash_manifest_path(work.path(), "install").exists());
+        assert!(!hash_manifest_path(work.path(), "greet").exists());
+    }
+
+    // Runs the chain against a real Docker daemon.
+    #[test]
+    #[ignore = "needs a Docker daemon"]
+    fn test_run_tasks_docker() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(&TASK_FILE.replace("alpine:3.20", "busybox:1.36")).unwrap();
+
+        run_tasks(
+            "docker",
+            "sealed-test",
+            &task_file,
+            &[],
+            dir.path(),
+            None,
+            &HashMap::new(),
+            &[],
+            &NoCache::None,
+            &test_settings(),
+            None,
+            2,
+            false,
+            Path::new("kept-containers"),
+            None,
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_to_string(dir.path().join("greeting.txt")).unwrap(),
+            "hello flynn",
+        );
+    }
+
+    // Restores the outputs of a task from its image when it's cached, against a real Docker
+    // daemon.
+    #[test]
+    #[ignore = "needs a Docker daemon"]
+    fn test_run_tasks_docker_cached_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("name.txt"), "flynn").unwrap();
+        let task_file = parse(&TASK_FILE.replace("alpine:3.20", "busybox:1.36")).unwrap();
+        let run = || {
+            run_tasks(
+                "docker",
+                "sealed-test",
+                &task_file,
+                &[],
+                dir.path(),
+                None,
+                &HashMap::new(),
+                &[],
+                &NoCache::None,
+                &test_settings(),
+                None,
+                1,
+                false,
+                Path::new("kept-containers"),
+                None,
+                &Arc::new(AtomicBool::new(false)),
+            )
+            .unwrap()
+        };
+
+        run();
+        std::fs::remove_file(dir.path().join("greeting.txt")).unwrap();
+
+        let plan = run();
+        assert!(plan.iter().all(|task| task.status == TaskStatus::Cached));
+        assert_eq!(
+            read_to_string(dir.path().join("greeting.txt")).unwrap(),
+            "hello flynn",
+        );
+    }
+}
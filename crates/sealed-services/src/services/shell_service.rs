@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use sealed_common::cache::HashAlgorithm;
+use sealed_database::{
+    task::MappingPath,
+    taskfile::{environment, location, shell, user, TaskFile},
+};
+use typed_path::UnixPathBuf;
+
+use crate::{
+    docker_service::{new_run_id, spawn_shell, ContainerLabels, ExistingImages},
+    error::{SealedServicesError, SealedServicesResult},
+    plan_service::{explain, NoCache, TaskStatus},
+};
+
+// Everything needed to recreate the container a task runs in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaskShell {
+    pub task: String,
+    pub image: String,
+    pub environment: HashMap<String, String>,
+    pub location: UnixPathBuf,
+    pub mount_paths: Vec<MappingPath>,
+    pub mount_readonly: bool,
+    pub ports: Vec<String>,
+    pub user: String,
+    pub shell: String,
+    pub extra_args: Vec<String>,
+}
+
+// Work out the container `name` would run in. The image is the one its dependencies leave behind,
+// which is only available if every task before it in the schedule is cached. Otherwise the shell
+// starts from the last cached image, or from the base image if nothing is cached. Variables missing
+// from the process's environment are taken from `file_environment`, and the images are named with
+// `hash_algorithm` like they are when the tasks run.
+pub fn task_shell(
+    docker_cli: &str,
+    docker_repo: &str,
+    task_file: &TaskFile,
+    name: &str,
+    file_environment: &HashMap<String, String>,
+    hash_algorithm: HashAlgorithm,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<TaskShell> {
+    let task = task_file.tasks.get(name).ok_or_else(|| {
+        SealedServicesError::FailedToRunUserCommand(format!("There is no task {name}."), None)
+    })?;
+
+    let plan = explain(
+        docker_cli,
+        docker_repo,
+        task_file,
+        &[name],
+        &HashMap::new(),
+        file_environment,
+        &NoCache::None,
+        &ExistingImages::default(),
+        hash_algorithm,
+        interrupted,
+    )?;
+
+    // The last entry in the plan is the task itself.
+    let mut image = task_file.image.clone();
+    for planned in &plan[..plan.len() - 1] {
+        if planned.status != TaskStatus::Cached {
+            break;
+        }
+        image.clone_from(&planned.image);
+    }
+
+    let environment = environment(task, file_environment).map_err(|error| {
+        SealedServicesError::FailedToRunUserCommand(format!("Task {name} {error}."), None)
+    })?;
+
+    Ok(TaskShell {
+        task: name.to_owned(),
+        image,
+        environment,
+        location: location(task_file, task),
+        mount_paths: task.mount_paths.clone(),
+        mount_readonly: task.mount_readonly,
+        ports: task.ports.clone(),
+        user: user(task_file, task),
+        shell: shell(task).to_owned(),
+        extra_args: task.extra_docker_arguments.clone(),
+    })
+}
+
+// Drop the user into an interactive shell in the container described by `task_shell`. The shell
+// counts as a run of its own, so it gets a fresh run ID. Setting `interrupted` lets the caller
+// detach cleanly when the user presses Ctrl-C.
+pub fn spawn_task_shell(
+    docker_cli: &str,
+    source_dir: &Path,
+    task_shell: &TaskShell,
+    interrupted: &Arc<AtomicBool>,
+) -> SealedServicesResult<()> {
+    spawn_shell(
+        docker_cli,
+        &task_shell.image,
+        source_dir,
+        &task_shell.environment,
+        &task_shell.location,
+        &task_shell.mount_paths,
+        task_shell.mount_readonly,
+        &task_shell.ports,
+        &task_shell.user,
+        &task_shell.shell,
+        &ContainerLabels {
+            task: &task_shell.task,
+            run_id: &new_run_id(),
+        },
+        &task_shell.extra_args,
+        interrupted,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use sealed_database::taskfile::parse;
+    use typed_path::UnixPath;
+
+    use super::*;
+    use crate::test_utils::FakeDocker;
+
+    const TASK_FILE: &str = r"
+image: alpine:3.20
+location: /scratch
+user: flynn
+tasks:
+  install:
+    command: apk add make
+  serve:
+    dependencies:
+      - install
+    cache: false
+    environment:
+      GRID: tron
+    mount_paths:
+      - src:code:ro
+    ports:
+      - 8080:80
+    shell: /bin/bash
+    extra_docker_arguments:
+      - --cpus=2
+    command: make serve
+";
+
+    #[test]
+    fn test_task_shell_matches_task() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        // Nothing is cached, so the shell starts from the base image.
+        let docker = FakeDocker::new("exit 1");
+        let shell = task_shell(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            "serve",
+            &HashMap::new(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+
+        let task = &task_file.tasks["serve"];
+        assert_eq!(shell.task, "serve");
+        assert_eq!(shell.image, "alpine:3.20");
+        assert_eq!(shell.environment["GRID"], "tron");
+        assert_eq!(shell.location, UnixPath::new("/scratch"));
+        assert_eq!(shell.mount_paths, task.mount_paths);
+        assert_eq!(shell.ports, vec!["8080:80".to_owned()]);
+        assert_eq!(shell.user, "flynn");
+        assert_eq!(shell.shell, "/bin/bash");
+        assert_eq!(shell.extra_args, vec!["--cpus=2".to_owned()]);
+    }
+
+    #[test]
+    fn test_task_shell_uses_cached_dependency() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+
+        let uncached = explain(
+            FakeDocker::new("exit 1").cli(),
+            "sealed",
+            &task_file,
+            &["serve"],
+            &HashMap::new(),
+            &HashMap::new(),
+            &NoCache::None,
+            &ExistingImages::default(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+        let docker = FakeDocker::new(&format!(
+            "[ \"$1 $2 $3\" = \"image inspect {}\" ] && exit 0\nexit 1",
+            uncached[0].image,
+        ));
+
+        let shell = task_shell(
+            docker.cli(),
+            "sealed",
+            &task_file,
+            "serve",
+            &HashMap::new(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+        assert_eq!(shell.image, uncached[0].image);
+    }
+
+    #[test]
+    fn test_task_shell_unknown_task() {
+        let task_file = parse(TASK_FILE).unwrap();
+        assert!(task_shell(
+            FakeDocker::new("exit 1").cli(),
+            "sealed",
+            &task_file,
+            "deploy",
+            &HashMap::new(),
+            HashAlgorithm::default(),
+            &Arc::new(AtomicBool::new(false)),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_spawn_task_shell_args() {
+        let task_file = parse(TASK_FILE).unwrap();
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let shell = task_shell(
+            FakeDocker::new("exit 1").cli(),
+            "sealed",
+            &task_file,
+            "serve",
+            &HashMap::new(),
+            HashAlgorithm::default(),
+            &interrupted,
+        )
+        .unwrap();
+
+        let docker = FakeDocker::new("exit 0");
+        spawn_task_shell(docker.cli(), Path::new("."), &shell, &interrupted).unwrap();
+
+        let calls = docker.calls();
+        assert_eq!(calls.len(), 1);
+        let call = &calls[0];
+        assert!(call.starts_with("container run --rm --interactive --tty --init --user root"));
+        assert!(call.contains("--env GRID=tron"));
+        assert!(call.contains("--workdir /scratch"));
+        assert!(call.contains(",target=/scratch/code,readonly"));
+        assert!(call.contains("--publish 8080:80"));
+        assert!(call.contains("--label sealed.task=serve"));
+        assert!(call.ends_with("--cpus=2 alpine:3.20 /bin/su -s /bin/bash flynn"));
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::{canonicalize, read_to_string},
+    path::Path,
+    time::Duration,
+};
+
+use sealed_common::util::format::CodeStr;
+use sealed_database::{
+    error::{SealedDatabaseError, SealedDatabaseResult},
+    taskfile::{check_with, is_url, parse_with, Loader, TaskFile},
+};
+
+// How long to wait for a server to accept the connection, and then for each read, when fetching an
+// included TaskFile, so an unresponsive server fails the load instead of hanging it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Reads TaskFiles from disk, or from the web for URL includes.
+pub struct TaskFileLoader;
+
+impl Loader for TaskFileLoader {
+    fn load(&self, location: &str) -> SealedDatabaseResult<String> {
+        if !is_url(location) {
+            return read_to_string(location).map_err(|error| {
+                SealedDatabaseError::System(
+                    format!("Unable to read {}.", location.code_str()),
+                    Some(Box::new(error)),
+                )
+            });
+        }
+
+        let response = attohttpc::get(location)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .send()
+            .map_err(|error| {
+                SealedDatabaseError::System(
+                    format!("Unable to fetch {}.", location.code_str()),
+                    Some(Box::new(error)),
+                )
+            })?;
+
+        if !response.is_success() {
+            return Err(SealedDatabaseError::System(
+                format!(
+                    "Unable to fetch {} (status {}).",
+                    location.code_str(),
+                    response.status(),
+                ),
+                None,
+            ));
+        }
+
+        response.text().map_err(|error| {
+            SealedDatabaseError::System(
+                format!("Unable to fetch {}.", location.code_str()),
+                Some(Box::new(error)),
+            )
+        })
+    }
+}
+
+// Identify a TaskFile by its canonical path, so includes resolve the same way wherever it's read
+// from.
+fn location(path: &Path) -> String {
+    canonicalize(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Read and parse a TaskFile. Included TaskFiles are resolved relative to it.
+pub fn parse_file(path: &Path) -> SealedDatabaseResult<TaskFile> {
+    let location = location(path);
+    parse_with(&TaskFileLoader.load(&location)?, &location, &TaskFileLoader)
+}
+
+// Read a TaskFile and merge its includes like `parse_file`, but report every problem with it
+// rather than failing on the first one.
+pub fn check_file(path: &Path) -> SealedDatabaseResult<Vec<SealedDatabaseError>> {
+    let location = location(path);
+    check_with(&TaskFileLoader.load(&location)?, &location, &TaskFileLoader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, write};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_file_include() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("lib")).unwrap();
+        write(
+            dir.path().join("lib").join("base.yml"),
+            "image: encom:os-12\ntasks:\n  lint:\n    command: cargo clippy\n",
+        )
+        .unwrap();
+        write(
+            dir.path().join("project.yml"),
+            "include: [lib/base.yml]\ntasks:\n  build:\n    dependencies: [lint]\n",
+        )
+        .unwrap();
+
+        let task_file = parse_file(&dir.path().join("project.yml")).unwrap();
+
+        assert_eq!(task_file.image, "encom:os-12");
+        assert_eq!(task_file.tasks.len(), 2);
+    }
+
+    #[test]
+    fn test_check_file_reports_every_violation() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sealed.yml");
+        write(
+            &path,
+            "image: encom:os-12\ntasks:\n  foo:\n    dependencies: [foo]\n  bar:\n    environment:\n      FOO=BAR: null\n",
+        )
+        .unwrap();
+
+        assert_eq!(check_file(&path).unwrap().len(), 2);
+        assert!(parse_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = tempdir().unwrap();
+
+        let error = parse_file(&dir.path().join("sealed.yml")).unwrap_err();
+
+        assert!(error.message().starts_with("Unable to read"));
+    }
+
+    #[test]
+    fn test_unreachable_include() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sealed.yml");
+        write(
+            &path,
+            "image: encom:os-12\ninclude: [http://127.0.0.1:9/base.yml]\n",
+        )
+        .unwrap();
+
+        let error = parse_file(&path).unwrap_err();
+
+        assert!(error.message().starts_with("Unable to fetch"));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{read_to_string, write},
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use tempfile::{tempdir, TempDir};
+
+use crate::{error::SealedServicesResult, remote_cache_service::RemoteCache};
+
+// A stand-in for the Docker CLI. It's a shell script which appends its arguments to a log file
+// (one invocation per line) and then runs `script`, so tests can assert on what would have been
+// run without needing a Docker daemon.
+pub struct FakeDocker {
+    // Kept alive so the script and the log aren't deleted.
+    _dir: TempDir,
+    cli: PathBuf,
+    log: PathBuf,
+}
+
+impl FakeDocker {
+    pub fn new(script: &str) -> Self {
+        let dir = tempdir().unwrap();
+        let cli = dir.path().join("docker");
+        let log = dir.path().join("calls.log");
+
+        write(
+            &cli,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> '{}'\n{}\n",
+                log.to_string_lossy(),
+                script,
+            ),
+        )
+        .unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&cli, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        FakeDocker {
+            _dir: dir,
+            cli,
+            log,
+        }
+    }
+
+    // The path to pass as `docker_cli`.
+    pub fn cli(&self) -> &str {
+        self.cli.to_str().unwrap()
+    }
+
+    // The arguments of every invocation so far, in order.
+    pub fn calls(&self) -> Vec<String> {
+        read_to_string(&self.log)
+            .unwrap_or_default()
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+}
+
+// A remote cache which keeps everything in memory.
+#[derive(Default)]
+pub struct MemoryCache {
+    pub objects: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl RemoteCache for MemoryCache {
+    fn get(&self, key: &str, value: &mut (dyn Write + Send)) -> SealedServicesResult<bool> {
+        let objects = self.objects.borrow();
+        let Some(object) = objects.get(key) else {
+            return Ok(false);
+        };
+        value.write_all(object)?;
+        Ok(true)
+    }
+
+    fn put(&self, key: &str, value: &mut dyn Read) -> SealedServicesResult<()> {
+        let mut content = vec![];
+        value.read_to_end(&mut content)?;
+        self.objects.borrow_mut().insert(key.to_owned(), content);
+        Ok(())
+    }
+}
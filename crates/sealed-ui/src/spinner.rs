@@ -1,11 +1,12 @@
 use {
     atty::Stream,
-    crossbeam::channel::{bounded, Sender},
+    crossbeam::channel::{bounded, Receiver, Sender},
     indicatif::{ProgressBar, ProgressStyle},
     lazy_static::lazy_static,
-    log::info,
     scopeguard::guard,
     std::{
+        env,
+        io::{self, Write},
         sync::{
             atomic::{AtomicBool, Ordering},
             Arc,
@@ -15,8 +16,60 @@ use {
     },
 };
 
-// Render a spinner in the terminal. When the returned value is dropped, the spinner is stopped.
+// Setting this environment variable (to anything) disables the spinner animation.
+pub const NO_SPINNER_ENV: &str = "NO_SPINNER";
+
+// Set by `disable_spinner`, e.g., for `--no-spinner`.
+static SPINNER_DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Turn the spinner animation off for the rest of the program.
+pub fn disable_spinner() {
+    SPINNER_DISABLED.store(true, Ordering::SeqCst);
+}
+
+// Whether to animate spinners. Animations are only useful in an interactive terminal, and in CI
+// logs they turn into garbage.
+fn spinner_enabled(is_terminal: bool) -> bool {
+    !SPINNER_DISABLED.load(Ordering::SeqCst) && env::var_os(NO_SPINNER_ENV).is_none() && is_terminal
+}
+
+// Print the message once, as plain text, in place of the spinner.
+fn announce<W: Write>(writer: &mut W, message: &str) {
+    let _ = writeln!(writer, "{}", message);
+}
+
+// A running spinner: the flag which keeps it spinning, and the channel on which the spinner service
+// confirms it has stopped.
+type Spinner = (Arc<AtomicBool>, Receiver<()>);
+
+// Stop the spinner, if one was started, and wait for it to be cleaned up.
+fn stop_spinner(spinner: Option<Spinner>) {
+    if let Some((spinning, response_receiver)) = spinner {
+        // Tell the spinner service to stop the spinner.
+        spinning.store(false, Ordering::SeqCst);
+
+        // Wait for the spinner to stop. The `unwrap` is safe since we never hang up the channel.
+        response_receiver.recv().unwrap();
+    }
+}
+
+// Render a spinner in the terminal. When the returned value is dropped, the spinner is stopped. If
+// the spinner is disabled, the message is printed once instead.
 pub fn spin(message: &str) -> impl Drop {
+    spin_to(
+        &mut io::stderr(),
+        atty::is(Stream::Stdout) && atty::is(Stream::Stderr),
+        message,
+    )
+}
+
+// `spin`, announcing the message on `writer` when it isn't a terminal.
+fn spin_to<W: Write>(writer: &mut W, is_terminal: bool, message: &str) -> impl Drop {
+    if !spinner_enabled(is_terminal) {
+        announce(writer, message);
+        return guard(None, stop_spinner);
+    }
+
     // Start a thread for our spinner-as-a-service. This thread will only be created once and will
     // live for the duration of the whole program.
     lazy_static! {
@@ -31,12 +84,6 @@ pub fn spin(message: &str) -> impl Drop {
           let (message, spinning, response_sender) =
             request_receiver.recv().unwrap();
 
-          // If STDERR is not a TTY, the spinner will be hidden. In that case, just print
-          // the message to STDERR.
-          if !atty::is(Stream::Stderr) {
-            info!("{}", message);
-          }
-
           // Create the spinner!
           let spinner = ProgressBar::new(1);
           spinner.set_style(ProgressStyle::default_spinner());
@@ -83,11 +130,35 @@ pub fn spin(message: &str) -> impl Drop {
         .unwrap();
 
     // Return a guard that stops the spinner via its destructor.
-    guard((), move |()| {
-        // Tell the spinner service to stop the spinner.
-        spinning.store(false, Ordering::SeqCst);
+    guard(Some((spinning, response_receiver)), stop_spinner)
+}
 
-        // Wait for the spinner to stop. The `unwrap` is safe since we never hang up the channel.
-        response_receiver.recv().unwrap();
-    })
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spin_without_terminal() {
+        let mut output = vec![];
+
+        // The guard doesn't wait on the spinner service.
+        drop(spin_to(&mut output, false, "Pulling image\u{2026}"));
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "Pulling image\u{2026}\n");
+        assert!(!output.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn test_no_spinner_env() {
+        env::set_var(NO_SPINNER_ENV, "1");
+        assert!(!spinner_enabled(true));
+
+        let mut output = vec![];
+        drop(spin_to(&mut output, true, "Pulling image\u{2026}"));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Pulling image\u{2026}\n"
+        );
+    }
 }
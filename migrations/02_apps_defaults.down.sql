@@ -0,0 +1,2 @@
+-- Revert migration: Apps require an owner again
+ALTER TABLE apps ALTER COLUMN owner_id SET NOT NULL;
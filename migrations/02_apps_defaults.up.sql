@@ -0,0 +1,8 @@
+-- Apps are created by the server before any user owns them
+ALTER TABLE apps ALTER COLUMN owner_id DROP NOT NULL;
+
+-- Make sure the timestamps exist and are filled in by the database
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE apps ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE apps ALTER COLUMN created_at SET DEFAULT NOW();
+ALTER TABLE apps ALTER COLUMN updated_at SET DEFAULT NOW();
@@ -0,0 +1,4 @@
+-- Revert migration: Drop the task tables
+DROP INDEX IF EXISTS tasks_app_id_idx;
+DROP TABLE IF EXISTS tasks;
+DROP TABLE IF EXISTS taskfiles;
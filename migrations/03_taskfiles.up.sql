@@ -0,0 +1,25 @@
+-- Create taskfiles table, holding the top-level settings of an app's TaskFile
+CREATE TABLE IF NOT EXISTS taskfiles (
+  app_id BIGINT PRIMARY KEY REFERENCES apps(id) ON DELETE CASCADE,
+  image TEXT NOT NULL,
+  default_task TEXT,
+  location TEXT NOT NULL,
+  "user" TEXT NOT NULL,
+  command_prefix TEXT NOT NULL DEFAULT '',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create tasks table, one row per task of a TaskFile
+CREATE TABLE IF NOT EXISTS tasks (
+  id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  app_id BIGINT NOT NULL REFERENCES taskfiles(app_id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  description TEXT,
+  definition JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (app_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS tasks_app_id_idx ON tasks (app_id);
@@ -5,6 +5,9 @@ use sealed_common::error;
 async fn main() {
     match exec().await {
         Ok(_) => (),
-        Err(e) => error!("Error: {}", e),
+        Err(e) => {
+            error!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
 }